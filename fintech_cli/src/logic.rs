@@ -1,10 +1,34 @@
+use fintech_common::accounts::AccountStatus;
 use fintech_common::cli::constants::*;
 use fintech_common::cli::helpers::*;
+use fintech_common::fx::{CurrencyScales, FxRates};
+use fintech_common::margin::MarginConfig;
+use fintech_common::positions::MarkPrice;
+use fintech_common::statements::StatementFilter;
 use fintech_common::trading_platform::TradingPlatform;
-use fintech_common::types::{Order, Side};
+use fintech_common::types::{Money, Order, Price, Quantity, Side};
 
 pub fn main_loop() {
-    let mut trading_platform = TradingPlatform::new();
+    // Conversions use these rates, such as "EUR/USD=1.08,USD/EUR=0.92"; there are none by default.
+    let fx_rates = match std::env::var("FINTECH_FX_RATES") {
+        Ok(fx_rates) => fx_rates.parse::<FxRates>().unwrap_or_else(|err| {
+            eprintln!("[ERROR] {}", err);
+            FxRates::default()
+        }),
+        Err(_) => FxRates::default(),
+    };
+    // Amounts have two decimals, unless these say otherwise, such as "JPY=0,BTC=8".
+    let currency_scales = match std::env::var("FINTECH_CURRENCY_SCALES") {
+        Ok(scales) => scales.parse::<CurrencyScales>().unwrap_or_else(|err| {
+            eprintln!("[ERROR] {}", err);
+            CurrencyScales::default()
+        }),
+        Err(_) => CurrencyScales::default(),
+    };
+    let mut trading_platform = TradingPlatform::new()
+        .with_margin(MarginConfig::default())
+        .with_fx_rates(fx_rates)
+        .with_currency_scales(currency_scales);
 
     loop {
         if let Some(line) = read_from_stdin(PROMPT) {
@@ -15,13 +39,30 @@ pub fn main_loop() {
                 HELP | "h" => help(),
                 DEPOSIT | "d" => deposit(words, &mut trading_platform),
                 WITHDRAW | "w" => withdraw(words, &mut trading_platform),
+                DEPOSIT_UNITS | "du" => deposit_units(words, &mut trading_platform),
+                WITHDRAW_UNITS | "wu" => withdraw_units(words, &mut trading_platform),
                 SEND | "s" => send(words, &mut trading_platform),
+                CONVERT | "cv" => convert(words, &mut trading_platform),
+                CLOSE | "cl" => close(words, &mut trading_platform),
+                STATUS | "st" => status(words, &mut trading_platform),
+                LIMITS | "li" => limits(words, &trading_platform),
+                MARGIN | "mg" => margin(words, &mut trading_platform),
+                POSITIONS | "ps" => positions(words, &trading_platform),
+                STATEMENT | "sm" => statement(words, &trading_platform),
                 PRINT | LEDGER | TX_LOG | "p" | "l" | "t" => print_ledger(&trading_platform),
                 ACCOUNTS | "a" => print_accounts(&trading_platform),
                 CLIENT | "c" => print_single_account(words, &mut trading_platform),
                 ORDER | "o" => order(words, &mut trading_platform),
                 ORDER_BOOK | "ob" => order_book(words, &trading_platform),
                 ORDER_BOOK_BY_PRICE | "obp" => order_book_by_price(words, &trading_platform),
+                OCO | "oc" => oco(words, &mut trading_platform),
+                BRACKET | "br" => bracket(words, &mut trading_platform),
+                ORDER_GROUP | "g" => print_order_group(words, &trading_platform),
+                ORDER_GROUPS | "gs" => print_order_groups(&trading_platform),
+                CANCEL_ORDER_GROUP | "cg" => cancel_order_group(words, &mut trading_platform),
+                CANCEL_ALL | "ca" => cancel_all(words, &mut trading_platform),
+                RESET | "r" => reset(words, &mut trading_platform),
+                VERIFY | "v" => verify(&trading_platform),
                 QUIT | "q" => break,
                 _ => println!("Unrecognized command; try `help`."),
             }
@@ -39,29 +80,33 @@ pub fn main_loop() {
 /// If it doesn't exist, it will be created on this occasion.
 /// It is allowed to deposit 0, and this transaction will be recorded.
 ///
+/// An optional currency, such as EUR, can follow the amount;
+/// without it, the funds are in the platform's currency.
+///
 /// Performs basic input validation of the signer's name,
-/// and of the amount, which should be a non-negative integer.
+/// and of the amount, which should be a non-negative decimal number.
 ///
 /// Prints a success or an error message depending on the status of the
 /// transaction, and records the transaction in the success case.
 ///
 /// # Errors
-/// - Attempted overflow (account over-funded), `AccountingError::AccountOverFunded`
+/// - Attempted overflow (account over-funded), `AccountError::OverFunded`
+///   or `FxError::OverFunded`
 ///
-/// We could pattern-match it for a different output format and the message
-/// contents, but haven't done that here. Error is still printed.
+/// The error is printed with its code and message.
 fn deposit(words: Vec<&str>, trading_platform: &mut TradingPlatform) {
+    let (words, currency) = words_and_optional_currency(&words);
     let words_len = words.len();
 
     if words_len < 3 {
-        println!("The deposit command: {DEPOSIT} 'signer full name' <amount>");
+        println!("The deposit command: {DEPOSIT} 'signer full name' <amount> [currency]");
         return;
     }
 
     let signer = words[1..(words_len - 1)].join(" ");
     let signer = signer.trim_matches(|c| c == '\'' || c == '\"').trim();
 
-    let amount = match words[words_len - 1].parse::<u64>() {
+    let amount = match words[words_len - 1].parse::<Money>() {
         Ok(amount) => amount,
         Err(_err) => {
             cannot_parse_number(words[words_len - 1]);
@@ -69,9 +114,12 @@ fn deposit(words: Vec<&str>, trading_platform: &mut TradingPlatform) {
         }
     };
 
+    let currency = currency.unwrap_or_else(|| trading_platform.currency().to_string());
     if is_valid_name(signer) {
-        let tx = trading_platform.deposit(signer, amount);
-        println!("{:?}", tx);
+        match trading_platform.deposit_in(signer, &currency, amount) {
+            Ok(tx) => println!("{:?}", tx),
+            Err(err) => print_error(&err),
+        }
     }
 }
 
@@ -87,33 +135,111 @@ fn deposit(words: Vec<&str>, trading_platform: &mut TradingPlatform) {
 ///
 /// It is allowed to withdraw 0, and this transaction will be recorded.
 ///
+/// An optional currency can follow the amount, same as in `deposit`.
+///
 /// Performs basic input validation of the signer's name,
-/// and of the amount, which should be a non-negative integer.
+/// and of the amount, which should be a non-negative decimal number.
 ///
 /// Prints a success or an error message depending on the status of the
 /// transaction, and records the transaction in the success case.
 ///
 /// # Errors
-/// - Account doesn't exist, `AccountingError::AccountNotFound`;
-/// - Attempted overflow (account under-funded), `AccountingError::AccountUnderFunded`.
+/// - Account doesn't exist, `AccountError::NotFound`;
+/// - Attempted overflow (account under-funded), `AccountError::UnderFunded`
+///   or `FxError::UnderFunded`.
 ///
-/// We could pattern-match them for a different output format and the message
-/// contents, but haven't done that here. Errors are still printed.
+/// Errors are printed with their codes and messages.
 fn withdraw(words: Vec<&str>, trading_platform: &mut TradingPlatform) {
+    let (words, currency) = words_and_optional_currency(&words);
+    let words_len = words.len();
+
+    if words_len < 3 {
+        println!("The withdraw command: {WITHDRAW} 'signer full name' <amount> [currency]");
+        return;
+    }
+
+    let signer = words[1..(words_len - 1)].join(" ");
+    let signer = signer.trim_matches(|c| c == '\'' || c == '\"').trim();
+
+    let currency = currency.unwrap_or_else(|| trading_platform.currency().to_string());
+    if let Ok(amount) = words[words_len - 1].parse::<Money>() {
+        if is_valid_name(signer) {
+            match trading_platform.withdraw_in(signer, &currency, amount) {
+                Ok(tx) => println!("{:?}", tx),
+                Err(err) => print_error(&err),
+            }
+        }
+    } else {
+        cannot_parse_number(words[words_len - 1]);
+    }
+}
+
+/// **Deposit units of the traded instrument to an account**
+///
+/// The signer's name can consist of multiple words, same as in `deposit`.
+///
+/// The deposit account doesn't need to exist in advance.
+/// If it doesn't exist, it will be created on this occasion, with a zero balance.
+///
+/// Performs basic input validation of the signer's name,
+/// and of the units, which should be a non-negative decimal number.
+///
+/// # Errors
+/// - Attempted overflow (too many units), `AccountError::HoldingsOverflow`
+fn deposit_units(words: Vec<&str>, trading_platform: &mut TradingPlatform) {
+    let words_len = words.len();
+
+    if words_len < 3 {
+        println!("The deposit units command: {DEPOSIT_UNITS} 'signer full name' <units>");
+        return;
+    }
+
+    let signer = words[1..(words_len - 1)].join(" ");
+    let signer = signer.trim_matches(|c| c == '\'' || c == '\"').trim();
+
+    if let Ok(units) = words[words_len - 1].parse::<Quantity>() {
+        if is_valid_name(signer) {
+            let symbol = trading_platform.symbol().to_string();
+            match trading_platform.deposit_units(signer, &symbol, units) {
+                Ok(tx) => println!("{:?}", tx),
+                Err(err) => print_error(&err),
+            }
+        }
+    } else {
+        cannot_parse_number(words[words_len - 1]);
+    }
+}
+
+/// **Withdraw units of the traded instrument from an account**
+///
+/// The signer's name can consist of multiple words, same as in `withdraw`.
+///
+/// The withdrawal account needs to exist in advance.
+///
+/// Performs basic input validation of the signer's name,
+/// and of the units, which should be a non-negative decimal number.
+///
+/// # Errors
+/// - Account doesn't exist, `AccountError::NotFound`;
+/// - Not enough units held, `AccountError::HoldingsInsufficient`.
+fn withdraw_units(words: Vec<&str>, trading_platform: &mut TradingPlatform) {
     let words_len = words.len();
 
     if words_len < 3 {
-        println!("The withdraw command: {WITHDRAW} 'signer full name' <amount>");
+        println!("The withdraw units command: {WITHDRAW_UNITS} 'signer full name' <units>");
         return;
     }
 
     let signer = words[1..(words_len - 1)].join(" ");
     let signer = signer.trim_matches(|c| c == '\'' || c == '\"').trim();
 
-    if let Ok(amount) = words[words_len - 1].parse::<u64>() {
+    if let Ok(units) = words[words_len - 1].parse::<Quantity>() {
         if is_valid_name(signer) {
-            let tx = trading_platform.withdraw(signer, amount);
-            println!("{:?}", tx);
+            let symbol = trading_platform.symbol().to_string();
+            match trading_platform.withdraw_units(signer, &symbol, units) {
+                Ok(tx) => println!("{:?}", tx),
+                Err(err) => print_error(&err),
+            }
         }
     } else {
         cannot_parse_number(words[words_len - 1]);
@@ -134,34 +260,37 @@ fn withdraw(words: Vec<&str>, trading_platform: &mut TradingPlatform) {
 /// If it doesn't exist, an error message will be output to
 /// the user, but the execution won't break.
 ///
-/// It is allowed to send 0, and these two transactions,
-/// withdraw and deposit, will be recorded.
+/// An optional currency can follow the amount, same as in `deposit`,
+/// and an optional memo for the recipient can follow another separator after it.
+///
+/// It is allowed to send 0, and the transfer will be recorded.
 ///
 /// Performs basic input validation of the sender's and recipient's name,
-/// and of the amount, which should be a non-negative integer.
+/// and of the amount, which should be a non-negative decimal number.
 ///
 /// Prints a success or an error message depending on the status of the
 /// transaction, and records the two transactions in the success case.
 ///
 /// # Errors
-/// - Any of the two accounts doesn't exist, `AccountingError::AccountNotFound`;
-/// - Attempted overflow (sender's account under-funded), `AccountingError::AccountUnderFunded`;
-/// - Attempted overflow (recipient's account over-funded), `AccountingError::AccountOverFunded`.
+/// - Any of the two accounts doesn't exist, `AccountError::NotFound`;
+/// - Attempted overflow (sender's account under-funded), `AccountError::UnderFunded`;
+/// - Attempted overflow (recipient's account over-funded), `AccountError::OverFunded`.
 ///
-/// We could pattern-match them for a different output format and the message
-/// contents, but haven't done that here. Errors are still printed.
+/// Errors are printed with their codes and messages.
 fn send(words: Vec<&str>, trading_platform: &mut TradingPlatform) {
+    let (words, memo) = words_and_optional_memo(&words);
+    let (words, currency) = words_and_optional_currency(words);
     let words_len = words.len();
 
     if (words_len < 4) || !words.contains(&SEPARATOR) {
-        println!("The send command: {SEND} 'sender full name' {SEPARATOR} 'recipient full name' <amount>");
+        println!("The send command: {SEND} 'sender full name' {SEPARATOR} 'recipient full name' <amount> [currency] [{SEPARATOR} memo]");
         return;
     }
 
     let to_pos = words
         .iter()
         .position(|&r| r == SEPARATOR)
-        .expect(format!("The send command must contain '{}'.", SEPARATOR).as_str());
+        .unwrap_or_else(|| panic!("The send command must contain '{}'.", SEPARATOR));
 
     let sender = words[1..to_pos].join(" ");
     let sender = sender.trim_matches(|c| c == '\'' || c == '\"').trim();
@@ -169,29 +298,285 @@ fn send(words: Vec<&str>, trading_platform: &mut TradingPlatform) {
     let recipient = words[to_pos + 1..words_len - 1].join(" ");
     let recipient = recipient.trim_matches(|c| c == '\'' || c == '\"').trim();
 
-    if let Ok(amount) = words[words_len - 1].parse::<u64>() {
+    let currency = currency.unwrap_or_else(|| trading_platform.currency().to_string());
+    if let Ok(amount) = words[words_len - 1].parse::<Money>() {
         if is_valid_name(sender) && is_valid_name(recipient) {
-            let txs = trading_platform.send(sender, recipient, amount);
-            println!("{:?}", txs);
+            match trading_platform.send_in(sender, recipient, &currency, amount, memo.as_deref()) {
+                Ok(tx) => println!("{:?}", tx),
+                Err(err) => print_error(&err),
+            }
         }
     } else {
         cannot_parse_number(words[words_len - 1]);
     }
 }
 
-/// **Print the entire ledger (all transactions ever) - transaction log**
-fn print_ledger(trading_platform: &TradingPlatform) {
+/// **Convert funds of an account from one currency into another**
+///
+/// The signer's name can consist of multiple words, same as in `deposit`,
+/// and is followed by the amount, and the codes of the two currencies, such as `10 USD EUR`.
+/// The funds are converted at the rates from the `FINTECH_FX_RATES` environment variable.
+///
+/// # Errors
+/// - There's no rate for the two currencies, `FxError::RateNotFound`;
+/// - Account doesn't exist, `AccountError::NotFound`;
+/// - Attempted overflow (available funds too low), `AccountError::UnderFunded`
+///   or `FxError::UnderFunded`.
+fn convert(words: Vec<&str>, trading_platform: &mut TradingPlatform) {
+    let words_len = words.len();
+
+    if words_len < 5 {
+        println!("The convert command: {CONVERT} 'signer full name' <amount> <from> <to>");
+        return;
+    }
+
+    let signer = words[1..(words_len - 3)].join(" ");
+    let signer = signer.trim_matches(|c| c == '\'' || c == '\"').trim();
+    let (from, to) = (words[words_len - 2], words[words_len - 1]);
+
+    if let Ok(amount) = words[words_len - 3].parse::<Money>() {
+        if is_valid_name(signer) {
+            match trading_platform.convert(signer, from, to, amount) {
+                Ok(tx) => println!("{}", tx),
+                Err(err) => print_error(&err),
+            }
+        }
+    } else {
+        cannot_parse_number(words[words_len - 3]);
+    }
+}
+
+/// **Close an account**
+///
+/// The signer's name, and the optional recipient's name after the separator,
+/// can consist of multiple words, same as in `send`.
+///
+/// The account needs to be empty, unless a recipient is given, who then gets all of
+/// its funds and units. Accounts with open orders can't be closed.
+///
+/// # Errors
+/// - Any of the two accounts doesn't exist, `AccountError::NotFound`;
+/// - The signer has open orders, `AccountError::HasOpenOrders`;
+/// - The account isn't empty, and there's no recipient, `AccountError::NotEmpty`.
+fn close(words: Vec<&str>, trading_platform: &mut TradingPlatform) {
+    if words.len() < 2 {
+        println!(
+            "The close command: {CLOSE} 'signer full name' [{SEPARATOR} 'recipient full name']"
+        );
+        return;
+    }
+
+    let (signer, recipient) = signer_and_optional_recipient(&words[1..]);
+
+    if is_valid_name(&signer) && recipient.as_deref().is_none_or(is_valid_name) {
+        match trading_platform.close_account(&signer, recipient.as_deref()) {
+            Ok(txs) => println!("{:?}", txs),
+            Err(err) => print_error(&err),
+        }
+    }
+}
+
+/// **Print or set the status of an account**
+///
+/// The signer's name can consist of multiple words, same as in `close`.
+/// Without a status after the separator, the account's current status is printed.
+/// Otherwise, it's set to active, frozen, withdraw-only or closed, and the resting orders
+/// that the status doesn't allow are cancelled.
+///
+/// # Errors
+/// - Account doesn't exist, `AccountError::NotFound`;
+/// - Account is closed, and isn't being made active, `AccountError::Closed`;
+/// - Any of the errors from `close`, when closing it.
+fn status(words: Vec<&str>, trading_platform: &mut TradingPlatform) {
+    if words.len() < 2 {
+        println!(
+            "The status command: {STATUS} 'signer full name' [{SEPARATOR} active|frozen|withdraw-only|closed]"
+        );
+        return;
+    }
+
+    let (signer, status) = signer_and_optional_recipient(&words[1..]);
+
+    if !is_valid_name(&signer) {
+        return;
+    }
+
+    match status.map(|status| status.parse::<AccountStatus>()) {
+        None => {
+            let status = trading_platform.accounts.status_of(&signer);
+            match trading_platform.accounts.balance_of(&signer) {
+                Err(err) if status != AccountStatus::Closed => print_error(&err),
+                _ => println!(r#"The client "{}" is {}."#, signer, status),
+            }
+        }
+        Some(Ok(status)) => match trading_platform.set_account_status(&signer, status) {
+            Ok(txs) => {
+                for tx in txs {
+                    println!("{}", tx);
+                }
+            }
+            Err(err) => print_error(&err),
+        },
+        Some(Err(msg)) => eprintln!("[ERROR] {}", msg),
+    }
+}
+
+/// **Print what's left of a client's limits on withdrawals and transfers**
+fn limits(words: Vec<&str>, trading_platform: &TradingPlatform) {
+    if words.len() < 2 {
+        println!("The limits command: {LIMITS} 'signer full name'");
+        return;
+    }
+
+    let signer = words[1..].join(" ");
+    let signer = signer.trim_matches(|c| c == '\'' || c == '\"').trim();
+
+    if !is_valid_name(signer) {
+        return;
+    }
+
     println!(
-        "The ledger (full transaction log, complete order history): {:#?}",
-        trading_platform.tx_log
+        r#"The limits of "{}": {}"#,
+        signer,
+        trading_platform.allowance_of(signer)
     );
 }
 
-/// **Print all accounts and their balances**
+/// **Print a margin account, open one, or repay a part of its loan**
+///
+/// The signer's name can consist of multiple words, same as in `close`.
+/// Without an action after the separator, the account is printed, valued at the last trade price.
+/// Otherwise, `open` lets the account buy with borrowed funds, and `repay <amount>`
+/// pays that amount of the loan back from the account's available funds.
+///
+/// # Errors
+/// - Account doesn't exist, `AccountError::NotFound`;
+/// - Account isn't a margin account, `MarginError::AccountNotFound`;
+/// - Account owes less than the amount, `MarginError::LoanExceeded`;
+/// - Attempted overflow (available balance too low), `AccountError::UnderFunded`.
+fn margin(words: Vec<&str>, trading_platform: &mut TradingPlatform) {
+    if words.len() < 2 {
+        println!(
+            "The margin command: {MARGIN} 'signer full name' [{SEPARATOR} open|repay <amount>]"
+        );
+        return;
+    }
+
+    let (signer, action) = signer_and_optional_recipient(&words[1..]);
+
+    if !is_valid_name(&signer) {
+        return;
+    }
+
+    let action = action.unwrap_or_default();
+    let result = match action.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [] => match trading_platform.margin_of(&signer) {
+            Ok(summary) => {
+                println!(r#"The margin account of "{}": {}"#, signer, summary);
+                return;
+            }
+            Err(err) => Err(err),
+        },
+        ["open"] => trading_platform.open_margin_account(&signer),
+        ["repay", amount] => match amount.parse::<Money>() {
+            Ok(amount) => trading_platform.repay_loan(&signer, amount),
+            Err(_err) => {
+                cannot_parse_number(amount);
+                return;
+            }
+        },
+        _ => {
+            eprintln!(
+                r#"[ERROR] Invalid margin action: "{}"; expected "open" or "repay <amount>""#,
+                action
+            );
+            return;
+        }
+    };
+
+    match result {
+        Ok(tx) => println!("{}", tx),
+        Err(err) => print_error(&err),
+    }
+}
+
+/// **Print the positions of all clients, or of one, with their profit and loss**
+///
+/// The signer's name, if any, can consist of multiple words, same as in `close`.
+/// The open units are valued at the last trade price, unless `mid` follows the separator,
+/// which values them halfway between the best bid and the best ask.
+fn positions(words: Vec<&str>, trading_platform: &TradingPlatform) {
+    let (signer, mark) = signer_and_optional_recipient(&words[1..]);
+
+    if !signer.is_empty() && !is_valid_name(&signer) {
+        return;
+    }
+
+    let mark = match mark.map(|mark| mark.parse::<MarkPrice>()) {
+        None => MarkPrice::default(),
+        Some(Ok(mark)) => mark,
+        Some(Err(msg)) => {
+            eprintln!("[ERROR] {}", msg);
+            return;
+        }
+    };
+
+    let signer = Some(signer.as_str()).filter(|signer| !signer.is_empty());
+    let positions = trading_platform.positions(signer, mark);
+    if positions.is_empty() {
+        println!("No positions.");
+    }
+    for position in positions {
+        println!("{}", position);
+    }
+}
+
+/// **Print a page of a client's statement, with their balance after each transaction**
+///
+/// The signer's name can consist of multiple words, same as in `close`.
+/// An optional filter follows the separator, as space-separated `<part>=<value>` pairs,
+/// such as `kinds=deposit,withdraw since=1700000000000 after=12 limit=20`;
+/// see [`StatementFilter`]. A page that isn't the last one ends with the cursor
+/// that goes into `after` for the next page.
+fn statement(words: Vec<&str>, trading_platform: &TradingPlatform) {
+    if words.len() < 2 {
+        println!("The statement command: {STATEMENT} 'signer full name' [{SEPARATOR} <filter>]");
+        return;
+    }
+
+    let (signer, filter) = signer_and_optional_recipient(&words[1..]);
+
+    if !is_valid_name(&signer) {
+        return;
+    }
+
+    let filter = match filter.unwrap_or_default().parse::<StatementFilter>() {
+        Ok(filter) => filter,
+        Err(msg) => {
+            eprintln!("[ERROR] {}", msg);
+            return;
+        }
+    };
+
+    match trading_platform.statement(&signer, &filter) {
+        Ok(statement) => println!("{}", statement),
+        Err(err) => print_error(&err),
+    }
+}
+
+/// **Print the entire ledger (all transactions ever) - transaction log**
+fn print_ledger(trading_platform: &TradingPlatform) {
+    println!("The ledger (full transaction log, complete order history):");
+    for tx in trading_platform.tx_log.records() {
+        println!("{}", tx);
+    }
+}
+
+/// **Print all accounts with their balances and holdings**
 pub fn print_accounts(trading_platform: &TradingPlatform) {
     println!(
-        "Accounts and their balances: {:#?}",
-        trading_platform.accounts.accounts
+        "Accounts with their balances and holdings: {:#?}",
+        trading_platform.accounts.summaries()
     );
 }
 
@@ -201,7 +586,8 @@ pub fn print_accounts(trading_platform: &TradingPlatform) {
 /// We can wrap the signer's name in single or double quotes,
 /// but we don't have to use any quotes at all.
 ///
-/// Prints the signer's balance.
+/// Prints the signer's balance and holdings, and the parts of them held for resting orders,
+/// along with the account's status, unless it's active, and the loan of a margin account.
 fn print_single_account(words: Vec<&str>, trading_platform: &mut TradingPlatform) {
     let words_len = words.len();
 
@@ -214,14 +600,32 @@ fn print_single_account(words: Vec<&str>, trading_platform: &mut TradingPlatform
     let signer = signer.trim_matches(|c| c == '\'' || c == '\"').trim();
 
     if is_valid_name(signer) {
-        match trading_platform.balance_of(signer) {
-            Ok(balance) => {
+        match trading_platform.accounts.summary_of(signer) {
+            Ok(summary) => {
                 println!(
                     r#"The client "{}" has the following balance: {}."#,
-                    signer, balance
-                )
+                    signer, summary.balance
+                );
+                if summary.status != AccountStatus::Active {
+                    println!("The account is {}.", summary.status);
+                }
+                if summary.margin {
+                    println!("It's a margin account, which owes {}.", summary.loan);
+                }
+                if !summary.held.is_zero() {
+                    println!("Of that, {} is held for resting orders.", summary.held);
+                }
+                for (symbol, units) in summary.holdings {
+                    match summary.held_units.get(&symbol) {
+                        Some(held) => println!(
+                            "Holds {} {}, of which {} are held for resting orders.",
+                            units, symbol, held
+                        ),
+                        None => println!("Holds {} {}.", units, symbol),
+                    }
+                }
             }
-            Err(_) => println!(r#"The client "{}" doesn't exist."#, signer),
+            Err(err) => print_error(&err),
         }
     }
 }
@@ -235,15 +639,16 @@ fn print_single_account(words: Vec<&str>, trading_platform: &mut TradingPlatform
 /// The account needs to exist in advance.
 ///
 /// Performs basic input validation of the signer's name, of the side,
-/// and of the price and amount, which should be non-negative integers.
+/// and of the price and amount, which should be non-negative decimal numbers.
 ///
 /// Prints a success or an error message depending on the status of the
 /// receipt (of the processing of the order).
 ///
 /// # Errors
-/// - Account not found, `AccountingError::AccountNotFound`;
-/// - Account has insufficient funds, `AccountingError::AccountUnderFunded`;
-/// - Account would be over-funded, `AccountingError::AccountOverFunded`.
+/// - Account not found, `AccountError::NotFound`;
+/// - Buyer can't afford the order, `OrderError::UnderFunded`;
+/// - Seller doesn't hold enough units, `OrderError::HoldingsInsufficient`;
+/// - Settling a match would over-fund an account, `AccountError::OverFunded`.
 fn order(words: Vec<&str>, trading_platform: &mut TradingPlatform) {
     let words_len = words.len();
 
@@ -264,7 +669,7 @@ fn order(words: Vec<&str>, trading_platform: &mut TradingPlatform) {
         }
     };
 
-    let price = match words[words_len - 2].parse::<u64>() {
+    let price = match words[words_len - 2].parse::<Price>() {
         Ok(price) => price,
         Err(_err) => {
             cannot_parse_number(words[words_len - 2]);
@@ -272,7 +677,7 @@ fn order(words: Vec<&str>, trading_platform: &mut TradingPlatform) {
         }
     };
 
-    let amount = match words[words_len - 1].parse::<u64>() {
+    let amount = match words[words_len - 1].parse::<Quantity>() {
         Ok(amount) => amount,
         Err(_err) => {
             cannot_parse_number(words[words_len - 1]);
@@ -282,8 +687,10 @@ fn order(words: Vec<&str>, trading_platform: &mut TradingPlatform) {
 
     if is_valid_name(signer) {
         let order = Order::new(price, amount, side, signer.to_string());
-        let receipt = trading_platform.process_order(order);
-        println!("{:?}", receipt);
+        match trading_platform.process_order(order) {
+            Ok(receipt) => println!("{:?}", receipt),
+            Err(err) => print_error(&err),
+        }
     }
 }
 
@@ -351,3 +758,253 @@ fn order_book_by_price(words: Vec<&str>, trading_platform: &TradingPlatform) {
         trading_platform.order_book_by_price(desc)
     );
 }
+
+/// **Place a one-cancels-other pair of a take-profit and a stop-loss order**
+///
+/// The signer's name can consist of multiple words.
+/// We can wrap the signer's name in single or double quotes,
+/// but we don't have to use any quotes at all.
+///
+/// The side is the side of both exits, i.e., "sell" to close a long position.
+///
+/// The take-profit is put in the order book right away, while the stop-loss waits
+/// for the last trade price to reach its price. A fill on either cancels the other.
+///
+/// Prints the order group, or an error message.
+///
+/// # Errors
+/// - Legs don't fit together, `OrderError::GroupInvalid`;
+/// - Account not found, `AccountError::NotFound`;
+/// - Account has insufficient funds, `OrderError::UnderFunded`.
+fn oco(words: Vec<&str>, trading_platform: &mut TradingPlatform) {
+    let words_len = words.len();
+
+    if words_len < 6 {
+        println!(
+            "The oco command: {OCO} 'signer full name' <side> <take-profit price> <stop-loss price> <amount>"
+        );
+        return;
+    }
+
+    let signer = words[1..(words_len - 4)].join(" ");
+    let signer = signer.trim_matches(|c| c == '\'' || c == '\"').trim();
+
+    let side = match words[words_len - 4] {
+        "buy" | "bid" => Side::Buy,
+        "sell" | "ask" => Side::Sell,
+        _ => {
+            eprintln!(r#"[ERROR] Side can be either "buy"/"bid" or "sell"/"ask"."#);
+            return;
+        }
+    };
+
+    let mut prices = [Price::ZERO; 2];
+    for (price, word) in prices
+        .iter_mut()
+        .zip(&words[(words_len - 3)..(words_len - 1)])
+    {
+        match word.parse::<Price>() {
+            Ok(p) => *price = p,
+            Err(_err) => {
+                cannot_parse_number(word);
+                return;
+            }
+        }
+    }
+    let [take_profit_price, stop_loss_price] = prices;
+
+    let amount = match words[words_len - 1].parse::<Quantity>() {
+        Ok(amount) => amount,
+        Err(_err) => {
+            cannot_parse_number(words[words_len - 1]);
+            return;
+        }
+    };
+
+    if is_valid_name(signer) {
+        let take_profit = Order::new(take_profit_price, amount, side.clone(), signer.to_string());
+        let stop_loss = Order::new(stop_loss_price, amount, side, signer.to_string());
+        match trading_platform.place_oco(take_profit, stop_loss) {
+            Ok(group) => println!("{:#?}", group),
+            Err(err) => print_error(&err),
+        }
+    }
+}
+
+/// **Place an entry order with a bracket of a take-profit and a stop-loss order**
+///
+/// The signer's name can consist of multiple words.
+/// We can wrap the signer's name in single or double quotes,
+/// but we don't have to use any quotes at all.
+///
+/// The side is the side of the entry; the exits are on the opposite side.
+///
+/// The entry is put in the order book right away. Once it has been completely filled,
+/// the take-profit and the stop-loss become active as a one-cancels-other pair.
+///
+/// Prints the order group, or an error message.
+///
+/// # Errors
+/// - Legs don't fit together, `OrderError::GroupInvalid`;
+/// - Account not found, `AccountError::NotFound`;
+/// - Account has insufficient funds, `OrderError::UnderFunded`.
+fn bracket(words: Vec<&str>, trading_platform: &mut TradingPlatform) {
+    let words_len = words.len();
+
+    if words_len < 7 {
+        println!(
+            "The bracket command: {BRACKET} 'signer full name' <side> <entry price> \
+            <take-profit price> <stop-loss price> <amount>"
+        );
+        return;
+    }
+
+    let signer = words[1..(words_len - 5)].join(" ");
+    let signer = signer.trim_matches(|c| c == '\'' || c == '\"').trim();
+
+    let (entry_side, exit_side) = match words[words_len - 5] {
+        "buy" | "bid" => (Side::Buy, Side::Sell),
+        "sell" | "ask" => (Side::Sell, Side::Buy),
+        _ => {
+            eprintln!(r#"[ERROR] Side can be either "buy"/"bid" or "sell"/"ask"."#);
+            return;
+        }
+    };
+
+    let mut prices = [Price::ZERO; 3];
+    for (price, word) in prices
+        .iter_mut()
+        .zip(&words[(words_len - 4)..(words_len - 1)])
+    {
+        match word.parse::<Price>() {
+            Ok(p) => *price = p,
+            Err(_err) => {
+                cannot_parse_number(word);
+                return;
+            }
+        }
+    }
+    let [entry_price, take_profit_price, stop_loss_price] = prices;
+
+    let amount = match words[words_len - 1].parse::<Quantity>() {
+        Ok(amount) => amount,
+        Err(_err) => {
+            cannot_parse_number(words[words_len - 1]);
+            return;
+        }
+    };
+
+    if is_valid_name(signer) {
+        let entry = Order::new(entry_price, amount, entry_side, signer.to_string());
+        let take_profit = Order::new(
+            take_profit_price,
+            amount,
+            exit_side.clone(),
+            signer.to_string(),
+        );
+        let stop_loss = Order::new(stop_loss_price, amount, exit_side, signer.to_string());
+        match trading_platform.place_bracket(entry, take_profit, stop_loss) {
+            Ok(group) => println!("{:#?}", group),
+            Err(err) => print_error(&err),
+        }
+    }
+}
+
+/// **Print a single order group and the status of each of its legs**
+fn print_order_group(words: Vec<&str>, trading_platform: &TradingPlatform) {
+    if words.len() != 2 {
+        println!("The group command: {ORDER_GROUP} <id>");
+        return;
+    }
+
+    match words[1].parse::<u64>() {
+        Ok(id) => match trading_platform.order_group(id) {
+            Ok(group) => println!("{:#?}", group),
+            Err(err) => print_error(&err),
+        },
+        Err(_err) => cannot_parse_number(words[1]),
+    }
+}
+
+/// **Print all order groups ever placed**
+fn print_order_groups(trading_platform: &TradingPlatform) {
+    println!("Order groups: {:#?}", trading_platform.order_groups());
+}
+
+/// **Cancel all open legs of an order group**
+fn cancel_order_group(words: Vec<&str>, trading_platform: &mut TradingPlatform) {
+    if words.len() != 2 {
+        println!("The cancel group command: {CANCEL_ORDER_GROUP} <id>");
+        return;
+    }
+
+    match words[1].parse::<u64>() {
+        Ok(id) => match trading_platform.cancel_order_group(id) {
+            Ok(group) => println!("{:#?}", group),
+            Err(err) => print_error(&err),
+        },
+        Err(_err) => cannot_parse_number(words[1]),
+    }
+}
+
+/// **Cancel all resting orders of a signer**
+///
+/// The signer's name can consist of multiple words.
+/// We can wrap the signer's name in single or double quotes,
+/// but we don't have to use any quotes at all.
+///
+/// Optionally ends with a side, to only cancel the orders on that side of the order book.
+///
+/// Prints the cancelled orders.
+fn cancel_all(words: Vec<&str>, trading_platform: &mut TradingPlatform) {
+    if words.len() < 2 {
+        println!("The cancel all command: {CANCEL_ALL} 'signer full name' [<side>]");
+        return;
+    }
+
+    let (signer, side) = signer_and_optional_side(&words[1..]);
+
+    if is_valid_name(&signer) {
+        println!("{:#?}", trading_platform.cancel_all(&signer, side));
+    }
+}
+
+/// **Check the accounts against the transaction log**
+///
+/// Rebuilds the accounts from the transaction log, and prints the accounts
+/// whose balances or holdings differ from the live ones, if any.
+fn verify(trading_platform: &TradingPlatform) {
+    println!("{}", trading_platform.reconcile());
+}
+
+/// **Clear everything: all accounts, the entire transaction log, and the order book**
+///
+/// Asks for a confirmation first.
+///
+/// Optionally takes a path of a file, to archive everything into before it's cleared.
+/// Nothing is cleared if the archive can't be written.
+fn reset(words: Vec<&str>, trading_platform: &mut TradingPlatform) {
+    let path = words[1..].join(" ");
+    let path = path.trim_matches(|c| c == '\'' || c == '\"').trim();
+
+    if !confirm("This clears all accounts, the transaction log and the order book.") {
+        println!("The reset was cancelled.");
+        return;
+    }
+
+    if !path.is_empty() {
+        if let Err(err) = save_archives(path, &[trading_platform.archive()]) {
+            eprintln!(
+                "[ERROR] Failed to write the archive to \"{}\": {}",
+                path, err
+            );
+            return;
+        }
+        println!("Archived into \"{}\".", path);
+    }
+
+    match trading_platform.reset() {
+        Ok(_) => println!("The platform has been reset."),
+        Err(err) => eprintln!("[ERROR] Failed to reset the platform: {}", err),
+    }
+}
@@ -1,105 +1,1454 @@
-use crate::errors::AccountingError;
-use crate::tx::Tx;
+use crate::errors::{AccountError, AccountingError, FxError, LimitError, MarginError};
+use crate::fees::DEFAULT_FEE_ACCOUNT;
+use crate::fx::{validate_currency, CurrencyScales, FxRates, DEFAULT_CURRENCY};
+use crate::journal::{Asset, EntryKind, Journal, LedgerAccount, SystemAccount};
+use crate::limits::{Allowance, Clock, LimitSchedule, Outflows, SystemClock};
+use crate::tx::{Leg, Tx};
+use crate::types::{Amount, Money, Quantity, Rounding, Side};
+use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
 
-/// **A type for managing accounts and their current currency balance**
+/// **A type for managing accounts, their current currency balance and their holdings**
 ///
-/// Maps a `String` account name to an `u64` account balance.
-#[derive(Debug)]
+/// Maps a `String` account name to a [`Money`] account balance.
+///
+/// Every account can also hold units of instruments, which it needs in order to sell them.
+///
+/// A part of the funds and of the units can be held for resting orders. Withdrawals and
+/// transfers can only spend the rest, the available part, so that the held part is still
+/// there when the orders are filled.
+///
+/// Every change of a balance or of a holding is posted to the double-entry [`Journal`].
+///
+/// Every account has an [`AccountStatus`]. The movements themselves don't look at it,
+/// so that the transaction log can always be replayed; the `check_*` methods tell whether
+/// the status allows a request, and [`TradingPlatform`](crate::trading_platform::TradingPlatform)
+/// checks them before doing anything that a customer asks for.
+///
+/// Withdrawals and transfers of funds are subject to the [`LimitSchedule`], which is evaluated
+/// against the [`Clock`] that also timestamps the transactions.
+///
+/// A margin account can also borrow funds, which it owes until it repays them.
+///
+/// Balances are in the accounts' [currency](Self::currency), which trades settle in.
+/// Funds in other currencies are kept apart from them, and can be converted into one another,
+/// and into the balances' currency, at the [`FxRates`].
+///
+/// Amounts of every currency have at most the number of decimals of its [`CurrencyScales`];
+/// deposits, withdrawals, transfers, conversions and repayments of amounts with more decimals
+/// are refused, so that the balances keep that scale.
+#[derive(Clone, Debug)]
 pub struct Accounts {
-    pub accounts: BTreeMap<String, u64>,
+    pub accounts: BTreeMap<String, Money>,
+    /// Maps an account name to the units it holds, by the instrument's symbol;
+    /// every account in here also has a balance.
+    pub holdings: BTreeMap<String, BTreeMap<String, Quantity>>,
+    /// Maps an account name to the part of its balance that is held for resting orders
+    pub held: BTreeMap<String, Money>,
+    /// Maps an account name to the units that are held for resting orders, by the instrument's symbol
+    pub held_units: BTreeMap<String, BTreeMap<String, Quantity>>,
+    /// Maps an account name to its status, unless it's active;
+    /// closed accounts stay in here until they are reopened.
+    statuses: BTreeMap<String, AccountStatus>,
+    /// The general ledger that every change is posted to
+    journal: Journal,
+    /// The account that collects fees; it's the fees system account in the journal.
+    fee_account: String,
+    /// The id of the last transaction; ids start with 1.
+    last_tx_id: u64,
+    /// The source of the transactions' timestamps, and of the time that limits are evaluated at
+    clock: Arc<dyn Clock>,
+    /// The limits on withdrawals and transfers
+    limits: LimitSchedule,
+    /// The recent withdrawals and transfers that the limits count
+    outflows: Outflows,
+    /// Maps the name of a margin account to the funds that it owes;
+    /// accounts that can't borrow aren't in here.
+    loans: BTreeMap<String, Money>,
+    /// The code of the currency that the balances are in
+    currency: String,
+    /// Maps an account name to its funds in other currencies, by the currency's code;
+    /// every account in here also has a balance.
+    balances: BTreeMap<String, BTreeMap<String, Money>>,
+    /// The rates that funds are converted at
+    fx_rates: FxRates,
+    /// The number of decimals of each currency's amounts
+    scales: CurrencyScales,
+}
+
+/// The memo of the transfers that sweep a closed account
+pub const SWEEP_MEMO: &str = "Account closure";
+
+impl Default for Accounts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// **What an account is allowed to do, as set by compliance**
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountStatus {
+    /// Anything goes.
+    #[default]
+    Active,
+    /// Nothing can leave the account: it can't trade, send or withdraw, but it can still receive.
+    /// Its resting orders are cancelled.
+    Frozen,
+    /// The account can only reduce its exposure: it can withdraw, send and sell,
+    /// but it can't buy, and nothing can be deposited or sent to it.
+    /// Its resting buy orders are cancelled.
+    WithdrawOnly,
+    /// The account was closed, and nothing can be deposited or sent to it, until it's reopened.
+    Closed,
+}
+
+impl AccountStatus {
+    /// Whether funds and units can be deposited or sent to an account with the status
+    pub fn allows_inflow(self) -> bool {
+        matches!(self, AccountStatus::Active | AccountStatus::Frozen)
+    }
+
+    /// Whether funds and units can be withdrawn or sent from an account with the status
+    pub fn allows_outflow(self) -> bool {
+        matches!(self, AccountStatus::Active | AccountStatus::WithdrawOnly)
+    }
+
+    /// Whether an account with the status can place orders on the `side`
+    pub fn allows_order(self, side: &Side) -> bool {
+        match self {
+            AccountStatus::Active => true,
+            AccountStatus::WithdrawOnly => *side == Side::Sell,
+            AccountStatus::Frozen | AccountStatus::Closed => false,
+        }
+    }
+}
+
+impl fmt::Display for AccountStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountStatus::Active => write!(f, "active"),
+            AccountStatus::Frozen => write!(f, "frozen"),
+            AccountStatus::WithdrawOnly => write!(f, "withdraw-only"),
+            AccountStatus::Closed => write!(f, "closed"),
+        }
+    }
+}
+
+impl FromStr for AccountStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "active" => Ok(AccountStatus::Active),
+            "frozen" => Ok(AccountStatus::Frozen),
+            "withdraw-only" | "withdraw_only" => Ok(AccountStatus::WithdrawOnly),
+            "closed" => Ok(AccountStatus::Closed),
+            _ => Err(format!(
+                "Invalid account status: \"{s}\"; expected \"active\", \"frozen\", \"withdraw-only\" or \"closed\""
+            )),
+        }
+    }
+}
+
+/// **An account's balance together with its holdings**
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AccountSummary {
+    pub balance: Money,
+    /// The part of the balance that is held for resting orders
+    pub held: Money,
+    /// Units held, by the instrument's symbol; instruments that aren't held are left out.
+    pub holdings: BTreeMap<String, Quantity>,
+    /// The part of the holdings that is held for resting orders, in the same way
+    pub held_units: BTreeMap<String, Quantity>,
+    #[serde(default)]
+    pub status: AccountStatus,
+    /// Whether it's a margin account
+    #[serde(default)]
+    pub margin: bool,
+    /// The funds that a margin account owes
+    #[serde(default)]
+    pub loan: Money,
+    /// Funds in currencies other than the balance's, by the currency's code;
+    /// currencies without any funds are left out.
+    #[serde(default)]
+    pub balances: BTreeMap<String, Money>,
 }
 
 impl Accounts {
     /// Returns an empty instance of the [`Accounts`] type
+    ///
+    /// Fees are collected in [`DEFAULT_FEE_ACCOUNT`], unless another fee account is opened.
     pub fn new() -> Self {
         Accounts {
             accounts: BTreeMap::new(),
+            holdings: BTreeMap::new(),
+            held: BTreeMap::new(),
+            held_units: BTreeMap::new(),
+            statuses: BTreeMap::new(),
+            journal: Journal::new(),
+            fee_account: DEFAULT_FEE_ACCOUNT.to_string(),
+            last_tx_id: 0,
+            clock: Arc::new(SystemClock),
+            limits: LimitSchedule::default(),
+            outflows: Outflows::default(),
+            loans: BTreeMap::new(),
+            currency: DEFAULT_CURRENCY.to_string(),
+            balances: BTreeMap::new(),
+            fx_rates: FxRates::default(),
+            scales: CurrencyScales::default(),
+        }
+    }
+
+    /// Opens the account that collects fees, with a zero balance if it doesn't exist yet.
+    ///
+    /// The journal posts its changes to the fees system account.
+    pub fn open_fee_account(&mut self, fee_account: &str) {
+        self.accounts.entry(fee_account.to_string()).or_default();
+        self.fee_account = fee_account.to_string();
+    }
+
+    /// The general ledger that every change is posted to
+    pub fn journal(&self) -> &Journal {
+        &self.journal
+    }
+
+    /// Replaces the clock that timestamps the transactions and that limits are evaluated at.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// The clock that timestamps the transactions and that limits are evaluated at
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        Arc::clone(&self.clock)
+    }
+
+    /// Replaces the limits on withdrawals and transfers.
+    ///
+    /// The withdrawals and transfers that were already made still count towards the new limits,
+    /// even the ones made without any limits, as long as they're within a day, or within the
+    /// longest velocity window of the limits at the time. Only the ones in another currency that
+    /// there was no rate for, while there weren't any limits, don't count.
+    pub fn set_limits(&mut self, limits: LimitSchedule) {
+        self.limits = limits;
+    }
+
+    /// The limits on withdrawals and transfers
+    pub fn limits(&self) -> &LimitSchedule {
+        &self.limits
+    }
+
+    /// Sets the code of the currency that the balances are in, [`DEFAULT_CURRENCY`] by default.
+    ///
+    /// Nothing is converted, so it's set before any funds come in.
+    pub fn set_currency(&mut self, currency: &str) {
+        self.currency = currency.to_string();
+    }
+
+    /// The code of the currency that the balances are in
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    /// Replaces the rates that funds are converted at.
+    pub fn set_fx_rates(&mut self, fx_rates: FxRates) {
+        self.fx_rates = fx_rates;
+    }
+
+    /// The rates that funds are converted at
+    pub fn fx_rates(&self) -> &FxRates {
+        &self.fx_rates
+    }
+
+    /// Replaces the number of decimals of each currency's amounts.
+    ///
+    /// Balances aren't rounded, so it's set before any funds come in.
+    pub fn set_currency_scales(&mut self, scales: CurrencyScales) {
+        self.scales = scales;
+    }
+
+    /// The number of decimals of each currency's amounts
+    pub fn currency_scales(&self) -> &CurrencyScales {
+        &self.scales
+    }
+
+    /// The number of decimals of the balances, and of the values of trades that settle in them
+    pub fn cash_scale(&self) -> u8 {
+        self.scales.scale(&self.currency)
+    }
+
+    /// What's left of the `signer`'s limits at the moment
+    pub fn allowance_of(&self, signer: &str) -> Allowance {
+        self.outflows
+            .allowance(&self.limits, signer, self.clock.now())
+    }
+
+    /// Checks whether the `signer` can withdraw or send the `amount` without exceeding a limit.
+    fn check_limits(&self, signer: &str, amount: Money) -> Result<(), AccountingError> {
+        match self.allowance_of(signer).exceeded_by(amount) {
+            Some(limit) => Err(LimitError::Exceeded(signer.to_string(), limit).into()),
+            None => Ok(()),
+        }
+    }
+
+    /// The value of the `amount` in the `currency` that the limits count, which is
+    /// in the balances' currency.
+    ///
+    /// # Errors
+    /// - There's no rate into the balances' currency, `FxError::RateNotFound`
+    fn limited_value(&self, currency: &str, amount: Money) -> Result<Money, AccountingError> {
+        // Rounding up, the limits count at least what left the account.
+        self.fx_rates
+            .convert(
+                amount,
+                currency,
+                &self.currency,
+                self.cash_scale(),
+                Rounding::Up,
+            )
+            .map(|(_, value)| value)
+    }
+
+    /// Counts the `amount` that left the `signer`'s account towards their limits,
+    /// including the ones that may only be set later.
+    fn record_outflow(&mut self, signer: &str, amount: Money) {
+        let now = self.clock.now();
+        self.outflows.record(&self.limits, signer, amount, now);
+    }
+
+    /// Assigns the id and the timestamp of the next transaction
+    pub(crate) fn next_tx(&mut self) -> (u64, u64) {
+        self.last_tx_id += 1;
+        (self.last_tx_id, self.clock.now())
+    }
+
+    /// The ledger account of an account's name
+    fn ledger_account(&self, signer: &str) -> LedgerAccount {
+        if signer == self.fee_account {
+            LedgerAccount::System(SystemAccount::Fees)
+        } else {
+            LedgerAccount::Customer(signer.to_string())
+        }
+    }
+
+    /// The journal's asset of the funds in the `currency`
+    fn cash(&self, currency: &str) -> Asset {
+        match currency {
+            DEFAULT_CURRENCY => Asset::Cash,
+            currency => Asset::Currency(currency.to_string()),
+        }
+    }
+
+    /// Posts the move of the `amount` of the `asset` between two accounts to the journal.
+    ///
+    /// Moving nothing doesn't change any balance, so it isn't posted.
+    fn post(
+        &mut self,
+        kind: EntryKind,
+        from: LedgerAccount,
+        to: LedgerAccount,
+        asset: Asset,
+        amount: impl Into<Amount>,
+    ) {
+        let amount = amount.into();
+        if !amount.is_zero() {
+            self.journal.post(kind, from, to, asset, amount);
+        }
+    }
+
+    /// Retrieves the balance and the holdings of an account
+    ///
+    /// # Errors
+    /// - Account doesn't exist, `AccountError::NotFound`
+    pub fn summary_of(&self, signer: &str) -> Result<AccountSummary, AccountingError> {
+        let balance = *self.balance_of(signer)?;
+        let held_of = |units: &BTreeMap<String, BTreeMap<String, Quantity>>| {
+            units
+                .get(signer)
+                .map(|units| {
+                    units
+                        .iter()
+                        .filter(|(_, units)| !units.is_zero())
+                        .map(|(symbol, units)| (symbol.clone(), *units))
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        Ok(AccountSummary {
+            balance,
+            held: self.held_of(signer),
+            holdings: held_of(&self.holdings),
+            held_units: held_of(&self.held_units),
+            status: self.status_of(signer),
+            margin: self.is_margin_account(signer),
+            loan: self.loan_of(signer),
+            balances: self
+                .balances
+                .get(signer)
+                .map(|balances| {
+                    balances
+                        .iter()
+                        .filter(|(_, amount)| !amount.is_zero())
+                        .map(|(currency, amount)| (currency.clone(), *amount))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Retrieves the status of an account; accounts that don't exist are active, unless they were closed.
+    pub fn status_of(&self, signer: &str) -> AccountStatus {
+        self.statuses.get(signer).copied().unwrap_or_default()
+    }
+
+    /// Sets the status of the `signer` account.
+    ///
+    /// Closing the account is the same as [`close`](Self::close) without a sweep.
+    /// A closed account can only be made active again, after which a deposit recreates it.
+    ///
+    /// Returns `Tx::AccountStatusChanged`, or the transactions of the closure.
+    ///
+    /// # Errors
+    /// - Account doesn't exist, `AccountError::NotFound`;
+    /// - Account is closed, and isn't being made active, `AccountError::Closed`;
+    /// - Any of the errors from [`close`](Self::close), when closing it.
+    pub fn set_status(
+        &mut self,
+        signer: &str,
+        status: AccountStatus,
+    ) -> Result<Vec<Tx>, AccountingError> {
+        match (self.status_of(signer), status) {
+            (AccountStatus::Closed, AccountStatus::Active) => {
+                self.statuses.remove(signer);
+            }
+            (AccountStatus::Closed, _) => {
+                return Err(AccountError::Closed(signer.to_string()).into())
+            }
+            (_, AccountStatus::Closed) => return self.close(signer, None),
+            (_, AccountStatus::Active) => {
+                self.balance_of(signer)?;
+                self.statuses.remove(signer);
+            }
+            (_, status) => {
+                self.balance_of(signer)?;
+                self.statuses.insert(signer.to_string(), status);
+            }
+        }
+
+        let (id, timestamp) = self.next_tx();
+        Ok(vec![Tx::AccountStatusChanged {
+            id,
+            timestamp,
+            account: signer.to_string(),
+            status,
+        }])
+    }
+
+    /// Makes sure that the account's status lets funds and units in.
+    ///
+    /// # Errors
+    /// - Account is withdraw-only or closed, `AccountError::WithdrawOnly`
+    ///   or `AccountError::Closed`
+    pub fn check_inflow(&self, signer: &str) -> Result<(), AccountingError> {
+        self.check_status(signer, AccountStatus::allows_inflow)
+    }
+
+    /// Makes sure that the account's status lets funds and units out.
+    ///
+    /// # Errors
+    /// - Account is frozen or closed, `AccountError::Frozen` or `AccountError::Closed`
+    pub fn check_outflow(&self, signer: &str) -> Result<(), AccountingError> {
+        self.check_status(signer, AccountStatus::allows_outflow)
+    }
+
+    /// Makes sure that the account's status lets it place orders on the `side`.
+    ///
+    /// # Errors
+    /// - Account is frozen, withdraw-only and buying, or closed, `AccountError::Frozen`,
+    ///   `AccountError::WithdrawOnly` or `AccountError::Closed`
+    pub fn check_order(&self, signer: &str, side: &Side) -> Result<(), AccountingError> {
+        self.check_status(signer, |status| status.allows_order(side))
+    }
+
+    fn check_status(
+        &self,
+        signer: &str,
+        allows: impl Fn(AccountStatus) -> bool,
+    ) -> Result<(), AccountingError> {
+        let signer = signer.to_string();
+        match self.status_of(&signer) {
+            status if allows(status) => Ok(()),
+            AccountStatus::Frozen => Err(AccountError::Frozen(signer).into()),
+            AccountStatus::WithdrawOnly => Err(AccountError::WithdrawOnly(signer).into()),
+            // Active accounts are allowed everything.
+            AccountStatus::Active | AccountStatus::Closed => {
+                Err(AccountError::Closed(signer).into())
+            }
+        }
+    }
+
+    /// Retrieves the part of an account's balance that is held for resting orders
+    pub fn held_of(&self, signer: &str) -> Money {
+        self.held.get(signer).copied().unwrap_or_default()
+    }
+
+    /// Retrieves the part of an account's balance that isn't held for resting orders
+    ///
+    /// # Errors
+    /// - Account doesn't exist, `AccountError::NotFound`
+    pub fn available_of(&self, signer: &str) -> Result<Money, AccountingError> {
+        Ok(self
+            .balance_of(signer)?
+            .saturating_sub(self.held_of(signer)))
+    }
+
+    /// Retrieves the units of the instrument with the `symbol` that are held for resting orders
+    pub fn units_held_of(&self, signer: &str, symbol: &str) -> Quantity {
+        self.held_units
+            .get(signer)
+            .and_then(|units| units.get(symbol))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Retrieves the units of the instrument with the `symbol` that aren't held for resting orders
+    ///
+    /// # Errors
+    /// - Account doesn't exist, `AccountError::NotFound`
+    pub fn units_available_of(
+        &self,
+        signer: &str,
+        symbol: &str,
+    ) -> Result<Quantity, AccountingError> {
+        Ok(self
+            .units_of(signer, symbol)?
+            .saturating_sub(self.units_held_of(signer, symbol)))
+    }
+
+    /// Retrieves the balances and the holdings of all accounts
+    pub fn summaries(&self) -> BTreeMap<String, AccountSummary> {
+        self.accounts
+            .keys()
+            .map(|signer| {
+                // The account certainly exists, as it's one of the keys.
+                let summary = self.summary_of(signer).unwrap_or_default();
+                (signer.clone(), summary)
+            })
+            .collect()
+    }
+
+    /// Retrieves the units of the instrument with the `symbol` that an account holds
+    ///
+    /// # Errors
+    /// - Account doesn't exist, `AccountError::NotFound`
+    pub fn units_of(&self, signer: &str, symbol: &str) -> Result<Quantity, AccountingError> {
+        self.balance_of(signer)?;
+
+        Ok(self
+            .holdings
+            .get(signer)
+            .and_then(|holdings| holdings.get(symbol))
+            .copied()
+            .unwrap_or_default())
+    }
+
+    /// Deposits the `units` of the instrument with the `symbol` into the `signer` account,
+    /// which is created with a zero balance if it doesn't exist.
+    ///
+    /// # Errors
+    /// - Attempted overflow (too many units), `AccountError::HoldingsOverflow`
+    pub fn deposit_units(
+        &mut self,
+        signer: &str,
+        symbol: &str,
+        units: Quantity,
+    ) -> Result<Tx, AccountingError> {
+        self.deposit_units_from(signer, symbol, units, SystemAccount::CashIn)
+    }
+
+    /// Deposits the units in the same way as [`deposit_units`](Self::deposit_units),
+    /// with the units coming from the `source` system account in the journal.
+    ///
+    /// # Errors
+    /// - Attempted overflow (too many units), `AccountError::HoldingsOverflow`
+    pub fn deposit_units_from(
+        &mut self,
+        signer: &str,
+        symbol: &str,
+        units: Quantity,
+        source: SystemAccount,
+    ) -> Result<Tx, AccountingError> {
+        self.credit_units(signer, symbol, units)?;
+        self.post_deposit(signer, Asset::Units(symbol.to_string()), units, source);
+
+        let (id, timestamp) = self.next_tx();
+        Ok(Tx::DepositUnits {
+            id,
+            timestamp,
+            account: signer.to_string(),
+            symbol: symbol.to_string(),
+            units,
+        })
+    }
+
+    /// Adds the units to the account's holdings, without posting them to the journal.
+    fn credit_units(
+        &mut self,
+        signer: &str,
+        symbol: &str,
+        units: Quantity,
+    ) -> Result<(), AccountingError> {
+        let held = self
+            .holdings
+            .get(signer)
+            .and_then(|holdings| holdings.get(symbol))
+            .copied()
+            .unwrap_or_default();
+        let held = held
+            .checked_add(units)
+            .ok_or(AccountError::HoldingsOverflow(
+                signer.to_string(),
+                symbol.to_string(),
+                units,
+            ))?;
+
+        self.open(signer);
+        self.holdings
+            .entry(signer.to_string())
+            .or_default()
+            .insert(symbol.to_string(), held);
+        Ok(())
+    }
+
+    /// Withdraws the `units` of the instrument with the `symbol` from the `signer` account,
+    /// if it exists, and if they aren't held for resting orders.
+    ///
+    /// # Errors
+    /// - Account doesn't exist, `AccountError::NotFound`;
+    /// - Not enough units available, `AccountError::HoldingsInsufficient`.
+    pub fn withdraw_units(
+        &mut self,
+        signer: &str,
+        symbol: &str,
+        units: Quantity,
+    ) -> Result<Tx, AccountingError> {
+        self.withdraw_units_to(signer, symbol, units, SystemAccount::CashIn)
+    }
+
+    /// Withdraws the units in the same way as [`withdraw_units`](Self::withdraw_units),
+    /// with the units going to the `destination` system account in the journal.
+    ///
+    /// # Errors
+    /// - Account doesn't exist, `AccountError::NotFound`;
+    /// - Not enough units available, `AccountError::HoldingsInsufficient`.
+    pub fn withdraw_units_to(
+        &mut self,
+        signer: &str,
+        symbol: &str,
+        units: Quantity,
+        destination: SystemAccount,
+    ) -> Result<Tx, AccountingError> {
+        self.debit_available_units(signer, symbol, units)?;
+        self.post_withdrawal(signer, Asset::Units(symbol.to_string()), units, destination);
+        Ok(self.units_withdrawal(signer, symbol, units))
+    }
+
+    /// **Moves the `units` of the instrument with the `symbol` between the account and suspense,
+    /// reserving them for the account's orders in a market**
+    ///
+    /// Units leave the account with the [`Leg::Out`], as long as they are available.
+    ///
+    /// # Errors
+    /// - Account doesn't exist, `AccountError::NotFound`;
+    /// - Not enough units available, `AccountError::HoldingsInsufficient`;
+    /// - Attempted overflow (too many units), `AccountError::HoldingsOverflow`.
+    pub fn reserve_units(
+        &mut self,
+        signer: &str,
+        symbol: &str,
+        units: Quantity,
+        leg: Leg,
+    ) -> Result<Tx, AccountingError> {
+        self.suspend_units(signer, symbol, units, leg, false)?;
+
+        let (id, timestamp) = self.next_tx();
+        Ok(Tx::ReservationUnits {
+            id,
+            timestamp,
+            account: signer.to_string(),
+            symbol: symbol.to_string(),
+            units,
+            leg,
+        })
+    }
+
+    /// **Moves the `units` of the instrument with the `symbol` between the account and suspense,
+    /// releasing them from the account's orders in a market**
+    ///
+    /// Units leave the account with the [`Leg::Out`], including the held ones.
+    ///
+    /// # Errors
+    /// - Account doesn't exist, `AccountError::NotFound`;
+    /// - Not enough units, `AccountError::HoldingsInsufficient`;
+    /// - Attempted overflow (too many units), `AccountError::HoldingsOverflow`.
+    pub fn release_units(
+        &mut self,
+        signer: &str,
+        symbol: &str,
+        units: Quantity,
+        leg: Leg,
+    ) -> Result<Tx, AccountingError> {
+        self.suspend_units(signer, symbol, units, leg, true)?;
+
+        let (id, timestamp) = self.next_tx();
+        Ok(Tx::ReleaseUnits {
+            id,
+            timestamp,
+            account: signer.to_string(),
+            symbol: symbol.to_string(),
+            units,
+            leg,
+        })
+    }
+
+    /// Moves the units between the account and suspense, and posts them to the journal;
+    /// the ones that leave the account have to be available, unless `held` ones can leave, too.
+    fn suspend_units(
+        &mut self,
+        signer: &str,
+        symbol: &str,
+        units: Quantity,
+        leg: Leg,
+        held: bool,
+    ) -> Result<(), AccountingError> {
+        let asset = Asset::Units(symbol.to_string());
+        match leg {
+            Leg::Out => {
+                match held {
+                    true => self.debit_units(signer, symbol, units)?,
+                    false => self.debit_available_units(signer, symbol, units)?,
+                }
+                self.post_withdrawal(signer, asset, units, SystemAccount::Suspense);
+            }
+            Leg::In => {
+                self.credit_units(signer, symbol, units)?;
+                self.post_deposit(signer, asset, units, SystemAccount::Suspense);
+            }
+        }
+        Ok(())
+    }
+
+    fn units_withdrawal(&mut self, signer: &str, symbol: &str, units: Quantity) -> Tx {
+        let (id, timestamp) = self.next_tx();
+        Tx::WithdrawUnits {
+            id,
+            timestamp,
+            account: signer.to_string(),
+            symbol: symbol.to_string(),
+            units,
+        }
+    }
+
+    /// Takes the available units out of the account's holdings, without posting them to the journal.
+    fn debit_available_units(
+        &mut self,
+        signer: &str,
+        symbol: &str,
+        units: Quantity,
+    ) -> Result<(), AccountingError> {
+        if self.units_available_of(signer, symbol)? < units {
+            return Err(AccountError::HoldingsInsufficient(
+                signer.to_string(),
+                symbol.to_string(),
+                units,
+            )
+            .into());
+        }
+
+        self.debit_units(signer, symbol, units)
+    }
+
+    /// Takes the units out of the account's holdings, including the held ones,
+    /// without posting them to the journal.
+    fn debit_units(
+        &mut self,
+        signer: &str,
+        symbol: &str,
+        units: Quantity,
+    ) -> Result<(), AccountingError> {
+        let held = self.units_of(signer, symbol)?.checked_sub(units).ok_or(
+            AccountError::HoldingsInsufficient(signer.to_string(), symbol.to_string(), units),
+        )?;
+
+        self.holdings
+            .entry(signer.to_string())
+            .or_default()
+            .insert(symbol.to_string(), held);
+        Ok(())
+    }
+
+    /// Withdraws the units from the sender's account and deposits them
+    /// in the recipient's account, which needs to exist, if it wouldn't overflow.
+    ///
+    /// Units that are held for the sender's resting orders can't be sent.
+    /// The sender can add a `memo` for the recipient.
+    ///
+    /// # Errors
+    /// - Any of the two accounts doesn't exist, `AccountError::NotFound`;
+    /// - Not enough units available to the sender, `AccountError::HoldingsInsufficient`;
+    /// - Attempted overflow (too many units for the recipient), `AccountError::HoldingsOverflow`.
+    pub fn send_units(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        symbol: &str,
+        units: Quantity,
+        memo: Option<&str>,
+    ) -> Result<Tx, AccountingError> {
+        self.move_units(
+            sender,
+            recipient,
+            symbol,
+            units,
+            Self::debit_available_units,
+            EntryKind::Transfer,
+        )?;
+
+        let (id, timestamp) = self.next_tx();
+        Ok(Tx::TransferUnits {
+            id,
+            timestamp,
+            from: sender.to_string(),
+            to: recipient.to_string(),
+            symbol: symbol.to_string(),
+            units,
+            memo: memo.map(str::to_string),
+        })
+    }
+
+    /// The same as [`send_units`](Self::send_units), but the held units can be sent, too;
+    /// matches are settled with it, as that's what the units are held for.
+    /// The caller records the transaction.
+    pub(crate) fn transfer_units(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        symbol: &str,
+        units: Quantity,
+    ) -> Result<(), AccountingError> {
+        self.move_units(
+            sender,
+            recipient,
+            symbol,
+            units,
+            Self::debit_units,
+            EntryKind::Settlement,
+        )
+    }
+
+    fn move_units(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        symbol: &str,
+        units: Quantity,
+        withdraw_units: fn(&mut Self, &str, &str, Quantity) -> Result<(), AccountingError>,
+        kind: EntryKind,
+    ) -> Result<(), AccountingError> {
+        // Same as in `send`, the recipient is checked in advance,
+        // so that the sender's units don't have to be returned.
+        if self
+            .units_of(recipient, symbol)?
+            .checked_add(units)
+            .is_none()
+        {
+            return Err(AccountError::HoldingsOverflow(
+                recipient.to_string(),
+                symbol.to_string(),
+                units,
+            )
+            .into());
+        }
+
+        withdraw_units(self, sender, symbol, units)?;
+        self.credit_units(recipient, symbol, units)?;
+        self.post(
+            kind,
+            self.ledger_account(sender),
+            self.ledger_account(recipient),
+            Asset::Units(symbol.to_string()),
+            units,
+        );
+        Ok(())
+    }
+
+    /// Closes the `signer` account, and removes it along with its holdings; its status becomes closed.
+    ///
+    /// The account has to be empty, unless `sweep_to` names another account,
+    /// which then receives all of its funds, in every currency, and units first.
+    /// Nothing that is held for resting orders can be swept, though. The sweep is all or nothing:
+    /// every currency and every instrument is checked before the first one moves.
+    ///
+    /// Returns the transfers of the sweep, if any, followed by `Tx::AccountClosed`;
+    /// the transfers carry the [`SWEEP_MEMO`]. The limits on withdrawals and transfers don't apply
+    /// to the sweep, and it doesn't count towards them, since it empties the account for good.
+    ///
+    /// # Errors
+    /// - Any of the two accounts doesn't exist, `AccountError::NotFound`;
+    /// - Funds or units are held for resting orders, `AccountError::HasOpenOrders`;
+    /// - Account owes a margin loan, `MarginError::LoanOutstanding`;
+    /// - Account isn't empty, and there's nowhere to sweep it, `AccountError::NotEmpty`;
+    /// - Attempted overflow (recipient's account over-funded), `AccountError::OverFunded`
+    ///   or `AccountError::HoldingsOverflow`.
+    pub fn close(
+        &mut self,
+        signer: &str,
+        sweep_to: Option<&str>,
+    ) -> Result<Vec<Tx>, AccountingError> {
+        let summary = self.summary_of(signer)?;
+        if !summary.held.is_zero() || !summary.held_units.is_empty() {
+            return Err(AccountError::HasOpenOrders(signer.to_string()).into());
         }
+        if !summary.loan.is_zero() {
+            return Err(MarginError::LoanOutstanding(signer.to_string()).into());
+        }
+
+        let is_empty =
+            summary.balance.is_zero() && summary.holdings.is_empty() && summary.balances.is_empty();
+        let mut txs = match sweep_to {
+            _ if is_empty => vec![],
+            Some(recipient) if recipient != signer => {
+                // Every leg is checked before the first one is moved, so that the sweep can't stop halfway.
+                self.check_sweep(recipient, &summary)?;
+                self.sweep(signer, recipient, &summary)?
+            }
+            _ => return Err(AccountError::NotEmpty(signer.to_string()).into()),
+        };
+
+        self.accounts.remove(signer);
+        self.holdings.remove(signer);
+        self.held.remove(signer);
+        self.held_units.remove(signer);
+        self.loans.remove(signer);
+        self.balances.remove(signer);
+        self.statuses
+            .insert(signer.to_string(), AccountStatus::Closed);
+
+        let (id, timestamp) = self.next_tx();
+        txs.push(Tx::AccountClosed {
+            id,
+            timestamp,
+            account: signer.to_string(),
+        });
+        Ok(txs)
+    }
+
+    /// Checks whether the `recipient` can take everything in the `summary` of the account to close.
+    ///
+    /// The account has nothing held, so all of it is available to the sweep.
+    ///
+    /// # Errors
+    /// - The recipient doesn't exist, `AccountError::NotFound`;
+    /// - Attempted overflow (recipient's account over-funded), `AccountError::OverFunded`,
+    ///   `FxError::OverFunded` or `AccountError::HoldingsOverflow`.
+    fn check_sweep(
+        &self,
+        recipient: &str,
+        summary: &AccountSummary,
+    ) -> Result<(), AccountingError> {
+        if self
+            .balance_of(recipient)?
+            .checked_add(summary.balance)
+            .is_none()
+        {
+            return Err(AccountError::OverFunded(recipient.to_string(), summary.balance).into());
+        }
+        for (currency, amount) in &summary.balances {
+            if self
+                .balance_in(recipient, currency)?
+                .checked_add(*amount)
+                .is_none()
+            {
+                return Err(
+                    FxError::OverFunded(recipient.to_string(), currency.clone(), *amount).into(),
+                );
+            }
+        }
+        for (symbol, units) in &summary.holdings {
+            if self
+                .units_of(recipient, symbol)?
+                .checked_add(*units)
+                .is_none()
+            {
+                return Err(AccountError::HoldingsOverflow(
+                    recipient.to_string(),
+                    symbol.clone(),
+                    *units,
+                )
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Moves everything in the `summary` of the `signer` account to the `recipient`,
+    /// once [`check_sweep`](Self::check_sweep) has passed, and returns the transfers.
+    ///
+    /// The transfers are only assigned their ids once all of them have been moved.
+    fn sweep(
+        &mut self,
+        signer: &str,
+        recipient: &str,
+        summary: &AccountSummary,
+    ) -> Result<Vec<Tx>, AccountingError> {
+        if !summary.balance.is_zero() {
+            self.move_funds(
+                signer,
+                recipient,
+                summary.balance,
+                Self::debit_available,
+                EntryKind::Transfer,
+            )?;
+        }
+        for (currency, amount) in &summary.balances {
+            self.move_funds_in(signer, recipient, currency, *amount, Self::debit_in)?;
+        }
+        for (symbol, units) in &summary.holdings {
+            self.move_units(
+                signer,
+                recipient,
+                symbol,
+                *units,
+                Self::debit_available_units,
+                EntryKind::Transfer,
+            )?;
+        }
+
+        let mut txs = vec![];
+        if !summary.balance.is_zero() {
+            txs.push(self.transfer_tx(signer, recipient, summary.balance, None));
+        }
+        for (currency, amount) in &summary.balances {
+            txs.push(self.transfer_tx(signer, recipient, *amount, Some(currency)));
+        }
+        for (symbol, units) in &summary.holdings {
+            let (id, timestamp) = self.next_tx();
+            txs.push(Tx::TransferUnits {
+                id,
+                timestamp,
+                from: signer.to_string(),
+                to: recipient.to_string(),
+                symbol: symbol.to_string(),
+                units: *units,
+                memo: Some(SWEEP_MEMO.to_string()),
+            });
+        }
+        Ok(txs)
     }
 
     /// Retrieves the balance of an account
     ///
     /// # Errors
-    /// - Account doesn't exist, `AccountingError::AccountNotFound`
-    pub fn balance_of(&self, signer: &str) -> Result<&u64, AccountingError> {
+    /// - Account doesn't exist, `AccountError::NotFound`
+    pub fn balance_of(&self, signer: &str) -> Result<&Money, AccountingError> {
         self.accounts
             .get(signer)
-            .ok_or(AccountingError::AccountNotFound(signer.to_string()))
+            .ok_or(AccountingError::Account(AccountError::NotFound(
+                signer.to_string(),
+            )))
     }
 
     /// Deposits the `amount` provided into the new `signer` account if it doesn't exist,
     /// or adds the `amount` to the existing account.
     ///
     /// # Errors
-    /// - Attempted overflow (account over-funded), `AccountingError::AccountOverFunded`
-    pub fn deposit(&mut self, signer: &str, amount: u64) -> Result<Tx, AccountingError> {
+    /// - The amount has more decimals than the currency, `FxError::AmountInvalid`;
+    /// - Attempted overflow (account over-funded), `AccountError::OverFunded`
+    pub fn deposit(&mut self, signer: &str, amount: Money) -> Result<Tx, AccountingError> {
+        self.scales.check(amount, &self.currency)?;
+        self.deposit_from(signer, amount, SystemAccount::CashIn)
+    }
+
+    /// Deposits the `amount` in the same way as [`deposit`](Self::deposit),
+    /// with the funds coming from the `source` system account in the journal.
+    ///
+    /// # Errors
+    /// - Attempted overflow (account over-funded), `AccountError::OverFunded`
+    pub fn deposit_from(
+        &mut self,
+        signer: &str,
+        amount: Money,
+        source: SystemAccount,
+    ) -> Result<Tx, AccountingError> {
+        self.credit(signer, amount)?;
+        self.post_deposit(signer, self.cash(&self.currency), amount, source);
+        Ok(self.deposit_tx(signer, amount, None))
+    }
+
+    fn deposit_tx(&mut self, signer: &str, amount: Money, currency: Option<&str>) -> Tx {
+        let (id, timestamp) = self.next_tx();
+        Tx::Deposit {
+            id,
+            timestamp,
+            account: signer.to_string(),
+            amount,
+            currency: currency.map(str::to_string),
+        }
+    }
+
+    /// Posts a deposit of the `amount` of the `asset` from a system account to the journal.
+    fn post_deposit(
+        &mut self,
+        signer: &str,
+        asset: Asset,
+        amount: impl Into<Amount>,
+        source: SystemAccount,
+    ) {
+        let kind = match source {
+            SystemAccount::CashIn => EntryKind::Deposit,
+            SystemAccount::Fees => EntryKind::Rebate,
+            SystemAccount::Suspense => EntryKind::Suspense,
+            SystemAccount::Margin => EntryKind::Loan,
+            SystemAccount::Fx => EntryKind::Conversion,
+        };
+        self.post(
+            kind,
+            LedgerAccount::System(source),
+            self.ledger_account(signer),
+            asset,
+            amount,
+        );
+    }
+
+    /// Adds the `amount` to the account's balance, without posting it to the journal.
+    fn credit(&mut self, signer: &str, amount: Money) -> Result<(), AccountingError> {
         if let Some(balance) = self.accounts.get_mut(signer) {
             (*balance)
                 .checked_add(amount)
-                .and_then(|r| {
+                .inspect(|&r| {
                     *balance = r;
-                    Some(r)
                 })
-                .ok_or(AccountingError::AccountOverFunded(
+                .ok_or(AccountingError::Account(AccountError::OverFunded(
                     signer.to_string(),
                     amount,
-                ))
+                )))
                 // Using map() here is an easy way to manipulate the non-error result only.
-                .map(|_| Tx::Deposit {
-                    account: signer.to_string(),
-                    amount,
-                })
+                .map(|_| ())
         } else {
-            self.accounts.insert(signer.to_string(), amount);
-            Ok(Tx::Deposit {
-                account: signer.to_string(),
-                amount,
-            })
+            *self.open(signer) = amount;
+            Ok(())
+        }
+    }
+
+    /// Creates the account, with a zero balance, if it doesn't exist; a closed account is reopened.
+    fn open(&mut self, signer: &str) -> &mut Money {
+        if !self.accounts.contains_key(signer) {
+            self.statuses.remove(signer);
         }
+        self.accounts.entry(signer.to_string()).or_default()
+    }
+
+    /// Lets the `signer` account borrow funds; opening a margin account twice changes nothing.
+    ///
+    /// Returns `Tx::MarginAccountOpened`.
+    ///
+    /// # Errors
+    /// - Account doesn't exist, `AccountError::NotFound`
+    pub fn open_margin(&mut self, signer: &str) -> Result<Tx, AccountingError> {
+        self.balance_of(signer)?;
+        self.loans.entry(signer.to_string()).or_default();
+
+        let (id, timestamp) = self.next_tx();
+        Ok(Tx::MarginAccountOpened {
+            id,
+            timestamp,
+            account: signer.to_string(),
+        })
+    }
+
+    /// Whether the `signer` account can borrow funds
+    pub fn is_margin_account(&self, signer: &str) -> bool {
+        self.loans.contains_key(signer)
+    }
+
+    /// Retrieves the funds that every margin account owes, by the account's name
+    pub fn loans(&self) -> &BTreeMap<String, Money> {
+        &self.loans
+    }
+
+    /// Retrieves the funds that a margin account owes; other accounts owe nothing.
+    pub fn loan_of(&self, signer: &str) -> Money {
+        self.loans.get(signer).copied().unwrap_or_default()
     }
 
-    /// Withdraws the `amount` from the `signer` account, if it exists.
+    /// Lends the `amount` to the `signer` margin account, which then owes it.
     ///
     /// # Errors
-    /// - Account doesn't exist, `AccountingError::AccountNotFound`;
-    /// - Attempted overflow (account under-funded), `AccountingError::AccountUnderFunded`.
-    pub fn withdraw(&mut self, signer: &str, amount: u64) -> Result<Tx, AccountingError> {
+    /// - Account isn't a margin account, `MarginError::AccountNotFound`;
+    /// - Attempted overflow (account over-funded), `AccountError::OverFunded`.
+    pub fn borrow(&mut self, signer: &str, amount: Money) -> Result<Tx, AccountingError> {
+        let loan = self
+            .loans
+            .get(signer)
+            .ok_or(MarginError::AccountNotFound(signer.to_string()))?
+            .checked_add(amount)
+            .ok_or(AccountError::OverFunded(signer.to_string(), amount))?;
+
+        self.credit(signer, amount)?;
+        self.loans.insert(signer.to_string(), loan);
+        self.post(
+            EntryKind::Loan,
+            LedgerAccount::System(SystemAccount::Margin),
+            self.ledger_account(signer),
+            self.cash(&self.currency),
+            amount,
+        );
+
+        let (id, timestamp) = self.next_tx();
+        Ok(Tx::MarginLoan {
+            id,
+            timestamp,
+            account: signer.to_string(),
+            amount,
+        })
+    }
+
+    /// Pays the `amount` of the `signer` margin account's loan back, from its available funds.
+    /// Repayments stay on the platform, so limits don't apply.
+    ///
+    /// # Errors
+    /// - The amount has more decimals than the currency, `FxError::AmountInvalid`;
+    /// - Account isn't a margin account, `MarginError::AccountNotFound`;
+    /// - Account owes less than the amount, `MarginError::LoanExceeded`;
+    /// - Attempted overflow (available balance too low), `AccountError::UnderFunded`.
+    pub fn repay(&mut self, signer: &str, amount: Money) -> Result<Tx, AccountingError> {
+        self.scales.check(amount, &self.currency)?;
+        let loan = *self
+            .loans
+            .get(signer)
+            .ok_or(MarginError::AccountNotFound(signer.to_string()))?;
+        let rest = loan
+            .checked_sub(amount)
+            .ok_or(MarginError::LoanExceeded(signer.to_string(), amount))?;
+
+        self.debit_available(signer, amount)?;
+        self.loans.insert(signer.to_string(), rest);
+        self.post_withdrawal(
+            signer,
+            self.cash(&self.currency),
+            amount,
+            SystemAccount::Margin,
+        );
+
+        let (id, timestamp) = self.next_tx();
+        Ok(Tx::MarginRepayment {
+            id,
+            timestamp,
+            account: signer.to_string(),
+            amount,
+        })
+    }
+
+    /// Withdraws the `amount` from the `signer` account, if it exists,
+    /// if it isn't held for resting orders, and if the limits allow it.
+    ///
+    /// # Errors
+    /// - The amount has more decimals than the currency, `FxError::AmountInvalid`;
+    /// - Account doesn't exist, `AccountError::NotFound`;
+    /// - The withdrawal would exceed a limit, `LimitError::Exceeded`;
+    /// - Attempted overflow (available balance too low), `AccountError::UnderFunded`.
+    pub fn withdraw(&mut self, signer: &str, amount: Money) -> Result<Tx, AccountingError> {
+        self.scales.check(amount, &self.currency)?;
+        self.debit_within_limits(signer, amount)?;
+        self.post_withdrawal(
+            signer,
+            self.cash(&self.currency),
+            amount,
+            SystemAccount::CashIn,
+        );
+        Ok(self.withdrawal(signer, amount, None))
+    }
+
+    /// Withdraws the `amount` in the same way as [`withdraw`](Self::withdraw),
+    /// with the funds going to the `destination` system account in the journal.
+    /// Limits don't apply, so that a recorded withdrawal can always be replayed.
+    ///
+    /// # Errors
+    /// - Account doesn't exist, `AccountError::NotFound`;
+    /// - Attempted overflow (available balance too low), `AccountError::UnderFunded`.
+    pub fn withdraw_to(
+        &mut self,
+        signer: &str,
+        amount: Money,
+        destination: SystemAccount,
+    ) -> Result<Tx, AccountingError> {
+        self.debit_available(signer, amount)?;
+        self.post_withdrawal(signer, self.cash(&self.currency), amount, destination);
+        Ok(self.withdrawal(signer, amount, None))
+    }
+
+    fn withdrawal(&mut self, signer: &str, amount: Money, currency: Option<&str>) -> Tx {
+        let (id, timestamp) = self.next_tx();
+        Tx::Withdraw {
+            id,
+            timestamp,
+            account: signer.to_string(),
+            amount,
+            currency: currency.map(str::to_string),
+        }
+    }
+
+    /// Posts a withdrawal of the `amount` of the `asset` to a system account to the journal.
+    fn post_withdrawal(
+        &mut self,
+        signer: &str,
+        asset: Asset,
+        amount: impl Into<Amount>,
+        destination: SystemAccount,
+    ) {
+        let kind = match destination {
+            SystemAccount::CashIn => EntryKind::Withdrawal,
+            SystemAccount::Fees => EntryKind::Fee,
+            SystemAccount::Suspense => EntryKind::Suspense,
+            SystemAccount::Margin => EntryKind::Repayment,
+            SystemAccount::Fx => EntryKind::Conversion,
+        };
+        self.post(
+            kind,
+            self.ledger_account(signer),
+            LedgerAccount::System(destination),
+            asset,
+            amount,
+        );
+    }
+
+    /// Takes the available `amount` out of the account's balance, without posting it to the journal.
+    fn debit_available(&mut self, signer: &str, amount: Money) -> Result<(), AccountingError> {
+        if self.available_of(signer)? < amount {
+            return Err(AccountError::UnderFunded(signer.to_string(), amount).into());
+        }
+
+        self.debit(signer, amount)
+    }
+
+    /// Takes the available `amount` out of the account's balance in the same way as
+    /// [`debit_available`](Self::debit_available), if the limits allow it, and counts it towards them.
+    fn debit_within_limits(&mut self, signer: &str, amount: Money) -> Result<(), AccountingError> {
+        self.balance_of(signer)?;
+        self.check_limits(signer, amount)?;
+        self.debit_available(signer, amount)?;
+        self.record_outflow(signer, amount);
+        Ok(())
+    }
+
+    /// Takes the `amount` out of the account's balance, including the held funds,
+    /// without posting it to the journal.
+    fn debit(&mut self, signer: &str, amount: Money) -> Result<(), AccountingError> {
         if let Some(balance) = self.accounts.get_mut(signer) {
             (*balance)
                 .checked_sub(amount)
-                .map(|r| {
+                .inspect(|&r| {
                     *balance = r;
-                    r
                 })
-                .ok_or(AccountingError::AccountUnderFunded(
+                .ok_or(AccountingError::Account(AccountError::UnderFunded(
                     signer.to_string(),
                     amount,
-                ))
-                .map(|_| Tx::Withdraw {
-                    account: signer.to_string(),
-                    amount,
-                })
+                )))
+                .map(|_| ())
         } else {
-            Err(AccountingError::AccountNotFound(signer.to_string()))
+            Err(AccountError::NotFound(signer.to_string()).into())
         }
     }
 
     /// Withdraws the amount from the sender's account and deposits it
     /// in the recipient's account if it wouldn't overflow.
     ///
+    /// Funds that are held for the sender's resting orders can't be sent,
+    /// and the transfer counts towards the sender's limits.
+    /// The sender can add a `memo` for the recipient.
+    ///
     /// # Errors
-    /// - Any of the two accounts doesn't exist, `AccountingError::AccountNotFound`;
-    /// - Attempted overflow (sender's account under-funded), `AccountingError::AccountUnderFunded`;
-    /// - Attempted overflow (recipient's account over-funded), `AccountingError::AccountOverFunded`.
+    /// - The amount has more decimals than the currency, `FxError::AmountInvalid`;
+    /// - Any of the two accounts doesn't exist, `AccountError::NotFound`;
+    /// - The transfer would exceed a limit of the sender, `LimitError::Exceeded`;
+    /// - Attempted overflow (sender's available balance too low), `AccountError::UnderFunded`;
+    /// - Attempted overflow (recipient's account over-funded), `AccountError::OverFunded`.
     pub fn send(
         &mut self,
         sender: &str,
         recipient: &str,
-        amount: u64,
-    ) -> Result<(Tx, Tx), AccountingError> {
+        amount: Money,
+        memo: Option<&str>,
+    ) -> Result<Tx, AccountingError> {
+        self.scales.check(amount, &self.currency)?;
+        self.move_funds(
+            sender,
+            recipient,
+            amount,
+            Self::debit_within_limits,
+            EntryKind::Transfer,
+        )?;
+
+        let (id, timestamp) = self.next_tx();
+        Ok(Tx::Transfer {
+            id,
+            timestamp,
+            from: sender.to_string(),
+            to: recipient.to_string(),
+            amount,
+            memo: memo.map(str::to_string),
+            currency: None,
+        })
+    }
+
+    /// The same as [`send`](Self::send), but the held funds can be sent, too;
+    /// matches are settled with it, as that's what the funds are held for, and fees are charged.
+    /// The journal entry is of the given `kind`, and the caller records the transaction.
+    pub(crate) fn transfer(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Money,
+        kind: EntryKind,
+    ) -> Result<(), AccountingError> {
+        self.move_funds(sender, recipient, amount, Self::debit, kind)
+    }
+
+    /// Assigns the next transaction to a transfer of the sweep of a closed account, see [`close`](Self::close).
+    fn transfer_tx(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Money,
+        currency: Option<&str>,
+    ) -> Tx {
+        let (id, timestamp) = self.next_tx();
+        Tx::Transfer {
+            id,
+            timestamp,
+            from: sender.to_string(),
+            to: recipient.to_string(),
+            amount,
+            memo: Some(SWEEP_MEMO.to_string()),
+            currency: currency.map(str::to_string),
+        }
+    }
+
+    fn move_funds(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Money,
+        withdraw: fn(&mut Self, &str, Money) -> Result<(), AccountingError>,
+        kind: EntryKind,
+    ) -> Result<(), AccountingError> {
         // We don't have to check for the existence or balance of the sender in advance,
-        // because both things are checked in `self.withdraw(sender, amount)`, which we call.
+        // because both things are checked in `withdraw(self, sender, amount)`, which we call.
         // We are omitting that as a form of an optimization - we don't need to check
         // for the same things twice.
 
@@ -113,53 +1462,540 @@ impl Accounts {
         // sender in case depositing fails because of an overflow.
         let recipient_balance = match self.accounts.get(recipient) {
             Some(balance) => *balance,
-            None => return Err(AccountingError::AccountNotFound(recipient.to_string())),
+            None => return Err(AccountError::NotFound(recipient.to_string()).into()),
         };
-        if recipient_balance > u64::MAX - amount {
-            return Err(AccountingError::AccountOverFunded(
-                recipient.to_string(),
+        if recipient_balance.checked_add(amount).is_none() {
+            return Err(AccountError::OverFunded(recipient.to_string(), amount).into());
+        }
+
+        withdraw(self, sender, amount)?;
+        self.credit(recipient, amount)?;
+        self.post(
+            kind,
+            self.ledger_account(sender),
+            self.ledger_account(recipient),
+            self.cash(&self.currency),
+            amount,
+        );
+        Ok(())
+    }
+
+    /// Retrieves an account's funds in the `currency`, which is its balance in the balances' currency.
+    ///
+    /// # Errors
+    /// - Account doesn't exist, `AccountError::NotFound`
+    pub fn balance_in(&self, signer: &str, currency: &str) -> Result<Money, AccountingError> {
+        let balance = *self.balance_of(signer)?;
+        if currency == self.currency {
+            return Ok(balance);
+        }
+
+        Ok(self
+            .balances
+            .get(signer)
+            .and_then(|balances| balances.get(currency))
+            .copied()
+            .unwrap_or_default())
+    }
+
+    /// Deposits the `amount` in the `currency` in the same way as [`deposit`](Self::deposit).
+    ///
+    /// # Errors
+    /// - The currency's code is invalid, `FxError::CurrencyInvalid`;
+    /// - The amount has more decimals than the currency, `FxError::AmountInvalid`;
+    /// - Attempted overflow (account over-funded), `AccountError::OverFunded`
+    ///   or `FxError::OverFunded`.
+    pub fn deposit_in(
+        &mut self,
+        signer: &str,
+        currency: &str,
+        amount: Money,
+    ) -> Result<Tx, AccountingError> {
+        self.scales.check(amount, currency)?;
+        self.deposit_in_from(signer, currency, amount, SystemAccount::CashIn)
+    }
+
+    /// Deposits the `amount` in the `currency` in the same way as [`deposit_from`](Self::deposit_from).
+    ///
+    /// # Errors
+    /// - The currency's code is invalid, `FxError::CurrencyInvalid`;
+    /// - Attempted overflow (account over-funded), `AccountError::OverFunded`
+    ///   or `FxError::OverFunded`.
+    pub fn deposit_in_from(
+        &mut self,
+        signer: &str,
+        currency: &str,
+        amount: Money,
+        source: SystemAccount,
+    ) -> Result<Tx, AccountingError> {
+        if currency == self.currency {
+            return self.deposit_from(signer, amount, source);
+        }
+
+        validate_currency(currency)?;
+        self.credit_in(signer, currency, amount)?;
+        self.post_deposit(signer, self.cash(currency), amount, source);
+        Ok(self.deposit_tx(signer, amount, Some(currency)))
+    }
+
+    /// Adds the `amount` to the account's funds in another `currency` than the balances',
+    /// without posting it to the journal; the account is created if it doesn't exist.
+    fn credit_in(
+        &mut self,
+        signer: &str,
+        currency: &str,
+        amount: Money,
+    ) -> Result<(), AccountingError> {
+        let balance = self
+            .balances
+            .get(signer)
+            .and_then(|balances| balances.get(currency))
+            .copied()
+            .unwrap_or_default()
+            .checked_add(amount)
+            .ok_or(FxError::OverFunded(
+                signer.to_string(),
+                currency.to_string(),
+                amount,
+            ))?;
+
+        self.open(signer);
+        self.balances
+            .entry(signer.to_string())
+            .or_default()
+            .insert(currency.to_string(), balance);
+        Ok(())
+    }
+
+    /// Takes the `amount` out of the account's funds in another `currency` than the balances',
+    /// without posting it to the journal.
+    fn debit_in(
+        &mut self,
+        signer: &str,
+        currency: &str,
+        amount: Money,
+    ) -> Result<(), AccountingError> {
+        let balance = self
+            .balance_in(signer, currency)?
+            .checked_sub(amount)
+            .ok_or(FxError::UnderFunded(
+                signer.to_string(),
+                currency.to_string(),
                 amount,
-            ));
+            ))?;
+
+        self.balances
+            .entry(signer.to_string())
+            .or_default()
+            .insert(currency.to_string(), balance);
+        Ok(())
+    }
+
+    /// Takes the `amount` out of the account's funds in another `currency` in the same way as
+    /// [`debit_in`](Self::debit_in), if the limits allow it, and counts it towards them,
+    /// at its value in the balances' currency.
+    fn debit_in_within_limits(
+        &mut self,
+        signer: &str,
+        currency: &str,
+        amount: Money,
+    ) -> Result<(), AccountingError> {
+        self.balance_of(signer)?;
+        let value = match self.limited_value(currency, amount) {
+            Ok(value) => Some(value),
+            // Without any limits, an outflow that can't be valued only isn't counted.
+            Err(_) if self.limits.is_unlimited() => None,
+            Err(err) => return Err(err),
+        };
+        if let Some(value) = value {
+            self.check_limits(signer, value)?;
+        }
+        self.debit_in(signer, currency, amount)?;
+        if let Some(value) = value {
+            self.record_outflow(signer, value);
+        }
+        Ok(())
+    }
+
+    /// Withdraws the `amount` in the `currency` in the same way as [`withdraw`](Self::withdraw).
+    ///
+    /// Funds in another currency than the balances' count towards the limits
+    /// at their value in the balances' currency.
+    ///
+    /// # Errors
+    /// - The amount has more decimals than the currency, `FxError::AmountInvalid`;
+    /// - Account doesn't exist, `AccountError::NotFound`;
+    /// - The withdrawal is limited, and there's no rate into the balances' currency,
+    ///   `FxError::RateNotFound`;
+    /// - The withdrawal would exceed a limit, `LimitError::Exceeded`;
+    /// - Attempted overflow (available funds too low), `AccountError::UnderFunded`
+    ///   or `FxError::UnderFunded`.
+    pub fn withdraw_in(
+        &mut self,
+        signer: &str,
+        currency: &str,
+        amount: Money,
+    ) -> Result<Tx, AccountingError> {
+        if currency == self.currency {
+            return self.withdraw(signer, amount);
+        }
+
+        self.scales.check(amount, currency)?;
+        self.debit_in_within_limits(signer, currency, amount)?;
+        self.post_withdrawal(signer, self.cash(currency), amount, SystemAccount::CashIn);
+        Ok(self.withdrawal(signer, amount, Some(currency)))
+    }
+
+    /// Withdraws the `amount` in the `currency` in the same way as [`withdraw_to`](Self::withdraw_to).
+    ///
+    /// # Errors
+    /// - Account doesn't exist, `AccountError::NotFound`;
+    /// - Attempted overflow (available funds too low), `AccountError::UnderFunded`
+    ///   or `FxError::UnderFunded`.
+    pub fn withdraw_in_to(
+        &mut self,
+        signer: &str,
+        currency: &str,
+        amount: Money,
+        destination: SystemAccount,
+    ) -> Result<Tx, AccountingError> {
+        if currency == self.currency {
+            return self.withdraw_to(signer, amount, destination);
+        }
+
+        self.debit_in(signer, currency, amount)?;
+        self.post_withdrawal(signer, self.cash(currency), amount, destination);
+        Ok(self.withdrawal(signer, amount, Some(currency)))
+    }
+
+    /// **Moves the `amount` in the `currency` between the account and suspense,
+    /// reserving it for the account's orders in a market**
+    ///
+    /// The ledger reserves the funds of orders with the [`Leg::Out`], as long as they are available,
+    /// and the market takes them in with the [`Leg::In`]. Limits don't apply,
+    /// and neither does the currency's scale, as orders may cost more decimals than it has.
+    ///
+    /// # Errors
+    /// - Account doesn't exist, `AccountError::NotFound`;
+    /// - The code of the currency is invalid, `FxError::CurrencyInvalid`;
+    /// - Not enough funds available, `AccountError::UnderFunded`
+    ///   or `FxError::UnderFunded`;
+    /// - Attempted overflow, `AccountError::OverFunded` or `FxError::OverFunded`.
+    pub fn reserve_in(
+        &mut self,
+        signer: &str,
+        currency: &str,
+        amount: Money,
+        leg: Leg,
+    ) -> Result<Tx, AccountingError> {
+        let currency = self.suspend_in(signer, currency, amount, leg, false)?;
+
+        let (id, timestamp) = self.next_tx();
+        Ok(Tx::Reservation {
+            id,
+            timestamp,
+            account: signer.to_string(),
+            amount,
+            currency,
+            leg,
+        })
+    }
+
+    /// **Moves the `amount` in the `currency` between the account and suspense,
+    /// releasing it from the account's orders in a market**
+    ///
+    /// The market releases the funds that orders don't need with the [`Leg::Out`], including the held ones,
+    /// and the ledger takes them back with the [`Leg::In`]. See [`reserve_in`](Self::reserve_in).
+    ///
+    /// # Errors
+    /// - Account doesn't exist, `AccountError::NotFound`;
+    /// - The code of the currency is invalid, `FxError::CurrencyInvalid`;
+    /// - Not enough funds, `AccountError::UnderFunded` or `FxError::UnderFunded`;
+    /// - Attempted overflow, `AccountError::OverFunded` or `FxError::OverFunded`.
+    pub fn release_in(
+        &mut self,
+        signer: &str,
+        currency: &str,
+        amount: Money,
+        leg: Leg,
+    ) -> Result<Tx, AccountingError> {
+        let currency = self.suspend_in(signer, currency, amount, leg, true)?;
+
+        let (id, timestamp) = self.next_tx();
+        Ok(Tx::Release {
+            id,
+            timestamp,
+            account: signer.to_string(),
+            amount,
+            currency,
+            leg,
+        })
+    }
+
+    /// Moves the funds between the account and suspense, and posts them to the journal;
+    /// the ones that leave the account have to be available, unless `held` ones can leave, too.
+    ///
+    /// Returns the currency, unless it's the balances' one, as transactions record it.
+    fn suspend_in(
+        &mut self,
+        signer: &str,
+        currency: &str,
+        amount: Money,
+        leg: Leg,
+        held: bool,
+    ) -> Result<Option<String>, AccountingError> {
+        let other = currency != self.currency;
+        match (leg, other) {
+            (Leg::Out, false) if held => self.debit(signer, amount)?,
+            (Leg::Out, false) => self.debit_available(signer, amount)?,
+            (Leg::Out, true) => self.debit_in(signer, currency, amount)?,
+            (Leg::In, false) => self.credit(signer, amount)?,
+            (Leg::In, true) => {
+                validate_currency(currency)?;
+                self.credit_in(signer, currency, amount)?;
+            }
+        }
+
+        match leg {
+            Leg::Out => {
+                self.post_withdrawal(signer, self.cash(currency), amount, SystemAccount::Suspense)
+            }
+            Leg::In => {
+                self.post_deposit(signer, self.cash(currency), amount, SystemAccount::Suspense)
+            }
+        }
+        Ok(other.then(|| currency.to_string()))
+    }
+
+    /// Sends the `amount` in the `currency` in the same way as [`send`](Self::send).
+    ///
+    /// Funds in another currency than the balances' count towards the sender's limits
+    /// at their value in the balances' currency.
+    ///
+    /// # Errors
+    /// - The amount has more decimals than the currency, `FxError::AmountInvalid`;
+    /// - Any of the two accounts doesn't exist, `AccountError::NotFound`;
+    /// - The transfer is limited, and there's no rate into the balances' currency,
+    ///   `FxError::RateNotFound`;
+    /// - The transfer would exceed a limit of the sender, `LimitError::Exceeded`;
+    /// - Attempted overflow (sender's available funds too low), `AccountError::UnderFunded`
+    ///   or `FxError::UnderFunded`;
+    /// - Attempted overflow (recipient's account over-funded), `AccountError::OverFunded`
+    ///   or `FxError::OverFunded`.
+    pub fn send_in(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        currency: &str,
+        amount: Money,
+        memo: Option<&str>,
+    ) -> Result<Tx, AccountingError> {
+        if currency == self.currency {
+            return self.send(sender, recipient, amount, memo);
+        }
+
+        self.scales.check(amount, currency)?;
+        self.move_funds_in(
+            sender,
+            recipient,
+            currency,
+            amount,
+            Self::debit_in_within_limits,
+        )?;
+
+        let (id, timestamp) = self.next_tx();
+        Ok(Tx::Transfer {
+            id,
+            timestamp,
+            from: sender.to_string(),
+            to: recipient.to_string(),
+            amount,
+            memo: memo.map(str::to_string),
+            currency: Some(currency.to_string()),
+        })
+    }
+
+    fn move_funds_in(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        currency: &str,
+        amount: Money,
+        withdraw: fn(&mut Self, &str, &str, Money) -> Result<(), AccountingError>,
+    ) -> Result<(), AccountingError> {
+        // Same as in `move_funds`, the recipient is checked in advance.
+        if self
+            .balance_in(recipient, currency)?
+            .checked_add(amount)
+            .is_none()
+        {
+            return Err(
+                FxError::OverFunded(recipient.to_string(), currency.to_string(), amount).into(),
+            );
+        }
+
+        withdraw(self, sender, currency, amount)?;
+        self.credit_in(recipient, currency, amount)?;
+        self.post(
+            EntryKind::Transfer,
+            self.ledger_account(sender),
+            self.ledger_account(recipient),
+            self.cash(currency),
+            amount,
+        );
+        Ok(())
+    }
+
+    /// Converts the `amount` of the `signer`'s funds in the `from` currency into the `to` currency,
+    /// at the [`FxRates`]; the converted amount is rounded down to the decimals of the `to` currency.
+    ///
+    /// Funds that are held for resting orders can't be converted.
+    /// Conversions stay on the platform, so limits don't apply.
+    ///
+    /// # Errors
+    /// - The `to` currency's code is invalid, or it's the `from` currency, `FxError::CurrencyInvalid`;
+    /// - The amount has more decimals than the `from` currency, `FxError::AmountInvalid`;
+    /// - There's no rate for the pair, `FxError::RateNotFound`;
+    /// - Account doesn't exist, `AccountError::NotFound`;
+    /// - Attempted overflow (available funds too low), `AccountError::UnderFunded`
+    ///   or `FxError::UnderFunded`;
+    /// - Attempted overflow (too many funds in the `to` currency), `AccountError::OverFunded`,
+    ///   `FxError::OverFunded` or `AccountingError::ArithmeticOverflow`.
+    pub fn convert(
+        &mut self,
+        signer: &str,
+        from: &str,
+        to: &str,
+        amount: Money,
+    ) -> Result<Tx, AccountingError> {
+        validate_currency(to)?;
+        if from == to {
+            return Err(FxError::CurrencyInvalid(format!(
+                "Funds in {} can't be converted into {}.",
+                from, to
+            ))
+            .into());
         }
+        self.scales.check(amount, from)?;
+        let (rate, converted) =
+            self.fx_rates
+                .convert(amount, from, to, self.scales.scale(to), Rounding::Down)?;
+        if from == self.currency && self.available_of(signer)? < amount {
+            return Err(AccountError::UnderFunded(signer.to_string(), amount).into());
+        }
+
+        self.exchange(signer, from, to, amount, converted)?;
 
-        let withdrawal = self.withdraw(sender, amount)?;
-        let deposit = self.deposit(recipient, amount)?;
+        let (id, timestamp) = self.next_tx();
+        Ok(Tx::Conversion {
+            id,
+            timestamp,
+            account: signer.to_string(),
+            from_currency: from.to_string(),
+            to_currency: to.to_string(),
+            amount,
+            rate,
+            converted,
+        })
+    }
+
+    /// Exchanges the `amount` of the `from` currency for the `converted` amount of the `to` currency,
+    /// through the exchange's system account in the journal. The caller records the transaction.
+    pub(crate) fn exchange(
+        &mut self,
+        signer: &str,
+        from: &str,
+        to: &str,
+        amount: Money,
+        converted: Money,
+    ) -> Result<(), AccountingError> {
+        // The converted funds are checked in advance, so that the debit doesn't have to be undone.
+        if self
+            .balance_in(signer, to)?
+            .checked_add(converted)
+            .is_none()
+        {
+            return Err(match to == self.currency {
+                true => AccountingError::Account(AccountError::OverFunded(
+                    signer.to_string(),
+                    converted,
+                )),
+                false => AccountingError::Fx(FxError::OverFunded(
+                    signer.to_string(),
+                    to.to_string(),
+                    converted,
+                )),
+            });
+        }
 
-        Ok((withdrawal, deposit))
+        match from == self.currency {
+            true => self.debit(signer, amount)?,
+            false => self.debit_in(signer, from, amount)?,
+        }
+        match to == self.currency {
+            true => self.credit(signer, converted)?,
+            false => self.credit_in(signer, to, converted)?,
+        }
+        self.post(
+            EntryKind::Conversion,
+            self.ledger_account(signer),
+            LedgerAccount::System(SystemAccount::Fx),
+            self.cash(from),
+            amount,
+        );
+        self.post(
+            EntryKind::Conversion,
+            LedgerAccount::System(SystemAccount::Fx),
+            self.ledger_account(signer),
+            self.cash(to),
+            converted,
+        );
+        Ok(())
     }
 }
 
 #[cfg(test)]
+// The original tests check for accounts that don't exist with `get`.
+#[allow(clippy::unnecessary_get_then_check)]
 mod tests {
     use super::*;
+    use crate::journal::JournalEntry;
+    use crate::limits::{Limit, Limits, ManualClock, Velocity, DAY_MILLIS};
 
     #[test]
     fn deposit_multiple_ok() {
         let mut accounts = Accounts::new();
         let client = "Alice";
 
-        let mut tx = accounts.deposit(client, 25);
+        let mut tx = accounts.deposit(client, 25.into());
         assert!(tx.is_ok());
         assert_eq!(
             Ok(Tx::Deposit {
+                id: 1,
+                timestamp: tx.as_ref().unwrap().timestamp(),
                 account: client.to_string(),
-                amount: 25
+                amount: 25.into(),
+                currency: None,
             }),
             tx,
         );
-        assert_eq!(&25, accounts.accounts.get(client).unwrap());
+        assert_eq!(&Money::from(25), accounts.accounts.get(client).unwrap());
 
-        tx = accounts.deposit(client, 50);
+        tx = accounts.deposit(client, 50.into());
         assert!(tx.is_ok());
+        let tx = tx.unwrap();
         assert_eq!(
             Tx::Deposit {
+                id: 2,
+                timestamp: tx.timestamp(),
                 account: client.to_string(),
-                amount: 50
+                amount: 50.into(),
+                currency: None,
             },
-            tx.unwrap(),
+            tx,
         );
 
-        assert_eq!(&75, accounts.accounts.get(client).unwrap());
+        assert_eq!(&Money::from(75), accounts.accounts.get(client).unwrap());
     }
 
     #[test]
@@ -168,17 +2004,23 @@ mod tests {
         let mut accounts = Accounts::new();
         let client = "Bob";
 
-        let mut tx = accounts.deposit(client, u64::MAX);
+        let mut tx = accounts.deposit(client, u64::MAX.into());
         assert!(tx.is_ok());
 
-        tx = accounts.deposit(client, 10);
+        tx = accounts.deposit(client, 10.into());
         assert!(tx.is_err());
         assert_eq!(
-            Err(AccountingError::AccountOverFunded(client.to_string(), 10)),
+            Err(AccountingError::Account(AccountError::OverFunded(
+                client.to_string(),
+                10.into()
+            ))),
             tx
         );
 
-        assert_eq!(&(u64::MAX), accounts.accounts.get(client).unwrap());
+        assert_eq!(
+            &Money::from(u64::MAX),
+            accounts.accounts.get(client).unwrap()
+        );
     }
 
     #[test]
@@ -186,29 +2028,36 @@ mod tests {
         let mut accounts = Accounts::new();
         let client = "Charlie";
 
-        let _ = accounts.deposit(client, 25);
-        let tx = accounts.withdraw(client, 5);
+        let _ = accounts.deposit(client, 25.into());
+        let tx = accounts.withdraw(client, 5.into());
         assert!(tx.is_ok());
         assert_eq!(
             Ok(Tx::Withdraw {
+                id: 2,
+                timestamp: tx.as_ref().unwrap().timestamp(),
                 account: client.to_string(),
-                amount: 5,
+                amount: 5.into(),
+                currency: None,
             }),
             tx
         );
-        assert_eq!(&20, accounts.accounts.get(client).unwrap());
+        assert_eq!(&Money::from(20), accounts.accounts.get(client).unwrap());
 
-        let tx = accounts.withdraw(client, 20);
+        let tx = accounts.withdraw(client, 20.into());
         assert!(tx.is_ok());
+        let tx = tx.unwrap();
         assert_eq!(
             Tx::Withdraw {
+                id: 3,
+                timestamp: tx.timestamp(),
                 account: client.to_string(),
-                amount: 20,
+                amount: 20.into(),
+                currency: None,
             },
-            tx.unwrap()
+            tx
         );
 
-        assert_eq!(&0, accounts.accounts.get(client).unwrap());
+        assert_eq!(&Money::from(0), accounts.accounts.get(client).unwrap());
     }
 
     #[test]
@@ -216,21 +2065,23 @@ mod tests {
         let mut accounts = Accounts::new();
         let client = "Charlie";
 
-        let tx = accounts.withdraw(client, 100);
+        let tx = accounts.withdraw(client, 100.into());
 
         assert!(tx.is_err());
         assert_eq!(
-            Err(AccountingError::AccountNotFound(client.to_string())),
+            Err(AccountingError::Account(AccountError::NotFound(
+                client.to_string()
+            ))),
             tx
         );
         assert_eq!(
-            AccountingError::AccountNotFound(client.to_string()),
+            AccountingError::Account(AccountError::NotFound(client.to_string())),
             tx.unwrap_err()
         );
 
         assert!(accounts.accounts.get(client).is_none());
         assert_eq!(
-            AccountingError::AccountNotFound(client.to_string()),
+            AccountingError::Account(AccountError::NotFound(client.to_string())),
             accounts.balance_of(client).unwrap_err()
         );
     }
@@ -240,15 +2091,18 @@ mod tests {
         let mut accounts = Accounts::new();
         let client = "Maria";
 
-        let _ = accounts.deposit(client, 25);
-        let tx = accounts.withdraw(client, 125);
+        let _ = accounts.deposit(client, 25.into());
+        let tx = accounts.withdraw(client, 125.into());
         assert!(tx.is_err());
         assert_eq!(
-            Err(AccountingError::AccountUnderFunded(client.to_string(), 125)),
+            Err(AccountingError::Account(AccountError::UnderFunded(
+                client.to_string(),
+                125.into()
+            ))),
             tx,
         );
 
-        assert_eq!(&25, accounts.accounts.get(client).unwrap());
+        assert_eq!(&Money::from(25), accounts.accounts.get(client).unwrap());
     }
 
     #[test]
@@ -257,18 +2111,18 @@ mod tests {
         let sender = "Alice";
         let recipient = "Bob";
 
-        let _ = accounts.deposit(sender, 100);
-        let _ = accounts.deposit(recipient, 50);
+        let _ = accounts.deposit(sender, 100.into());
+        let _ = accounts.deposit(recipient, 50.into());
 
-        let status = accounts.send(sender, recipient, 10);
+        let status = accounts.send(sender, recipient, 10.into(), None);
 
         assert!(status.is_ok());
 
-        assert_eq!(&90, accounts.accounts.get(sender).unwrap());
-        assert_eq!(&60, accounts.accounts.get(recipient).unwrap());
+        assert_eq!(&Money::from(90), accounts.accounts.get(sender).unwrap());
+        assert_eq!(&Money::from(60), accounts.accounts.get(recipient).unwrap());
 
-        assert_eq!(&90, accounts.balance_of(sender).unwrap());
-        assert_eq!(&60, accounts.balance_of(recipient).unwrap());
+        assert_eq!(&Money::from(90), accounts.balance_of(sender).unwrap());
+        assert_eq!(&Money::from(60), accounts.balance_of(recipient).unwrap());
     }
 
     #[test]
@@ -277,18 +2131,18 @@ mod tests {
         let sender = "Alice";
         let recipient = "Bob";
 
-        let _ = accounts.deposit(recipient, 50);
+        let _ = accounts.deposit(recipient, 50.into());
 
-        let status = accounts.send(sender, recipient, 10);
+        let status = accounts.send(sender, recipient, 10.into(), None);
 
         assert!(status.is_err());
         assert_eq!(
-            AccountingError::AccountNotFound(sender.to_string()),
+            AccountingError::Account(AccountError::NotFound(sender.to_string())),
             status.unwrap_err()
         );
 
         assert!(accounts.accounts.get(sender).is_none());
-        assert_eq!(&50, accounts.accounts.get(recipient).unwrap());
+        assert_eq!(&Money::from(50), accounts.accounts.get(recipient).unwrap());
     }
 
     #[test]
@@ -297,17 +2151,17 @@ mod tests {
         let sender = "Alice";
         let recipient = "Bob";
 
-        let _ = accounts.deposit(sender, 100);
+        let _ = accounts.deposit(sender, 100.into());
 
-        let status = accounts.send(sender, recipient, 10);
+        let status = accounts.send(sender, recipient, 10.into(), None);
 
         assert!(status.is_err());
         assert_eq!(
-            AccountingError::AccountNotFound(recipient.to_string()),
+            AccountingError::Account(AccountError::NotFound(recipient.to_string())),
             status.unwrap_err()
         );
 
-        assert_eq!(&100, accounts.accounts.get(sender).unwrap());
+        assert_eq!(&Money::from(100), accounts.accounts.get(sender).unwrap());
         assert!(accounts.accounts.get(recipient).is_none());
     }
 
@@ -317,13 +2171,13 @@ mod tests {
         let sender = "Alice";
         let recipient = "Bob";
 
-        let status = accounts.send(sender, recipient, 10);
+        let status = accounts.send(sender, recipient, 10.into(), None);
 
         assert!(status.is_err());
 
         // Recipient is checked first
         assert_eq!(
-            AccountingError::AccountNotFound(recipient.to_string()),
+            AccountingError::Account(AccountError::NotFound(recipient.to_string())),
             status.unwrap_err()
         );
 
@@ -337,19 +2191,19 @@ mod tests {
         let sender = "Alice";
         let recipient = "Bob";
 
-        let _ = accounts.deposit(sender, 100);
-        let _ = accounts.deposit(recipient, 50);
+        let _ = accounts.deposit(sender, 100.into());
+        let _ = accounts.deposit(recipient, 50.into());
 
-        let status = accounts.send(sender, recipient, 200);
+        let status = accounts.send(sender, recipient, 200.into(), None);
 
         assert!(status.is_err());
         assert_eq!(
-            AccountingError::AccountUnderFunded(sender.to_string(), 200),
+            AccountingError::Account(AccountError::UnderFunded(sender.to_string(), 200.into())),
             status.unwrap_err()
         );
 
-        assert_eq!(&100, accounts.accounts.get(sender).unwrap());
-        assert_eq!(&50, accounts.accounts.get(recipient).unwrap());
+        assert_eq!(&Money::from(100), accounts.accounts.get(sender).unwrap());
+        assert_eq!(&Money::from(50), accounts.accounts.get(recipient).unwrap());
     }
 
     #[test]
@@ -358,18 +2212,686 @@ mod tests {
         let sender = "Alice";
         let recipient = "Bob";
 
-        let _ = accounts.deposit(sender, 100);
-        let _ = accounts.deposit(recipient, u64::MAX);
+        let _ = accounts.deposit(sender, 100.into());
+        let _ = accounts.deposit(recipient, u64::MAX.into());
 
-        let status = accounts.send(sender, recipient, 10);
+        let status = accounts.send(sender, recipient, 10.into(), None);
 
         assert!(status.is_err());
         assert_eq!(
-            AccountingError::AccountOverFunded(recipient.to_string(), 10),
+            AccountingError::Account(AccountError::OverFunded(recipient.to_string(), 10.into())),
             status.unwrap_err()
         );
 
-        assert_eq!(&100, accounts.accounts.get(sender).unwrap());
-        assert_eq!(&u64::MAX, accounts.accounts.get(recipient).unwrap());
+        assert_eq!(&Money::from(100), accounts.accounts.get(sender).unwrap());
+        assert_eq!(
+            &Money::from(u64::MAX),
+            accounts.accounts.get(recipient).unwrap()
+        );
+    }
+
+    #[test]
+    fn deposit_and_withdraw_units_ok() {
+        let mut accounts = Accounts::new();
+        let client = "Alice";
+
+        let tx = accounts.deposit_units(client, "ABC", 10.into());
+        assert_eq!(
+            Ok(Tx::DepositUnits {
+                id: 1,
+                timestamp: tx.as_ref().unwrap().timestamp(),
+                account: client.to_string(),
+                symbol: "ABC".to_string(),
+                units: 10.into(),
+            }),
+            tx
+        );
+        // The account is created on this occasion.
+        assert_eq!(&Money::ZERO, accounts.balance_of(client).unwrap());
+
+        assert!(accounts.withdraw_units(client, "ABC", 4.into()).is_ok());
+        assert_eq!(Ok(Quantity::from(6)), accounts.units_of(client, "ABC"));
+        assert_eq!(Ok(Quantity::ZERO), accounts.units_of(client, "XYZ"));
+    }
+
+    #[test]
+    fn withdraw_units_err_insufficient() {
+        let mut accounts = Accounts::new();
+        let client = "Bob";
+
+        assert_eq!(
+            Err(AccountingError::Account(AccountError::NotFound(
+                client.to_string()
+            ))),
+            accounts.withdraw_units(client, "ABC", 1.into())
+        );
+
+        let _ = accounts.deposit_units(client, "ABC", 1.into());
+        assert_eq!(
+            Err(AccountingError::Account(
+                AccountError::HoldingsInsufficient(client.to_string(), "ABC".to_string(), 2.into())
+            )),
+            accounts.withdraw_units(client, "ABC", 2.into())
+        );
+        assert_eq!(Ok(Quantity::from(1)), accounts.units_of(client, "ABC"));
+    }
+
+    #[test]
+    fn withdraw_and_send_err_held() {
+        let mut accounts = Accounts::new();
+        let client = "Alice";
+
+        let _ = accounts.deposit(client, 100.into());
+        let _ = accounts.deposit("Bob", 0.into());
+        let _ = accounts.deposit_units(client, "ABC", 10.into());
+        accounts.held.insert(client.to_string(), 70.into());
+        accounts.held_units.insert(
+            client.to_string(),
+            BTreeMap::from([("ABC".to_string(), 4.into())]),
+        );
+
+        assert_eq!(Ok(Money::from(30)), accounts.available_of(client));
+        assert_eq!(
+            Err(AccountingError::Account(AccountError::UnderFunded(
+                client.to_string(),
+                31.into()
+            ))),
+            accounts.withdraw(client, 31.into())
+        );
+        assert!(accounts.send(client, "Bob", 31.into(), None).is_err());
+        assert!(accounts.send(client, "Bob", 30.into(), None).is_ok());
+
+        assert_eq!(
+            Ok(Quantity::from(6)),
+            accounts.units_available_of(client, "ABC")
+        );
+        assert!(accounts
+            .send_units(client, "Bob", "ABC", 7.into(), None)
+            .is_err());
+        assert!(accounts.withdraw_units(client, "ABC", 6.into()).is_ok());
+
+        // Settlement spends the held part, too.
+        assert!(accounts
+            .transfer(client, "Bob", 70.into(), EntryKind::Settlement)
+            .is_ok());
+        assert!(accounts
+            .transfer_units(client, "Bob", "ABC", 4.into())
+            .is_ok());
+        assert!(accounts.balance_of(client).unwrap().is_zero());
+    }
+
+    #[test]
+    fn every_change_is_posted_to_the_journal() {
+        let mut accounts = Accounts::new();
+        accounts.open_fee_account("Fees");
+
+        assert!(accounts.deposit("Alice", 100.into()).is_ok());
+        assert!(accounts.deposit_units("Bob", "ABC", 5.into()).is_ok());
+        assert!(accounts.send("Alice", "Bob", 40.into(), None).is_ok());
+        assert!(accounts
+            .transfer("Bob", "Fees", 1.into(), EntryKind::Fee)
+            .is_ok());
+        assert!(accounts
+            .withdraw_to("Alice", 60.into(), SystemAccount::Suspense)
+            .is_ok());
+        // Nothing is posted for moving nothing.
+        assert!(accounts
+            .send_units("Bob", "Alice", "ABC", 0.into(), None)
+            .is_ok());
+
+        let entries = accounts.journal().entries();
+        assert!(entries.iter().all(JournalEntry::is_balanced));
+        assert_eq!(
+            vec![
+                EntryKind::Deposit,
+                EntryKind::Deposit,
+                EntryKind::Transfer,
+                EntryKind::Fee,
+                EntryKind::Suspense
+            ],
+            entries
+                .into_iter()
+                .map(|entry| entry.kind)
+                .collect::<Vec<_>>()
+        );
+
+        let trial_balance = accounts.journal().trial_balance();
+        assert!(trial_balance.is_balanced());
+        let balance_of = |account: LedgerAccount, asset: Asset| {
+            accounts.journal().balance_of(&account, &asset).credit
+        };
+        assert_eq!(
+            Amount::from(39),
+            balance_of(LedgerAccount::Customer("Bob".to_string()), Asset::Cash)
+        );
+        assert_eq!(
+            Amount::from(1),
+            balance_of(LedgerAccount::System(SystemAccount::Fees), Asset::Cash)
+        );
+        assert_eq!(
+            Amount::from(60),
+            balance_of(LedgerAccount::System(SystemAccount::Suspense), Asset::Cash)
+        );
+        assert_eq!(
+            Amount::from(5),
+            balance_of(
+                LedgerAccount::Customer("Bob".to_string()),
+                Asset::Units("ABC".to_string())
+            )
+        );
+    }
+
+    #[test]
+    fn close_ok_empty_and_swept() {
+        let mut accounts = Accounts::new();
+
+        let _ = accounts.deposit("Alice", 0.into());
+        let txs = accounts.close("Alice", None).unwrap();
+        assert_eq!(
+            vec![Tx::AccountClosed {
+                id: 2,
+                timestamp: txs[0].timestamp(),
+                account: "Alice".to_string()
+            }],
+            txs
+        );
+        assert!(accounts.balance_of("Alice").is_err());
+
+        let _ = accounts.deposit("Bob", 10.into());
+        let _ = accounts.deposit_units("Bob", "ABC", 2.into());
+        let _ = accounts.deposit("Charlie", 5.into());
+        let txs = accounts.close("Bob", Some("Charlie")).unwrap();
+        assert_eq!(3, txs.len());
+        assert!(matches!(
+            &txs[0],
+            Tx::Transfer { from, to, amount, memo: Some(memo), .. }
+                if from == "Bob" && to == "Charlie" && *amount == 10.into() && memo == SWEEP_MEMO
+        ));
+        assert_eq!(
+            AccountSummary {
+                balance: 15.into(),
+                holdings: BTreeMap::from([("ABC".to_string(), 2.into())]),
+                ..Default::default()
+            },
+            accounts.summary_of("Charlie").unwrap()
+        );
+        assert!(!accounts.holdings.contains_key("Bob"));
+    }
+
+    #[test]
+    fn close_err_not_empty_or_held() {
+        let mut accounts = Accounts::new();
+        let client = "Alice";
+
+        let _ = accounts.deposit(client, 10.into());
+        assert_eq!(
+            Err(AccountingError::Account(AccountError::NotEmpty(
+                client.to_string()
+            ))),
+            accounts.close(client, None)
+        );
+        assert_eq!(
+            Err(AccountingError::Account(AccountError::NotFound(
+                "Nobody".to_string()
+            ))),
+            accounts.close(client, Some("Nobody"))
+        );
+
+        let _ = accounts.deposit("Bob", 0.into());
+        accounts.held.insert(client.to_string(), 1.into());
+        assert_eq!(
+            Err(AccountingError::Account(AccountError::HasOpenOrders(
+                client.to_string()
+            ))),
+            accounts.close(client, Some("Bob"))
+        );
+        assert_eq!(&Money::from(10), accounts.balance_of(client).unwrap());
+    }
+
+    #[test]
+    fn set_status_and_reopen() {
+        let mut accounts = Accounts::new();
+
+        assert_eq!(Ok(AccountStatus::WithdrawOnly), "withdraw-only".parse());
+        assert_eq!(Ok(AccountStatus::WithdrawOnly), "withdraw_only".parse());
+        assert!("suspended".parse::<AccountStatus>().is_err());
+        assert_eq!(
+            "\"withdraw_only\"",
+            serde_json::to_string(&AccountStatus::WithdrawOnly).unwrap()
+        );
+
+        let _ = accounts.deposit("Alice", 0.into());
+        assert_eq!(AccountStatus::Active, accounts.status_of("Alice"));
+        let _ = accounts.set_status("Alice", AccountStatus::Frozen).unwrap();
+        assert_eq!(
+            Err(AccountingError::Account(AccountError::Frozen(
+                "Alice".to_string()
+            ))),
+            accounts.check_outflow("Alice")
+        );
+        assert_eq!(Ok(()), accounts.check_inflow("Alice"));
+
+        let _ = accounts.close("Alice", None).unwrap();
+        assert_eq!(AccountStatus::Closed, accounts.status_of("Alice"));
+        assert_eq!(
+            Err(AccountingError::Account(AccountError::Closed(
+                "Alice".to_string()
+            ))),
+            accounts.check_inflow("Alice")
+        );
+
+        // Crediting a closed account, such as a market releasing funds to it, reopens it.
+        let _ = accounts.deposit("Alice", 1.into());
+        assert_eq!(AccountStatus::Active, accounts.status_of("Alice"));
+    }
+
+    #[test]
+    fn borrow_and_repay_margin_loan() {
+        let mut accounts = Accounts::new();
+        let client = "Alice";
+
+        let _ = accounts.deposit(client, 10.into());
+        assert_eq!(
+            Err(AccountingError::Margin(MarginError::AccountNotFound(
+                client.to_string()
+            ))),
+            accounts.borrow(client, 50.into())
+        );
+        assert!(accounts.open_margin(client).is_ok());
+        assert!(accounts.borrow(client, 50.into()).is_ok());
+        assert_eq!(&Money::from(60), accounts.balance_of(client).unwrap());
+        assert_eq!(Money::from(50), accounts.loan_of(client));
+
+        assert_eq!(
+            Err(AccountingError::Margin(MarginError::LoanExceeded(
+                client.to_string(),
+                51.into()
+            ))),
+            accounts.repay(client, 51.into())
+        );
+        assert_eq!(
+            Err(AccountingError::Margin(MarginError::LoanOutstanding(
+                client.to_string()
+            ))),
+            accounts.close(client, None)
+        );
+        assert!(accounts.repay(client, 50.into()).is_ok());
+        assert_eq!(
+            AccountSummary {
+                balance: 10.into(),
+                margin: true,
+                ..Default::default()
+            },
+            accounts.summary_of(client).unwrap()
+        );
+
+        let kinds: Vec<EntryKind> = accounts
+            .journal()
+            .entries()
+            .into_iter()
+            .map(|entry| entry.kind)
+            .collect();
+        assert_eq!(
+            vec![EntryKind::Deposit, EntryKind::Loan, EntryKind::Repayment],
+            kinds
+        );
+    }
+
+    #[test]
+    fn withdraw_and_send_within_limits() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut accounts = Accounts::new();
+        accounts.set_clock(clock.clone());
+        accounts.set_limits(
+            LimitSchedule::new(Limits {
+                daily: Some(100.into()),
+                ..Default::default()
+            })
+            .with_account_limits(
+                "Alice",
+                Limits {
+                    per_transaction: Some(60.into()),
+                    velocity: Some(Velocity {
+                        count: 2,
+                        window_secs: 60,
+                    }),
+                    ..Default::default()
+                },
+            ),
+        );
+        let _ = accounts.deposit("Alice", 500.into());
+        let _ = accounts.deposit("Bob", 500.into());
+
+        let limit_exceeded = |limit| {
+            Err(AccountingError::Limit(LimitError::Exceeded(
+                "Alice".to_string(),
+                limit,
+            )))
+        };
+        assert_eq!(
+            limit_exceeded(Limit::PerTransaction),
+            accounts.withdraw("Alice", 61.into())
+        );
+        assert!(accounts.withdraw("Alice", 50.into()).is_ok());
+        assert!(accounts.send("Alice", "Bob", 10.into(), None).is_ok());
+        assert_eq!(
+            limit_exceeded(Limit::Velocity),
+            accounts.send("Alice", "Bob", 10.into(), None)
+        );
+
+        clock.advance(60_000);
+        assert_eq!(
+            limit_exceeded(Limit::Daily),
+            accounts.withdraw("Alice", 41.into())
+        );
+        assert_eq!(
+            Allowance {
+                per_transaction: Some(60.into()),
+                daily_remaining: Some(40.into()),
+                velocity_remaining: Some(2),
+            },
+            accounts.allowance_of("Alice")
+        );
+        // Rejections don't count, and nothing left the account for them.
+        assert_eq!(Ok(Money::from(440)), accounts.balance_of("Alice").copied());
+
+        // Bob only has the global limit.
+        assert!(accounts.withdraw("Bob", 100.into()).is_ok());
+        assert_eq!(
+            Some(Money::ZERO),
+            accounts.allowance_of("Bob").daily_remaining
+        );
+
+        clock.advance(DAY_MILLIS);
+        assert!(accounts.withdraw("Alice", 60.into()).is_ok());
+        let tx = accounts.withdraw("Bob", 100.into()).unwrap();
+        assert_eq!(1_000 + 60_000 + DAY_MILLIS, tx.timestamp());
+    }
+
+    #[test]
+    fn limits_set_later_count_the_earlier_outflows() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut accounts = Accounts::new();
+        accounts.set_clock(clock.clone());
+        let _ = accounts.deposit("Alice", 500.into());
+        let _ = accounts.deposit("Bob", 500.into());
+
+        assert!(accounts.withdraw("Alice", 70.into()).is_ok());
+        assert!(accounts.send("Alice", "Bob", 20.into(), None).is_ok());
+        clock.advance(60_000);
+
+        accounts.set_limits(LimitSchedule::new(Limits {
+            daily: Some(100.into()),
+            velocity: Some(Velocity {
+                count: 2,
+                window_secs: 120,
+            }),
+            ..Default::default()
+        }));
+        assert_eq!(
+            Allowance {
+                per_transaction: None,
+                daily_remaining: Some(10.into()),
+                velocity_remaining: Some(0),
+            },
+            accounts.allowance_of("Alice")
+        );
+        assert_eq!(
+            Err(AccountingError::Limit(LimitError::Exceeded(
+                "Alice".to_string(),
+                Limit::Daily
+            ))),
+            accounts.withdraw("Alice", 11.into())
+        );
+
+        // The earlier outflows fall out of their windows in time, like any others.
+        clock.advance(60_000);
+        assert!(accounts.withdraw("Alice", 10.into()).is_ok());
+        clock.advance(DAY_MILLIS);
+        assert!(accounts.withdraw("Alice", 100.into()).is_ok());
+    }
+
+    #[test]
+    fn send_units_ok_and_summaries() {
+        let mut accounts = Accounts::new();
+        let sender = "Alice";
+        let recipient = "Bob";
+
+        let _ = accounts.deposit_units(sender, "ABC", 5.into());
+        assert_eq!(
+            Err(AccountingError::Account(AccountError::NotFound(
+                recipient.to_string()
+            ))),
+            accounts.send_units(sender, recipient, "ABC", 5.into(), None)
+        );
+
+        let _ = accounts.deposit(recipient, 50.into());
+        assert!(accounts
+            .send_units(sender, recipient, "ABC", 5.into(), None)
+            .is_ok());
+
+        let summaries = accounts.summaries();
+        // Instruments that aren't held anymore are left out.
+        assert!(summaries[sender].holdings.is_empty());
+        assert_eq!(
+            AccountSummary {
+                balance: 50.into(),
+                holdings: BTreeMap::from([("ABC".to_string(), 5.into())]),
+                ..Default::default()
+            },
+            summaries[recipient]
+        );
+    }
+
+    #[test]
+    fn deposit_withdraw_and_send_in_currencies() {
+        let mut accounts = Accounts::new();
+        accounts.set_fx_rates(
+            FxRates::new()
+                .with_rate("EUR", "USD", "1.1".parse().unwrap())
+                .unwrap(),
+        );
+
+        assert_eq!(
+            "CURRENCY_INVALID",
+            accounts
+                .deposit_in("Alice", "eur", 1.into())
+                .unwrap_err()
+                .code()
+        );
+        let tx = accounts.deposit_in("Alice", "EUR", 100.into()).unwrap();
+        assert!(matches!(&tx, Tx::Deposit { currency: Some(currency), .. } if currency == "EUR"));
+        // The balances' currency is the balance itself.
+        let tx = accounts.deposit_in("Alice", "USD", 10.into()).unwrap();
+        assert!(matches!(tx, Tx::Deposit { currency: None, .. }));
+        let _ = accounts.deposit("Bob", 0.into());
+
+        assert!(accounts
+            .send_in("Alice", "Bob", "EUR", 30.into(), None)
+            .is_ok());
+        assert_eq!(
+            Err(AccountingError::Fx(FxError::UnderFunded(
+                "Alice".to_string(),
+                "EUR".to_string(),
+                71.into()
+            ))),
+            accounts.withdraw_in("Alice", "EUR", 71.into())
+        );
+        assert!(accounts.withdraw_in("Alice", "EUR", 70.into()).is_ok());
+        assert_eq!(Ok(Money::ZERO), accounts.balance_in("Alice", "EUR"));
+        assert_eq!(Ok(Money::from(10)), accounts.balance_in("Alice", "USD"));
+
+        // Funds that are gone are left out of the summary.
+        let summaries = accounts.summaries();
+        assert!(summaries["Alice"].balances.is_empty());
+        assert_eq!(
+            BTreeMap::from([("EUR".to_string(), 30.into())]),
+            summaries["Bob"].balances
+        );
+        assert_eq!(
+            Amount::from(30),
+            accounts
+                .journal()
+                .balance_of(
+                    &LedgerAccount::Customer("Bob".to_string()),
+                    &Asset::Currency("EUR".to_string())
+                )
+                .credit
+        );
+
+        // Limits count the funds at their value in the balances' currency.
+        accounts.set_limits(LimitSchedule::new(Limits {
+            per_transaction: Some(30.into()),
+            ..Default::default()
+        }));
+        assert_eq!(
+            Err(AccountingError::Limit(LimitError::Exceeded(
+                "Bob".to_string(),
+                Limit::PerTransaction
+            ))),
+            accounts.withdraw_in("Bob", "EUR", 28.into())
+        );
+        assert!(accounts.withdraw_in("Bob", "EUR", 27.into()).is_ok());
+        let _ = accounts.deposit_in("Bob", "GBP", 5.into());
+        assert_eq!(
+            Err(AccountingError::Fx(FxError::RateNotFound(
+                "GBP".to_string(),
+                "USD".to_string()
+            ))),
+            accounts.send_in("Bob", "Alice", "GBP", 5.into(), None)
+        );
+    }
+
+    #[test]
+    fn amounts_keep_the_scale_of_their_currency() {
+        let mut accounts = Accounts::new();
+        accounts.set_currency_scales("JPY=0,BTC=8".parse().unwrap());
+        accounts.set_fx_rates("USD/JPY=150.125,BTC/USD=60000.5".parse().unwrap());
+        let invalid = |result: Result<Tx, AccountingError>| result.unwrap_err().code();
+
+        assert_eq!(
+            "AMOUNT_INVALID",
+            invalid(accounts.deposit("Alice", "1.001".parse().unwrap()))
+        );
+        assert_eq!(
+            "AMOUNT_INVALID",
+            invalid(accounts.deposit_in("Alice", "JPY", "1.5".parse().unwrap()))
+        );
+        assert!(accounts.deposit("Alice", "100.25".parse().unwrap()).is_ok());
+        assert!(accounts
+            .deposit_in("Alice", "BTC", "0.12345678".parse().unwrap())
+            .is_ok());
+
+        for result in [
+            accounts.withdraw("Alice", "0.001".parse().unwrap()),
+            accounts.withdraw_in("Alice", "BTC", "0.000000001".parse().unwrap()),
+            accounts.send_in(
+                "Alice",
+                "Alice",
+                "BTC",
+                "0.000000001".parse().unwrap(),
+                None,
+            ),
+            accounts.convert("Alice", "USD", "JPY", "0.001".parse().unwrap()),
+        ] {
+            assert_eq!("AMOUNT_INVALID", invalid(result));
+        }
+
+        // Converted amounts are rounded down to the decimals of the currency they're converted into.
+        let tx = accounts
+            .convert("Alice", "USD", "JPY", "10.25".parse().unwrap())
+            .unwrap();
+        assert!(matches!(tx, Tx::Conversion { converted, .. } if converted == Money::from(1538)));
+        let tx = accounts
+            .convert("Alice", "BTC", "USD", "0.00001234".parse().unwrap())
+            .unwrap();
+        assert!(
+            matches!(tx, Tx::Conversion { converted, .. } if converted == "0.74".parse().unwrap())
+        );
+        assert_eq!(
+            Ok("90.74".parse().unwrap()),
+            accounts.balance_in("Alice", "USD")
+        );
+    }
+
+    #[test]
+    fn convert_and_close_with_currencies() {
+        let mut accounts = Accounts::new();
+        accounts.set_fx_rates("EUR/USD=1.08,USD/EUR=0.92".parse().unwrap());
+        let mut tx_log = vec![accounts.deposit("Alice", 100.into()).unwrap()];
+
+        assert_eq!(
+            "CURRENCY_INVALID",
+            accounts
+                .convert("Alice", "USD", "USD", 1.into())
+                .unwrap_err()
+                .code()
+        );
+        assert_eq!(
+            Err(AccountingError::Fx(FxError::RateNotFound(
+                "USD".to_string(),
+                "GBP".to_string()
+            ))),
+            accounts.convert("Alice", "USD", "GBP", 1.into())
+        );
+        // Held funds can't be converted.
+        accounts.held.insert("Alice".to_string(), 60.into());
+        assert_eq!(
+            Err(AccountingError::Account(AccountError::UnderFunded(
+                "Alice".to_string(),
+                41.into()
+            ))),
+            accounts.convert("Alice", "USD", "EUR", 41.into())
+        );
+
+        let tx = accounts.convert("Alice", "USD", "EUR", 40.into()).unwrap();
+        assert_eq!(
+            Tx::Conversion {
+                id: 2,
+                timestamp: tx.timestamp(),
+                account: "Alice".to_string(),
+                from_currency: "USD".to_string(),
+                to_currency: "EUR".to_string(),
+                amount: 40.into(),
+                rate: "0.92".parse().unwrap(),
+                converted: "36.8".parse().unwrap(),
+            },
+            tx
+        );
+        tx_log.push(tx);
+        tx_log.push(accounts.convert("Alice", "EUR", "USD", 10.into()).unwrap());
+        assert_eq!(
+            Ok("70.8".parse().unwrap()),
+            accounts.balance_in("Alice", "USD")
+        );
+        assert_eq!(
+            Ok("26.8".parse().unwrap()),
+            accounts.balance_in("Alice", "EUR")
+        );
+        let balance_of =
+            |account: LedgerAccount, asset: Asset| accounts.journal().balance_of(&account, &asset);
+        // The exchange took in 40 dollars, and paid out 10.8 of them.
+        assert_eq!(
+            Amount::from("29.2".parse::<Money>().unwrap()),
+            balance_of(LedgerAccount::System(SystemAccount::Fx), Asset::Cash).credit
+        );
+        assert!(accounts.journal().trial_balance().is_balanced());
+
+        // The funds in every currency are swept.
+        accounts.held.remove("Alice");
+        tx_log.push(accounts.deposit("Bob", 0.into()).unwrap());
+        tx_log.extend(accounts.close("Alice", Some("Bob")).unwrap());
+        assert_eq!(
+            AccountSummary {
+                balance: "70.8".parse().unwrap(),
+                balances: BTreeMap::from([("EUR".to_string(), "26.8".parse().unwrap())]),
+                ..Default::default()
+            },
+            accounts.summary_of("Bob").unwrap()
+        );
+        assert!(!accounts.balances.contains_key("Alice"));
+
+        let rebuilt = crate::reconciliation::replay(Accounts::new(), &tx_log).unwrap();
+        assert!(crate::reconciliation::divergences(&rebuilt, &accounts).is_empty());
     }
 }
@@ -1,9 +1,18 @@
-/// CLI Commands
+// CLI Commands
 
 pub const HELP: &str = "help";
 pub const DEPOSIT: &str = "deposit";
 pub const WITHDRAW: &str = "withdraw";
+pub const DEPOSIT_UNITS: &str = "depositunits";
+pub const WITHDRAW_UNITS: &str = "withdrawunits";
 pub const SEND: &str = "send";
+pub const CONVERT: &str = "convert";
+pub const CLOSE: &str = "close";
+pub const STATUS: &str = "status";
+pub const LIMITS: &str = "limits";
+pub const MARGIN: &str = "margin";
+pub const POSITIONS: &str = "positions";
+pub const STATEMENT: &str = "statement";
 pub const PRINT: &str = "print";
 pub const LEDGER: &str = "ledger";
 pub const TX_LOG: &str = "txlog";
@@ -12,9 +21,17 @@ pub const CLIENT: &str = "client";
 pub const ORDER: &str = "order";
 pub const ORDER_BOOK: &str = "orderbook";
 pub const ORDER_BOOK_BY_PRICE: &str = "orderbookbyprice";
+pub const OCO: &str = "oco";
+pub const BRACKET: &str = "bracket";
+pub const ORDER_GROUP: &str = "group";
+pub const ORDER_GROUPS: &str = "groups";
+pub const CANCEL_ORDER_GROUP: &str = "cancelgroup";
+pub const CANCEL_ALL: &str = "cancelall";
+pub const RESET: &str = "reset";
+pub const VERIFY: &str = "verify";
 pub const QUIT: &str = "quit";
 
-/// Various CLI constants
+// Various CLI constants
 
 pub const PROMPT: &str = "> ";
 pub const SEPARATOR: &str = "--";
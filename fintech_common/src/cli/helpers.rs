@@ -1,9 +1,13 @@
 //! Helper functions that are common to CLI apps
 
 use crate::cli::constants::*;
-use crate::errors::SIGNER_NAME_NOT_VALID_MSG;
+use crate::errors::{AccountingError, SIGNER_NAME_NOT_VALID_MSG};
+use crate::fx::validate_currency;
+use crate::trading_platform::PlatformArchive;
+use crate::types::{Money, Side};
 use crate::validation;
-use std::io::{stdin, stdout, Write};
+use std::fs::File;
+use std::io::{self, stdin, stdout, BufWriter, Write};
 
 /// **Contains full variants of all existing commands.**
 ///
@@ -11,8 +15,9 @@ use std::io::{stdin, stdout, Write};
 /// so that we don't forget to include a newly-added command to help.
 fn help_contents_full() -> String {
     let msg = format!(
-        "{HELP} {DEPOSIT} {WITHDRAW} {SEND} {PRINT} {LEDGER} {TX_LOG} {ACCOUNTS} \
-         {CLIENT} {ORDER} {ORDER_BOOK} {ORDER_BOOK_BY_PRICE} {QUIT}"
+        "{HELP} {DEPOSIT} {WITHDRAW} {DEPOSIT_UNITS} {WITHDRAW_UNITS} {SEND} {CONVERT} {CLOSE} {STATUS} {LIMITS} {MARGIN} {POSITIONS} {STATEMENT} {PRINT} {LEDGER} {TX_LOG} {ACCOUNTS} \
+         {CLIENT} {ORDER} {ORDER_BOOK} {ORDER_BOOK_BY_PRICE} {OCO} {BRACKET} \
+         {ORDER_GROUP} {ORDER_GROUPS} {CANCEL_ORDER_GROUP} {CANCEL_ALL} {RESET} {VERIFY} {QUIT}"
     );
     msg
 }
@@ -22,7 +27,7 @@ fn help_contents_full() -> String {
 /// Wrapped by `help()` so we can unit-test the contents,
 /// so that we don't forget to include a newly-added command to help.
 fn help_contents_short() -> String {
-    "h d w s p l t a c o ob obp q".to_string()
+    "h d w du wu s cv cl st li mg ps sm p l t a c o ob obp oc br g gs cg ca r v q".to_string()
 }
 
 /// **Prints all existing commands in their full and short variants.**
@@ -61,6 +66,31 @@ pub fn read_from_stdin(label: &str) -> Option<String> {
     }
 }
 
+/// **Asks the user to confirm an action, by typing "yes"**
+///
+/// Any other answer, including an empty one, declines it.
+pub fn confirm(question: &str) -> bool {
+    read_from_stdin(&format!("{question} Type \"yes\" to confirm: "))
+        .is_some_and(|answer| is_confirmation(&answer))
+}
+
+/// Whether an answer to [`confirm`] confirms the action
+fn is_confirmation(answer: &str) -> bool {
+    answer.trim() == "yes"
+}
+
+/// **Writes the archives of a platform reset to a file, as pretty-printed JSON**
+///
+/// An existing file is overwritten.
+///
+/// # Errors
+/// - The file can't be created or written to.
+pub fn save_archives(path: &str, archives: &[PlatformArchive]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, archives)?;
+    writer.flush()
+}
+
 /// **Basic input validation for a signer's name**
 ///
 /// Checks for:
@@ -78,6 +108,87 @@ pub fn is_valid_name(signer: &str) -> bool {
     }
 }
 
+/// **Splits the words of a command into a signer's name and an optional side at the end**
+///
+/// The side is recognized as "buy"/"bid" or "sell"/"ask"; any other last word
+/// is considered a part of the signer's name.
+pub fn signer_and_optional_side(words: &[&str]) -> (String, Option<Side>) {
+    let (name_words, side) = match words.split_last() {
+        Some((&("buy" | "bid"), rest)) if !rest.is_empty() => (rest, Some(Side::Buy)),
+        Some((&("sell" | "ask"), rest)) if !rest.is_empty() => (rest, Some(Side::Sell)),
+        _ => (words, None),
+    };
+
+    let signer = name_words.join(" ");
+    let signer = signer
+        .trim_matches(|c| c == '\'' || c == '\"')
+        .trim()
+        .to_string();
+
+    (signer, side)
+}
+
+/// **Splits the words of a close command into a signer's name and an optional recipient's name**
+///
+/// The recipient, who gets the closed account's funds and units, follows the [`SEPARATOR`].
+/// A status command is split in the same way, with the status following the separator,
+/// and so is a margin command, with its action following it,
+/// a positions command, with its mark price following it,
+/// and a statement command, with its filter following it.
+pub fn signer_and_optional_recipient(words: &[&str]) -> (String, Option<String>) {
+    let unquote = |words: &[&str]| {
+        words
+            .join(" ")
+            .trim_matches(|c| c == '\'' || c == '\"')
+            .trim()
+            .to_string()
+    };
+
+    match words.iter().position(|&word| word == SEPARATOR) {
+        Some(pos) => (unquote(&words[..pos]), Some(unquote(&words[pos + 1..]))),
+        None => (unquote(words), None),
+    }
+}
+
+/// **Splits the words of a send command into the transfer and an optional memo**
+///
+/// The memo follows a second [`SEPARATOR`], the first one coming before the recipient's name.
+pub fn words_and_optional_memo<'a, 'b>(words: &'b [&'a str]) -> (&'b [&'a str], Option<String>) {
+    match words
+        .iter()
+        .enumerate()
+        .filter(|(_, &word)| word == SEPARATOR)
+        .nth(1)
+    {
+        Some((pos, _)) => {
+            let memo = words[pos + 1..].join(" ");
+            let memo = memo.trim_matches(|c| c == '\'' || c == '\"').trim();
+            (&words[..pos], (!memo.is_empty()).then(|| memo.to_string()))
+        }
+        None => (words, None),
+    }
+}
+
+/// **Splits the words of a deposit, withdraw or send command into the rest and an optional currency**
+///
+/// The currency's code, such as "EUR", follows the amount; without it,
+/// the amount is in the platform's currency.
+pub fn words_and_optional_currency<'a, 'b>(
+    words: &'b [&'a str],
+) -> (&'b [&'a str], Option<String>) {
+    match words.split_last() {
+        Some((currency, rest))
+            if validate_currency(currency).is_ok()
+                && rest
+                    .last()
+                    .is_some_and(|amount| amount.parse::<Money>().is_ok()) =>
+        {
+            (rest, Some(currency.to_string()))
+        }
+        _ => (words, None),
+    }
+}
+
 /// Prints an error message about not being able to parse
 /// a string into an integer, so that our users can get a
 /// more informative message than the provided generic message
@@ -87,20 +198,32 @@ pub fn is_valid_name(signer: &str) -> bool {
 /// This function can be converted into a macro.
 pub fn cannot_parse_number(word: &str) {
     eprintln!(
-        "[ERROR] Only non-negative integer numbers are allowed as the amount; you provided '{}'.",
+        "[ERROR] Only non-negative decimal numbers are allowed; you provided '{}'.",
         word
     );
 }
 
+/// **Prints an error's code and message**
+///
+/// As in: `[ERROR] ACCOUNT_NOT_FOUND: The account "Bob" doesn't exist.`
+pub fn print_error(err: &AccountingError) {
+    eprintln!("[ERROR] {}: {}", err.code(), err);
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{help_contents_full, help_contents_short, is_valid_name};
+    use super::{
+        help_contents_full, help_contents_short, is_confirmation, is_valid_name,
+        signer_and_optional_recipient, signer_and_optional_side, words_and_optional_currency,
+        words_and_optional_memo,
+    };
     use crate::cli::constants::SEPARATOR;
+    use crate::types::Side;
 
     #[test]
     fn test_help_contents() {
-        let expected = "help deposit withdraw send print ledger txlog accounts \
-        client order orderbook orderbookbyprice quit"
+        let expected = "help deposit withdraw depositunits withdrawunits send convert close status limits margin positions statement print ledger txlog accounts \
+        client order orderbook orderbookbyprice oco bracket group groups cancelgroup cancelall reset verify quit"
             .trim()
             .to_string();
         assert_eq!(help_contents_full(), expected);
@@ -108,7 +231,9 @@ mod tests {
 
     #[test]
     fn test_help_contents_short() {
-        let expected = "h d w s p l t a c o ob obp q".to_string();
+        let expected =
+            "h d w du wu s cv cl st li mg ps sm p l t a c o ob obp oc br g gs cg ca r v q"
+                .to_string();
         assert_eq!(help_contents_short(), expected);
     }
 
@@ -118,6 +243,43 @@ mod tests {
         assert_eq!(SEPARATOR, expected);
     }
 
+    #[test]
+    fn test_words_and_optional_memo() {
+        let words = ["send", "Ana", SEPARATOR, "Ivan", "10"];
+        assert_eq!((&words[..], None), words_and_optional_memo(&words));
+
+        let words = [
+            "send", "Ana", SEPARATOR, "Ivan", "10", SEPARATOR, "'For", "the", "books'",
+        ];
+        assert_eq!(
+            (&words[..5], Some("For the books".to_string())),
+            words_and_optional_memo(&words)
+        );
+    }
+
+    #[test]
+    fn test_words_and_optional_currency() {
+        let words = ["deposit", "Ana", "10", "EUR"];
+        assert_eq!(
+            (&words[..3], Some("EUR".to_string())),
+            words_and_optional_currency(&words)
+        );
+
+        // A name that ends like a currency isn't one without an amount before it.
+        let words = ["deposit", "Ana", "BOB", "10"];
+        assert_eq!((&words[..], None), words_and_optional_currency(&words));
+        let words = ["deposit", "Ana", "10", "eur"];
+        assert_eq!((&words[..], None), words_and_optional_currency(&words));
+    }
+
+    #[test]
+    fn test_is_confirmation() {
+        assert!(is_confirmation("yes\n"));
+        assert!(!is_confirmation("y"));
+        assert!(!is_confirmation("no"));
+        assert!(!is_confirmation("Yes, but no"));
+    }
+
     #[test]
     fn test_valid_name_passes() {
         assert!(is_valid_name("Ivan"));
@@ -127,4 +289,32 @@ mod tests {
     fn test_empty_name_fails() {
         assert!(!is_valid_name(""));
     }
+
+    #[test]
+    fn test_signer_and_optional_side() {
+        assert_eq!(
+            ("Ivan B".to_string(), Some(Side::Buy)),
+            signer_and_optional_side(&["'Ivan", "B'", "buy"])
+        );
+        assert_eq!(
+            ("Ivan B".to_string(), None),
+            signer_and_optional_side(&["Ivan", "B"])
+        );
+        assert_eq!(
+            ("sell".to_string(), None),
+            signer_and_optional_side(&["sell"])
+        );
+    }
+
+    #[test]
+    fn test_signer_and_optional_recipient() {
+        assert_eq!(
+            ("Ivan B".to_string(), Some("Ana".to_string())),
+            signer_and_optional_recipient(&["'Ivan", "B'", SEPARATOR, "\"Ana\""])
+        );
+        assert_eq!(
+            ("Ivan B".to_string(), None),
+            signer_and_optional_recipient(&["Ivan", "B"])
+        );
+    }
 }
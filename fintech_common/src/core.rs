@@ -1,4 +1,5 @@
+pub mod fixed_point;
 mod matching;
 pub mod types;
 
-pub use matching::MatchingEngine;
+pub use matching::{ExecutionPriceRule, MatchingEngine, Rollback};
@@ -0,0 +1,1112 @@
+//! Fixed-point decimal newtypes: [`Price`], [`Quantity`], [`Money`] and [`Amount`]
+//!
+//! Each value is an unsigned integer number of units together with a decimal scale,
+//! i.e., the number of digits after the decimal point, so `12.35` is `1235` units at scale `2`.
+//! Values are self-describing, so values with different scales can be compared and combined;
+//! `12.3` and `12.30` are equal.
+//!
+//! All arithmetic is checked and returns `None` instead of overflowing or losing precision.
+//!
+//! Values are serialized as decimal strings, such as `"12.35"`, so that no precision is lost
+//! in JSON, and are deserialized either from such strings or from JSON numbers.
+//!
+//! How many decimals the prices and quantities of an instrument may have
+//! is configured through an [`InstrumentSpec`]. Amounts of cash that are worked out from them,
+//! such as the value of a trade, are rounded to the scale of their currency with an explicit
+//! [`Rounding`], so that balances keep the same number of decimals, however much they trade.
+
+use crate::errors::{AccountingError, OrderError};
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// The highest supported scale; `10^MAX_SCALE` still fits into a `u64`.
+pub const MAX_SCALE: u8 = 18;
+
+/// `10^exp` for `exp <= MAX_SCALE`
+fn pow10(exp: u8) -> u64 {
+    10_u64.pow(exp as u32)
+}
+
+/// Brings `units` at `from` scale to the higher `to` scale, if that doesn't overflow.
+fn upscale(units: u64, from: u8, to: u8) -> Option<u64> {
+    debug_assert!(from <= to);
+    units.checked_mul(pow10(to - from))
+}
+
+/// **How a value that has more decimals than a scale allows is brought to it**
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Rounding {
+    /// Towards zero; the extra decimals are dropped.
+    Down,
+    /// Away from zero, unless the extra decimals are all zeros
+    Up,
+    /// To the nearest value, and to the even one of the two when it's halfway between them
+    HalfEven,
+}
+
+impl Rounding {
+    /// Divides `units` by `10^exp`, rounding the quotient; `exp` is at most `2 * MAX_SCALE`.
+    fn divide(self, units: u128, exp: u8) -> u128 {
+        let divisor = 10_u128.pow(exp as u32);
+        let (quotient, remainder) = (units / divisor, units % divisor);
+        let up = match self {
+            Rounding::Down => false,
+            Rounding::Up => remainder > 0,
+            Rounding::HalfEven => match remainder.cmp(&(divisor - remainder)) {
+                Ordering::Less => false,
+                Ordering::Equal => quotient % 2 == 1,
+                Ordering::Greater => true,
+            },
+        };
+        quotient + up as u128
+    }
+}
+
+/// Brings `units` at the `exact` scale to at most `scale` decimals, rounding them as the `rounding` says.
+fn round_units(units: u128, exact: u8, scale: u8, rounding: Rounding) -> (u128, u8) {
+    match exact > scale {
+        true => (rounding.divide(units, exact - scale), scale),
+        false => (units, exact),
+    }
+}
+
+/// Compares two fixed-point values of possibly different scales, exactly.
+fn compare(a_units: u64, a_scale: u8, b_units: u64, b_scale: u8) -> Ordering {
+    let scale = a_scale.max(b_scale);
+    let a = a_units as u128 * pow10(scale - a_scale) as u128;
+    let b = b_units as u128 * pow10(scale - b_scale) as u128;
+    a.cmp(&b)
+}
+
+/// Parses a non-negative decimal string, such as `"12"` or `"12.35"`, into units and a scale.
+fn parse(s: &str) -> Result<(u64, u8), String> {
+    let invalid = || format!("\"{s}\" is not a non-negative decimal number");
+
+    let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+    if whole.is_empty()
+        || !whole.bytes().all(|b| b.is_ascii_digit())
+        || !fraction.bytes().all(|b| b.is_ascii_digit())
+        || (s.contains('.') && fraction.is_empty())
+    {
+        return Err(invalid());
+    }
+    if fraction.len() > MAX_SCALE as usize {
+        return Err(format!(
+            "\"{s}\" has more than {MAX_SCALE} digits after the decimal point"
+        ));
+    }
+
+    let scale = fraction.len() as u8;
+    let too_large = || format!("\"{s}\" is too large");
+    let whole = whole.parse::<u64>().map_err(|_| too_large())?;
+    let fraction = if fraction.is_empty() {
+        0
+    } else {
+        fraction.parse::<u64>().map_err(|_| invalid())?
+    };
+    let units = upscale(whole, 0, scale)
+        .and_then(|units| units.checked_add(fraction))
+        .ok_or_else(too_large)?;
+
+    Ok((units, scale))
+}
+
+/// Defines a fixed-point newtype with its constructors, checked arithmetic,
+/// comparison, formatting, parsing and serde representation.
+macro_rules! fixed_point {
+    ($(#[$doc:meta])* $name:ident) => {
+        $(#[$doc])*
+        #[derive(Clone, Copy)]
+        pub struct $name {
+            units: u64,
+            scale: u8,
+        }
+
+        impl $name {
+            /// Zero, at scale `0`
+            pub const ZERO: $name = $name { units: 0, scale: 0 };
+
+            /// The highest value, at scale `0`
+            pub const MAX: $name = $name {
+                units: u64::MAX,
+                scale: 0,
+            };
+
+            /// Creates a value of `units` at the given `scale`, i.e., `units / 10^scale`.
+            ///
+            /// # Panics
+            /// Panics if `scale` is greater than [`MAX_SCALE`].
+            pub fn new(units: u64, scale: u8) -> Self {
+                assert!(scale <= MAX_SCALE, "Scale must not exceed {}.", MAX_SCALE);
+                Self { units, scale }
+            }
+
+            /// The number of units at the value's scale
+            pub fn units(&self) -> u64 {
+                self.units
+            }
+
+            /// The number of digits after the decimal point
+            pub fn scale(&self) -> u8 {
+                self.scale
+            }
+
+            pub fn is_zero(&self) -> bool {
+                self.units == 0
+            }
+
+            /// Converts the value to another scale, if that can be done exactly.
+            ///
+            /// Returns `None` if the value has more significant decimals than `scale`
+            /// allows, or if it doesn't fit at the higher scale.
+            pub fn rescale(self, scale: u8) -> Option<Self> {
+                if scale > MAX_SCALE {
+                    return None;
+                }
+                if scale >= self.scale {
+                    upscale(self.units, self.scale, scale).map(|units| Self { units, scale })
+                } else {
+                    let divisor = pow10(self.scale - scale);
+                    (self.units % divisor == 0).then(|| Self {
+                        units: self.units / divisor,
+                        scale,
+                    })
+                }
+            }
+
+            /// Brings the value to at most `scale` decimals, rounding it as the `rounding` says;
+            /// a value that doesn't have more decimals is returned as it is.
+            pub fn round(self, scale: u8, rounding: Rounding) -> Self {
+                let (units, scale) = round_units(self.units as u128, self.scale, scale, rounding);
+                // Dropping at least one decimal leaves room for rounding up.
+                Self {
+                    units: units as u64,
+                    scale,
+                }
+            }
+
+            /// Brings both values to the higher of their two scales.
+            fn align(self, other: Self) -> Option<(u64, u64, u8)> {
+                let scale = self.scale.max(other.scale);
+                Some((
+                    upscale(self.units, self.scale, scale)?,
+                    upscale(other.units, other.scale, scale)?,
+                    scale,
+                ))
+            }
+
+            /// Adds two values; the result has the higher of their two scales.
+            pub fn checked_add(self, other: Self) -> Option<Self> {
+                let (a, b, scale) = self.align(other)?;
+                a.checked_add(b).map(|units| Self { units, scale })
+            }
+
+            /// Subtracts two values; the result has the higher of their two scales.
+            pub fn checked_sub(self, other: Self) -> Option<Self> {
+                let (a, b, scale) = self.align(other)?;
+                a.checked_sub(b).map(|units| Self { units, scale })
+            }
+
+            /// Subtracts two values, stopping at zero.
+            ///
+            /// Meant for values of the same scale, where the result can't overflow.
+            pub fn saturating_sub(self, other: Self) -> Self {
+                self.checked_sub(other).unwrap_or(Self {
+                    units: 0,
+                    scale: self.scale.max(other.scale),
+                })
+            }
+
+            /// Adds two values, stopping at the maximum.
+            ///
+            /// Meant for values of the same scale, where the result can't overflow.
+            pub fn saturating_add(self, other: Self) -> Self {
+                self.checked_add(other).unwrap_or(Self {
+                    units: u64::MAX,
+                    scale: self.scale.max(other.scale),
+                })
+            }
+
+            /// Multiplies the value by an integer factor.
+            pub fn checked_mul_int(self, factor: u64) -> Option<Self> {
+                self.units.checked_mul(factor).map(|units| Self {
+                    units,
+                    scale: self.scale,
+                })
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self::ZERO
+            }
+        }
+
+        impl From<u64> for $name {
+            /// A whole number, at scale `0`
+            fn from(value: u64) -> Self {
+                Self {
+                    units: value,
+                    scale: 0,
+                }
+            }
+        }
+
+        impl PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                self.cmp(other) == Ordering::Equal
+            }
+        }
+
+        impl Eq for $name {}
+
+        impl PartialOrd for $name {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for $name {
+            fn cmp(&self, other: &Self) -> Ordering {
+                compare(self.units, self.scale, other.units, other.scale)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                if self.scale == 0 {
+                    write!(f, "{}", self.units)
+                } else {
+                    let divisor = pow10(self.scale);
+                    write!(
+                        f,
+                        "{}.{:0width$}",
+                        self.units / divisor,
+                        self.units % divisor,
+                        width = self.scale as usize
+                    )
+                }
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}({})", stringify!($name), self)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = String;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                parse(s.trim()).map(|(units, scale)| Self { units, scale })
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                struct FixedPointVisitor;
+
+                impl<'de> Visitor<'de> for FixedPointVisitor {
+                    type Value = $name;
+
+                    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                        f.write_str("a non-negative decimal number or a string containing one")
+                    }
+
+                    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                        v.parse().map_err(E::custom)
+                    }
+
+                    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                        Ok($name::from(v))
+                    }
+
+                    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                        u64::try_from(v)
+                            .map($name::from)
+                            .map_err(|_| E::custom(format!("{v} is negative")))
+                    }
+
+                    /// The shortest representation that round-trips, e.g., `12.35`,
+                    /// is exactly what the sender meant.
+                    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+                        if !v.is_finite() {
+                            return Err(E::custom(format!("{v} is not a finite number")));
+                        }
+                        v.to_string().parse().map_err(E::custom)
+                    }
+                }
+
+                deserializer.deserialize_any(FixedPointVisitor)
+            }
+        }
+    };
+}
+
+fixed_point!(
+    /// **A price per unit of an instrument**
+    Price
+);
+
+fixed_point!(
+    /// **A number of units of an instrument**
+    Quantity
+);
+
+fixed_point!(
+    /// **An amount of cash**
+    Money
+);
+
+/// **An amount of any asset, cash or units of an instrument, in the journal**
+///
+/// The balances of the journal's system accounts add up the balances of many accounts,
+/// so an amount has 128 bits of units, unlike the other types. Otherwise, it works the same way.
+#[derive(Clone, Copy)]
+pub struct Amount {
+    units: u128,
+    scale: u8,
+}
+
+impl Amount {
+    /// Zero, at scale `0`
+    pub const ZERO: Amount = Amount { units: 0, scale: 0 };
+
+    pub fn is_zero(&self) -> bool {
+        self.units == 0
+    }
+
+    /// Brings both values to the higher of their two scales.
+    fn align(self, other: Self) -> Option<(u128, u128, u8)> {
+        let scale = self.scale.max(other.scale);
+        let upscale = |units: u128, from: u8| units.checked_mul(10_u128.pow((scale - from) as u32));
+        Some((
+            upscale(self.units, self.scale)?,
+            upscale(other.units, other.scale)?,
+            scale,
+        ))
+    }
+
+    /// Adds two values; the result has the higher of their two scales.
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        let (a, b, scale) = self.align(other)?;
+        a.checked_add(b).map(|units| Self { units, scale })
+    }
+
+    /// Subtracts two values; the result has the higher of their two scales.
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        let (a, b, scale) = self.align(other)?;
+        a.checked_sub(b).map(|units| Self { units, scale })
+    }
+
+    /// Subtracts two values, stopping at zero.
+    pub fn saturating_sub(self, other: Self) -> Self {
+        self.checked_sub(other).unwrap_or(Self {
+            units: 0,
+            scale: self.scale.max(other.scale),
+        })
+    }
+}
+
+impl Default for Amount {
+    fn default() -> Self {
+        Self::ZERO
+    }
+}
+
+impl From<u64> for Amount {
+    /// A whole number, at scale `0`
+    fn from(value: u64) -> Self {
+        Self {
+            units: value as u128,
+            scale: 0,
+        }
+    }
+}
+
+impl From<Money> for Amount {
+    fn from(money: Money) -> Self {
+        Self {
+            units: money.units as u128,
+            scale: money.scale,
+        }
+    }
+}
+
+impl From<Quantity> for Amount {
+    fn from(quantity: Quantity) -> Self {
+        Self {
+            units: quantity.units as u128,
+            scale: quantity.scale,
+        }
+    }
+}
+
+impl PartialEq for Amount {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Amount {}
+
+impl PartialOrd for Amount {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Amount {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match self.align(*other) {
+            Some((a, b, _)) => a.cmp(&b),
+            // Only the value with the lower scale is brought up, and it didn't fit,
+            // so it's the greater one.
+            None if self.scale < other.scale => Ordering::Greater,
+            None => Ordering::Less,
+        }
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.scale == 0 {
+            write!(f, "{}", self.units)
+        } else {
+            let divisor = 10_u128.pow(self.scale as u32);
+            write!(
+                f,
+                "{}.{:0width$}",
+                self.units / divisor,
+                self.units % divisor,
+                width = self.scale as usize
+            )
+        }
+    }
+}
+
+impl fmt::Debug for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Amount({})", self)
+    }
+}
+
+impl FromStr for Amount {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || format!("\"{s}\" is not a non-negative decimal number");
+
+        let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+        if whole.is_empty()
+            || !whole.bytes().all(|b| b.is_ascii_digit())
+            || !fraction.bytes().all(|b| b.is_ascii_digit())
+            || (s.contains('.') && fraction.is_empty())
+            || fraction.len() > MAX_SCALE as usize
+        {
+            return Err(invalid());
+        }
+
+        let units = format!("{whole}{fraction}")
+            .parse::<u128>()
+            .map_err(|_| format!("\"{s}\" is too large"))?;
+        Ok(Self {
+            units,
+            scale: fraction.len() as u8,
+        })
+    }
+}
+
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    /// Amounts are only ever serialized as strings.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl Price {
+    /// The price halfway between two prices.
+    ///
+    /// One more decimal is used when the two prices are an odd number of units apart,
+    /// unless the scale is already at [`MAX_SCALE`], in which case the result is rounded down.
+    pub fn midpoint(self, other: Price) -> Price {
+        let scale = self.scale.max(other.scale);
+        let sum = self.units as u128 * pow10(scale - self.scale) as u128
+            + other.units as u128 * pow10(scale - other.scale) as u128;
+
+        let (mut units, mut scale) = if sum % 2 == 1 && scale < MAX_SCALE {
+            (sum * 5, scale + 1)
+        } else {
+            (sum / 2, scale)
+        };
+        // Values that don't fit at this scale lose their last decimals.
+        while units > u64::MAX as u128 && scale > 0 {
+            units /= 10;
+            scale -= 1;
+        }
+        Price::new(u64::try_from(units).unwrap_or(u64::MAX), scale)
+    }
+
+    /// The exact value of `quantity` units at this price.
+    ///
+    /// The result's scale is the sum of the two scales, so no precision is lost. That's meant
+    /// for valuations, which aren't booked; what is booked is the [`notional`](Self::notional).
+    pub fn checked_notional(self, quantity: Quantity) -> Option<Money> {
+        let scale = self.scale + quantity.scale;
+        if scale > MAX_SCALE {
+            return None;
+        }
+        self.units
+            .checked_mul(quantity.units)
+            .map(|units| Money::new(units, scale))
+    }
+
+    /// The total value of `quantity` units at this price, with at most `scale` decimals,
+    /// the currency's, rounded as the `rounding` says.
+    ///
+    /// # Errors
+    /// - The value doesn't fit into [`Money`], `AccountingError::ArithmeticOverflow`
+    pub fn notional(
+        self,
+        quantity: Quantity,
+        scale: u8,
+        rounding: Rounding,
+    ) -> Result<Money, AccountingError> {
+        let (units, scale) = round_units(
+            self.units as u128 * quantity.units as u128,
+            self.scale + quantity.scale,
+            scale.min(MAX_SCALE),
+            rounding,
+        );
+        u64::try_from(units)
+            .map(|units| Money::new(units, scale))
+            .map_err(|_| {
+                AccountingError::ArithmeticOverflow(format!(
+                    "The value of {} at {} is too large.",
+                    quantity, self
+                ))
+            })
+    }
+}
+
+impl Money {
+    /// The given number of basis points (1/100th of a percent) of this amount,
+    /// with at most `scale` decimals, the currency's, rounded as the `rounding` says.
+    ///
+    /// More than 10 000 basis points saturate at `u64::MAX` units.
+    pub fn basis_points(self, bps: u32, scale: u8, rounding: Rounding) -> Money {
+        let (mut units, mut scale) = round_units(
+            self.units as u128 * bps as u128,
+            self.scale + 4,
+            scale.min(MAX_SCALE),
+            rounding,
+        );
+        // Drop the trailing zeros that were introduced by the additional decimals.
+        while scale > self.scale && units.is_multiple_of(10) {
+            units /= 10;
+            scale -= 1;
+        }
+        Money::new(u64::try_from(units).unwrap_or(u64::MAX), scale)
+    }
+
+    /// The part of this amount that `part` of the `whole` units account for,
+    /// such as the cost of some of the units of a position; rounded down.
+    ///
+    /// The result has the same scale as this amount. Zero units account for nothing,
+    /// and a `part` that isn't smaller than the `whole` accounts for all of it.
+    pub fn pro_rata(self, part: Quantity, whole: Quantity) -> Money {
+        if whole.is_zero() {
+            return Money::ZERO;
+        }
+        if part >= whole {
+            return self;
+        }
+
+        let scale = part.scale.max(whole.scale);
+        let mut part = part.units as u128 * pow10(scale - part.scale) as u128;
+        let mut whole = whole.units as u128 * pow10(scale - whole.scale) as u128;
+        // The product below fits, as long as the whole does; the last decimals of both go first.
+        while whole > u64::MAX as u128 {
+            part /= 10;
+            whole /= 10;
+        }
+        // Smaller than this amount, as the part is smaller than the whole.
+        Money::new((self.units as u128 * part / whole) as u64, self.scale)
+    }
+
+    /// This amount converted at the exchange `rate`, the price of one of its units in another currency,
+    /// with at most `scale` decimals, the other currency's, rounded as the `rounding` says.
+    ///
+    /// The trailing zeros that the rate's decimals add are dropped again.
+    /// Returns `None` if the result doesn't fit.
+    pub fn checked_convert(self, rate: Price, scale: u8, rounding: Rounding) -> Option<Money> {
+        let (mut units, mut scale) = round_units(
+            self.units as u128 * rate.units as u128,
+            self.scale + rate.scale,
+            scale.min(MAX_SCALE),
+            rounding,
+        );
+        while scale > self.scale && units.is_multiple_of(10) {
+            units /= 10;
+            scale -= 1;
+        }
+        u64::try_from(units)
+            .ok()
+            .map(|units| Money::new(units, scale))
+    }
+
+    /// The price of each of the `units`, if this is what all of them are worth.
+    ///
+    /// Four decimals are added to the scale, as far as [`MAX_SCALE`] allows,
+    /// and the result is rounded down; trailing zeros are dropped again.
+    /// Returns `None` for zero units, or if the price doesn't fit.
+    pub fn checked_per_unit(self, units: Quantity) -> Option<Price> {
+        if units.is_zero() {
+            return None;
+        }
+
+        let mut scale = (self.scale + 4).min(MAX_SCALE);
+        let exp = (scale - self.scale + units.scale) as u32;
+        let mut price =
+            (self.units as u128).checked_mul(10_u128.checked_pow(exp)?)? / units.units as u128;
+        let natural_scale = self.scale.saturating_sub(units.scale);
+        while scale > natural_scale && price.is_multiple_of(10) {
+            price /= 10;
+            scale -= 1;
+        }
+        u64::try_from(price)
+            .ok()
+            .map(|units| Price::new(units, scale))
+    }
+}
+
+/// **The decimal scales of an instrument**
+///
+/// Prices and quantities of orders for the instrument may have at most
+/// `price_scale` and `quantity_scale` digits after the decimal point, respectively.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct InstrumentSpec {
+    pub price_scale: u8,
+    pub quantity_scale: u8,
+}
+
+impl InstrumentSpec {
+    pub fn new(price_scale: u8, quantity_scale: u8) -> Self {
+        Self {
+            price_scale,
+            quantity_scale,
+        }
+    }
+
+    /// Brings a price to the instrument's price scale.
+    ///
+    /// # Errors
+    /// - The price has more decimals than allowed, `OrderError::Invalid`
+    pub fn normalize_price(&self, price: Price) -> Result<Price, AccountingError> {
+        price.rescale(self.price_scale).ok_or_else(|| {
+            AccountingError::Order(OrderError::Invalid(format!(
+                "The price {} has more than {} decimals.",
+                price, self.price_scale
+            )))
+        })
+    }
+
+    /// Brings a quantity to the instrument's quantity scale.
+    ///
+    /// # Errors
+    /// - The quantity has more decimals than allowed, `OrderError::Invalid`
+    pub fn normalize_quantity(&self, quantity: Quantity) -> Result<Quantity, AccountingError> {
+        quantity.rescale(self.quantity_scale).ok_or_else(|| {
+            AccountingError::Order(OrderError::Invalid(format!(
+                "The quantity {} has more than {} decimals.",
+                quantity, self.quantity_scale
+            )))
+        })
+    }
+}
+
+impl FromStr for InstrumentSpec {
+    type Err = String;
+
+    /// Parses `<price scale>:<quantity scale>`, such as `"2:0"`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid instrument scales: \"{s}\"; expected \"<price scale>:<quantity scale>\", \
+                 each at most {MAX_SCALE}"
+            )
+        };
+
+        let (price_scale, quantity_scale) = s.trim().split_once(':').ok_or_else(invalid)?;
+        let price_scale = price_scale.parse::<u8>().map_err(|_| invalid())?;
+        let quantity_scale = quantity_scale.parse::<u8>().map_err(|_| invalid())?;
+        if price_scale > MAX_SCALE || quantity_scale > MAX_SCALE {
+            return Err(invalid());
+        }
+
+        Ok(Self::new(price_scale, quantity_scale))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_display() {
+        assert_eq!("12.35", "12.35".parse::<Price>().unwrap().to_string());
+        assert_eq!("0.05", "0.05".parse::<Price>().unwrap().to_string());
+        assert_eq!("12", "12".parse::<Quantity>().unwrap().to_string());
+        assert_eq!(Money::new(1235, 2), "12.35".parse().unwrap());
+
+        for bad in [
+            "",
+            "-1",
+            "1.",
+            ".5",
+            "1.2.3",
+            "1e5",
+            "abc",
+            "18446744073709551616",
+        ] {
+            assert!(bad.parse::<Money>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn amount_adds_beyond_u64_and_round_trips() {
+        let max = Amount::from(Money::new(u64::MAX, 0));
+        let sum = max.checked_add(max).unwrap();
+        assert_eq!("36893488147419103230", sum.to_string());
+        assert!(sum > max);
+        assert_eq!(Some(max), sum.checked_sub(max));
+
+        let cents = Amount::from(Money::new(1235, 2));
+        assert_eq!(Amount::from(Quantity::new(12350, 3)), cents);
+        assert!(max > cents);
+        assert_eq!(
+            cents,
+            serde_json::from_str(&serde_json::to_string(&cents).unwrap()).unwrap()
+        );
+        assert_eq!(sum, sum.to_string().parse().unwrap());
+    }
+
+    #[test]
+    fn equality_and_ordering_across_scales() {
+        assert_eq!(Price::new(1230, 2), Price::new(123, 1));
+        assert!(Price::new(1235, 2) > Price::from(12));
+        assert!(Price::new(1199, 2) < Price::from(12));
+        assert_eq!(Price::ZERO, Price::new(0, 5));
+    }
+
+    #[test]
+    fn checked_arithmetic() {
+        let a = Money::new(1235, 2);
+        let b = Money::new(5, 1);
+
+        assert_eq!(Some(Money::new(1285, 2)), a.checked_add(b));
+        assert_eq!(Some(Money::new(1185, 2)), a.checked_sub(b));
+        assert_eq!(None, b.checked_sub(a));
+        assert_eq!(None, Money::from(u64::MAX).checked_add(Money::from(1)));
+        assert_eq!(None, Money::from(u64::MAX).checked_add(Money::new(1, 1)));
+        assert_eq!(Money::ZERO, b.saturating_sub(a));
+    }
+
+    #[test]
+    fn checked_notional_keeps_all_decimals() {
+        let price: Price = "12.35".parse().unwrap();
+        let quantity: Quantity = "0.5".parse().unwrap();
+
+        assert_eq!(
+            Some("6.175".parse::<Money>().unwrap()),
+            price.checked_notional(quantity)
+        );
+        assert_eq!(
+            None,
+            Price::from(u64::MAX).checked_notional(Quantity::from(2))
+        );
+    }
+
+    #[test]
+    fn notional_is_rounded_to_the_scale() {
+        let price: Price = "100.01".parse().unwrap();
+        let quantity: Quantity = "0.12345678".parse().unwrap();
+
+        let value = price.notional(quantity, 2, Rounding::Down).unwrap();
+        assert_eq!("12.34", value.to_string());
+        assert_eq!(2, value.scale());
+        assert_eq!(
+            "12.35",
+            price
+                .notional(quantity, 2, Rounding::Up)
+                .unwrap()
+                .to_string()
+        );
+        // Fewer decimals than the scale are kept as they are.
+        assert_eq!(
+            "6.175",
+            Price::new(1235, 2)
+                .notional(Quantity::new(5, 1), 4, Rounding::Down)
+                .unwrap()
+                .to_string()
+        );
+        // Values that only fit once they're rounded are fine.
+        assert_eq!(
+            Ok(Money::from(u64::MAX / 10)),
+            Price::new(u64::MAX / 10, 9).notional(
+                Quantity::new(1_000_000_000, 0),
+                0,
+                Rounding::Down
+            )
+        );
+        assert!(Price::from(u64::MAX)
+            .notional(Quantity::from(2), 2, Rounding::Down)
+            .is_err());
+    }
+
+    #[test]
+    fn round_with_every_rounding() {
+        let value = Money::new(12345, 3);
+        assert_eq!("12.34", value.round(2, Rounding::Down).to_string());
+        assert_eq!("12.35", value.round(2, Rounding::Up).to_string());
+        assert_eq!("12.34", value.round(2, Rounding::HalfEven).to_string());
+        assert_eq!(
+            "12.36",
+            Money::new(12355, 3)
+                .round(2, Rounding::HalfEven)
+                .to_string()
+        );
+        assert_eq!(
+            "12.35",
+            Money::new(123451, 4)
+                .round(2, Rounding::HalfEven)
+                .to_string()
+        );
+        assert_eq!("12.345", value.round(4, Rounding::Up).to_string());
+        assert_eq!("13", value.round(0, Rounding::Up).to_string());
+        assert_eq!(
+            Money::new(u64::MAX / 10 + 1, 0),
+            Money::new(u64::MAX, 1).round(0, Rounding::Up)
+        );
+    }
+
+    #[test]
+    fn rescale_is_exact() {
+        assert_eq!(Some(Price::new(1230, 2)), Price::new(123, 1).rescale(2));
+        assert_eq!(Some(Price::new(123, 1)), Price::new(1230, 2).rescale(1));
+        assert_eq!(None, Price::new(1235, 2).rescale(1));
+        assert_eq!(None, Price::from(u64::MAX).rescale(1));
+    }
+
+    #[test]
+    fn serde_string_and_number() {
+        assert_eq!(
+            "\"12.35\"",
+            serde_json::to_string(&Price::new(1235, 2)).unwrap()
+        );
+
+        assert_eq!(
+            Price::new(1235, 2),
+            serde_json::from_str::<Price>("\"12.35\"").unwrap()
+        );
+        assert_eq!(
+            Price::new(1235, 2),
+            serde_json::from_str::<Price>("12.35").unwrap()
+        );
+        assert_eq!(
+            Price::from(12),
+            serde_json::from_str::<Price>("12").unwrap()
+        );
+        assert!(serde_json::from_str::<Price>("-12").is_err());
+        assert!(serde_json::from_str::<Price>("\"twelve\"").is_err());
+    }
+
+    #[test]
+    fn instrument_spec_normalizes() {
+        let spec = InstrumentSpec::new(2, 0);
+
+        assert_eq!(
+            Ok(Price::new(1200, 2)),
+            spec.normalize_price(Price::from(12))
+        );
+        assert_eq!(2, spec.normalize_price(Price::from(12)).unwrap().scale());
+        assert!(spec.normalize_price("12.345".parse().unwrap()).is_err());
+        assert!(spec.normalize_quantity("0.5".parse().unwrap()).is_err());
+        assert_eq!(
+            Ok(Quantity::from(3)),
+            spec.normalize_quantity("3.00".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn instrument_spec_from_str() {
+        assert_eq!(Ok(InstrumentSpec::new(2, 0)), "2:0".parse());
+        assert!("2".parse::<InstrumentSpec>().is_err());
+        assert!("2:x".parse::<InstrumentSpec>().is_err());
+        assert!("19:0".parse::<InstrumentSpec>().is_err());
+    }
+
+    #[test]
+    fn basis_points() {
+        let bps = |value: Money, bps: u32, rounding: Rounding| {
+            value.basis_points(bps, 2, rounding).to_string()
+        };
+        assert_eq!("0.02", bps(Money::from(20), 10, Rounding::Down));
+        assert_eq!("2", bps(Money::from(200), 100, Rounding::Down));
+        assert_eq!("0", bps(Money::from(20), 0, Rounding::Up));
+        assert_eq!(
+            Money::from(u64::MAX),
+            Money::from(u64::MAX).basis_points(10_000, 2, Rounding::Down)
+        );
+
+        // 20 bps of 12.34 is 0.024680, which has more decimals than the scale.
+        let value: Money = "12.34".parse().unwrap();
+        assert_eq!("0.02", bps(value, 20, Rounding::Down));
+        assert_eq!("0.03", bps(value, 20, Rounding::Up));
+        assert_eq!(
+            "0.02468",
+            value.basis_points(20, 6, Rounding::Down).to_string()
+        );
+        assert_eq!(
+            Money::ZERO,
+            Money::new(1, MAX_SCALE).basis_points(1, MAX_SCALE, Rounding::Down)
+        );
+    }
+
+    #[test]
+    fn checked_convert_rounds_and_drops_trailing_zeros() {
+        let rate: Price = "1.08".parse().unwrap();
+        assert_eq!(
+            "108",
+            Money::from(100)
+                .checked_convert(rate, 2, Rounding::Down)
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            "10.584",
+            Money::new(980, 2)
+                .checked_convert(rate, 4, Rounding::Down)
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            "10.58",
+            Money::new(980, 2)
+                .checked_convert(rate, 2, Rounding::Down)
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            "0.50",
+            Money::new(50, 2)
+                .checked_convert(Price::from(1), 2, Rounding::Down)
+                .unwrap()
+                .to_string()
+        );
+        // Into a currency without decimals
+        assert_eq!(
+            "1084",
+            Money::new(100375, 2)
+                .checked_convert(rate, 0, Rounding::Down)
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            None,
+            Money::MAX.checked_convert(Price::from(2), 2, Rounding::Down)
+        );
+    }
+
+    #[test]
+    fn pro_rata_and_per_unit() {
+        assert_eq!(
+            Money::from(30),
+            Money::from(100).pro_rata(3.into(), 10.into())
+        );
+        assert_eq!(
+            "33.33",
+            Money::new(10000, 2)
+                .pro_rata(1.into(), 3.into())
+                .to_string()
+        );
+        assert_eq!(
+            Money::from(100),
+            Money::from(100).pro_rata(11.into(), 10.into())
+        );
+        assert_eq!(Money::ZERO, Money::from(100).pro_rata(1.into(), 0.into()));
+        assert_eq!(
+            Money::from(u64::MAX / 2),
+            Money::from(u64::MAX).pro_rata(Quantity::new(5, 1), Quantity::from(1))
+        );
+
+        assert_eq!(
+            Some(Price::from(10)),
+            Money::from(100).checked_per_unit(10.into())
+        );
+        assert_eq!(
+            "10",
+            Money::from(100)
+                .checked_per_unit(10.into())
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            "33.3333",
+            Money::from(100)
+                .checked_per_unit(3.into())
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            "12.5",
+            Money::new(2500, 2)
+                .checked_per_unit(Quantity::new(20, 1))
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(None, Money::from(100).checked_per_unit(Quantity::ZERO));
+        assert_eq!(
+            None,
+            Money::from(u64::MAX).checked_per_unit(Quantity::new(1, 18))
+        );
+    }
+
+    #[test]
+    fn midpoint() {
+        assert_eq!(Price::from(11), Price::from(10).midpoint(Price::from(12)));
+        assert_eq!(
+            "10.5",
+            Price::from(10).midpoint(Price::from(11)).to_string()
+        );
+        assert_eq!(
+            "10.125",
+            Price::new(1000, 2)
+                .midpoint(Price::new(1025, 2))
+                .to_string()
+        );
+        assert_eq!(
+            Price::from(u64::MAX),
+            Price::from(u64::MAX).midpoint(Price::from(u64::MAX))
+        );
+    }
+}
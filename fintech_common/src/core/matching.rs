@@ -1,6 +1,60 @@
 use crate::errors::AccountingError;
-use crate::types::{Order, PartialOrder, Receipt, Side};
+use crate::history::{HistorySink, RingBufferSink, DEFAULT_HISTORY_CAPACITY};
+use crate::types::{Order, PartialOrder, Price, Quantity, Receipt, Side};
 use std::collections::{BTreeMap, BinaryHeap};
+use std::fmt;
+use std::str::FromStr;
+
+/// **The rule that sets the price at which a match is executed**
+///
+/// A match always happens between a resting order in the order book, the maker,
+/// and an incoming order, the taker, whose limit prices overlap.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ExecutionPriceRule {
+    /// The maker's limit price, as in a lit order book
+    #[default]
+    MakerPrice,
+    /// The taker's limit price
+    TakerPrice,
+    /// Halfway between the two limit prices, as in dark crossing
+    Midpoint,
+}
+
+impl ExecutionPriceRule {
+    /// The price of a match between a maker's and a taker's limit price.
+    pub fn execution_price(&self, maker: Price, taker: Price) -> Price {
+        match self {
+            ExecutionPriceRule::MakerPrice => maker,
+            ExecutionPriceRule::TakerPrice => taker,
+            ExecutionPriceRule::Midpoint => maker.midpoint(taker),
+        }
+    }
+}
+
+impl fmt::Display for ExecutionPriceRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ExecutionPriceRule::MakerPrice => "maker",
+            ExecutionPriceRule::TakerPrice => "taker",
+            ExecutionPriceRule::Midpoint => "midpoint",
+        })
+    }
+}
+
+impl FromStr for ExecutionPriceRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "maker" => Ok(ExecutionPriceRule::MakerPrice),
+            "taker" => Ok(ExecutionPriceRule::TakerPrice),
+            "midpoint" => Ok(ExecutionPriceRule::Midpoint),
+            _ => Err(format!(
+                "Invalid execution price rule: \"{s}\"; expected \"maker\", \"taker\" or \"midpoint\""
+            )),
+        }
+    }
+}
 
 /// **A FIFO matching engine**
 ///
@@ -49,26 +103,54 @@ pub struct MatchingEngine {
     pub ordinal: u64,
     /// The "Ask" or "Sell" side of the order book; ordered by the price first, and then by the ordinal number (FIFO).
     /// Maps the price by which it is sorted ascending first to a priority queue of [`PartialOrder`]s.
-    pub asks: BTreeMap<u64, BinaryHeap<PartialOrder>>,
+    pub asks: BTreeMap<Price, BinaryHeap<PartialOrder>>,
     /// The "Bid" or "Buy" side of the order book; ordered by the price first, and then by the ordinal number (FIFO).
     /// Maps the price by which it is sorted ascending first to a priority queue of [`PartialOrder`]s.
-    pub bids: BTreeMap<u64, BinaryHeap<PartialOrder>>,
+    pub bids: BTreeMap<Price, BinaryHeap<PartialOrder>>,
     /// The history of all previous orders, or receipts, to be more precise,
     /// matched or unmatched, for record keeping.
-    pub history: Vec<Receipt>,
+    /// Where the receipts go, and how many of them are retained, depends on the sink.
+    pub history: Box<dyn HistorySink<Receipt>>,
+    /// The rule that sets the price of every match
+    price_rule: ExecutionPriceRule,
+}
+
+impl Default for MatchingEngine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MatchingEngine {
     /// Creates a new [`MatchingEngine`] with ordinal of 0 and empty sides of the order book.
+    ///
+    /// The history is kept in memory, in a ring buffer of [`DEFAULT_HISTORY_CAPACITY`] receipts.
     pub fn new() -> MatchingEngine {
+        MatchingEngine::with_history(Box::new(RingBufferSink::new(DEFAULT_HISTORY_CAPACITY)))
+    }
+
+    /// Creates a new [`MatchingEngine`] that records its receipts in the provided `history` sink.
+    pub fn with_history(history: Box<dyn HistorySink<Receipt>>) -> MatchingEngine {
         MatchingEngine {
             ordinal: 0_u64,
             asks: BTreeMap::new(),
             bids: BTreeMap::new(),
-            history: Vec::new(),
+            history,
+            price_rule: ExecutionPriceRule::default(),
         }
     }
 
+    /// Sets the rule that sets the price of every match; the maker's price by default.
+    pub fn with_price_rule(mut self, price_rule: ExecutionPriceRule) -> MatchingEngine {
+        self.price_rule = price_rule;
+        self
+    }
+
+    /// The rule that sets the price of every match
+    pub fn price_rule(&self) -> ExecutionPriceRule {
+        self.price_rule
+    }
+
     /// Processes an [`Order`] and returns a [`Receipt`].
     ///
     /// A receipt contains the order's ordinal sequence number (`u64`),
@@ -96,14 +178,36 @@ impl MatchingEngine {
     /// - `Ok(Receipt)`
     ///
     /// # Errors
-    /// - Doesn't return an error variant.
-    /// - The return type of `Result<Receipt, AccountingError>` was chosen for consistency with rest of code.
+    /// - The ordinal sequence numbers are exhausted, `AccountingError::ArithmeticOverflow`.
     pub fn process(&mut self, order: Order) -> Result<Receipt, AccountingError> {
+        let (receipt, _) = self.process_unrecorded(order)?;
+
+        // Keep a record of all orders, even unmatched ones.
+        self.history.record(receipt.clone());
+
+        Ok(receipt)
+    }
+
+    /// Processes an [`Order`] in the same way as [`process`](Self::process), but doesn't record
+    /// the receipt in the history, so that the caller can [`rollback`](Self::rollback) the order
+    /// before it's recorded, such as when its matches can't be settled.
+    ///
+    /// # Errors
+    /// - Same as [`process`](Self::process).
+    pub fn process_unrecorded(
+        &mut self,
+        order: Order,
+    ) -> Result<(Receipt, Rollback), AccountingError> {
         // We record every order, even if it turns out to be unmatched
         // at the moment of entering the order book or any time later when processed.
         // It may be matched at some point, either fully, or partially.
         // That is why we set a unique ordinal number for every order that comes in.
-        self.ordinal += 1;
+        self.ordinal = self.ordinal.checked_add(1).ok_or_else(|| {
+            AccountingError::ArithmeticOverflow(format!(
+                "The ordinal sequence numbers are exhausted at {}.",
+                self.ordinal
+            ))
+        })?;
 
         let original_amount = order.get_initial_amount();
 
@@ -113,29 +217,37 @@ impl MatchingEngine {
         // to help us through the matching process.
         let mut partial_order = order.into_partial_order(self.ordinal, original_amount);
 
+        // The resting orders that get matched, as they were before, for a rollback.
+        let mut displaced = vec![];
+
         // Orders are matched to the opposite side of the order book.
         let receipt = match partial_order.side {
             Side::Buy => {
                 // Fetch all orders in the expected price range from the opposite side of the order book.
                 // The best price in case of buying (bidding) is the lowest price, so we start with it.
                 // We take a mutable reference to the min-heap so the matching engine can remove any matching entries.
-                let sell_entries = self.asks.range_mut(0..=partial_order.price);
-
-                let buy_receipt = MatchingEngine::match_order(&partial_order, sell_entries)?;
-                let matched_buy_amount: u64 =
-                    buy_receipt.matches.iter().map(|po| po.current_amount).sum();
+                let sell_entries = self.asks.range_mut(..=partial_order.price);
+
+                let buy_receipt = MatchingEngine::match_order(
+                    &partial_order,
+                    sell_entries,
+                    self.price_rule,
+                    &mut displaced,
+                )?;
+                let matched_buy_amount =
+                    buy_receipt.matches.iter().fold(Quantity::ZERO, |sum, po| {
+                        sum.saturating_add(po.current_amount)
+                    });
 
                 // After going through the entire price range, if some unmatched amount
                 // to buy remains, update the existing order.
                 // After updating it, we need to put it back in the order book because
                 // `match_order` removes the matching entries.
                 if matched_buy_amount < original_amount {
-                    partial_order.current_amount = original_amount - matched_buy_amount;
+                    partial_order.current_amount =
+                        original_amount.saturating_sub(matched_buy_amount);
                     partial_order.remaining_amount = partial_order.current_amount;
-                    let heap = self
-                        .bids
-                        .entry(partial_order.price)
-                        .or_insert(BinaryHeap::new());
+                    let heap = self.bids.entry(partial_order.price).or_default();
                     heap.push(partial_order);
                 }
 
@@ -146,26 +258,28 @@ impl MatchingEngine {
                 // The best price in case of selling (asking) is the highest price, so we reverse the iterator.
                 // Note: The course creator doesn't reverse the iterator. I think it is a bug on their account.
                 // We take a mutable reference to the min-heap so the matching engine can remove any matching entries.
-                let buy_entries = self.bids.range_mut(partial_order.price..=u64::MAX).rev();
-
-                let sell_receipt = MatchingEngine::match_order(&partial_order, buy_entries)?;
-                let matched_sell_amount: u64 = sell_receipt
-                    .matches
-                    .iter()
-                    .map(|po| po.current_amount)
-                    .sum();
+                let buy_entries = self.bids.range_mut(partial_order.price..).rev();
+
+                let sell_receipt = MatchingEngine::match_order(
+                    &partial_order,
+                    buy_entries,
+                    self.price_rule,
+                    &mut displaced,
+                )?;
+                let matched_sell_amount =
+                    sell_receipt.matches.iter().fold(Quantity::ZERO, |sum, po| {
+                        sum.saturating_add(po.current_amount)
+                    });
 
                 // After going through the entire price range, if some unmatched amount
                 // to sell remains, update the existing order.
                 // After updating it, we need to put it back in the order book because
                 // `match_order` removes the matching entries.
                 if matched_sell_amount < original_amount {
-                    partial_order.current_amount = original_amount - matched_sell_amount;
+                    partial_order.current_amount =
+                        original_amount.saturating_sub(matched_sell_amount);
                     partial_order.remaining_amount = partial_order.current_amount;
-                    let heap = self
-                        .asks
-                        .entry(partial_order.price)
-                        .or_insert(BinaryHeap::new());
+                    let heap = self.asks.entry(partial_order.price).or_default();
                     heap.push(partial_order);
                 }
 
@@ -177,10 +291,107 @@ impl MatchingEngine {
         self.asks.retain(|_price, heap| !heap.is_empty());
         self.bids.retain(|_price, heap| !heap.is_empty());
 
-        // Keep a record of all orders, even unmatched ones.
-        self.history.push(receipt.clone());
+        let rollback = Rollback {
+            ordinal: self.ordinal,
+            displaced,
+        };
+        Ok((receipt, rollback))
+    }
 
-        Ok(receipt)
+    /// Undoes the changes that an order made to the order book, in
+    /// [`process_unrecorded`](Self::process_unrecorded).
+    ///
+    /// The order's remainder is removed from the book, and the resting orders that it matched
+    /// are put back as they were, at their prices. The order's ordinal isn't reused.
+    /// It has to be the last order that was processed.
+    pub fn rollback(&mut self, rollback: Rollback) {
+        self.cancel(rollback.ordinal);
+
+        for (price, po) in rollback.displaced {
+            // The remainder of a partially matched order is in the book in its place.
+            self.cancel(po.ordinal);
+
+            let side = match po.side {
+                Side::Buy => &mut self.bids,
+                Side::Sell => &mut self.asks,
+            };
+            side.entry(price).or_default().push(po);
+        }
+    }
+
+    /// Cancels a resting order by its ordinal sequence number.
+    ///
+    /// Looks for the order on both sides of the order book, removes it from the book,
+    /// and returns it in its current state, i.e., with its unmatched amount.
+    ///
+    /// Removes the price entry from the order book if it becomes empty.
+    ///
+    /// # Returns
+    /// - `Some(PartialOrder)` if the order was found in the book;
+    /// - `None` if there is no such order in the book, either because it never existed,
+    ///   because it has been fully matched, or because it has already been cancelled.
+    pub fn cancel(&mut self, ordinal: u64) -> Option<PartialOrder> {
+        for side in [&mut self.asks, &mut self.bids] {
+            let mut found = None;
+
+            for (price, heap) in side.iter_mut() {
+                if heap.iter().any(|po| po.ordinal == ordinal) {
+                    let (removed, kept): (Vec<PartialOrder>, Vec<PartialOrder>) =
+                        heap.drain().partition(|po| po.ordinal == ordinal);
+                    heap.extend(kept);
+                    found = removed.into_iter().next().map(|po| (*price, po));
+                    break;
+                }
+            }
+
+            if let Some((price, po)) = found {
+                if side.get(&price).is_some_and(|heap| heap.is_empty()) {
+                    side.remove(&price);
+                }
+                return Some(po);
+            }
+        }
+
+        None
+    }
+
+    /// Cancels all resting orders of a signer, optionally only on one side of the order book.
+    ///
+    /// Removes the price entries from the order book that become empty.
+    ///
+    /// # Returns
+    /// - All removed orders, in their current state, ordered by their ordinal sequence number;
+    ///   empty if the signer has no resting orders.
+    pub fn cancel_all(&mut self, signer: &str, side: Option<Side>) -> Vec<PartialOrder> {
+        let mut cancelled = Vec::new();
+
+        for (book_side, book) in [(Side::Sell, &mut self.asks), (Side::Buy, &mut self.bids)] {
+            if side.as_ref().is_some_and(|side| *side != book_side) {
+                continue;
+            }
+
+            for heap in book.values_mut() {
+                if heap.iter().any(|po| po.signer == signer) {
+                    let (removed, kept): (Vec<PartialOrder>, Vec<PartialOrder>) =
+                        heap.drain().partition(|po| po.signer == signer);
+                    heap.extend(kept);
+                    cancelled.extend(removed);
+                }
+            }
+            book.retain(|_price, heap| !heap.is_empty());
+        }
+
+        cancelled.sort_by_key(|po| po.ordinal);
+        cancelled
+    }
+
+    /// Looks up a resting order by its ordinal sequence number, on both sides of the order book.
+    pub fn get(&self, ordinal: u64) -> Option<&PartialOrder> {
+        self.asks
+            .values()
+            .chain(self.bids.values())
+            .flatten()
+            .find(|po| po.ordinal == ordinal)
     }
 
     /// Processes a [`PartialOrder`] and returns a [`Receipt`].
@@ -194,17 +405,19 @@ impl MatchingEngine {
     ///
     /// Puts both full and partial matches in the `Receipt` that it returns at the end.
     ///
-    /// Updates the price in the receipt, i.e., a bill, for the matched orders.
-    /// It updates it for the matched part of an existing order, and for a fully-matched order.
+    /// Updates the price in the receipt, i.e., a bill, for the matched orders, in accordance with
+    /// the `price_rule`. It updates it for the matched part of an existing order, and for a fully-matched order.
     ///
     /// It doesn't update the price for the remaining part of a partially-matched order, naturally.
     ///
     /// # Parameters
     /// - `partial_order`: A new [`PartialOrder`] to match in the order book.
     /// - `price_range_entries`: A pre-filtered iterator for the existing order book entries in the
-    ///    requested price range, ordered by the best price:
-    ///    an iterator over tuples of prices (key, `u64`)
-    ///    and accompanying priority queues of pending orders at those prices (value, `BinaryHeap<PartialOrder>`).
+    ///   requested price range, ordered by the best price:
+    ///   an iterator over tuples of prices (key, `Price`)
+    ///   and accompanying priority queues of pending orders at those prices (value, `BinaryHeap<PartialOrder>`).
+    /// - `price_rule`: The rule that sets the price of every match.
+    /// - `displaced`: Collects the matched existing orders as they were, along with their prices.
     ///
     /// # Returns
     /// - `Ok(Receipt)`
@@ -215,9 +428,11 @@ impl MatchingEngine {
     fn match_order<'a, T>(
         partial_order: &PartialOrder,
         mut price_range_entries: T,
+        price_rule: ExecutionPriceRule,
+        displaced: &mut Vec<(Price, PartialOrder)>,
     ) -> Result<Receipt, AccountingError>
     where
-        T: Iterator<Item = (&'a u64, &'a mut BinaryHeap<PartialOrder>)>,
+        T: Iterator<Item = (&'a Price, &'a mut BinaryHeap<PartialOrder>)>,
     {
         // Remaining amount to match.
         let mut remaining_amount = partial_order.current_amount;
@@ -226,8 +441,8 @@ impl MatchingEngine {
         let mut matches: Vec<PartialOrder> = vec![];
 
         // Each matching position's amount is subtracted.
-        'outer: while remaining_amount > 0 {
-            // The iterator contains all order book entries at the given price points (u64), from a given price range,
+        'outer: while !remaining_amount.is_zero() {
+            // The iterator contains all order book entries at the given price points (Price), from a given price range,
             // in the form of a priority queue (BinaryHeap<PartialOrder>), and here we iterate over those entries.
             match price_range_entries.next() {
                 Some((price, price_entry)) => {
@@ -248,6 +463,7 @@ impl MatchingEngine {
                         if current_partial_order.signer == partial_order.signer {
                             self_matches.push(current_partial_order);
                         } else {
+                            displaced.push((*price, current_partial_order.clone()));
                             if remaining_amount < current_partial_order.remaining_amount {
                                 // We've fully matched the required amount,
                                 // and the existing order hasn't been fully exhausted.
@@ -258,23 +474,28 @@ impl MatchingEngine {
                                 // But, the matched part of the amount may have the price updated!
                                 // It will get the current price, which may be different than the
                                 // order's original price.
-                                current_partial_order.remaining_amount -= remaining_amount;
+                                current_partial_order.remaining_amount = current_partial_order
+                                    .remaining_amount
+                                    .saturating_sub(remaining_amount);
                                 let mut new_partial_order = current_partial_order.clone();
                                 new_partial_order.current_amount =
                                     new_partial_order.remaining_amount;
                                 price_entry.push(new_partial_order);
-                                current_partial_order.price = *price;
+                                current_partial_order.price =
+                                    price_rule.execution_price(*price, partial_order.price);
                                 matches.push(current_partial_order);
                                 break 'outer;
                             } else {
                                 // We may still have some remaining amount unmatched,
                                 // and we'll potentially have to keep iterating after this.
                                 // We have exhausted the current partial order in this case.
-                                remaining_amount -= current_partial_order.remaining_amount;
-                                current_partial_order.price = *price;
-                                current_partial_order.remaining_amount = 0;
+                                remaining_amount = remaining_amount
+                                    .saturating_sub(current_partial_order.remaining_amount);
+                                current_partial_order.price =
+                                    price_rule.execution_price(*price, partial_order.price);
+                                current_partial_order.remaining_amount = Quantity::ZERO;
                                 matches.push(current_partial_order);
-                                if remaining_amount == 0 {
+                                if remaining_amount.is_zero() {
                                     break 'outer;
                                 }
                             }
@@ -299,6 +520,17 @@ impl MatchingEngine {
     }
 }
 
+/// **What an order changed in the order book, so that it can be undone**
+///
+/// See [`MatchingEngine::rollback`].
+#[derive(Debug)]
+pub struct Rollback {
+    /// The order's ordinal
+    ordinal: u64,
+    /// The resting orders that the order matched, as they were, along with their prices
+    displaced: Vec<(Price, PartialOrder)>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -327,6 +559,30 @@ mod tests {
         assert_eq!(receipt.ordinal, matching_engine.ordinal);
     }
 
+    #[test]
+    fn process_same_price_earlier_order_first() {
+        let mut matching_engine = MatchingEngine::new();
+
+        // A larger order that comes later still waits behind the earlier ones at its price.
+        for (amount, signer) in [(1, "Alice"), (5, "Bob"), (3, "Charlie")] {
+            matching_engine
+                .process(Order::new(10, amount, Side::Sell, String::from(signer)))
+                .unwrap();
+        }
+
+        let dave_receipt = matching_engine
+            .process(Order::new(10, 4, Side::Buy, String::from("Dave")))
+            .unwrap();
+        assert_eq!(
+            vec![("Alice", 1), ("Bob", 2)],
+            dave_receipt
+                .matches
+                .iter()
+                .map(|po| (po.signer.as_str(), po.ordinal))
+                .collect::<Vec<_>>()
+        );
+    }
+
     #[test]
     fn process_partially_matched_buy_order_same_price_seller_first() {
         let mut matching_engine = MatchingEngine::new();
@@ -347,12 +603,12 @@ mod tests {
         assert_eq!(1, bob_receipt.matches.len());
         assert_eq!(
             PartialOrder {
-                price: 10,
-                current_amount: 1,
+                price: 10.into(),
+                current_amount: 1.into(),
                 side: Side::Sell,
                 signer: String::from("Alice"),
                 ordinal: 1,
-                remaining_amount: 0,
+                remaining_amount: 0.into(),
             },
             bob_receipt.matches[0]
         );
@@ -361,19 +617,19 @@ mod tests {
         assert!(matching_engine.asks.is_empty());
 
         assert_eq!(1, matching_engine.bids.len());
-        assert_eq!(1, matching_engine.bids.get(&10).unwrap().len());
+        assert_eq!(1, matching_engine.bids.get(&Price::from(10)).unwrap().len());
         assert_eq!(
             PartialOrder {
-                price: 10,
-                current_amount: 1,
+                price: 10.into(),
+                current_amount: 1.into(),
                 side: Side::Buy,
                 signer: String::from("Bob"),
                 ordinal: 2,
-                remaining_amount: 1,
+                remaining_amount: 1.into(),
             },
             matching_engine
                 .bids
-                .get(&10)
+                .get(&Price::from(10))
                 .unwrap()
                 .peek()
                 .unwrap()
@@ -392,16 +648,16 @@ mod tests {
                 Receipt {
                     ordinal: 2,
                     matches: vec![PartialOrder {
-                        price: 10,
-                        current_amount: 1,
+                        price: 10.into(),
+                        current_amount: 1.into(),
                         side: Side::Sell,
                         signer: String::from("Alice"),
                         ordinal: 1,
-                        remaining_amount: 0,
+                        remaining_amount: 0.into(),
                     }],
                 }
             ],
-            matching_engine.history
+            matching_engine.history.records()
         );
     }
 
@@ -422,12 +678,12 @@ mod tests {
         assert_eq!(1, bob_receipt.matches.len());
         assert_eq!(
             PartialOrder {
-                price: 10,
-                current_amount: 2,
+                price: 10.into(),
+                current_amount: 2.into(),
                 side: Side::Sell,
                 signer: String::from("Alice"),
                 ordinal: 1,
-                remaining_amount: 1,
+                remaining_amount: 1.into(),
             },
             bob_receipt.matches[0]
         );
@@ -436,19 +692,19 @@ mod tests {
         assert!(matching_engine.bids.is_empty());
 
         assert_eq!(1, matching_engine.asks.len());
-        assert_eq!(1, matching_engine.asks.get(&10).unwrap().len());
+        assert_eq!(1, matching_engine.asks.get(&Price::from(10)).unwrap().len());
         assert_eq!(
             PartialOrder {
-                price: 10,
-                current_amount: 1,
+                price: 10.into(),
+                current_amount: 1.into(),
                 side: Side::Sell,
                 signer: String::from("Alice"),
                 ordinal: 1,
-                remaining_amount: 1,
+                remaining_amount: 1.into(),
             },
             matching_engine
                 .asks
-                .get(&10)
+                .get(&Price::from(10))
                 .unwrap()
                 .peek()
                 .unwrap()
@@ -478,12 +734,12 @@ mod tests {
         assert_eq!(2, alice_receipt.ordinal);
         assert_eq!(
             PartialOrder {
-                price: 10,
-                current_amount: 2,
+                price: 10.into(),
+                current_amount: 2.into(),
                 side: Side::Buy,
                 signer: String::from("Bob"),
                 ordinal: 1,
-                remaining_amount: 1,
+                remaining_amount: 1.into(),
             },
             alice_receipt.matches[0]
         );
@@ -492,19 +748,19 @@ mod tests {
         assert!(matching_engine.asks.is_empty());
 
         assert_eq!(1, matching_engine.bids.len());
-        assert_eq!(1, matching_engine.bids.get(&10).unwrap().len());
+        assert_eq!(1, matching_engine.bids.get(&Price::from(10)).unwrap().len());
         assert_eq!(
             PartialOrder {
-                price: 10,
-                current_amount: 1,
+                price: 10.into(),
+                current_amount: 1.into(),
                 side: Side::Buy,
                 signer: String::from("Bob"),
                 ordinal: 1,
-                remaining_amount: 1,
+                remaining_amount: 1.into(),
             },
             matching_engine
                 .bids
-                .get(&10)
+                .get(&Price::from(10))
                 .unwrap()
                 .peek()
                 .unwrap()
@@ -523,16 +779,16 @@ mod tests {
                 Receipt {
                     ordinal: 2,
                     matches: vec![PartialOrder {
-                        price: 10,
-                        current_amount: 2,
+                        price: 10.into(),
+                        current_amount: 2.into(),
                         side: Side::Buy,
                         signer: String::from("Bob"),
                         ordinal: 1,
-                        remaining_amount: 1,
+                        remaining_amount: 1.into(),
                     }],
                 }
             ],
-            matching_engine.history
+            matching_engine.history.records()
         );
     }
 
@@ -552,12 +808,12 @@ mod tests {
         assert_eq!(2, alice_receipt.ordinal);
         assert_eq!(
             PartialOrder {
-                price: 10,
-                current_amount: 1,
+                price: 10.into(),
+                current_amount: 1.into(),
                 side: Side::Buy,
                 signer: String::from("Bob"),
                 ordinal: 1,
-                remaining_amount: 0,
+                remaining_amount: 0.into(),
             },
             alice_receipt.matches[0]
         );
@@ -566,19 +822,19 @@ mod tests {
         assert!(matching_engine.bids.is_empty());
 
         assert_eq!(1, matching_engine.asks.len());
-        assert_eq!(1, matching_engine.asks.get(&10).unwrap().len());
+        assert_eq!(1, matching_engine.asks.get(&Price::from(10)).unwrap().len());
         assert_eq!(
             PartialOrder {
-                price: 10,
-                current_amount: 1,
+                price: 10.into(),
+                current_amount: 1.into(),
                 side: Side::Sell,
                 signer: String::from("Alice"),
                 ordinal: 2,
-                remaining_amount: 1,
+                remaining_amount: 1.into(),
             },
             matching_engine
                 .asks
-                .get(&10)
+                .get(&Price::from(10))
                 .unwrap()
                 .peek()
                 .unwrap()
@@ -606,12 +862,12 @@ mod tests {
         assert_eq!(1, bob_receipt.matches.len());
         assert_eq!(
             PartialOrder {
-                price: 10,
-                current_amount: 1,
+                price: 10.into(),
+                current_amount: 1.into(),
                 side: Side::Sell,
                 signer: String::from("Alice"),
                 ordinal: 1,
-                remaining_amount: 0,
+                remaining_amount: 0.into(),
             },
             bob_receipt.matches[0]
         );
@@ -620,19 +876,19 @@ mod tests {
         assert!(matching_engine.asks.is_empty());
 
         assert_eq!(1, matching_engine.bids.len());
-        assert_eq!(1, matching_engine.bids.get(&11).unwrap().len());
+        assert_eq!(1, matching_engine.bids.get(&Price::from(11)).unwrap().len());
         assert_eq!(
             PartialOrder {
-                price: 11,
-                current_amount: 1,
+                price: 11.into(),
+                current_amount: 1.into(),
                 side: Side::Buy,
                 signer: String::from("Bob"),
                 ordinal: 2,
-                remaining_amount: 1,
+                remaining_amount: 1.into(),
             },
             matching_engine
                 .bids
-                .get(&11)
+                .get(&Price::from(11))
                 .unwrap()
                 .peek()
                 .unwrap()
@@ -660,12 +916,12 @@ mod tests {
         assert_eq!(1, bob_receipt.matches.len());
         assert_eq!(
             PartialOrder {
-                price: 10,
-                current_amount: 2,
+                price: 10.into(),
+                current_amount: 2.into(),
                 side: Side::Sell,
                 signer: String::from("Alice"),
                 ordinal: 1,
-                remaining_amount: 1,
+                remaining_amount: 1.into(),
             },
             bob_receipt.matches[0]
         );
@@ -674,19 +930,19 @@ mod tests {
         assert!(matching_engine.bids.is_empty());
 
         assert_eq!(1, matching_engine.asks.len());
-        assert_eq!(1, matching_engine.asks.get(&10).unwrap().len());
+        assert_eq!(1, matching_engine.asks.get(&Price::from(10)).unwrap().len());
         assert_eq!(
             PartialOrder {
-                price: 10,
-                current_amount: 1,
+                price: 10.into(),
+                current_amount: 1.into(),
                 side: Side::Sell,
                 signer: String::from("Alice"),
                 ordinal: 1,
-                remaining_amount: 1,
+                remaining_amount: 1.into(),
             },
             matching_engine
                 .asks
-                .get(&10)
+                .get(&Price::from(10))
                 .unwrap()
                 .peek()
                 .unwrap()
@@ -713,12 +969,12 @@ mod tests {
         assert_eq!(2, alice_receipt.ordinal);
         assert_eq!(
             PartialOrder {
-                price: 11,
-                current_amount: 2,
+                price: 11.into(),
+                current_amount: 2.into(),
                 side: Side::Buy,
                 signer: String::from("Bob"),
                 ordinal: 1,
-                remaining_amount: 1,
+                remaining_amount: 1.into(),
             },
             alice_receipt.matches[0]
         );
@@ -727,19 +983,19 @@ mod tests {
         assert!(matching_engine.asks.is_empty());
 
         assert_eq!(1, matching_engine.bids.len());
-        assert_eq!(1, matching_engine.bids.get(&11).unwrap().len());
+        assert_eq!(1, matching_engine.bids.get(&Price::from(11)).unwrap().len());
         assert_eq!(
             PartialOrder {
-                price: 11,
-                current_amount: 1,
+                price: 11.into(),
+                current_amount: 1.into(),
                 side: Side::Buy,
                 signer: String::from("Bob"),
                 ordinal: 1,
-                remaining_amount: 1,
+                remaining_amount: 1.into(),
             },
             matching_engine
                 .bids
-                .get(&11)
+                .get(&Price::from(11))
                 .unwrap()
                 .peek()
                 .unwrap()
@@ -766,12 +1022,12 @@ mod tests {
         assert_eq!(2, alice_receipt.ordinal);
         assert_eq!(
             PartialOrder {
-                price: 11,
-                current_amount: 1,
+                price: 11.into(),
+                current_amount: 1.into(),
                 side: Side::Buy,
                 signer: String::from("Bob"),
                 ordinal: 1,
-                remaining_amount: 0,
+                remaining_amount: 0.into(),
             },
             alice_receipt.matches[0]
         );
@@ -780,19 +1036,19 @@ mod tests {
         assert!(matching_engine.bids.is_empty());
 
         assert_eq!(1, matching_engine.asks.len());
-        assert_eq!(1, matching_engine.asks.get(&10).unwrap().len());
+        assert_eq!(1, matching_engine.asks.get(&Price::from(10)).unwrap().len());
         assert_eq!(
             PartialOrder {
-                price: 10,
-                current_amount: 1,
+                price: 10.into(),
+                current_amount: 1.into(),
                 side: Side::Sell,
                 signer: String::from("Alice"),
                 ordinal: 2,
-                remaining_amount: 1,
+                remaining_amount: 1.into(),
             },
             matching_engine
                 .asks
-                .get(&10)
+                .get(&Price::from(10))
                 .unwrap()
                 .peek()
                 .unwrap()
@@ -819,12 +1075,12 @@ mod tests {
         assert_eq!(2, bob_receipt.ordinal);
         assert_eq!(
             vec![PartialOrder {
-                price: 10,
-                current_amount: 2,
+                price: 10.into(),
+                current_amount: 2.into(),
                 side: Side::Sell,
                 signer: "Alice".to_string(),
                 ordinal: 1,
-                remaining_amount: 0,
+                remaining_amount: 0.into(),
             }],
             bob_receipt.matches,
         );
@@ -852,12 +1108,12 @@ mod tests {
         assert_eq!(2, bob_receipt.ordinal);
         assert_eq!(
             vec![PartialOrder {
-                price: 10,
-                current_amount: 2,
+                price: 10.into(),
+                current_amount: 2.into(),
                 side: Side::Sell,
                 signer: "Alice".to_string(),
                 ordinal: 1,
-                remaining_amount: 0,
+                remaining_amount: 0.into(),
             }],
             bob_receipt.matches,
         );
@@ -891,7 +1147,7 @@ mod tests {
         assert_eq!(1, matching_engine.asks.len());
 
         // This is a way to count the ask orders at a price level.
-        assert_eq!(2, matching_engine.asks.get(&10).unwrap().len());
+        assert_eq!(2, matching_engine.asks.get(&Price::from(10)).unwrap().len());
 
         // There are no bids at this point.
         assert_eq!(0, matching_engine.bids.len());
@@ -905,17 +1161,17 @@ mod tests {
         assert_eq!(
             vec![
                 PartialOrder {
-                    price: 10,
-                    current_amount: 1,
-                    remaining_amount: 0,
+                    price: 10.into(),
+                    current_amount: 1.into(),
+                    remaining_amount: 0.into(),
                     side: Side::Sell,
                     signer: "Alice".to_string(),
                     ordinal: 1
                 },
                 PartialOrder {
-                    price: 10,
-                    current_amount: 1,
-                    remaining_amount: 0,
+                    price: 10.into(),
+                    current_amount: 1.into(),
+                    remaining_amount: 0.into(),
                     side: Side::Sell,
                     signer: "Charlie".to_string(),
                     ordinal: 2
@@ -953,8 +1209,8 @@ mod tests {
         assert_eq!(2, matching_engine.asks.len());
 
         // This is a way to count the ask orders at a price level.
-        assert_eq!(1, matching_engine.asks.get(&10).unwrap().len());
-        assert_eq!(1, matching_engine.asks.get(&12).unwrap().len());
+        assert_eq!(1, matching_engine.asks.get(&Price::from(10)).unwrap().len());
+        assert_eq!(1, matching_engine.asks.get(&Price::from(12)).unwrap().len());
 
         // There are no bids at this moment.
         assert_eq!(0, matching_engine.bids.len());
@@ -969,17 +1225,17 @@ mod tests {
         assert_eq!(
             vec![
                 PartialOrder {
-                    price: 10,
-                    current_amount: 1,
-                    remaining_amount: 0,
+                    price: 10.into(),
+                    current_amount: 1.into(),
+                    remaining_amount: 0.into(),
                     side: Side::Sell,
                     signer: "Charlie".to_string(),
                     ordinal: 2
                 },
                 PartialOrder {
-                    price: 12,
-                    current_amount: 1,
-                    remaining_amount: 0,
+                    price: 12.into(),
+                    current_amount: 1.into(),
+                    remaining_amount: 0.into(),
                     side: Side::Sell,
                     signer: "Alice".to_string(),
                     ordinal: 1
@@ -1017,8 +1273,8 @@ mod tests {
         assert_eq!(2, matching_engine.bids.len());
 
         // This is a way to count the ask orders at a price level.
-        assert_eq!(1, matching_engine.bids.get(&10).unwrap().len());
-        assert_eq!(1, matching_engine.bids.get(&12).unwrap().len());
+        assert_eq!(1, matching_engine.bids.get(&Price::from(10)).unwrap().len());
+        assert_eq!(1, matching_engine.bids.get(&Price::from(12)).unwrap().len());
 
         // There are no asks at this point.
         assert_eq!(0, matching_engine.asks.len());
@@ -1033,17 +1289,17 @@ mod tests {
         assert_eq!(
             vec![
                 PartialOrder {
-                    price: 12,
-                    current_amount: 1,
-                    remaining_amount: 0,
+                    price: 12.into(),
+                    current_amount: 1.into(),
+                    remaining_amount: 0.into(),
                     side: Side::Buy,
                     signer: "Charlie".to_string(),
                     ordinal: 2
                 },
                 PartialOrder {
-                    price: 10,
-                    current_amount: 1,
-                    remaining_amount: 0,
+                    price: 10.into(),
+                    current_amount: 1.into(),
+                    remaining_amount: 0.into(),
                     side: Side::Buy,
                     signer: "Alice".to_string(),
                     ordinal: 1
@@ -1082,17 +1338,17 @@ mod tests {
         assert_eq!(
             vec![
                 PartialOrder {
-                    price: 10,
-                    current_amount: 1,
-                    remaining_amount: 0,
+                    price: 10.into(),
+                    current_amount: 1.into(),
+                    remaining_amount: 0.into(),
                     side: Side::Sell,
                     signer: "Charlie".to_string(),
                     ordinal: 2
                 },
                 PartialOrder {
-                    price: 11,
-                    current_amount: 1,
-                    remaining_amount: 0,
+                    price: 11.into(),
+                    current_amount: 1.into(),
+                    remaining_amount: 0.into(),
                     side: Side::Sell,
                     signer: "Alice".to_string(),
                     ordinal: 1
@@ -1105,19 +1361,19 @@ mod tests {
         assert!(matching_engine.asks.is_empty());
 
         assert_eq!(1, matching_engine.bids.len());
-        assert_eq!(1, matching_engine.bids.get(&11).unwrap().len());
+        assert_eq!(1, matching_engine.bids.get(&Price::from(11)).unwrap().len());
         assert_eq!(
             PartialOrder {
-                price: 11,
-                current_amount: 1,
+                price: 11.into(),
+                current_amount: 1.into(),
                 side: Side::Buy,
                 signer: String::from("Bob"),
                 ordinal: 3,
-                remaining_amount: 1,
+                remaining_amount: 1.into(),
             },
             matching_engine
                 .bids
-                .get(&11)
+                .get(&Price::from(11))
                 .unwrap()
                 .peek()
                 .unwrap()
@@ -1150,30 +1406,30 @@ mod tests {
         assert_eq!(3, bob_receipt.ordinal);
         assert_eq!(
             vec![PartialOrder {
-                price: 10,
-                current_amount: 1,
+                price: 10.into(),
+                current_amount: 1.into(),
                 side: Side::Sell,
                 signer: String::from("Charlie"),
                 ordinal: 2,
-                remaining_amount: 0,
+                remaining_amount: 0.into(),
             }],
             bob_receipt.matches
         );
 
         assert_eq!(1, matching_engine.asks.len());
-        assert_eq!(1, matching_engine.asks.get(&11).unwrap().len());
+        assert_eq!(1, matching_engine.asks.get(&Price::from(11)).unwrap().len());
         assert_eq!(
             PartialOrder {
-                price: 11,
-                current_amount: 1,
+                price: 11.into(),
+                current_amount: 1.into(),
                 side: Side::Sell,
                 signer: String::from("Alice"),
                 ordinal: 1,
-                remaining_amount: 1,
+                remaining_amount: 1.into(),
             },
             matching_engine
                 .asks
-                .get(&11)
+                .get(&Price::from(11))
                 .unwrap()
                 .peek()
                 .unwrap()
@@ -1181,19 +1437,19 @@ mod tests {
         );
 
         assert_eq!(1, matching_engine.bids.len());
-        assert_eq!(1, matching_engine.bids.get(&10).unwrap().len());
+        assert_eq!(1, matching_engine.bids.get(&Price::from(10)).unwrap().len());
         assert_eq!(
             PartialOrder {
-                price: 10,
-                current_amount: 1,
+                price: 10.into(),
+                current_amount: 1.into(),
                 side: Side::Buy,
                 signer: String::from("Bob"),
                 ordinal: 3,
-                remaining_amount: 1,
+                remaining_amount: 1.into(),
             },
             matching_engine
                 .bids
-                .get(&10)
+                .get(&Price::from(10))
                 .unwrap()
                 .peek()
                 .unwrap()
@@ -1225,9 +1481,9 @@ mod tests {
         assert_eq!(3, alice_receipt.ordinal);
         assert_eq!(
             vec![PartialOrder {
-                price: 10,
-                current_amount: 1,
-                remaining_amount: 0,
+                price: 10.into(),
+                current_amount: 1.into(),
+                remaining_amount: 0.into(),
                 side: Side::Sell,
                 signer: "Charlie".to_string(),
                 ordinal: 2
@@ -1319,19 +1575,19 @@ mod tests {
         assert_eq!(0, matching_engine.bids.len());
 
         // The matching engine contains an ask at 10.
-        assert_eq!(1, matching_engine.asks.get(&10).unwrap().len());
+        assert_eq!(1, matching_engine.asks.get(&Price::from(10)).unwrap().len());
         assert_eq!(
             PartialOrder {
-                price: 10,
-                current_amount: 8,
+                price: 10.into(),
+                current_amount: 8.into(),
                 side: Side::Sell,
                 signer: String::from("Alice"),
                 ordinal: 1,
-                remaining_amount: 8,
+                remaining_amount: 8.into(),
             },
             matching_engine
                 .asks
-                .get(&10)
+                .get(&Price::from(10))
                 .unwrap()
                 .peek()
                 .unwrap()
@@ -1344,12 +1600,12 @@ mod tests {
         assert_eq!(2, bob_receipt.ordinal);
         assert_eq!(
             vec![PartialOrder {
-                price: 10,
-                current_amount: 8,
+                price: 10.into(),
+                current_amount: 8.into(),
                 side: Side::Sell,
                 signer: "Alice".to_string(),
                 ordinal: 1,
-                remaining_amount: 6,
+                remaining_amount: 6.into(),
             }],
             bob_receipt.matches,
         );
@@ -1358,19 +1614,19 @@ mod tests {
         assert_eq!(0, matching_engine.bids.len());
 
         // The matching engine contains an ask at 10.
-        assert_eq!(1, matching_engine.asks.get(&10).unwrap().len());
+        assert_eq!(1, matching_engine.asks.get(&Price::from(10)).unwrap().len());
         assert_eq!(
             PartialOrder {
-                price: 10,
-                current_amount: 6,
+                price: 10.into(),
+                current_amount: 6.into(),
                 side: Side::Sell,
                 signer: String::from("Alice"),
                 ordinal: 1,
-                remaining_amount: 6,
+                remaining_amount: 6.into(),
             },
             matching_engine
                 .asks
-                .get(&10)
+                .get(&Price::from(10))
                 .unwrap()
                 .peek()
                 .unwrap()
@@ -1383,12 +1639,12 @@ mod tests {
         assert_eq!(3, charlie_receipt.ordinal);
         assert_eq!(
             vec![PartialOrder {
-                price: 10,
-                current_amount: 6,
+                price: 10.into(),
+                current_amount: 6.into(),
                 side: Side::Sell,
                 signer: "Alice".to_string(),
                 ordinal: 1,
-                remaining_amount: 2,
+                remaining_amount: 2.into(),
             }],
             charlie_receipt.matches,
         );
@@ -1397,19 +1653,19 @@ mod tests {
         assert_eq!(0, matching_engine.bids.len());
 
         // The matching engine contains an ask at 10.
-        assert_eq!(1, matching_engine.asks.get(&10).unwrap().len());
+        assert_eq!(1, matching_engine.asks.get(&Price::from(10)).unwrap().len());
         assert_eq!(
             PartialOrder {
-                price: 10,
-                current_amount: 2,
+                price: 10.into(),
+                current_amount: 2.into(),
                 side: Side::Sell,
                 signer: String::from("Alice"),
                 ordinal: 1,
-                remaining_amount: 2,
+                remaining_amount: 2.into(),
             },
             matching_engine
                 .asks
-                .get(&10)
+                .get(&Price::from(10))
                 .unwrap()
                 .peek()
                 .unwrap()
@@ -1426,12 +1682,12 @@ mod tests {
         assert_eq!(4, donna_receipt.ordinal);
         assert_eq!(
             vec![PartialOrder {
-                price: 10,
-                current_amount: 2,
+                price: 10.into(),
+                current_amount: 2.into(),
                 side: Side::Sell,
                 signer: "Alice".to_string(),
                 ordinal: 1,
-                remaining_amount: 0,
+                remaining_amount: 0.into(),
             }],
             donna_receipt.matches,
         );
@@ -1440,19 +1696,19 @@ mod tests {
         assert_eq!(1, matching_engine.bids.len());
 
         // The matching engine contains a bid at 10.
-        assert_eq!(1, matching_engine.bids.get(&10).unwrap().len());
+        assert_eq!(1, matching_engine.bids.get(&Price::from(10)).unwrap().len());
         assert_eq!(
             PartialOrder {
-                price: 10,
-                current_amount: 3,
+                price: 10.into(),
+                current_amount: 3.into(),
                 side: Side::Buy,
                 signer: String::from("Donna"),
                 ordinal: 4,
-                remaining_amount: 3,
+                remaining_amount: 3.into(),
             },
             matching_engine
                 .bids
-                .get(&10)
+                .get(&Price::from(10))
                 .unwrap()
                 .peek()
                 .unwrap()
@@ -1465,12 +1721,12 @@ mod tests {
         assert_eq!(5, emma_receipt.ordinal);
         assert_eq!(
             vec![PartialOrder {
-                price: 10,
-                current_amount: 3,
+                price: 10.into(),
+                current_amount: 3.into(),
                 side: Side::Buy,
                 signer: "Donna".to_string(),
                 ordinal: 4,
-                remaining_amount: 1,
+                remaining_amount: 1.into(),
             }],
             emma_receipt.matches,
         );
@@ -1479,19 +1735,19 @@ mod tests {
         assert_eq!(1, matching_engine.bids.len());
 
         // The matching engine contains a bid at 10.
-        assert_eq!(1, matching_engine.bids.get(&10).unwrap().len());
+        assert_eq!(1, matching_engine.bids.get(&Price::from(10)).unwrap().len());
         assert_eq!(
             PartialOrder {
-                price: 10,
-                current_amount: 1,
+                price: 10.into(),
+                current_amount: 1.into(),
                 side: Side::Buy,
                 signer: String::from("Donna"),
                 ordinal: 4,
-                remaining_amount: 1,
+                remaining_amount: 1.into(),
             },
             matching_engine
                 .bids
-                .get(&10)
+                .get(&Price::from(10))
                 .unwrap()
                 .peek()
                 .unwrap()
@@ -1505,12 +1761,12 @@ mod tests {
         assert_eq!(6, filip_receipt.ordinal);
         assert_eq!(
             vec![PartialOrder {
-                price: 10,
-                current_amount: 1,
+                price: 10.into(),
+                current_amount: 1.into(),
                 side: Side::Buy,
                 signer: "Donna".to_string(),
                 ordinal: 4,
-                remaining_amount: 0,
+                remaining_amount: 0.into(),
             }],
             filip_receipt.matches,
         );
@@ -1519,19 +1775,19 @@ mod tests {
         assert_eq!(0, matching_engine.bids.len());
 
         // The matching engine contains an ask at 9.
-        assert_eq!(1, matching_engine.asks.get(&9).unwrap().len());
+        assert_eq!(1, matching_engine.asks.get(&Price::from(9)).unwrap().len());
         assert_eq!(
             PartialOrder {
-                price: 9,
-                current_amount: 2,
+                price: 9.into(),
+                current_amount: 2.into(),
                 side: Side::Sell,
                 signer: String::from("Filip"),
                 ordinal: 6,
-                remaining_amount: 2,
+                remaining_amount: 2.into(),
             },
             matching_engine
                 .asks
-                .get(&9)
+                .get(&Price::from(9))
                 .unwrap()
                 .peek()
                 .unwrap()
@@ -1545,12 +1801,12 @@ mod tests {
         assert_eq!(7, gina_receipt.ordinal);
         assert_eq!(
             vec![PartialOrder {
-                price: 9,
-                current_amount: 2,
+                price: 9.into(),
+                current_amount: 2.into(),
                 side: Side::Sell,
                 signer: "Filip".to_string(),
                 ordinal: 6,
-                remaining_amount: 0,
+                remaining_amount: 0.into(),
             }],
             gina_receipt.matches,
         );
@@ -1567,27 +1823,27 @@ mod tests {
                 Receipt {
                     ordinal: 2,
                     matches: vec![PartialOrder {
-                        price: 10,
-                        current_amount: 8,
+                        price: 10.into(),
+                        current_amount: 8.into(),
                         side: Side::Sell,
                         signer: "Alice".to_string(),
                         ordinal: 1,
-                        remaining_amount: 6,
+                        remaining_amount: 6.into(),
                     }],
                 },
                 Receipt {
                     ordinal: 3,
                     matches: vec![PartialOrder {
-                        price: 10,
-                        current_amount: 6,
+                        price: 10.into(),
+                        current_amount: 6.into(),
                         side: Side::Sell,
                         signer: "Alice".to_string(),
                         ordinal: 1,
-                        remaining_amount: 2,
+                        remaining_amount: 2.into(),
                     }]
                 },
             ],
-            matching_engine.history[..3]
+            matching_engine.history.records()[..3]
         );
 
         // We also keep a record of unmatched orders, not only the matched ones.
@@ -1636,12 +1892,12 @@ mod tests {
         assert_eq!(3, bob_receipt.ordinal);
         assert_eq!(
             vec![PartialOrder {
-                price: 10,
-                current_amount: 5,
+                price: 10.into(),
+                current_amount: 5.into(),
                 side: Side::Sell,
                 signer: String::from("Charlie"),
                 ordinal: 2,
-                remaining_amount: 3,
+                remaining_amount: 3.into(),
             }],
             bob_receipt.matches
         );
@@ -1694,36 +1950,36 @@ mod tests {
         assert_eq!(0, matching_engine.bids.len());
 
         // Charlie, Maria and Donna are current sellers at 10.
-        assert_eq!(3, matching_engine.asks.get(&10).unwrap().len());
+        assert_eq!(3, matching_engine.asks.get(&Price::from(10)).unwrap().len());
 
         let sellers_at_10 = vec![
             PartialOrder {
-                price: 10,
-                current_amount: 3,
+                price: 10.into(),
+                current_amount: 3.into(),
                 side: Side::Sell,
                 signer: String::from("Charlie"),
                 ordinal: 2,
-                remaining_amount: 3,
+                remaining_amount: 3.into(),
             },
             PartialOrder {
-                price: 10,
-                current_amount: 2,
+                price: 10.into(),
+                current_amount: 2.into(),
                 side: Side::Sell,
                 signer: String::from("Maria"),
                 ordinal: 4,
-                remaining_amount: 2,
+                remaining_amount: 2.into(),
             },
             PartialOrder {
-                price: 10,
-                current_amount: 2,
+                price: 10.into(),
+                current_amount: 2.into(),
                 side: Side::Sell,
                 signer: String::from("Donna"),
                 ordinal: 5,
-                remaining_amount: 2,
+                remaining_amount: 2.into(),
             },
         ];
         let mut expected_sellers_at_10 = BinaryHeap::from(sellers_at_10);
-        let mut exact_sellers_at_10 = matching_engine.asks.get(&10).unwrap().clone();
+        let mut exact_sellers_at_10 = matching_engine.asks.get(&Price::from(10)).unwrap().clone();
         for _ in 0..3 {
             let expected_seller = expected_sellers_at_10.pop().unwrap();
             let exact_seller = exact_sellers_at_10.pop().unwrap();
@@ -1742,52 +1998,52 @@ mod tests {
         assert_eq!(
             vec![
                 PartialOrder {
-                    price: 9,
-                    current_amount: 1,
+                    price: 9.into(),
+                    current_amount: 1.into(),
                     side: Side::Sell,
                     signer: String::from("Dianne"),
                     ordinal: 7,
-                    remaining_amount: 0,
+                    remaining_amount: 0.into(),
                 },
                 PartialOrder {
-                    price: 10,
-                    current_amount: 3,
+                    price: 10.into(),
+                    current_amount: 3.into(),
                     side: Side::Sell,
                     signer: String::from("Charlie"),
                     ordinal: 2,
-                    remaining_amount: 0,
+                    remaining_amount: 0.into(),
                 },
                 PartialOrder {
-                    price: 10,
-                    current_amount: 2,
+                    price: 10.into(),
+                    current_amount: 2.into(),
                     side: Side::Sell,
                     signer: String::from("Maria"),
                     ordinal: 4,
-                    remaining_amount: 0,
+                    remaining_amount: 0.into(),
                 },
                 PartialOrder {
-                    price: 11,
-                    current_amount: 3,
+                    price: 11.into(),
+                    current_amount: 3.into(),
                     side: Side::Sell,
                     signer: String::from("Alice"),
                     ordinal: 1,
-                    remaining_amount: 0,
+                    remaining_amount: 0.into(),
                 },
                 PartialOrder {
-                    price: 11,
-                    current_amount: 1,
+                    price: 11.into(),
+                    current_amount: 1.into(),
                     side: Side::Sell,
                     signer: String::from("Maria"),
                     ordinal: 8,
-                    remaining_amount: 0,
+                    remaining_amount: 0.into(),
                 },
                 PartialOrder {
-                    price: 12,
-                    current_amount: 8,
+                    price: 12.into(),
+                    current_amount: 8.into(),
                     side: Side::Sell,
                     signer: String::from("Mark"),
                     ordinal: 6,
-                    remaining_amount: 6,
+                    remaining_amount: 6.into(),
                 }
             ],
             donna_receipt.matches
@@ -1810,19 +2066,19 @@ mod tests {
         // Bob is buying at 7.
         assert_eq!(1, matching_engine.bids.len());
 
-        assert_eq!(1, matching_engine.bids.get(&7).unwrap().len());
+        assert_eq!(1, matching_engine.bids.get(&Price::from(7)).unwrap().len());
         assert_eq!(
             PartialOrder {
-                price: 7,
-                current_amount: 2,
+                price: 7.into(),
+                current_amount: 2.into(),
                 side: Side::Buy,
                 signer: String::from("Bob"),
                 ordinal: 10,
-                remaining_amount: 2,
+                remaining_amount: 2.into(),
             },
             matching_engine
                 .bids
-                .get(&7)
+                .get(&Price::from(7))
                 .unwrap()
                 .peek()
                 .unwrap()
@@ -1877,20 +2133,20 @@ mod tests {
         assert_eq!(
             vec![
                 PartialOrder {
-                    price: 9,
-                    current_amount: 2,
+                    price: 9.into(),
+                    current_amount: 2.into(),
                     side: Side::Buy,
                     signer: String::from("Don"),
                     ordinal: 12,
-                    remaining_amount: 0,
+                    remaining_amount: 0.into(),
                 },
                 PartialOrder {
-                    price: 7,
-                    current_amount: 2,
+                    price: 7.into(),
+                    current_amount: 2.into(),
                     side: Side::Buy,
                     signer: String::from("Bob"),
                     ordinal: 10,
-                    remaining_amount: 1,
+                    remaining_amount: 1.into(),
                 },
             ],
             jane_receipt.matches
@@ -1925,20 +2181,20 @@ mod tests {
         assert_eq!(
             vec![
                 PartialOrder {
-                    price: 9,
-                    current_amount: 2,
+                    price: 9.into(),
+                    current_amount: 2.into(),
                     side: Side::Buy,
                     signer: String::from("Don"),
                     ordinal: 15,
-                    remaining_amount: 0,
+                    remaining_amount: 0.into(),
                 },
                 PartialOrder {
-                    price: 7,
-                    current_amount: 1,
+                    price: 7.into(),
+                    current_amount: 1.into(),
                     side: Side::Buy,
                     signer: String::from("Bob"),
                     ordinal: 10,
-                    remaining_amount: 0,
+                    remaining_amount: 0.into(),
                 },
             ],
             jane_receipt.matches
@@ -1952,4 +2208,211 @@ mod tests {
         // We also keep a record of unmatched orders, not only the matched ones.
         assert_eq!(16, matching_engine.history.len());
     }
+
+    #[test]
+    fn cancel_removes_resting_order_and_empty_price_entry() {
+        let mut matching_engine = MatchingEngine::new();
+
+        matching_engine
+            .process(Order::new(10, 2, Side::Sell, String::from("Alice")))
+            .unwrap();
+        matching_engine
+            .process(Order::new(10, 3, Side::Sell, String::from("Bob")))
+            .unwrap();
+        matching_engine
+            .process(Order::new(8, 1, Side::Buy, String::from("Charlie")))
+            .unwrap();
+
+        assert_eq!(
+            Some(PartialOrder {
+                price: 10.into(),
+                current_amount: 2.into(),
+                side: Side::Sell,
+                signer: String::from("Alice"),
+                ordinal: 1,
+                remaining_amount: 2.into(),
+            }),
+            matching_engine.cancel(1)
+        );
+        assert_eq!(1, matching_engine.asks.get(&Price::from(10)).unwrap().len());
+        assert!(matching_engine.get(1).is_none());
+        assert_eq!(2, matching_engine.get(2).unwrap().ordinal);
+
+        // The last order at a price point takes the price point with it.
+        assert_eq!(3, matching_engine.cancel(3).unwrap().ordinal);
+        assert!(matching_engine.bids.is_empty());
+
+        // An order can't be cancelled twice.
+        assert!(matching_engine.cancel(1).is_none());
+        assert!(matching_engine.cancel(42).is_none());
+    }
+
+    #[test]
+    fn rollback_restores_the_order_book() {
+        let mut matching_engine =
+            MatchingEngine::new().with_price_rule(ExecutionPriceRule::TakerPrice);
+        let book = |matching_engine: &MatchingEngine| {
+            [&matching_engine.asks, &matching_engine.bids].map(|side| {
+                side.iter()
+                    .map(|(price, heap)| (*price, heap.clone().into_sorted_vec()))
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        matching_engine
+            .process(Order::new(10, 5, Side::Sell, String::from("Alice")))
+            .unwrap();
+        matching_engine
+            .process(Order::new(11, 3, Side::Sell, String::from("Bob")))
+            .unwrap();
+        matching_engine
+            .process(Order::new(9, 1, Side::Buy, String::from("Bob")))
+            .unwrap();
+        let before = book(&matching_engine);
+
+        // Alice's order is filled, and Bob's is filled in part.
+        let (receipt, rollback) = matching_engine
+            .process_unrecorded(Order::new(12, 7, Side::Buy, String::from("Charlie")))
+            .unwrap();
+        assert_eq!(2, receipt.matches.len());
+        assert_ne!(before, book(&matching_engine));
+        matching_engine.rollback(rollback);
+        assert_eq!(before, book(&matching_engine));
+
+        // Both are filled, and the rest of the order rests.
+        let (receipt, rollback) = matching_engine
+            .process_unrecorded(Order::new(12, 10, Side::Buy, String::from("Charlie")))
+            .unwrap();
+        assert_eq!(2, receipt.matches.len());
+        assert!(matching_engine.get(receipt.ordinal).is_some());
+        matching_engine.rollback(rollback);
+        assert_eq!(before, book(&matching_engine));
+
+        // Nothing is recorded, and the ordinals aren't reused.
+        assert_eq!(3, matching_engine.history.len());
+        assert_eq!(5, matching_engine.ordinal);
+    }
+
+    #[test]
+    fn process_err_ordinals_exhausted() {
+        let mut matching_engine = MatchingEngine::new();
+        matching_engine
+            .process(Order::new(10, 5, Side::Sell, String::from("Alice")))
+            .unwrap();
+        matching_engine.ordinal = u64::MAX;
+
+        assert!(matches!(
+            matching_engine.process(Order::new(10, 5, Side::Buy, String::from("Bob"))),
+            Err(AccountingError::ArithmeticOverflow(_))
+        ));
+        assert_eq!(u64::MAX, matching_engine.ordinal);
+        assert_eq!(1, matching_engine.history.len());
+        assert_eq!(
+            Quantity::from(5),
+            matching_engine.get(1).unwrap().remaining_amount
+        );
+    }
+
+    #[test]
+    fn cancel_partially_matched_order_returns_unmatched_amount() {
+        let mut matching_engine = MatchingEngine::new();
+
+        matching_engine
+            .process(Order::new(10, 5, Side::Sell, String::from("Alice")))
+            .unwrap();
+        matching_engine
+            .process(Order::new(10, 2, Side::Buy, String::from("Bob")))
+            .unwrap();
+
+        let cancelled = matching_engine.cancel(1).unwrap();
+        assert_eq!(Quantity::from(3), cancelled.remaining_amount);
+        assert!(matching_engine.asks.is_empty());
+
+        // The cancelled order can't be matched anymore.
+        let charlie_receipt = matching_engine
+            .process(Order::new(10, 1, Side::Buy, String::from("Charlie")))
+            .unwrap();
+        assert!(charlie_receipt.matches.is_empty());
+    }
+
+    #[test]
+    fn cancel_all_removes_signers_orders_on_both_sides() {
+        let mut matching_engine = MatchingEngine::new();
+        for (price, side, signer) in [
+            (12, Side::Sell, "Alice"),
+            (11, Side::Sell, "Bob"),
+            (9, Side::Buy, "Alice"),
+            (12, Side::Sell, "Alice"),
+        ] {
+            matching_engine
+                .process(Order::new(price, 1, side, signer.to_string()))
+                .unwrap();
+        }
+
+        let cancelled = matching_engine.cancel_all("Alice", None);
+        assert_eq!(
+            vec![1, 3, 4],
+            cancelled.iter().map(|po| po.ordinal).collect::<Vec<_>>()
+        );
+
+        // Only Bob's order remains, and the emptied price levels are gone.
+        assert!(matching_engine.bids.is_empty());
+        assert_eq!(1, matching_engine.asks.len());
+        assert!(matching_engine.get(2).is_some());
+
+        assert!(matching_engine.cancel_all("Alice", None).is_empty());
+    }
+
+    #[test]
+    fn cancel_all_with_side_filter() {
+        let mut matching_engine = MatchingEngine::new();
+        matching_engine
+            .process(Order::new(12, 1, Side::Sell, "Alice".to_string()))
+            .unwrap();
+        matching_engine
+            .process(Order::new(9, 1, Side::Buy, "Alice".to_string()))
+            .unwrap();
+
+        let cancelled = matching_engine.cancel_all("Alice", Some(Side::Buy));
+        assert_eq!(1, cancelled.len());
+        assert_eq!(2, cancelled[0].ordinal);
+
+        assert!(matching_engine.bids.is_empty());
+        assert!(matching_engine.get(1).is_some());
+    }
+
+    #[test]
+    fn execution_price_rule_sets_match_price() {
+        for (price_rule, expected) in [
+            (ExecutionPriceRule::MakerPrice, Price::from(10)),
+            (ExecutionPriceRule::TakerPrice, Price::from(14)),
+            (ExecutionPriceRule::Midpoint, Price::from(12)),
+        ] {
+            let mut matching_engine = MatchingEngine::new().with_price_rule(price_rule);
+            assert_eq!(price_rule, matching_engine.price_rule());
+
+            matching_engine
+                .process(Order::new(10, 2, Side::Sell, "Alice".to_string()))
+                .unwrap();
+            let receipt = matching_engine
+                .process(Order::new(14, 1, Side::Buy, "Bob".to_string()))
+                .unwrap();
+            assert_eq!(expected, receipt.matches[0].price);
+
+            // The rest of the maker's order keeps its own limit price.
+            assert_eq!(Price::from(10), matching_engine.get(1).unwrap().price);
+        }
+    }
+
+    #[test]
+    fn execution_price_rule_from_str() {
+        for price_rule in [
+            ExecutionPriceRule::MakerPrice,
+            ExecutionPriceRule::TakerPrice,
+            ExecutionPriceRule::Midpoint,
+        ] {
+            assert_eq!(Ok(price_rule), price_rule.to_string().parse());
+        }
+        assert!("best".parse::<ExecutionPriceRule>().is_err());
+    }
 }
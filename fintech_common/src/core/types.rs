@@ -1,6 +1,11 @@
+use crate::errors::AccountingError;
 use serde::{Deserialize, Serialize};
 use std::cmp::{Ordering, Reverse};
 
+pub use crate::core::fixed_point::{
+    Amount, InstrumentSpec, Money, Price, Quantity, Rounding, MAX_SCALE,
+};
+
 /// **A simplified side of a position ([`PartialOrder`]) or of an [`Order`]**
 #[derive(Clone, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
 pub enum Side {
@@ -24,10 +29,10 @@ pub enum Side {
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct Order {
     /// Highest price to buy at or lowest price to sell at, per unit, depending on the side
-    pub price: u64,
+    pub price: Price,
     /// Initial number of units to trade when the order enters the order book;
     /// it's private because it is constant, but has a getter.
-    initial_amount: u64,
+    initial_amount: Quantity,
     /// The side in the order book (buy or sell)
     pub side: Side,
     /// The signer's account
@@ -35,17 +40,22 @@ pub struct Order {
 }
 
 impl Order {
-    pub fn new(price: u64, initial_amount: u64, side: Side, signer: String) -> Self {
+    pub fn new(
+        price: impl Into<Price>,
+        initial_amount: impl Into<Quantity>,
+        side: Side,
+        signer: String,
+    ) -> Self {
         Self {
-            price,
-            initial_amount,
+            price: price.into(),
+            initial_amount: initial_amount.into(),
             side,
             signer,
         }
     }
 
     /// Converts an [`Order`] into a [`PartialOrder`] with the added parameters.
-    pub fn into_partial_order(self, ordinal: u64, remaining_amount: u64) -> PartialOrder {
+    pub fn into_partial_order(self, ordinal: u64, remaining_amount: Quantity) -> PartialOrder {
         PartialOrder {
             price: self.price,
             current_amount: self.initial_amount,
@@ -56,9 +66,19 @@ impl Order {
         }
     }
 
-    pub fn get_initial_amount(&self) -> u64 {
+    pub fn get_initial_amount(&self) -> Quantity {
         self.initial_amount
     }
+
+    /// Brings the order's price and amount to the instrument's scales.
+    ///
+    /// # Errors
+    /// - The price or the amount has more decimals than allowed, `OrderError::Invalid`
+    pub fn normalize(mut self, spec: &InstrumentSpec) -> Result<Self, AccountingError> {
+        self.price = spec.normalize_price(self.price)?;
+        self.initial_amount = spec.normalize_quantity(self.initial_amount)?;
+        Ok(self)
+    }
 }
 
 /// **A position, or a `PartialOrder`, represents an unfilled order that is kept in the system for later filling.**
@@ -82,16 +102,16 @@ impl Order {
 /// Each `PartialOrder` has its unique sequence number as part of its metadata.
 /// In case of two equal offers, the one with a lower sequence number takes precedence over the other one.
 /// This is because it came into the system (into the order book) first, and this is the rule that we apply.
-#[derive(Clone, Debug, Deserialize, Eq, Ord, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct PartialOrder {
     /// Price per unit. This gets stored in the receipt as the best price of a matched order.
     /// So, it may start as one value, and end as another, better, value.
-    pub price: u64,
+    pub price: Price,
     /// Current number of units in the order, at the beginning of the processing of the partial order.
     /// So, this is an initial amount at the beginning of a single partial order processing, and not
     /// necessarily its global initial amount, when it was first introduced in the system
     /// (in the order book), i.e., when it was created from an `Order`.
-    pub current_amount: u64,
+    pub current_amount: Quantity,
     /// Buy or sell side of the book
     pub side: Side,
     /// Signer of the order
@@ -99,14 +119,20 @@ pub struct PartialOrder {
     /// The order's unique sequence number
     pub ordinal: u64,
     /// Remaining number of units after potential matches
-    pub remaining_amount: u64,
+    pub remaining_amount: Quantity,
 }
 
-impl PartialOrd for PartialOrder {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+impl Ord for PartialOrder {
+    fn cmp(&self, other: &Self) -> Ordering {
         // A `BinaryHeap` is a max-heap by default, so we have to `Reverse`
         // the comparison to create a min-heap which we need.
-        Reverse(self.ordinal).partial_cmp(&Reverse(other.ordinal))
+        Reverse(self.ordinal).cmp(&Reverse(other.ordinal))
+    }
+}
+
+impl PartialOrd for PartialOrder {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
@@ -120,8 +146,8 @@ impl PartialOrder {
     ///
     /// The new partial order's current (initial) amount is then overwritten by the `take` value,
     /// and its price is overwritten by the `price` value, and it is then returned by this function.
-    pub fn _take_from(current_po: &mut PartialOrder, take: u64, price: u64) -> PartialOrder {
-        current_po.remaining_amount -= take;
+    pub fn _take_from(current_po: &mut PartialOrder, take: Quantity, price: Price) -> PartialOrder {
+        current_po.remaining_amount = current_po.remaining_amount.saturating_sub(take);
         let mut new_pos = current_po.clone();
         new_pos.current_amount = take; // I think this is wrong!!! Then docstrings are wrong, too!
         new_pos.price = price; // I think this is wrong!!! Then docstrings are wrong, too!
@@ -1,10 +1,453 @@
+use crate::limits::Limit;
+use crate::types::{Money, Quantity};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt;
+use thiserror::Error;
+
 /// **An application-specific error type**
-#[derive(Debug, PartialEq)]
+///
+/// Errors of a domain, such as accounts or orders, have a type of their own, which converts into
+/// this one. Every error belongs to an [`ErrorKind`], and has a stable, machine-readable
+/// [`code`](Self::code); its `Display` is the human-readable message.
+#[derive(Clone, Debug, Error, PartialEq)]
 pub enum AccountingError {
+    #[error(transparent)]
+    Account(#[from] AccountError),
+    #[error(transparent)]
+    Order(#[from] OrderError),
+    #[error(transparent)]
+    Margin(#[from] MarginError),
+    #[error(transparent)]
+    Fx(#[from] FxError),
+    #[error(transparent)]
+    Limit(#[from] LimitError),
+    #[error("{0}")]
+    FeeScheduleInvalid(String),
+    #[error("There's no market for \"{0}\".")]
+    MarketNotFound(String),
+    #[error("{0}")]
+    ArithmeticOverflow(String),
+    #[error("The transactions up to #{0} aren't retained anymore; only the ones after it are.")]
+    HistoryUnavailable(u64),
+}
+
+/// **An error of an account's balance, holdings, or status**
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum AccountError {
+    #[error("The account \"{0}\" doesn't exist.")]
+    NotFound(String),
+    #[error("The account \"{0}\" doesn't have {1} available.")]
+    UnderFunded(String, Money),
+    #[error("The account \"{0}\" can't take another {1}.")]
+    OverFunded(String, Money),
+    #[error("The account \"{0}\" doesn't have {2} units of {1} available.")]
+    HoldingsInsufficient(String, String, Quantity),
+    #[error("The account \"{0}\" can't take another {2} units of {1}.")]
+    HoldingsOverflow(String, String, Quantity),
+    #[error("The account \"{0}\" isn't empty, and there's nowhere to sweep it.")]
+    NotEmpty(String),
+    #[error("The account \"{0}\" has open orders.")]
+    HasOpenOrders(String),
+    #[error("The account \"{0}\" is frozen.")]
+    Frozen(String),
+    #[error("The account \"{0}\" is withdraw-only; it can only reduce its exposure.")]
+    WithdrawOnly(String),
+    #[error("The account \"{0}\" is closed.")]
+    Closed(String),
+}
+
+/// **An error of an order, or of an order group, and of its risk checks**
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum OrderError {
+    #[error("{0}")]
+    Invalid(String),
+    #[error("{0}")]
+    GroupInvalid(String),
+    #[error("The order group {0} doesn't exist.")]
+    GroupNotFound(u64),
+    #[error("The account \"{0}\" doesn't have the {1} that the order may cost available.")]
+    UnderFunded(String, Money),
+    #[error(
+        "The account \"{0}\" doesn't have the {2} units of {1} that the order sells available."
+    )]
+    HoldingsInsufficient(String, String, Quantity),
+}
+
+/// **An error of margin trading**
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum MarginError {
+    #[error("{0}")]
+    ConfigInvalid(String),
+    #[error("Margin trading isn't enabled.")]
+    Disabled,
+    #[error("The account \"{0}\" isn't a margin account.")]
     AccountNotFound(String),
-    AccountUnderFunded(String, u64),
-    AccountOverFunded(String, u64),
+    #[error(
+        "The account \"{0}\" doesn't have the equity of {1} that the initial margin requires."
+    )]
+    Insufficient(String, Money),
+    #[error("The account \"{0}\" has a margin loan to repay.")]
+    LoanOutstanding(String),
+    #[error("The account \"{0}\" doesn't owe {1}.")]
+    LoanExceeded(String, Money),
+}
+
+/// **An error of currencies, their amounts, and their exchange rates**
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum FxError {
+    #[error("{0}")]
+    CurrencyInvalid(String),
+    #[error("There's no exchange rate from {0} to {1}.")]
+    RateNotFound(String, String),
+    #[error("The account \"{0}\" doesn't have {2} {1} available.")]
+    UnderFunded(String, String, Money),
+    #[error("The account \"{0}\" can't take another {2} {1}.")]
+    OverFunded(String, String, Money),
+    #[error("{0}")]
+    AmountInvalid(String),
+}
+
+/// **An error of an account's limits**
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum LimitError {
+    #[error("The account \"{0}\" would exceed its {1} limit.")]
+    Exceeded(String, Limit),
+}
+
+/// **The category of an error**
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// Balances or holdings don't allow the change, such as a withdrawal of more than is available.
+    Accounting,
+    /// An order, or another request, isn't valid by itself, regardless of the accounts.
+    Validation,
+    /// An order is valid, but the signer can't take its risk, such as buying more than they can afford.
+    Risk,
+    /// Something that the request refers to doesn't exist.
+    NotFound,
+    /// The request conflicts with the current state, such as closing an account with open orders,
+    /// or anything that the account's status doesn't allow.
+    Conflict,
+}
+
+impl AccountingError {
+    /// The category of the error
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            AccountingError::Account(err) => err.kind(),
+            AccountingError::Order(err) => err.kind(),
+            AccountingError::Margin(err) => err.kind(),
+            AccountingError::Fx(err) => err.kind(),
+            AccountingError::Limit(_) => ErrorKind::Risk,
+            AccountingError::FeeScheduleInvalid(_) | AccountingError::ArithmeticOverflow(_) => {
+                ErrorKind::Validation
+            }
+            AccountingError::MarketNotFound(_) | AccountingError::HistoryUnavailable(_) => {
+                ErrorKind::NotFound
+            }
+        }
+    }
+
+    /// The stable, machine-readable code of the error
+    pub fn code(&self) -> &'static str {
+        match self {
+            AccountingError::Account(err) => err.code(),
+            AccountingError::Order(err) => err.code(),
+            AccountingError::Margin(err) => err.code(),
+            AccountingError::Fx(err) => err.code(),
+            AccountingError::Limit(LimitError::Exceeded(..)) => "LIMIT_EXCEEDED",
+            AccountingError::FeeScheduleInvalid(_) => "FEE_SCHEDULE_INVALID",
+            AccountingError::MarketNotFound(_) => "MARKET_NOT_FOUND",
+            AccountingError::ArithmeticOverflow(_) => "ARITHMETIC_OVERFLOW",
+            AccountingError::HistoryUnavailable(_) => "HISTORY_UNAVAILABLE",
+        }
+    }
+
+    /// The kind of the error, along with the values that it refers to, such as an account
+    pub fn details(&self) -> Value {
+        let mut details = match self {
+            AccountingError::Account(err) => err.details(),
+            AccountingError::Order(err) => err.details(),
+            AccountingError::Margin(err) => err.details(),
+            AccountingError::Fx(err) => err.details(),
+            AccountingError::Limit(LimitError::Exceeded(signer, limit)) => {
+                json!({ "account": signer, "limit": limit })
+            }
+            AccountingError::MarketNotFound(symbol) => json!({ "symbol": symbol }),
+            AccountingError::HistoryUnavailable(id) => json!({ "tx_id": id }),
+            AccountingError::FeeScheduleInvalid(_) | AccountingError::ArithmeticOverflow(_) => {
+                json!({})
+            }
+        };
+        details["kind"] = json!(self.kind());
+        details
+    }
+}
+
+impl AccountError {
+    /// The category of the error
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            AccountError::NotFound(_) => ErrorKind::NotFound,
+            AccountError::UnderFunded(..)
+            | AccountError::OverFunded(..)
+            | AccountError::HoldingsInsufficient(..)
+            | AccountError::HoldingsOverflow(..) => ErrorKind::Accounting,
+            AccountError::NotEmpty(_)
+            | AccountError::HasOpenOrders(_)
+            | AccountError::Frozen(_)
+            | AccountError::WithdrawOnly(_)
+            | AccountError::Closed(_) => ErrorKind::Conflict,
+        }
+    }
+
+    /// The stable, machine-readable code of the error
+    pub fn code(&self) -> &'static str {
+        match self {
+            AccountError::NotFound(_) => "ACCOUNT_NOT_FOUND",
+            AccountError::UnderFunded(..) => "ACCOUNT_UNDER_FUNDED",
+            AccountError::OverFunded(..) => "ACCOUNT_OVER_FUNDED",
+            AccountError::HoldingsInsufficient(..) => "HOLDINGS_INSUFFICIENT",
+            AccountError::HoldingsOverflow(..) => "HOLDINGS_OVERFLOW",
+            AccountError::NotEmpty(_) => "ACCOUNT_NOT_EMPTY",
+            AccountError::HasOpenOrders(_) => "ACCOUNT_HAS_OPEN_ORDERS",
+            AccountError::Frozen(_) => "ACCOUNT_FROZEN",
+            AccountError::WithdrawOnly(_) => "ACCOUNT_WITHDRAW_ONLY",
+            AccountError::Closed(_) => "ACCOUNT_CLOSED",
+        }
+    }
+
+    fn details(&self) -> Value {
+        match self {
+            AccountError::NotFound(signer)
+            | AccountError::NotEmpty(signer)
+            | AccountError::HasOpenOrders(signer)
+            | AccountError::Frozen(signer)
+            | AccountError::WithdrawOnly(signer)
+            | AccountError::Closed(signer) => json!({ "account": signer }),
+            AccountError::UnderFunded(signer, amount)
+            | AccountError::OverFunded(signer, amount) => {
+                json!({ "account": signer, "amount": amount })
+            }
+            AccountError::HoldingsInsufficient(signer, symbol, units)
+            | AccountError::HoldingsOverflow(signer, symbol, units) => {
+                json!({ "account": signer, "symbol": symbol, "units": units })
+            }
+        }
+    }
+}
+
+impl OrderError {
+    /// The category of the error
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            OrderError::Invalid(_) | OrderError::GroupInvalid(_) => ErrorKind::Validation,
+            OrderError::GroupNotFound(_) => ErrorKind::NotFound,
+            OrderError::UnderFunded(..) | OrderError::HoldingsInsufficient(..) => ErrorKind::Risk,
+        }
+    }
+
+    /// The stable, machine-readable code of the error
+    pub fn code(&self) -> &'static str {
+        match self {
+            OrderError::Invalid(_) => "ORDER_INVALID",
+            OrderError::GroupInvalid(_) => "ORDER_GROUP_INVALID",
+            OrderError::GroupNotFound(_) => "ORDER_GROUP_NOT_FOUND",
+            OrderError::UnderFunded(..) => "ORDER_UNDER_FUNDED",
+            OrderError::HoldingsInsufficient(..) => "ORDER_HOLDINGS_INSUFFICIENT",
+        }
+    }
+
+    fn details(&self) -> Value {
+        match self {
+            OrderError::Invalid(_) | OrderError::GroupInvalid(_) => json!({}),
+            OrderError::GroupNotFound(id) => json!({ "order_group": id }),
+            OrderError::UnderFunded(signer, amount) => {
+                json!({ "account": signer, "amount": amount })
+            }
+            OrderError::HoldingsInsufficient(signer, symbol, units) => {
+                json!({ "account": signer, "symbol": symbol, "units": units })
+            }
+        }
+    }
+}
+
+impl MarginError {
+    /// The category of the error
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            MarginError::ConfigInvalid(_) | MarginError::Disabled => ErrorKind::Validation,
+            MarginError::AccountNotFound(_) => ErrorKind::NotFound,
+            MarginError::Insufficient(..) => ErrorKind::Risk,
+            MarginError::LoanOutstanding(_) => ErrorKind::Conflict,
+            MarginError::LoanExceeded(..) => ErrorKind::Accounting,
+        }
+    }
+
+    /// The stable, machine-readable code of the error
+    pub fn code(&self) -> &'static str {
+        match self {
+            MarginError::ConfigInvalid(_) => "MARGIN_CONFIG_INVALID",
+            MarginError::Disabled => "MARGIN_DISABLED",
+            MarginError::AccountNotFound(_) => "MARGIN_ACCOUNT_NOT_FOUND",
+            MarginError::Insufficient(..) => "INSUFFICIENT_MARGIN",
+            MarginError::LoanOutstanding(_) => "LOAN_OUTSTANDING",
+            MarginError::LoanExceeded(..) => "LOAN_EXCEEDED",
+        }
+    }
+
+    fn details(&self) -> Value {
+        match self {
+            MarginError::ConfigInvalid(_) | MarginError::Disabled => json!({}),
+            MarginError::AccountNotFound(signer) | MarginError::LoanOutstanding(signer) => {
+                json!({ "account": signer })
+            }
+            MarginError::Insufficient(signer, amount)
+            | MarginError::LoanExceeded(signer, amount) => {
+                json!({ "account": signer, "amount": amount })
+            }
+        }
+    }
+}
+
+impl FxError {
+    /// The category of the error
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            FxError::CurrencyInvalid(_) | FxError::AmountInvalid(_) => ErrorKind::Validation,
+            FxError::RateNotFound(..) => ErrorKind::NotFound,
+            FxError::UnderFunded(..) | FxError::OverFunded(..) => ErrorKind::Accounting,
+        }
+    }
+
+    /// The stable, machine-readable code of the error
+    pub fn code(&self) -> &'static str {
+        match self {
+            FxError::CurrencyInvalid(_) => "CURRENCY_INVALID",
+            FxError::RateNotFound(..) => "FX_RATE_NOT_FOUND",
+            FxError::UnderFunded(..) => "CURRENCY_UNDER_FUNDED",
+            FxError::OverFunded(..) => "CURRENCY_OVER_FUNDED",
+            FxError::AmountInvalid(_) => "AMOUNT_INVALID",
+        }
+    }
+
+    fn details(&self) -> Value {
+        match self {
+            FxError::CurrencyInvalid(_) | FxError::AmountInvalid(_) => json!({}),
+            FxError::RateNotFound(from, to) => json!({ "from": from, "to": to }),
+            FxError::UnderFunded(signer, currency, amount)
+            | FxError::OverFunded(signer, currency, amount) => {
+                json!({ "account": signer, "currency": currency, "amount": amount })
+            }
+        }
+    }
+}
+
+/// **An error, in the form that the web service responds with**
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ErrorResponse {
+    /// The stable, machine-readable code, such as [`AccountingError::code`]
+    pub code: String,
+    /// The human-readable message
+    pub message: String,
+    /// Anything else that is known about the error, such as [`AccountingError::details`]
+    pub details: Value,
+}
+
+impl ErrorResponse {
+    pub fn new(code: &str, message: impl fmt::Display) -> Self {
+        ErrorResponse {
+            code: code.to_string(),
+            message: message.to_string(),
+            details: json!({}),
+        }
+    }
+}
+
+impl From<&AccountingError> for ErrorResponse {
+    fn from(err: &AccountingError) -> Self {
+        ErrorResponse {
+            code: err.code().to_string(),
+            message: err.to_string(),
+            details: err.details(),
+        }
+    }
+}
+
+impl fmt::Display for ErrorResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
 }
 
 pub const SIGNER_NAME_NOT_VALID_MSG: &str = "The signer's name is not valid";
 pub const SIGNER_EMPTY_NAME_MSG: &str = "Signer's name cannot be empty.";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accounting_error_kind_code_and_message() {
+        let err = AccountingError::Account(AccountError::NotFound("Alice".to_string()));
+        assert_eq!(ErrorKind::NotFound, err.kind());
+        assert_eq!("ACCOUNT_NOT_FOUND", err.code());
+        assert_eq!("The account \"Alice\" doesn't exist.", err.to_string());
+        assert_eq!(err, AccountError::NotFound("Alice".to_string()).into());
+
+        let err = AccountingError::Order(OrderError::UnderFunded(
+            "Bob".to_string(),
+            "10.5".parse().unwrap(),
+        ));
+        assert_eq!(ErrorKind::Risk, err.kind());
+        assert_eq!("ORDER_UNDER_FUNDED", err.code());
+        assert_eq!(
+            "The account \"Bob\" doesn't have the 10.5 that the order may cost available.",
+            err.to_string()
+        );
+
+        let err = AccountingError::Order(OrderError::Invalid(
+            "The price 1.5 has more than 0 decimals.".into(),
+        ));
+        assert_eq!(ErrorKind::Validation, err.kind());
+        assert_eq!("The price 1.5 has more than 0 decimals.", err.to_string());
+
+        assert_eq!(
+            ErrorKind::Conflict,
+            AccountingError::Account(AccountError::HasOpenOrders("Alice".to_string())).kind()
+        );
+        assert_eq!(
+            "\"not_found\"",
+            serde_json::to_string(&ErrorKind::NotFound).unwrap()
+        );
+    }
+
+    #[test]
+    fn error_response_from_accounting_error() {
+        let err = AccountingError::Account(AccountError::HoldingsInsufficient(
+            "Bob".to_string(),
+            "ABC".to_string(),
+            5.into(),
+        ));
+        let response = ErrorResponse::from(&err);
+        assert_eq!(
+            json!({
+                "code": "HOLDINGS_INSUFFICIENT",
+                "message": "The account \"Bob\" doesn't have 5 units of ABC available.",
+                "details": {
+                    "kind": "accounting",
+                    "account": "Bob",
+                    "symbol": "ABC",
+                    "units": "5"
+                }
+            }),
+            serde_json::to_value(&response).unwrap()
+        );
+        assert_eq!(
+            "HOLDINGS_INSUFFICIENT: The account \"Bob\" doesn't have 5 units of ABC available.",
+            response.to_string()
+        );
+    }
+}
@@ -0,0 +1,291 @@
+//! Maker/taker fee schedule
+//!
+//! Every match has two sides: the maker, whose order was resting in the order book,
+//! and the taker, whose incoming order matched it. Each side pays a fee, in basis points
+//! (1/100th of a percent) of the match's value, to the platform's fee account.
+//!
+//! A negative maker fee is a rebate, which the fee account pays to the maker.
+//! A rebate is capped at the taker's fee on the same match,
+//! so the fee account never pays out more than it collects.
+//!
+//! Fees and rebates are rounded down to the decimals of the currency that the match settles in.
+//! A fee is then in the favour of the user who pays it, and a rebate in the favour of the platform,
+//! by less than one of the currency's smallest units. Rounding down also means that the fees
+//! of an order's fills never add up to more than its fee on the whole, which its funds are
+//! reserved for, and that balances keep the currency's decimals however many fills they take.
+
+use crate::errors::AccountingError;
+use crate::types::{Money, Price, Quantity, Rounding};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// The name of the account that collects fees by default
+pub const DEFAULT_FEE_ACCOUNT: &str = "Fintech Platform Fees";
+
+/// The highest fee, 100%, in basis points
+pub const MAX_FEE_BPS: u32 = 10_000;
+
+/// **A fee that is applied to one side of a match**
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Fee {
+    /// Paid by the signer to the fee account.
+    Charge(Money),
+    /// Paid by the fee account to the signer.
+    Rebate(Money),
+}
+
+/// **The maker and the taker fee, in basis points**
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct FeeTier {
+    /// Negative for a rebate
+    maker_bps: i32,
+    taker_bps: u32,
+}
+
+impl FeeTier {
+    /// Creates a tier, where a negative `maker_bps` is a rebate.
+    ///
+    /// # Errors
+    /// - A fee exceeds [`MAX_FEE_BPS`], or the maker rebate exceeds the taker fee,
+    ///   `AccountingError::FeeScheduleInvalid`
+    pub fn new(maker_bps: i32, taker_bps: u32) -> Result<Self, AccountingError> {
+        if maker_bps.unsigned_abs() > MAX_FEE_BPS || taker_bps > MAX_FEE_BPS {
+            return Err(AccountingError::FeeScheduleInvalid(format!(
+                "Fees can't exceed {} basis points.",
+                MAX_FEE_BPS
+            )));
+        }
+        if maker_bps < 0 && maker_bps.unsigned_abs() > taker_bps {
+            return Err(AccountingError::FeeScheduleInvalid(
+                "The maker rebate can't exceed the taker fee.".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            maker_bps,
+            taker_bps,
+        })
+    }
+
+    pub fn maker_bps(&self) -> i32 {
+        self.maker_bps
+    }
+
+    pub fn taker_bps(&self) -> u32 {
+        self.taker_bps
+    }
+
+    /// The maker's fee or rebate for a match of the given value,
+    /// rounded down to `scale` decimals, the currency's.
+    pub fn maker_fee(&self, value: Money, scale: u8) -> Fee {
+        let fee = value.basis_points(self.maker_bps.unsigned_abs(), scale, Rounding::Down);
+        if self.maker_bps < 0 {
+            Fee::Rebate(fee)
+        } else {
+            Fee::Charge(fee)
+        }
+    }
+
+    /// The taker's fee for a match of the given value, rounded down to `scale` decimals, the currency's.
+    pub fn taker_fee(&self, value: Money, scale: u8) -> Money {
+        value.basis_points(self.taker_bps, scale, Rounding::Down)
+    }
+
+    /// The highest fee for a match of the given value, whether as the maker or as the taker,
+    /// rounded down to `scale` decimals, the currency's.
+    pub fn max_fee(&self, value: Money, scale: u8) -> Money {
+        let maker_bps = u32::try_from(self.maker_bps).unwrap_or(0);
+        value.basis_points(maker_bps.max(self.taker_bps), scale, Rounding::Down)
+    }
+}
+
+impl FromStr for FeeTier {
+    type Err = String;
+
+    /// Parses `<maker bps>:<taker bps>`, such as `"-2:5"`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid fee tier: \"{s}\"; expected \"<maker bps>:<taker bps>\", such as \"-2:5\""
+            )
+        };
+
+        let (maker_bps, taker_bps) = s.trim().split_once(':').ok_or_else(invalid)?;
+        let maker_bps = maker_bps.parse::<i32>().map_err(|_| invalid())?;
+        let taker_bps = taker_bps.parse::<u32>().map_err(|_| invalid())?;
+
+        FeeTier::new(maker_bps, taker_bps).map_err(|err| format!("{}: {:?}", invalid(), err))
+    }
+}
+
+/// **The fees that apply to every match, with optional per-signer tiers**
+///
+/// By default, trading is free, and fees are collected in [`DEFAULT_FEE_ACCOUNT`].
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct FeeSchedule {
+    /// The tier of every signer without a tier of their own
+    default_tier: FeeTier,
+    /// Tiers of individual signers
+    signer_tiers: BTreeMap<String, FeeTier>,
+    /// The account that collects fees and pays rebates
+    fee_account: String,
+}
+
+impl Default for FeeSchedule {
+    fn default() -> Self {
+        Self::new(FeeTier::default())
+    }
+}
+
+impl FeeSchedule {
+    /// Creates a schedule in which every signer is in the `default_tier`.
+    pub fn new(default_tier: FeeTier) -> Self {
+        Self {
+            default_tier,
+            signer_tiers: BTreeMap::new(),
+            fee_account: DEFAULT_FEE_ACCOUNT.to_string(),
+        }
+    }
+
+    /// Puts the `signer` in their own `tier`.
+    pub fn with_signer_tier(mut self, signer: &str, tier: FeeTier) -> Self {
+        self.signer_tiers.insert(signer.to_string(), tier);
+        self
+    }
+
+    /// Collects fees in the `fee_account` instead of [`DEFAULT_FEE_ACCOUNT`].
+    pub fn with_fee_account(mut self, fee_account: &str) -> Self {
+        self.fee_account = fee_account.to_string();
+        self
+    }
+
+    /// The tier that applies to the `signer`
+    pub fn tier_of(&self, signer: &str) -> &FeeTier {
+        self.signer_tiers.get(signer).unwrap_or(&self.default_tier)
+    }
+
+    /// The account that collects fees and pays rebates
+    pub fn fee_account(&self) -> &str {
+        &self.fee_account
+    }
+
+    /// **The most that buying the `amount` at the `price` can cost the `signer`**
+    ///
+    /// That is the value at the price, rounded down to `scale` decimals, the currency's,
+    /// as the value of a trade is, plus the signer's highest fee on it, rounded in the same way.
+    ///
+    /// # Errors
+    /// - The cost is too large for [`Money`], `AccountingError::ArithmeticOverflow`
+    pub fn worst_case_cost(
+        &self,
+        signer: &str,
+        price: Price,
+        amount: Quantity,
+        scale: u8,
+    ) -> Result<Money, AccountingError> {
+        let value = price.notional(amount, scale, Rounding::Down)?;
+        let fee = self.tier_of(signer).max_fee(value, scale);
+        value.checked_add(fee).ok_or_else(|| {
+            AccountingError::ArithmeticOverflow(format!(
+                "The value of {} at {}, with a fee of {}, is too large.",
+                amount, price, fee
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fee_tier_new_validates() {
+        assert!(FeeTier::new(-2, 5).is_ok());
+        assert!(FeeTier::new(5, 5).is_ok());
+        assert_eq!(
+            Err(AccountingError::FeeScheduleInvalid(
+                "The maker rebate can't exceed the taker fee.".to_string()
+            )),
+            FeeTier::new(-6, 5)
+        );
+        assert!(FeeTier::new(0, MAX_FEE_BPS + 1).is_err());
+    }
+
+    #[test]
+    fn fee_tier_maker_rebate_and_taker_fee() {
+        let tier = FeeTier::new(-2, 5).unwrap();
+        assert_eq!(
+            Fee::Rebate("0.2".parse().unwrap()),
+            tier.maker_fee(1000.into(), 2)
+        );
+        assert_eq!(
+            "0.5".parse::<Money>().unwrap(),
+            tier.taker_fee(1000.into(), 2)
+        );
+
+        let tier = FeeTier::new(10, 20).unwrap();
+        assert_eq!(Fee::Charge(1.into()), tier.maker_fee(1000.into(), 2));
+
+        // Both are rounded down to the currency's decimals.
+        let value: Money = "12.34".parse().unwrap();
+        assert_eq!(
+            Fee::Charge("0.01".parse().unwrap()),
+            tier.maker_fee(value, 2)
+        );
+        assert_eq!("0.02".parse::<Money>().unwrap(), tier.taker_fee(value, 2));
+        assert_eq!(
+            Fee::Rebate(Money::ZERO),
+            FeeTier::new(-2, 5).unwrap().maker_fee(value, 2)
+        );
+    }
+
+    #[test]
+    fn fee_schedule_worst_case_cost_uses_highest_fee() {
+        let schedule = FeeSchedule::new(FeeTier::new(-2, 5).unwrap())
+            .with_signer_tier("Alice", FeeTier::new(20, 10).unwrap());
+
+        assert_eq!(
+            "1000.5".parse::<Money>().unwrap(),
+            schedule
+                .worst_case_cost("Bob", 100.into(), 10.into(), 2)
+                .unwrap()
+        );
+        assert_eq!(
+            Money::from(1002),
+            schedule
+                .worst_case_cost("Alice", 100.into(), 10.into(), 2)
+                .unwrap()
+        );
+
+        // The value fits, but not with the fee on top of it.
+        assert!(matches!(
+            schedule.worst_case_cost("Alice", Price::from(u64::MAX / 2), 2.into(), 2),
+            Err(AccountingError::ArithmeticOverflow(_))
+        ));
+        assert!(matches!(
+            schedule.worst_case_cost("Alice", Price::from(u64::MAX), 2.into(), 2),
+            Err(AccountingError::ArithmeticOverflow(_))
+        ));
+    }
+
+    #[test]
+    fn fee_tier_from_str() {
+        assert_eq!(Ok(FeeTier::new(-2, 5).unwrap()), "-2:5".parse());
+        assert!("5".parse::<FeeTier>().is_err());
+        assert!("1:-5".parse::<FeeTier>().is_err());
+        assert!("-6:5".parse::<FeeTier>().is_err());
+    }
+
+    #[test]
+    fn fee_schedule_signer_tiers() {
+        let vip = FeeTier::new(0, 1).unwrap();
+        let schedule = FeeSchedule::new(FeeTier::new(5, 10).unwrap())
+            .with_signer_tier("Alice", vip.clone())
+            .with_fee_account("Fees");
+
+        assert_eq!(&vip, schedule.tier_of("Alice"));
+        assert_eq!(&FeeTier::new(5, 10).unwrap(), schedule.tier_of("Bob"));
+        assert_eq!("Fees", schedule.fee_account());
+    }
+}
@@ -0,0 +1,316 @@
+//! Currencies and foreign exchange
+//!
+//! Every account has a balance in the currency that its platform's instrument is quoted in,
+//! which orders are priced in and trades settle in. On top of it, an account can hold funds
+//! in other currencies, which are deposited, withdrawn and sent in the same way,
+//! and converted into one another, and into the balance's currency, at the rates of [`FxRates`].
+//!
+//! A rate is the price of one unit of a currency in another one, such as 1.08 for `EUR/USD`.
+//! Each direction has a rate of its own, so that the two can differ by a spread;
+//! a rate is never inverted.
+//!
+//! Each currency has one fixed number of decimals, its scale in [`CurrencyScales`].
+//! Amounts with more decimals are refused, and converted amounts are rounded down to it.
+
+use crate::errors::{AccountingError, FxError};
+use crate::types::{Money, Price, Rounding, MAX_SCALE};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// The currency that instruments are quoted in, unless configured otherwise
+pub const DEFAULT_CURRENCY: &str = "USD";
+
+/// The number of decimals of a currency's amounts, unless configured otherwise
+pub const DEFAULT_CURRENCY_SCALE: u8 = 2;
+
+/// **Checks that a currency's code is three uppercase letters, such as `EUR`**
+///
+/// # Errors
+/// - The code is anything else, `FxError::CurrencyInvalid`
+pub fn validate_currency(currency: &str) -> Result<(), AccountingError> {
+    if currency.len() == 3 && currency.bytes().all(|c| c.is_ascii_uppercase()) {
+        Ok(())
+    } else {
+        Err(FxError::CurrencyInvalid(format!(
+            "Invalid currency: \"{currency}\"; expected three uppercase letters, such as \"EUR\""
+        ))
+        .into())
+    }
+}
+
+/// **The exchange rates that funds are converted at, by the pair of currencies**
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FxRates {
+    rates: BTreeMap<(String, String), Price>,
+}
+
+impl FxRates {
+    /// A table without any rates; only conversions into the same currency are possible.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the price of one unit of the `from` currency in the `to` currency.
+    ///
+    /// # Errors
+    /// - Any of the two codes is invalid, they are the same, or the rate is zero,
+    ///   `FxError::CurrencyInvalid`
+    pub fn with_rate(mut self, from: &str, to: &str, rate: Price) -> Result<Self, AccountingError> {
+        validate_currency(from)?;
+        validate_currency(to)?;
+        if from == to || rate.is_zero() {
+            return Err(FxError::CurrencyInvalid(format!(
+                "The rate of {from}/{to} has to be between two currencies, and above zero."
+            ))
+            .into());
+        }
+
+        self.rates.insert((from.to_string(), to.to_string()), rate);
+        Ok(self)
+    }
+
+    /// The price of one unit of the `from` currency in the `to` currency, if there is one;
+    /// a currency is always worth one unit of itself.
+    pub fn rate(&self, from: &str, to: &str) -> Option<Price> {
+        match from == to {
+            true => Some(Price::from(1)),
+            false => self.rates.get(&(from.to_string(), to.to_string())).copied(),
+        }
+    }
+
+    /// Converts the `amount` of the `from` currency into the `to` currency, with at most `scale`
+    /// decimals, rounded as the `rounding` says, and returns the rate along with the converted amount.
+    /// See [`Money::checked_convert`].
+    ///
+    /// # Errors
+    /// - There's no rate for the pair, `FxError::RateNotFound`;
+    /// - The converted amount doesn't fit into [`Money`], `AccountingError::ArithmeticOverflow`.
+    pub fn convert(
+        &self,
+        amount: Money,
+        from: &str,
+        to: &str,
+        scale: u8,
+        rounding: Rounding,
+    ) -> Result<(Price, Money), AccountingError> {
+        let rate = self.rate(from, to).ok_or_else(|| {
+            AccountingError::Fx(FxError::RateNotFound(from.to_string(), to.to_string()))
+        })?;
+        let converted = amount
+            .checked_convert(rate, scale, rounding)
+            .ok_or_else(|| {
+                AccountingError::ArithmeticOverflow(format!(
+                    "{} {} at {} is too large to convert into {}.",
+                    amount, from, rate, to
+                ))
+            })?;
+        Ok((rate, converted))
+    }
+
+    /// Whether there are no rates at all
+    pub fn is_empty(&self) -> bool {
+        self.rates.is_empty()
+    }
+}
+
+impl fmt::Display for FxRates {
+    /// The same format as the one that's parsed, such as `EUR/USD=1.08,USD/EUR=0.92`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, ((from, to), rate)) in self.rates.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}/{}={}", from, to, rate)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for FxRates {
+    type Err = String;
+
+    /// Parses comma-separated `<from>/<to>=<rate>` pairs, such as `"EUR/USD=1.08,USD/EUR=0.92"`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid exchange rates: \"{s}\"; expected \"<from>/<to>=<rate>,...\", such as \"EUR/USD=1.08,USD/EUR=0.92\""
+            )
+        };
+
+        s.split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .try_fold(FxRates::new(), |rates, pair| {
+                let (currencies, rate) = pair.split_once('=').ok_or_else(invalid)?;
+                let (from, to) = currencies.trim().split_once('/').ok_or_else(invalid)?;
+                let rate = rate.trim().parse::<Price>().map_err(|_| invalid())?;
+                rates
+                    .with_rate(from.trim(), to.trim(), rate)
+                    .map_err(|err| format!("{}: {}", invalid(), err))
+            })
+    }
+}
+
+/// **The number of decimals of each currency's amounts**
+///
+/// Currencies that aren't listed have [`DEFAULT_CURRENCY_SCALE`] decimals.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CurrencyScales {
+    scales: BTreeMap<String, u8>,
+}
+
+impl CurrencyScales {
+    /// Every currency with the default scale
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of decimals of the `currency`'s amounts.
+    ///
+    /// # Errors
+    /// - The code is invalid, or the scale exceeds [`MAX_SCALE`], `FxError::CurrencyInvalid`
+    pub fn with_scale(mut self, currency: &str, scale: u8) -> Result<Self, AccountingError> {
+        validate_currency(currency)?;
+        if scale > MAX_SCALE {
+            return Err(FxError::CurrencyInvalid(format!(
+                "{currency} can't have more than {MAX_SCALE} decimals."
+            ))
+            .into());
+        }
+
+        self.scales.insert(currency.to_string(), scale);
+        Ok(self)
+    }
+
+    /// The number of decimals of the `currency`'s amounts
+    pub fn scale(&self, currency: &str) -> u8 {
+        self.scales
+            .get(currency)
+            .copied()
+            .unwrap_or(DEFAULT_CURRENCY_SCALE)
+    }
+
+    /// Checks that the `amount` of the `currency` doesn't have more decimals than its scale.
+    ///
+    /// # Errors
+    /// - It has more, `FxError::AmountInvalid`
+    pub fn check(&self, amount: Money, currency: &str) -> Result<(), AccountingError> {
+        let scale = self.scale(currency);
+        match amount.round(scale, Rounding::Down) == amount {
+            true => Ok(()),
+            false => Err(FxError::AmountInvalid(format!(
+                "{amount} {currency} has more than the {scale} decimals of the currency."
+            ))
+            .into()),
+        }
+    }
+}
+
+impl fmt::Display for CurrencyScales {
+    /// The same format as the one that's parsed, such as `BTC=8,JPY=0`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (currency, scale)) in self.scales.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}={}", currency, scale)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for CurrencyScales {
+    type Err = String;
+
+    /// Parses comma-separated `<currency>=<decimals>` pairs, such as `"JPY=0,BTC=8"`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid currency scales: \"{s}\"; expected \"<currency>=<decimals>,...\", such as \"JPY=0,BTC=8\""
+            )
+        };
+
+        s.split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .try_fold(CurrencyScales::new(), |scales, pair| {
+                let (currency, scale) = pair.split_once('=').ok_or_else(invalid)?;
+                let scale = scale.trim().parse::<u8>().map_err(|_| invalid())?;
+                scales
+                    .with_scale(currency.trim(), scale)
+                    .map_err(|err| format!("{}: {}", invalid(), err))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_currency_codes() {
+        assert!(validate_currency("EUR").is_ok());
+        for bad in ["", "eur", "EURO", "E1R", "€"] {
+            assert_eq!(
+                "CURRENCY_INVALID",
+                validate_currency(bad).unwrap_err().code(),
+                "{bad}"
+            );
+        }
+    }
+
+    #[test]
+    fn fx_rates_parse_and_convert() {
+        let rates: FxRates = "EUR/USD=1.08, USD/EUR=0.92".parse().unwrap();
+        assert_eq!("EUR/USD=1.08,USD/EUR=0.92", rates.to_string());
+        assert_eq!(Some("1.08".parse().unwrap()), rates.rate("EUR", "USD"));
+        assert_eq!(Some(Price::from(1)), rates.rate("GBP", "GBP"));
+        assert_eq!(None, rates.rate("GBP", "USD"));
+
+        assert_eq!(
+            Ok(("0.92".parse().unwrap(), Money::from(92))),
+            rates.convert(100.into(), "USD", "EUR", 2, Rounding::Down)
+        );
+        assert_eq!(
+            Ok(("0.92".parse().unwrap(), "9.19".parse().unwrap())),
+            rates.convert("9.99".parse().unwrap(), "USD", "EUR", 2, Rounding::Down)
+        );
+        assert_eq!(
+            Err(AccountingError::Fx(FxError::RateNotFound(
+                "EUR".to_string(),
+                "GBP".to_string()
+            ))),
+            rates.convert(100.into(), "EUR", "GBP", 2, Rounding::Down)
+        );
+
+        assert_eq!(Ok(FxRates::new()), "".parse());
+        for bad in ["EUR/USD", "EURUSD=1", "EUR/USD=x", "EUR/EUR=1", "EUR/USD=0"] {
+            assert!(bad.parse::<FxRates>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn currency_scales_parse_and_check() {
+        let scales: CurrencyScales = "JPY=0, BTC=8".parse().unwrap();
+        assert_eq!("BTC=8,JPY=0", scales.to_string());
+        assert_eq!(0, scales.scale("JPY"));
+        assert_eq!(DEFAULT_CURRENCY_SCALE, scales.scale("USD"));
+
+        assert!(scales.check("0.12345678".parse().unwrap(), "BTC").is_ok());
+        assert!(scales.check("1.50".parse().unwrap(), "USD").is_ok());
+        assert!(scales.check("1.500".parse().unwrap(), "USD").is_ok());
+        assert_eq!(
+            Err(AccountingError::Fx(FxError::AmountInvalid(
+                "1.005 USD has more than the 2 decimals of the currency.".to_string()
+            ))),
+            scales.check("1.005".parse().unwrap(), "USD")
+        );
+        assert!(scales.check("1.5".parse().unwrap(), "JPY").is_err());
+
+        assert_eq!(Ok(CurrencyScales::new()), "".parse());
+        for bad in ["JPY", "JPY=x", "jpy=0", "JPY=19", "JPY=-1"] {
+            assert!(bad.parse::<CurrencyScales>().is_err(), "{bad}");
+        }
+    }
+}
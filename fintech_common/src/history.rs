@@ -0,0 +1,438 @@
+//! Pluggable sinks for historical records
+//!
+//! The matching engine keeps a history of all receipts, and the trading platform
+//! keeps a log of all transactions. In a long-running service, keeping all of them
+//! in memory makes the memory usage grow forever, so where they go is configurable:
+//! - [`RingBufferSink`] keeps only the most recent records in memory;
+//! - [`AppendFileSink`] appends every record to a file, one JSON document per line,
+//!   so nothing is kept in memory while all records are still retained for audit,
+//!   with the records of a previous run moved to a file of their own;
+//! - [`NoOpSink`] discards all records.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The number of records that an in-memory history keeps by default
+pub const DEFAULT_HISTORY_CAPACITY: usize = 100_000;
+
+/// **A destination for historical records, such as receipts or transactions**
+///
+/// Records are always appended in the order in which they were created.
+pub trait HistorySink<T>: Send {
+    /// Records a single item.
+    ///
+    /// Returns the record that is no longer retained because of it, if there's one:
+    /// the oldest record, when it's evicted to make room, or the item itself, when it isn't kept at all.
+    fn record(&mut self, item: T) -> Option<T>;
+
+    /// Returns all retained records, from the oldest to the newest.
+    ///
+    /// Every call collects them anew, which is as costly as the sink makes it:
+    /// a sink that keeps its records outside of memory reads all of them back.
+    fn records(&self) -> Vec<T>;
+
+    /// Returns the number of retained records.
+    fn len(&self) -> usize;
+
+    /// Returns `true` if there are no retained records.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Discards all retained records.
+    ///
+    /// # Errors
+    /// - The records are kept outside of memory, and they can't be discarded.
+    fn clear(&mut self) -> io::Result<()>;
+}
+
+/// **Keeps the most recent `capacity` records in memory**
+///
+/// Once the buffer is full, the oldest record is evicted for every new one.
+#[derive(Clone, Debug)]
+pub struct RingBufferSink<T> {
+    capacity: usize,
+    records: VecDeque<T>,
+}
+
+impl<T> RingBufferSink<T> {
+    /// Creates an empty ring buffer that keeps at most `capacity` records.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            records: VecDeque::with_capacity(capacity.min(1024)),
+        }
+    }
+}
+
+impl<T: Clone + Send> HistorySink<T> for RingBufferSink<T> {
+    fn record(&mut self, item: T) -> Option<T> {
+        if self.capacity == 0 {
+            return Some(item);
+        }
+        let evicted = match self.records.len() == self.capacity {
+            true => self.records.pop_front(),
+            false => None,
+        };
+        self.records.push_back(item);
+        evicted
+    }
+
+    fn records(&self) -> Vec<T> {
+        self.records.iter().cloned().collect()
+    }
+
+    fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.records.clear();
+        Ok(())
+    }
+}
+
+/// **Appends every record to a file, as one line of JSON**
+///
+/// Nothing is kept in memory, apart from the number of records in the file.
+/// Records are read back from the file when they are requested.
+///
+/// A sink always starts with an empty file: the records of a previous run
+/// belong to accounts that no longer exist, so they're moved aside rather than appended to.
+pub struct AppendFileSink<T> {
+    path: PathBuf,
+    file: File,
+    len: usize,
+    // `fn() -> T` keeps the sink `Send` regardless of `T`, because no `T` is ever stored.
+    _records: PhantomData<fn() -> T>,
+}
+
+impl<T> AppendFileSink<T> {
+    /// Opens a new, empty file at `path` for appending.
+    ///
+    /// A non-empty file already at `path`, such as the history of a previous run, is kept
+    /// by renaming it to `<stem>.<n>.<extension>` next to it, with the first `n` from 1
+    /// that isn't taken, so that no records are lost and none are mixed with the new ones.
+    ///
+    /// # Errors
+    /// - The existing file can't be renamed, or the new file can't be opened.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if std::fs::metadata(&path).is_ok_and(|metadata| metadata.len() > 0) {
+            let rotated = Self::rotated_path(&path);
+            std::fs::rename(&path, &rotated)?;
+            log::info!(
+                "Moved the previous history from \"{}\" to \"{}\"",
+                path.display(),
+                rotated.display()
+            );
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)?;
+
+        Ok(Self {
+            path,
+            file,
+            len: 0,
+            _records: PhantomData,
+        })
+    }
+
+    /// The first free `<stem>.<n>.<extension>` next to `path`
+    fn rotated_path(path: &Path) -> PathBuf {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = path
+            .extension()
+            .map(|extension| format!(".{}", extension.to_string_lossy()))
+            .unwrap_or_default();
+        let mut n = 1_u64;
+        loop {
+            let rotated = path.with_file_name(format!("{stem}.{n}{extension}"));
+            if !rotated.exists() {
+                return rotated;
+            }
+            n += 1;
+        }
+    }
+
+    /// The path of the underlying file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl<T> fmt::Debug for AppendFileSink<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AppendFileSink")
+            .field("path", &self.path)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> HistorySink<T> for AppendFileSink<T> {
+    /// Appends the record to the file and flushes it.
+    ///
+    /// A record that can't be written is logged as an error,
+    /// as the operation that produced it has already taken place.
+    /// Nothing is ever evicted from the file.
+    fn record(&mut self, item: T) -> Option<T> {
+        let written = serde_json::to_string(&item)
+            .map_err(io::Error::from)
+            .and_then(|line| writeln!(self.file, "{}", line))
+            .and_then(|_| self.file.flush());
+
+        match written {
+            Ok(_) => self.len += 1,
+            Err(err) => log::error!(
+                "Failed to append a record to \"{}\": {}",
+                self.path.display(),
+                err
+            ),
+        }
+        None
+    }
+
+    /// Reads all records back from the file.
+    ///
+    /// Nothing is cached, so every call reads and parses the whole file, which takes time
+    /// in proportion to its size; callers that need the records more than once should keep them.
+    /// Lines that can't be read or parsed are skipped.
+    fn records(&self) -> Vec<T> {
+        match File::open(&self.path) {
+            Ok(file) => BufReader::new(file)
+                .lines()
+                .map_while(Result::ok)
+                .filter_map(|line| serde_json::from_str(&line).ok())
+                .collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Truncates the file; new records are appended from its start.
+    fn clear(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+/// **Discards all records**
+#[derive(Debug, Default)]
+pub struct NoOpSink;
+
+impl<T> HistorySink<T> for NoOpSink {
+    fn record(&mut self, item: T) -> Option<T> {
+        Some(item)
+    }
+
+    fn records(&self) -> Vec<T> {
+        vec![]
+    }
+
+    fn len(&self) -> usize {
+        0
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// **Selects a [`HistorySink`] implementation**
+///
+/// Can be parsed from a string:
+/// - `ring:<capacity>` for a [`RingBufferSink`],
+/// - `file:<directory>` for an [`AppendFileSink`], with one file per history in the directory,
+/// - `none` for a [`NoOpSink`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum HistoryConfig {
+    RingBuffer(usize),
+    AppendFile(PathBuf),
+    NoOp,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig::RingBuffer(DEFAULT_HISTORY_CAPACITY)
+    }
+}
+
+impl HistoryConfig {
+    /// Builds a sink for the history called `name`.
+    ///
+    /// The name is only used by [`HistoryConfig::AppendFile`],
+    /// as the file name, `<name>.jsonl`, inside of the configured directory.
+    ///
+    /// # Errors
+    /// - The directory or the file can't be created or opened.
+    pub fn build<T>(&self, name: &str) -> io::Result<Box<dyn HistorySink<T>>>
+    where
+        T: Clone + Serialize + DeserializeOwned + Send + 'static,
+    {
+        Ok(match self {
+            HistoryConfig::RingBuffer(capacity) => Box::new(RingBufferSink::new(*capacity)),
+            HistoryConfig::AppendFile(dir) => {
+                std::fs::create_dir_all(dir)?;
+                Box::new(AppendFileSink::open(dir.join(format!("{name}.jsonl")))?)
+            }
+            HistoryConfig::NoOp => Box::new(NoOpSink),
+        })
+    }
+}
+
+impl FromStr for HistoryConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().split_once(':') {
+            Some(("ring", capacity)) => capacity
+                .parse::<usize>()
+                .map(HistoryConfig::RingBuffer)
+                .map_err(|_| format!("Invalid ring buffer capacity: \"{capacity}\"")),
+            Some(("file", dir)) if !dir.is_empty() => Ok(HistoryConfig::AppendFile(dir.into())),
+            None if s.trim() == "none" => Ok(HistoryConfig::NoOp),
+            _ => Err(format!(
+                "Invalid history configuration: \"{s}\"; expected \"ring:<capacity>\", \
+                 \"file:<directory>\" or \"none\""
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "fintech_history_{}_{}.jsonl",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn ring_buffer_evicts_oldest() {
+        let mut sink = RingBufferSink::new(3);
+        assert!(sink.is_empty());
+
+        for i in 1..=3_u64 {
+            assert_eq!(None, sink.record(i));
+        }
+        assert_eq!(Some(1), sink.record(4));
+        assert_eq!(Some(2), sink.record(5));
+
+        assert_eq!(3, sink.len());
+        assert_eq!(vec![3, 4, 5], sink.records());
+    }
+
+    #[test]
+    fn ring_buffer_zero_capacity_keeps_nothing() {
+        let mut sink = RingBufferSink::new(0);
+        assert_eq!(Some(1), sink.record(1_u64));
+        assert!(sink.is_empty());
+    }
+
+    #[test]
+    fn append_file_reads_back_and_moves_a_previous_run_aside() {
+        let path = temp_path("append");
+        let rotated = |n: u64| {
+            path.with_file_name(format!(
+                "fintech_history_append_{}.{n}.jsonl",
+                std::process::id()
+            ))
+        };
+        let _ = std::fs::remove_file(rotated(1));
+        let _ = std::fs::remove_file(rotated(2));
+
+        let mut sink = AppendFileSink::open(&path).unwrap();
+        sink.record(String::from("first"));
+        sink.record(String::from("second"));
+        assert_eq!(2, HistorySink::<String>::len(&sink));
+        assert_eq!(vec!["first", "second"], sink.records());
+
+        // Reopening starts a new file, and keeps the previous one next to it.
+        let mut sink: AppendFileSink<String> = AppendFileSink::open(&path).unwrap();
+        assert!(sink.is_empty());
+        sink.record(String::from("third"));
+        assert_eq!(vec!["third"], sink.records());
+        assert_eq!(
+            "\"first\"\n\"second\"\n",
+            std::fs::read_to_string(rotated(1)).unwrap()
+        );
+
+        // An empty file is simply reused, and the next run takes the next free name.
+        assert!(!rotated(2).exists());
+        let sink: AppendFileSink<String> = AppendFileSink::open(&path).unwrap();
+        assert!(sink.is_empty());
+        assert_eq!("\"third\"\n", std::fs::read_to_string(rotated(2)).unwrap());
+        let _: AppendFileSink<String> = AppendFileSink::open(&path).unwrap();
+        assert!(!rotated(3).exists());
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(rotated(1)).unwrap();
+        std::fs::remove_file(rotated(2)).unwrap();
+    }
+
+    #[test]
+    fn clear_discards_retained_records() {
+        let mut ring = RingBufferSink::new(3);
+        ring.record(1_u64);
+        ring.clear().unwrap();
+        assert!(ring.is_empty());
+        ring.record(2);
+        assert_eq!(vec![2], ring.records());
+
+        let path = temp_path("clear");
+        let mut file = AppendFileSink::open(&path).unwrap();
+        file.record(String::from("first"));
+        file.record(String::from("second"));
+        HistorySink::<String>::clear(&mut file).unwrap();
+        assert!(HistorySink::<String>::is_empty(&file));
+        file.record(String::from("third"));
+        assert_eq!(vec!["third"], file.records());
+
+        // The file itself is truncated, so the cleared records are gone from it.
+        assert_eq!("\"third\"\n", std::fs::read_to_string(&path).unwrap());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn no_op_discards() {
+        let mut sink = NoOpSink;
+        assert_eq!(Some(1), HistorySink::<u64>::record(&mut sink, 1));
+        assert!(HistorySink::<u64>::is_empty(&sink));
+        assert!(HistorySink::<u64>::records(&sink).is_empty());
+    }
+
+    #[test]
+    fn history_config_from_str() {
+        assert_eq!(Ok(HistoryConfig::RingBuffer(10)), "ring:10".parse());
+        assert_eq!(
+            Ok(HistoryConfig::AppendFile(PathBuf::from("/var/log/fintech"))),
+            "file:/var/log/fintech".parse()
+        );
+        assert_eq!(Ok(HistoryConfig::NoOp), "none".parse());
+        assert!("ring:many".parse::<HistoryConfig>().is_err());
+        assert!("file:".parse::<HistoryConfig>().is_err());
+        assert!("memory".parse::<HistoryConfig>().is_err());
+    }
+}
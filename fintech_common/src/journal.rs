@@ -0,0 +1,535 @@
+//! The double-entry general ledger behind [`Accounts`](crate::accounts::Accounts)
+//!
+//! Every business event, such as a deposit, a transfer or a fee, is posted to the journal
+//! as a balanced entry: for every asset, its debits add up to its credits.
+//!
+//! A posting debits the account that the value leaves, and credits the account that it arrives at.
+//! Customer accounts therefore have credit balances, which is what the platform owes to them,
+//! while the cash-in account has a debit balance of everything that came in from outside.
+//!
+//! The balances of all ledger accounts are kept up to date with every entry, so that
+//! the trial balance can be reported at any time. Its debits and credits always add up
+//! to the same totals, for every asset.
+
+use crate::history::{HistorySink, RingBufferSink, DEFAULT_HISTORY_CAPACITY};
+use crate::types::Amount;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// **An account of the platform itself**
+#[derive(Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub enum SystemAccount {
+    /// Funds and units that came in from outside of the platform, and haven't left it yet
+    CashIn,
+    /// Trading fees that were collected, less the maker rebates that were paid;
+    /// it's the fee account of [`Accounts`](crate::accounts::Accounts).
+    Fees,
+    /// Funds and units on their way between two books, such as the ledger and a market
+    Suspense,
+    /// Funds lent to margin accounts, and not repaid yet
+    Margin,
+    /// The currency exchange: the funds that customers converted, less the ones they got for them
+    Fx,
+}
+
+/// **An account in the general ledger**
+#[derive(Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub enum LedgerAccount {
+    /// A customer's account, by the signer's name
+    Customer(String),
+    System(SystemAccount),
+}
+
+/// **What a posting moves: cash, funds in another currency, or units of the instrument with the symbol**
+#[derive(Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub enum Asset {
+    /// Funds in the [`DEFAULT_CURRENCY`](crate::fx::DEFAULT_CURRENCY)
+    Cash,
+    Units(String),
+    /// Funds in the currency with the code
+    Currency(String),
+}
+
+/// **The side of a posting**
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum EntrySide {
+    Debit,
+    Credit,
+}
+
+/// **The business event behind a journal entry**
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum EntryKind {
+    /// Funds or units put into a customer's account from outside
+    Deposit,
+    /// Funds or units taken out of a customer's account
+    Withdrawal,
+    /// Funds or units sent from one customer to another
+    Transfer,
+    /// One side of a match: the value going to the seller, or the units going to the buyer
+    Settlement,
+    /// A trading fee, paid to the fee account
+    Fee,
+    /// A maker rebate, paid by the fee account
+    Rebate,
+    /// Funds or units moved to or from the suspense account
+    Suspense,
+    /// Funds lent to a margin account
+    Loan,
+    /// Funds paid back by a margin account
+    Repayment,
+    /// Funds exchanged for another currency, or the funds they were exchanged for
+    Conversion,
+}
+
+/// **A single debit or credit of an account**
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Posting {
+    pub account: LedgerAccount,
+    pub asset: Asset,
+    pub side: EntrySide,
+    pub amount: Amount,
+}
+
+/// **A balanced set of postings that records a single business event**
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct JournalEntry {
+    /// The entry's sequence number in its journal, starting with 1
+    pub id: u64,
+    pub kind: EntryKind,
+    pub postings: Vec<Posting>,
+}
+
+impl JournalEntry {
+    /// Whether the debits of every asset add up to its credits
+    pub fn is_balanced(&self) -> bool {
+        let mut totals = Totals::default();
+        for posting in &self.postings {
+            totals.add(&posting.asset, &posting.side, posting.amount);
+        }
+        totals.is_balanced()
+    }
+}
+
+/// **The net balance of a ledger account in an asset**
+///
+/// At most one of the two sides isn't zero.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Balance {
+    pub debit: Amount,
+    pub credit: Amount,
+}
+
+impl Balance {
+    /// Posts the `amount` to the `side`, and nets the two sides.
+    fn post(&mut self, side: &EntrySide, amount: Amount) {
+        let (same, other) = match side {
+            EntrySide::Debit => (&mut self.debit, &mut self.credit),
+            EntrySide::Credit => (&mut self.credit, &mut self.debit),
+        };
+
+        match other.checked_sub(amount) {
+            Some(rest) => *other = rest,
+            None => {
+                // The other side is smaller than the amount, so this can't underflow.
+                let rest = amount.saturating_sub(*other);
+                *same = same.checked_add(rest).expect("Sum overflowed!");
+                *other = Amount::ZERO;
+            }
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.debit.is_zero() && self.credit.is_zero()
+    }
+}
+
+/// The total debits and credits, by asset
+#[derive(Default)]
+struct Totals(BTreeMap<Asset, (Amount, Amount)>);
+
+impl Totals {
+    fn add(&mut self, asset: &Asset, side: &EntrySide, amount: Amount) {
+        let (debit, credit) = self.0.entry(asset.clone()).or_default();
+        let total = match side {
+            EntrySide::Debit => debit,
+            EntrySide::Credit => credit,
+        };
+        *total = total.checked_add(amount).expect("Sum overflowed!");
+    }
+
+    fn is_balanced(&self) -> bool {
+        self.0.values().all(|(debit, credit)| debit == credit)
+    }
+}
+
+/// **A line of the trial balance: the net balance of a ledger account in an asset**
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct TrialBalanceLine {
+    pub account: LedgerAccount,
+    pub asset: Asset,
+    pub debit: Amount,
+    pub credit: Amount,
+}
+
+/// **The total debits and credits of all lines of the trial balance in an asset**
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct TrialBalanceTotal {
+    pub asset: Asset,
+    pub debit: Amount,
+    pub credit: Amount,
+}
+
+/// **The net balances of all ledger accounts, with their totals by asset**
+///
+/// Accounts with a zero balance in an asset are left out.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct TrialBalance {
+    /// Ordered by the account, and then by the asset
+    pub lines: Vec<TrialBalanceLine>,
+    /// Ordered by the asset
+    pub totals: Vec<TrialBalanceTotal>,
+}
+
+impl TrialBalance {
+    /// Builds the trial balance from the balances of the ledger accounts.
+    fn new(balances: &BTreeMap<(LedgerAccount, Asset), Balance>) -> Self {
+        let lines: Vec<TrialBalanceLine> = balances
+            .iter()
+            .filter(|(_, balance)| !balance.is_zero())
+            .map(|((account, asset), balance)| TrialBalanceLine {
+                account: account.clone(),
+                asset: asset.clone(),
+                debit: balance.debit,
+                credit: balance.credit,
+            })
+            .collect();
+
+        let mut totals = Totals::default();
+        for line in &lines {
+            totals.add(&line.asset, &EntrySide::Debit, line.debit);
+            totals.add(&line.asset, &EntrySide::Credit, line.credit);
+        }
+        let totals = totals
+            .0
+            .into_iter()
+            .map(|(asset, (debit, credit))| TrialBalanceTotal {
+                asset,
+                debit,
+                credit,
+            })
+            .collect();
+
+        Self { lines, totals }
+    }
+
+    /// **Combines the trial balances of several journals into one**
+    ///
+    /// The lines of the same account and asset are netted, so that an account that is
+    /// kept in several books shows up once, and the suspense account between them
+    /// nets to zero once nothing is on its way anymore.
+    pub fn combine(parts: impl IntoIterator<Item = TrialBalance>) -> Self {
+        let mut balances: BTreeMap<(LedgerAccount, Asset), Balance> = BTreeMap::new();
+        for line in parts.into_iter().flat_map(|part| part.lines) {
+            let balance = balances.entry((line.account, line.asset)).or_default();
+            balance.post(&EntrySide::Debit, line.debit);
+            balance.post(&EntrySide::Credit, line.credit);
+        }
+
+        Self::new(&balances)
+    }
+
+    /// Whether the debits of every asset add up to its credits
+    pub fn is_balanced(&self) -> bool {
+        self.totals.iter().all(|total| total.debit == total.credit)
+    }
+}
+
+/// **The journal of all entries, together with the balances of all ledger accounts**
+///
+/// Only the most recent [`DEFAULT_HISTORY_CAPACITY`] entries are kept,
+/// while the balances always reflect all of them.
+#[derive(Clone)]
+pub struct Journal {
+    /// The id of the most recent entry
+    last_id: u64,
+    entries: RingBufferSink<JournalEntry>,
+    balances: BTreeMap<(LedgerAccount, Asset), Balance>,
+}
+
+impl Default for Journal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Journal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Journal")
+            .field("last_id", &self.last_id)
+            .field("balances", &self.balances)
+            .finish()
+    }
+}
+
+impl Journal {
+    /// Creates an empty journal.
+    pub fn new() -> Self {
+        Self {
+            last_id: 0,
+            entries: RingBufferSink::new(DEFAULT_HISTORY_CAPACITY),
+            balances: BTreeMap::new(),
+        }
+    }
+
+    /// **Posts an entry that moves the `amount` of the `asset` from one account to another**
+    ///
+    /// The account `from` is debited, and the account `to` is credited.
+    ///
+    /// Returns the entry's id.
+    pub fn post(
+        &mut self,
+        kind: EntryKind,
+        from: LedgerAccount,
+        to: LedgerAccount,
+        asset: Asset,
+        amount: Amount,
+    ) -> u64 {
+        self.last_id += 1;
+        let entry = JournalEntry {
+            id: self.last_id,
+            kind,
+            postings: vec![
+                Posting {
+                    account: from,
+                    asset: asset.clone(),
+                    side: EntrySide::Debit,
+                    amount,
+                },
+                Posting {
+                    account: to,
+                    asset,
+                    side: EntrySide::Credit,
+                    amount,
+                },
+            ],
+        };
+
+        for posting in &entry.postings {
+            self.balances
+                .entry((posting.account.clone(), posting.asset.clone()))
+                .or_default()
+                .post(&posting.side, posting.amount);
+        }
+        self.entries.record(entry);
+
+        self.last_id
+    }
+
+    /// The retained entries, from the oldest to the newest
+    pub fn entries(&self) -> Vec<JournalEntry> {
+        self.entries.records()
+    }
+
+    /// The net balance of a ledger account in an asset
+    pub fn balance_of(&self, account: &LedgerAccount, asset: &Asset) -> Balance {
+        self.balances
+            .get(&(account.clone(), asset.clone()))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// The trial balance of all ledger accounts
+    pub fn trial_balance(&self) -> TrialBalance {
+        TrialBalance::new(&self.balances)
+    }
+}
+
+impl fmt::Display for SystemAccount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SystemAccount::CashIn => write!(f, "cash-in"),
+            SystemAccount::Fees => write!(f, "fees"),
+            SystemAccount::Suspense => write!(f, "suspense"),
+            SystemAccount::Margin => write!(f, "margin"),
+            SystemAccount::Fx => write!(f, "fx"),
+        }
+    }
+}
+
+impl fmt::Display for LedgerAccount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LedgerAccount::Customer(signer) => write!(f, "{}", signer),
+            LedgerAccount::System(account) => write!(f, "[{}]", account),
+        }
+    }
+}
+
+impl fmt::Display for Asset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Asset::Cash => write!(f, "cash"),
+            Asset::Units(symbol) => write!(f, "{}", symbol),
+            Asset::Currency(currency) => write!(f, "{}", currency),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn customer(signer: &str) -> LedgerAccount {
+        LedgerAccount::Customer(signer.to_string())
+    }
+
+    const CASH_IN: LedgerAccount = LedgerAccount::System(SystemAccount::CashIn);
+
+    #[test]
+    fn post_nets_balances_and_keeps_entries_balanced() {
+        let mut journal = Journal::new();
+
+        assert_eq!(
+            1,
+            journal.post(
+                EntryKind::Deposit,
+                CASH_IN,
+                customer("Alice"),
+                Asset::Cash,
+                Amount::from(100)
+            )
+        );
+        journal.post(
+            EntryKind::Transfer,
+            customer("Alice"),
+            customer("Bob"),
+            Asset::Cash,
+            Amount::from(30),
+        );
+        journal.post(
+            EntryKind::Withdrawal,
+            customer("Bob"),
+            CASH_IN,
+            Asset::Cash,
+            Amount::from(10),
+        );
+
+        assert!(journal.entries().iter().all(JournalEntry::is_balanced));
+        assert_eq!(
+            vec![1, 2, 3],
+            journal
+                .entries()
+                .iter()
+                .map(|entry| entry.id)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Balance {
+                debit: Amount::ZERO,
+                credit: Amount::from(70),
+            },
+            journal.balance_of(&customer("Alice"), &Asset::Cash)
+        );
+        assert_eq!(
+            Balance {
+                debit: Amount::from(90),
+                credit: Amount::ZERO,
+            },
+            journal.balance_of(&CASH_IN, &Asset::Cash)
+        );
+    }
+
+    #[test]
+    fn trial_balance_sums_to_zero_by_asset() {
+        let mut journal = Journal::new();
+        journal.post(
+            EntryKind::Deposit,
+            CASH_IN,
+            customer("Alice"),
+            Asset::Cash,
+            Amount::from(100),
+        );
+        journal.post(
+            EntryKind::Deposit,
+            CASH_IN,
+            customer("Bob"),
+            Asset::Units("ACME".to_string()),
+            Amount::from(5),
+        );
+        // Alice's whole balance leaves, so her line is left out.
+        journal.post(
+            EntryKind::Withdrawal,
+            customer("Alice"),
+            CASH_IN,
+            Asset::Cash,
+            Amount::from(100),
+        );
+
+        let trial_balance = journal.trial_balance();
+        assert!(trial_balance.is_balanced());
+        assert_eq!(
+            vec![TrialBalanceTotal {
+                asset: Asset::Units("ACME".to_string()),
+                debit: Amount::from(5),
+                credit: Amount::from(5),
+            }],
+            trial_balance.totals
+        );
+        assert_eq!(2, trial_balance.lines.len());
+        assert!(trial_balance
+            .lines
+            .iter()
+            .all(|line| line.account != customer("Alice")));
+    }
+
+    #[test]
+    fn combine_nets_the_suspense_account() {
+        let suspense = LedgerAccount::System(SystemAccount::Suspense);
+
+        let mut ledger = Journal::new();
+        ledger.post(
+            EntryKind::Deposit,
+            CASH_IN,
+            customer("Alice"),
+            Asset::Cash,
+            Amount::from(100),
+        );
+        ledger.post(
+            EntryKind::Suspense,
+            customer("Alice"),
+            suspense.clone(),
+            Asset::Cash,
+            Amount::from(40),
+        );
+        let mut market = Journal::new();
+        market.post(
+            EntryKind::Suspense,
+            suspense,
+            customer("Alice"),
+            Asset::Cash,
+            Amount::from(40),
+        );
+
+        let combined = TrialBalance::combine([ledger.trial_balance(), market.trial_balance()]);
+        assert!(combined.is_balanced());
+        assert_eq!(
+            vec![
+                TrialBalanceLine {
+                    account: customer("Alice"),
+                    asset: Asset::Cash,
+                    debit: Amount::ZERO,
+                    credit: Amount::from(100),
+                },
+                TrialBalanceLine {
+                    account: CASH_IN,
+                    asset: Asset::Cash,
+                    debit: Amount::from(100),
+                    credit: Amount::ZERO,
+                },
+            ],
+            combined.lines
+        );
+    }
+}
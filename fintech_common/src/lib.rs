@@ -2,7 +2,17 @@ pub mod accounts;
 pub mod cli;
 pub mod core;
 pub mod errors;
+pub mod fees;
+pub mod fx;
+pub mod history;
+pub mod journal;
+pub mod limits;
+pub mod margin;
+pub mod order_groups;
+pub mod positions;
+pub mod reconciliation;
 pub mod requests;
+pub mod statements;
 pub mod trading_platform;
 pub mod tx;
 pub mod validation;
@@ -0,0 +1,392 @@
+//! Withdrawal and transfer limits
+//!
+//! Funds that leave an account, through a withdrawal or a transfer, can be limited in three ways:
+//! - per transaction, the most that a single withdrawal or transfer can move;
+//! - daily, the most that can leave in any rolling 24 hours;
+//! - by velocity, the most withdrawals and transfers in any rolling window, such as 5 in 60 seconds.
+//!
+//! The global limits apply to every account, and an account can have limits of its own,
+//! which apply on top of them, so the stricter of the two always wins.
+//!
+//! Limits are evaluated against a [`Clock`], which can be replaced, so that the rolling windows
+//! can be tested without waiting for them.
+
+use crate::types::Money;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The length of the daily limit's rolling window, in milliseconds
+pub const DAY_MILLIS: u64 = 86_400_000;
+
+/// **A source of the current time, in milliseconds since the Unix epoch**
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> u64;
+}
+
+/// **The system's clock**
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        crate::tx::now()
+    }
+}
+
+/// **A clock that only moves when it's told to**
+#[derive(Debug, Default)]
+pub struct ManualClock(AtomicU64);
+
+impl ManualClock {
+    /// Creates a clock that stands at `now`.
+    pub fn new(now: u64) -> Self {
+        Self(AtomicU64::new(now))
+    }
+
+    /// Moves the clock forward by `millis`.
+    pub fn advance(&self, millis: u64) {
+        self.0.fetch_add(millis, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// **At most `count` withdrawals and transfers in any `window_secs` seconds**
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Velocity {
+    pub count: u32,
+    pub window_secs: u64,
+}
+
+impl Velocity {
+    fn window_millis(&self) -> u64 {
+        self.window_secs.saturating_mul(1_000)
+    }
+}
+
+/// **A kind of limit**
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Limit {
+    PerTransaction,
+    Daily,
+    Velocity,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::PerTransaction => write!(f, "per-transaction"),
+            Limit::Daily => write!(f, "daily"),
+            Limit::Velocity => write!(f, "velocity"),
+        }
+    }
+}
+
+/// **The limits on the funds that leave an account; `None` is unlimited**
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Limits {
+    /// The most that a single withdrawal or transfer can move
+    pub per_transaction: Option<Money>,
+    /// The most that can leave in any rolling 24 hours
+    pub daily: Option<Money>,
+    pub velocity: Option<Velocity>,
+}
+
+impl Limits {
+    /// Whether there are no limits at all
+    pub fn is_unlimited(&self) -> bool {
+        self == &Limits::default()
+    }
+}
+
+impl FromStr for Limits {
+    type Err = String;
+
+    /// Parses `<per transaction>:<daily>:<count>/<seconds>`, such as `"1000:5000:10/60"`,
+    /// where an empty part is unlimited, such as in `"1000::"`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid limits: \"{s}\"; expected \"<per transaction>:<daily>:<count>/<seconds>\", \
+                 such as \"1000:5000:10/60\", where an empty part is unlimited"
+            )
+        };
+
+        let parts: Vec<&str> = s.trim().split(':').map(str::trim).collect();
+        let [per_transaction, daily, velocity] = parts[..] else {
+            return Err(invalid());
+        };
+        let money = |part: &str| match part {
+            "" => Ok(None),
+            part => part.parse::<Money>().map(Some).map_err(|_| invalid()),
+        };
+        let velocity = match velocity.split_once('/') {
+            None if velocity.is_empty() => None,
+            Some((count, window_secs)) => Some(Velocity {
+                count: count.trim().parse().map_err(|_| invalid())?,
+                window_secs: window_secs.trim().parse().map_err(|_| invalid())?,
+            }),
+            None => return Err(invalid()),
+        };
+
+        Ok(Limits {
+            per_transaction: money(per_transaction)?,
+            daily: money(daily)?,
+            velocity,
+        })
+    }
+}
+
+/// **The global limits, with optional limits of individual accounts on top of them**
+///
+/// By default, nothing is limited.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LimitSchedule {
+    /// The limits of every account
+    global: Limits,
+    /// Limits of individual accounts, which apply along with the global ones
+    account_limits: BTreeMap<String, Limits>,
+}
+
+impl LimitSchedule {
+    /// Creates a schedule in which every account has the `global` limits.
+    pub fn new(global: Limits) -> Self {
+        Self {
+            global,
+            account_limits: BTreeMap::new(),
+        }
+    }
+
+    /// Gives the `signer` limits of their own, on top of the global ones.
+    pub fn with_account_limits(mut self, signer: &str, limits: Limits) -> Self {
+        self.account_limits.insert(signer.to_string(), limits);
+        self
+    }
+
+    /// The limits of every account
+    pub fn global(&self) -> &Limits {
+        &self.global
+    }
+
+    /// The limits that apply to the `signer`: the global ones, and their own, if any
+    fn limits_of(&self, signer: &str) -> impl Iterator<Item = &Limits> {
+        std::iter::once(&self.global).chain(self.account_limits.get(signer))
+    }
+
+    /// Whether nothing is limited for anyone
+    pub fn is_unlimited(&self) -> bool {
+        self.global.is_unlimited() && self.account_limits.values().all(Limits::is_unlimited)
+    }
+}
+
+/// **What's left of an account's limits; `None` is unlimited**
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Allowance {
+    /// The most that a single withdrawal or transfer can move
+    pub per_transaction: Option<Money>,
+    /// What can still leave in the rolling 24 hours
+    pub daily_remaining: Option<Money>,
+    /// How many more withdrawals and transfers can be made in the velocity window
+    pub velocity_remaining: Option<u32>,
+}
+
+impl Allowance {
+    /// The limit that moving the `amount` would exceed, if any
+    pub fn exceeded_by(&self, amount: Money) -> Option<Limit> {
+        if self.per_transaction.is_some_and(|max| amount > max) {
+            Some(Limit::PerTransaction)
+        } else if self
+            .daily_remaining
+            .is_some_and(|remaining| amount > remaining)
+        {
+            Some(Limit::Daily)
+        } else if self.velocity_remaining == Some(0) {
+            Some(Limit::Velocity)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Allowance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unlimited = || "unlimited".to_string();
+        write!(
+            f,
+            "per transaction: {}; daily: {}; velocity: {}",
+            self.per_transaction
+                .map_or_else(unlimited, |max| max.to_string()),
+            self.daily_remaining
+                .map_or_else(unlimited, |remaining| format!("{remaining} left")),
+            self.velocity_remaining
+                .map_or_else(unlimited, |remaining| format!("{remaining} left")),
+        )
+    }
+}
+
+/// **The withdrawals and transfers of every account, as long as a limit can still count them**
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Outflows(BTreeMap<String, VecDeque<(u64, Money)>>);
+
+impl Outflows {
+    /// What's left of the `signer`'s limits at the time `now`
+    pub(crate) fn allowance(&self, schedule: &LimitSchedule, signer: &str, now: u64) -> Allowance {
+        let outflows = self.0.get(signer);
+        let since = |window: u64| {
+            outflows
+                .into_iter()
+                .flatten()
+                .filter(move |(timestamp, _)| timestamp.saturating_add(window) > now)
+        };
+
+        let mut allowance = Allowance::default();
+        for limits in schedule.limits_of(signer) {
+            if let Some(max) = limits.per_transaction {
+                allowance.per_transaction = Some(min(allowance.per_transaction, max));
+            }
+            if let Some(daily) = limits.daily {
+                let spent = since(DAY_MILLIS)
+                    .try_fold(Money::ZERO, |sum, (_, amount)| sum.checked_add(*amount))
+                    .unwrap_or(Money::MAX);
+                let remaining = daily.saturating_sub(spent);
+                allowance.daily_remaining = Some(min(allowance.daily_remaining, remaining));
+            }
+            if let Some(velocity) = limits.velocity {
+                let count =
+                    u32::try_from(since(velocity.window_millis()).count()).unwrap_or(u32::MAX);
+                let remaining = velocity.count.saturating_sub(count);
+                allowance.velocity_remaining = Some(
+                    allowance
+                        .velocity_remaining
+                        .map_or(remaining, |other| other.min(remaining)),
+                );
+            }
+        }
+
+        allowance
+    }
+
+    /// Records that the `amount` left the `signer`'s account at the time `now`,
+    /// and forgets the outflows that no limit counts anymore.
+    pub(crate) fn record(
+        &mut self,
+        schedule: &LimitSchedule,
+        signer: &str,
+        amount: Money,
+        now: u64,
+    ) {
+        let window = schedule
+            .limits_of(signer)
+            .filter_map(|limits| limits.velocity.map(|velocity| velocity.window_millis()))
+            .fold(DAY_MILLIS, u64::max);
+
+        let outflows = self.0.entry(signer.to_string()).or_default();
+        while outflows
+            .front()
+            .is_some_and(|(timestamp, _)| timestamp.saturating_add(window) <= now)
+        {
+            outflows.pop_front();
+        }
+        outflows.push_back((now, amount));
+    }
+}
+
+fn min(current: Option<Money>, other: Money) -> Money {
+    current.map_or(other, |current| current.min(other))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_from_str() {
+        assert_eq!(
+            Ok(Limits {
+                per_transaction: Some(1000.into()),
+                daily: Some(5000.into()),
+                velocity: Some(Velocity {
+                    count: 10,
+                    window_secs: 60
+                }),
+            }),
+            "1000:5000:10/60".parse()
+        );
+        assert_eq!(
+            Ok(Limits {
+                per_transaction: Some("12.5".parse().unwrap()),
+                ..Default::default()
+            }),
+            "12.5::".parse()
+        );
+        assert_eq!(Ok(Limits::default()), "::".parse());
+        for bad in ["", "1000", "1000:5000", "a::", "::10", "::10/", ":::"] {
+            assert!(bad.parse::<Limits>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn allowance_over_rolling_windows() {
+        let schedule = LimitSchedule::new(Limits {
+            per_transaction: Some(100.into()),
+            daily: Some(250.into()),
+            velocity: Some(Velocity {
+                count: 2,
+                window_secs: 60,
+            }),
+        })
+        .with_account_limits(
+            "Alice",
+            Limits {
+                per_transaction: Some(50.into()),
+                ..Default::default()
+            },
+        );
+        let mut outflows = Outflows::default();
+
+        assert_eq!(
+            Allowance {
+                per_transaction: Some(50.into()),
+                daily_remaining: Some(250.into()),
+                velocity_remaining: Some(2),
+            },
+            outflows.allowance(&schedule, "Alice", 0)
+        );
+
+        outflows.record(&schedule, "Bob", 100.into(), 0);
+        outflows.record(&schedule, "Bob", 100.into(), 1_000);
+        let allowance = outflows.allowance(&schedule, "Bob", 1_000);
+        assert_eq!(Some(Money::from(50)), allowance.daily_remaining);
+        assert_eq!(Some(0), allowance.velocity_remaining);
+        assert_eq!(Some(Limit::Velocity), allowance.exceeded_by(10.into()));
+
+        // A minute after the first one, the velocity window has moved on, but the day hasn't.
+        let allowance = outflows.allowance(&schedule, "Bob", 60_500);
+        assert_eq!(Some(1), allowance.velocity_remaining);
+        assert_eq!(Some(Limit::Daily), allowance.exceeded_by(60.into()));
+        assert_eq!(
+            Some(Limit::PerTransaction),
+            allowance.exceeded_by(101.into())
+        );
+        assert_eq!(None, allowance.exceeded_by(50.into()));
+
+        let allowance = outflows.allowance(&schedule, "Bob", DAY_MILLIS + 1_000);
+        assert_eq!(Some(Money::from(250)), allowance.daily_remaining);
+        assert_eq!(
+            "per transaction: 100; daily: 250 left; velocity: 2 left",
+            allowance.to_string()
+        );
+        assert_eq!(
+            "per transaction: unlimited; daily: unlimited; velocity: unlimited",
+            Allowance::default().to_string()
+        );
+    }
+}
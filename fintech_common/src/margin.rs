@@ -0,0 +1,236 @@
+//! Margin trading
+//!
+//! A margin account can buy with borrowed funds, against the units that it holds.
+//! Its equity is the value of the account, with the units at the mark price,
+//! the price of the last trade, less the loan.
+//!
+//! Two requirements, in basis points (1/100th of a percent) of the position's value, apply:
+//! - the initial margin, which the equity has to cover after every purchase and every withdrawal,
+//!   so that a loan is only taken, and funds only leave, with enough of a cushion;
+//! - the maintenance margin, which is lower; an account whose equity falls below it
+//!   is called, and its units are sold into the bids to repay the loan.
+//!
+//! Values are in the decimals of the account's currency: the position is rounded down,
+//! and the requirements are rounded up, so that the account is never valued at more than it's worth.
+
+use crate::errors::{AccountingError, MarginError};
+use crate::types::{Money, Price, Quantity, Rounding};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// The highest margin requirement, 100%, in basis points
+pub const MAX_MARGIN_BPS: u32 = 10_000;
+
+/// **The initial and the maintenance margin, in basis points of the position's value**
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct MarginConfig {
+    initial_bps: u32,
+    maintenance_bps: u32,
+}
+
+impl MarginConfig {
+    /// Creates a configuration, such as 5000 and 2500 for an initial margin of 50%
+    /// and a maintenance margin of 25%, which allows buying twice as much as the equity.
+    ///
+    /// # Errors
+    /// - The maintenance margin is zero, exceeds the initial margin,
+    ///   or the initial margin exceeds [`MAX_MARGIN_BPS`], `MarginError::ConfigInvalid`
+    pub fn new(initial_bps: u32, maintenance_bps: u32) -> Result<Self, AccountingError> {
+        if initial_bps > MAX_MARGIN_BPS {
+            return Err(MarginError::ConfigInvalid(format!(
+                "The initial margin can't exceed {} basis points.",
+                MAX_MARGIN_BPS
+            ))
+            .into());
+        }
+        if maintenance_bps == 0 || maintenance_bps > initial_bps {
+            return Err(MarginError::ConfigInvalid(
+                "The maintenance margin has to be above zero, and can't exceed the initial margin."
+                    .to_string(),
+            )
+            .into());
+        }
+
+        Ok(Self {
+            initial_bps,
+            maintenance_bps,
+        })
+    }
+
+    pub fn initial_bps(&self) -> u32 {
+        self.initial_bps
+    }
+
+    pub fn maintenance_bps(&self) -> u32 {
+        self.maintenance_bps
+    }
+
+    /// The equity that the initial margin requires for a position of the given value,
+    /// rounded up to `scale` decimals, the currency's.
+    pub fn initial_requirement(&self, value: Money, scale: u8) -> Money {
+        value.basis_points(self.initial_bps, scale, Rounding::Up)
+    }
+
+    /// The equity that the maintenance margin requires for a position of the given value,
+    /// rounded up to `scale` decimals, the currency's.
+    pub fn maintenance_requirement(&self, value: Money, scale: u8) -> Money {
+        value.basis_points(self.maintenance_bps, scale, Rounding::Up)
+    }
+}
+
+impl Default for MarginConfig {
+    /// An initial margin of 50%, and a maintenance margin of 25%
+    fn default() -> Self {
+        Self {
+            initial_bps: 5000,
+            maintenance_bps: 2500,
+        }
+    }
+}
+
+impl FromStr for MarginConfig {
+    type Err = String;
+
+    /// Parses `<initial bps>:<maintenance bps>`, such as `"5000:2500"`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid margin: \"{s}\"; expected \"<initial bps>:<maintenance bps>\", such as \"5000:2500\""
+            )
+        };
+
+        let (initial_bps, maintenance_bps) = s.trim().split_once(':').ok_or_else(invalid)?;
+        let initial_bps = initial_bps.parse::<u32>().map_err(|_| invalid())?;
+        let maintenance_bps = maintenance_bps.parse::<u32>().map_err(|_| invalid())?;
+
+        MarginConfig::new(initial_bps, maintenance_bps)
+            .map_err(|err| format!("{}: {:?}", invalid(), err))
+    }
+}
+
+/// **A margin account's position, valued at the mark price**
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct MarginSummary {
+    /// The funds in the account, including the borrowed ones
+    pub balance: Money,
+    pub loan: Money,
+    /// The units of the traded instrument in the account
+    pub units: Quantity,
+    /// The price of the last trade; the units are worth nothing without one.
+    pub mark_price: Option<Price>,
+    /// The units at the mark price
+    pub position_value: Money,
+    /// The balance and the position, less the loan; zero if the loan is larger than both.
+    pub equity: Money,
+    pub initial_requirement: Money,
+    pub maintenance_requirement: Money,
+}
+
+impl MarginSummary {
+    /// Values the `units` at the `mark_price`, and works out the equity and the requirements,
+    /// with `scale` decimals, the currency's.
+    pub fn new(
+        config: &MarginConfig,
+        balance: Money,
+        loan: Money,
+        units: Quantity,
+        mark_price: Option<Price>,
+        scale: u8,
+    ) -> Self {
+        // A position too large to be valued is as good as unlimited.
+        let position_value = mark_price
+            .map(|price| {
+                price
+                    .notional(units, scale, Rounding::Down)
+                    .unwrap_or(Money::MAX)
+            })
+            .unwrap_or_default();
+
+        MarginSummary {
+            balance,
+            loan,
+            units,
+            mark_price,
+            position_value,
+            equity: balance.saturating_add(position_value).saturating_sub(loan),
+            initial_requirement: config.initial_requirement(position_value, scale),
+            maintenance_requirement: config.maintenance_requirement(position_value, scale),
+        }
+    }
+
+    /// Whether the account owes anything, and its equity is below the maintenance margin,
+    /// or it wouldn't even cover the loan
+    pub fn is_below_maintenance(&self) -> bool {
+        !self.loan.is_zero()
+            && (self.equity < self.maintenance_requirement
+                || self.balance.saturating_add(self.position_value) < self.loan)
+    }
+}
+
+impl fmt::Display for MarginSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "balance {}, loan {}, {} units",
+            self.balance, self.loan, self.units
+        )?;
+        match self.mark_price {
+            Some(price) => write!(f, " at {} worth {}", price, self.position_value)?,
+            None => write!(f, " without a price")?,
+        }
+        write!(
+            f,
+            "; equity {}, initial margin {}, maintenance margin {}",
+            self.equity, self.initial_requirement, self.maintenance_requirement
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn margin_config_new_and_from_str() {
+        assert_eq!(
+            Ok(MarginConfig {
+                initial_bps: 5000,
+                maintenance_bps: 2500
+            }),
+            "5000:2500".parse()
+        );
+        assert!(MarginConfig::new(10_000, 10_000).is_ok());
+        for bad in ["", "5000", "2500:5000", "5000:0", "10001:2500", "a:b"] {
+            assert!(bad.parse::<MarginConfig>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn margin_summary_equity_and_requirements() {
+        let config = MarginConfig::new(5000, 2500).unwrap();
+
+        // Bought 10 units at 10 with 50 of its own and 50 borrowed; the price has fallen to 6.
+        let summary =
+            MarginSummary::new(&config, 0.into(), 50.into(), 10.into(), Some(6.into()), 2);
+        assert_eq!(Money::from(60), summary.position_value);
+        assert_eq!(Money::from(10), summary.equity);
+        assert_eq!(Money::from(30), summary.initial_requirement);
+        assert_eq!(Money::from(15), summary.maintenance_requirement);
+        assert!(summary.is_below_maintenance());
+
+        let summary =
+            MarginSummary::new(&config, 0.into(), 50.into(), 10.into(), Some(7.into()), 2);
+        assert_eq!(Money::from(20), summary.equity);
+        assert!(!summary.is_below_maintenance());
+
+        // Without a mark price, the units are worth nothing, and the loan isn't covered.
+        let summary = MarginSummary::new(&config, 10.into(), 50.into(), 10.into(), None, 2);
+        assert_eq!(Money::ZERO, summary.equity);
+        assert!(summary.is_below_maintenance());
+
+        // Nothing owed, nothing to call.
+        let summary = MarginSummary::new(&config, 0.into(), 0.into(), 10.into(), Some(1.into()), 2);
+        assert!(!summary.is_below_maintenance());
+    }
+}
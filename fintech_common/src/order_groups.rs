@@ -0,0 +1,300 @@
+//! Order groups: one-cancels-other (OCO) pairs and entries with a bracket
+//!
+//! A group links several [`Order`]s, its legs, so that an event on one leg
+//! (a fill, or a trigger of a stop) automatically cancels or activates the linked legs.
+//!
+//! The processing of groups is orchestrated by the
+//! [`TradingPlatform`](crate::trading_platform::TradingPlatform),
+//! while this module holds the types and validation.
+
+use crate::errors::{AccountingError, OrderError};
+use crate::types::{Order, Quantity, Side};
+use serde::{Deserialize, Serialize};
+
+/// **The kind of an [`OrderGroup`]**
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum OrderGroupKind {
+    /// A take-profit and a stop-loss leg; a fill on one of them cancels the other one.
+    OneCancelsOther,
+    /// An entry order whose complete fill activates a take-profit and a stop-loss leg,
+    /// which then behave as a one-cancels-other pair.
+    Bracket,
+}
+
+/// **The role of an [`OrderLeg`] inside of its group**
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum LegRole {
+    /// Opens the position; only exists in a bracket.
+    Entry,
+    /// A limit order that closes the position at a profit.
+    TakeProfit,
+    /// A stop-limit order that closes the position at a loss.
+    ///
+    /// It is kept outside of the order book until the last trade price reaches its price,
+    /// which then serves both as the trigger price and as the limit price.
+    StopLoss,
+}
+
+/// **The status of a single [`OrderLeg`]**
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum LegStatus {
+    /// Waiting for the entry of its bracket to be completely filled.
+    Waiting,
+    /// A stop-loss waiting for the last trade price to reach its trigger price.
+    Armed,
+    /// Submitted to the order book; it may be partially filled.
+    Working,
+    /// Completely filled.
+    Filled,
+    /// Cancelled, either by a linked leg or by the signer.
+    Cancelled,
+    /// Could not be submitted, for example, because of insufficient funds.
+    Rejected,
+}
+
+/// **The status of an [`OrderGroup`] as a whole**
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum OrderGroupStatus {
+    /// At least one leg is still waiting, armed or working.
+    Active,
+    /// No leg is waiting, armed or working anymore.
+    Completed,
+    /// Cancelled by the signer.
+    Cancelled,
+}
+
+/// **A single order inside of an [`OrderGroup`]**
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct OrderLeg {
+    /// The leg's role inside of the group
+    pub role: LegRole,
+    /// The order to submit when the leg becomes active
+    pub order: Order,
+    /// The leg's current status
+    pub status: LegStatus,
+    /// The ordinal sequence number the order got from the matching engine, once submitted
+    pub ordinal: Option<u64>,
+    /// Number of units filled so far
+    pub filled: Quantity,
+}
+
+impl OrderLeg {
+    /// Creates a new leg that hasn't been submitted yet.
+    pub fn new(role: LegRole, order: Order, status: LegStatus) -> Self {
+        Self {
+            role,
+            order,
+            status,
+            ordinal: None,
+            filled: Quantity::ZERO,
+        }
+    }
+
+    /// A leg is open while it is waiting, armed or working.
+    pub fn is_open(&self) -> bool {
+        matches!(
+            self.status,
+            LegStatus::Waiting | LegStatus::Armed | LegStatus::Working
+        )
+    }
+}
+
+/// **A group of linked orders**
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct OrderGroup {
+    /// The group's unique identifier
+    pub id: u64,
+    /// One-cancels-other or bracket
+    pub kind: OrderGroupKind,
+    /// The group's current status
+    pub status: OrderGroupStatus,
+    /// The group's legs; the entry, if there is one, always comes first.
+    pub legs: Vec<OrderLeg>,
+}
+
+impl OrderGroup {
+    /// Creates a new one-cancels-other group out of a take-profit and a stop-loss order.
+    ///
+    /// The take-profit leg starts as working, and the stop-loss leg starts as armed.
+    pub fn new_oco(id: u64, take_profit: Order, stop_loss: Order) -> Self {
+        Self {
+            id,
+            kind: OrderGroupKind::OneCancelsOther,
+            status: OrderGroupStatus::Active,
+            legs: vec![
+                OrderLeg::new(LegRole::TakeProfit, take_profit, LegStatus::Working),
+                OrderLeg::new(LegRole::StopLoss, stop_loss, LegStatus::Armed),
+            ],
+        }
+    }
+
+    /// Creates a new bracket group out of an entry, a take-profit and a stop-loss order.
+    ///
+    /// The entry leg starts as working, while the two exit legs wait for it to be filled.
+    pub fn new_bracket(id: u64, entry: Order, take_profit: Order, stop_loss: Order) -> Self {
+        Self {
+            id,
+            kind: OrderGroupKind::Bracket,
+            status: OrderGroupStatus::Active,
+            legs: vec![
+                OrderLeg::new(LegRole::Entry, entry, LegStatus::Working),
+                OrderLeg::new(LegRole::TakeProfit, take_profit, LegStatus::Waiting),
+                OrderLeg::new(LegRole::StopLoss, stop_loss, LegStatus::Waiting),
+            ],
+        }
+    }
+
+    /// Marks the group as completed if none of its legs is open anymore.
+    pub fn update_status(&mut self) {
+        if self.status == OrderGroupStatus::Active && !self.legs.iter().any(OrderLeg::is_open) {
+            self.status = OrderGroupStatus::Completed;
+        }
+    }
+}
+
+/// **Validates the two exit legs of a group**
+///
+/// Both legs need to be on the same side and have the same signer and amount.
+///
+/// The take-profit needs to be on the profitable side of the stop-loss:
+/// above it when selling, and below it when buying.
+///
+/// # Errors
+/// - Legs don't fit together, `OrderError::GroupInvalid`
+pub fn validate_exits(take_profit: &Order, stop_loss: &Order) -> Result<(), AccountingError> {
+    if take_profit.side != stop_loss.side {
+        return Err(OrderError::GroupInvalid(
+            "The take-profit and the stop-loss must be on the same side.".to_string(),
+        )
+        .into());
+    }
+    if take_profit.signer != stop_loss.signer {
+        return Err(OrderError::GroupInvalid(
+            "The take-profit and the stop-loss must have the same signer.".to_string(),
+        )
+        .into());
+    }
+    if take_profit.get_initial_amount() != stop_loss.get_initial_amount() {
+        return Err(OrderError::GroupInvalid(
+            "The take-profit and the stop-loss must have the same amount.".to_string(),
+        )
+        .into());
+    }
+
+    let profitable = match take_profit.side {
+        Side::Sell => take_profit.price > stop_loss.price,
+        Side::Buy => take_profit.price < stop_loss.price,
+    };
+    if !profitable {
+        return Err(OrderError::GroupInvalid(
+            "The take-profit must be on the profitable side of the stop-loss.".to_string(),
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// **Validates all three legs of a bracket**
+///
+/// The exits need to close the position that the entry opens, so they have to be
+/// on the opposite side of the entry, with the same signer and amount.
+///
+/// # Errors
+/// - Legs don't fit together, `OrderError::GroupInvalid`
+pub fn validate_bracket(
+    entry: &Order,
+    take_profit: &Order,
+    stop_loss: &Order,
+) -> Result<(), AccountingError> {
+    validate_exits(take_profit, stop_loss)?;
+
+    if entry.side == take_profit.side {
+        return Err(OrderError::GroupInvalid(
+            "The exits must be on the opposite side of the entry.".to_string(),
+        )
+        .into());
+    }
+    if entry.signer != take_profit.signer {
+        return Err(OrderError::GroupInvalid(
+            "The entry and the exits must have the same signer.".to_string(),
+        )
+        .into());
+    }
+    if entry.get_initial_amount() != take_profit.get_initial_amount() {
+        return Err(OrderError::GroupInvalid(
+            "The entry and the exits must have the same amount.".to_string(),
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(price: u64, amount: u64, side: Side) -> Order {
+        Order::new(price, amount, side, String::from("Alice"))
+    }
+
+    #[test]
+    fn validate_exits_ok_both_sides() {
+        assert!(validate_exits(&order(12, 1, Side::Sell), &order(8, 1, Side::Sell)).is_ok());
+        assert!(validate_exits(&order(8, 1, Side::Buy), &order(12, 1, Side::Buy)).is_ok());
+    }
+
+    #[test]
+    fn validate_exits_err_unprofitable() {
+        assert!(validate_exits(&order(8, 1, Side::Sell), &order(12, 1, Side::Sell)).is_err());
+        assert!(validate_exits(&order(12, 1, Side::Buy), &order(8, 1, Side::Buy)).is_err());
+    }
+
+    #[test]
+    fn validate_exits_err_mismatch() {
+        assert!(validate_exits(&order(12, 1, Side::Sell), &order(8, 1, Side::Buy)).is_err());
+        assert!(validate_exits(&order(12, 1, Side::Sell), &order(8, 2, Side::Sell)).is_err());
+        assert!(validate_exits(
+            &order(12, 1, Side::Sell),
+            &Order::new(8, 1, Side::Sell, String::from("Bob"))
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn validate_bracket_ok() {
+        assert!(validate_bracket(
+            &order(10, 1, Side::Buy),
+            &order(12, 1, Side::Sell),
+            &order(8, 1, Side::Sell)
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn validate_bracket_err_same_side() {
+        assert_eq!(
+            Err(AccountingError::Order(OrderError::GroupInvalid(
+                "The exits must be on the opposite side of the entry.".to_string()
+            ))),
+            validate_bracket(
+                &order(10, 1, Side::Sell),
+                &order(12, 1, Side::Sell),
+                &order(8, 1, Side::Sell)
+            )
+        );
+    }
+
+    #[test]
+    fn update_status_completes_group_without_open_legs() {
+        let mut group = OrderGroup::new_oco(1, order(12, 1, Side::Sell), order(8, 1, Side::Sell));
+        group.update_status();
+        assert_eq!(OrderGroupStatus::Active, group.status);
+
+        group.legs[0].status = LegStatus::Filled;
+        group.legs[1].status = LegStatus::Cancelled;
+        group.update_status();
+        assert_eq!(OrderGroupStatus::Completed, group.status);
+    }
+}
@@ -0,0 +1,299 @@
+//! Positions and their profit and loss
+//!
+//! A signer's position in an instrument is built from their fills alone, so units that were
+//! deposited and then sold make a short position, same as units sold short would.
+//! Buying adds to a long position, and selling to a short one, at the average price of the fills.
+//! A fill on the other side closes the position first, at the average price,
+//! which realizes its profit or loss; whatever is left of the fill opens a position the other way.
+//!
+//! The open units' unrealized profit or loss is what closing them at the mark price would realize.
+//! Fees aren't included in either.
+
+use crate::types::{Money, Price, Quantity, Side};
+use serde::de;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// **A profit, or a loss**
+///
+/// Serialized as a decimal string, which starts with a minus for a loss, such as `"-12.5"`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Pnl {
+    Profit(Money),
+    Loss(Money),
+}
+
+impl Default for Pnl {
+    fn default() -> Self {
+        Pnl::Profit(Money::ZERO)
+    }
+}
+
+impl Pnl {
+    /// What selling for `proceeds` makes, or loses, on what cost `cost`
+    pub fn of(proceeds: Money, cost: Money) -> Self {
+        if proceeds >= cost {
+            Pnl::Profit(proceeds.saturating_sub(cost))
+        } else {
+            Pnl::Loss(cost.saturating_sub(proceeds))
+        }
+    }
+
+    /// Nets a profit or a loss with another one, stopping at the maximum either way.
+    pub fn saturating_add(self, other: Pnl) -> Pnl {
+        match (self, other) {
+            (Pnl::Profit(a), Pnl::Profit(b)) => Pnl::Profit(a.saturating_add(b)),
+            (Pnl::Loss(a), Pnl::Loss(b)) => Pnl::Loss(a.saturating_add(b)),
+            (Pnl::Profit(profit), Pnl::Loss(loss)) | (Pnl::Loss(loss), Pnl::Profit(profit)) => {
+                Pnl::of(profit, loss)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Pnl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pnl::Loss(loss) if !loss.is_zero() => write!(f, "-{}", loss),
+            Pnl::Profit(amount) | Pnl::Loss(amount) => write!(f, "{}", amount),
+        }
+    }
+}
+
+impl FromStr for Pnl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |err| format!("Invalid profit or loss: \"{s}\"; {err}");
+        match s.trim().strip_prefix('-') {
+            Some(loss) => loss.parse().map(Pnl::Loss).map_err(invalid),
+            None => s.trim().parse().map(Pnl::Profit).map_err(invalid),
+        }
+    }
+}
+
+impl Serialize for Pnl {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Pnl {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// **The price that open positions are valued at**
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarkPrice {
+    /// The price of the most recent trade
+    #[default]
+    LastTrade,
+    /// Halfway between the best bid and the best ask
+    Mid,
+}
+
+impl fmt::Display for MarkPrice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MarkPrice::LastTrade => write!(f, "last-trade"),
+            MarkPrice::Mid => write!(f, "mid"),
+        }
+    }
+}
+
+impl FromStr for MarkPrice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "last-trade" | "last_trade" | "last" => Ok(MarkPrice::LastTrade),
+            "mid" => Ok(MarkPrice::Mid),
+            _ => Err(format!(
+                "Invalid mark price: \"{s}\"; expected \"last-trade\" or \"mid\""
+            )),
+        }
+    }
+}
+
+/// **A signer's net position in an instrument, built from their fills**
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Position {
+    /// Buy for a long position, of units bought, and sell for a short one, of units sold
+    pub side: Side,
+    /// The open units; a closed position has none.
+    pub units: Quantity,
+    /// What the open units were bought for, or sold for, when short
+    pub cost: Money,
+    /// What closing units has made or lost so far
+    pub realized: Pnl,
+}
+
+impl Position {
+    /// A position without any units, which the first fill opens
+    pub fn new(side: Side) -> Self {
+        Position {
+            side,
+            units: Quantity::ZERO,
+            cost: Money::ZERO,
+            realized: Pnl::default(),
+        }
+    }
+
+    /// Adds a fill of the `units` on the `side`, for the `value`.
+    ///
+    /// A fill on the other side closes as many units as it can, and realizes their profit or loss.
+    pub fn apply_fill(&mut self, side: &Side, units: Quantity, value: Money) {
+        if self.units.is_zero() || self.side == *side {
+            self.side = side.clone();
+            self.units = self.units.saturating_add(units);
+            self.cost = self.cost.saturating_add(value);
+            return;
+        }
+
+        let closed = units.min(self.units);
+        let closed_cost = self.cost.pro_rata(closed, self.units);
+        let closed_value = value.pro_rata(closed, units);
+        let pnl = match self.side {
+            Side::Buy => Pnl::of(closed_value, closed_cost),
+            Side::Sell => Pnl::of(closed_cost, closed_value),
+        };
+        self.realized = self.realized.saturating_add(pnl);
+        self.units = self.units.saturating_sub(closed);
+        self.cost = self.cost.saturating_sub(closed_cost);
+
+        let opened = units.saturating_sub(closed);
+        if !opened.is_zero() {
+            self.side = side.clone();
+            self.units = opened;
+            self.cost = value.saturating_sub(closed_value);
+        }
+    }
+
+    /// The average price of the open units; `None` if there are none
+    pub fn average_price(&self) -> Option<Price> {
+        self.cost.checked_per_unit(self.units)
+    }
+
+    /// What closing the open units at the `mark_price` would make or lose
+    pub fn unrealized(&self, mark_price: Price) -> Pnl {
+        // A position too large to be valued is as good as unlimited.
+        let value = mark_price
+            .checked_notional(self.units)
+            .unwrap_or(Money::MAX);
+        match self.side {
+            Side::Buy => Pnl::of(value, self.cost),
+            Side::Sell => Pnl::of(self.cost, value),
+        }
+    }
+}
+
+/// **A signer's position, valued at the mark price**
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct PositionSummary {
+    pub signer: String,
+    pub symbol: String,
+    pub side: Side,
+    pub units: Quantity,
+    /// The average price of the open units; `None` if there are none
+    pub average_price: Option<Price>,
+    pub mark_price: Option<Price>,
+    pub realized: Pnl,
+    /// `None` without a mark price
+    pub unrealized: Option<Pnl>,
+}
+
+impl PositionSummary {
+    /// Values the `signer`'s `position` in the instrument with the `symbol` at the `mark_price`.
+    pub fn new(signer: &str, symbol: &str, position: &Position, mark_price: Option<Price>) -> Self {
+        PositionSummary {
+            signer: signer.to_string(),
+            symbol: symbol.to_string(),
+            side: position.side.clone(),
+            units: position.units,
+            average_price: position.average_price(),
+            mark_price,
+            realized: position.realized,
+            unrealized: mark_price.map(|price| position.unrealized(price)),
+        }
+    }
+}
+
+impl fmt::Display for PositionSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}: ", self.signer, self.symbol)?;
+        match (&self.side, self.average_price) {
+            (Side::Buy, Some(price)) => write!(f, "long {} at {}", self.units, price)?,
+            (Side::Sell, Some(price)) => write!(f, "short {} at {}", self.units, price)?,
+            (_, None) => write!(f, "flat")?,
+        }
+        write!(f, "; realized {}", self.realized)?;
+        match (self.unrealized, self.mark_price) {
+            (Some(unrealized), Some(price)) => {
+                write!(f, ", unrealized {} at {}", unrealized, price)
+            }
+            _ => write!(f, ", unrealized unknown without a price"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pnl_nets_and_round_trips() {
+        let loss = Pnl::of(7.into(), 10.into());
+        assert_eq!(Pnl::Loss(3.into()), loss);
+        assert_eq!(
+            Pnl::Profit(2.into()),
+            loss.saturating_add(Pnl::Profit(5.into()))
+        );
+        assert_eq!(
+            Pnl::Loss(5.into()),
+            loss.saturating_add(Pnl::Loss(2.into()))
+        );
+
+        assert_eq!("-3", loss.to_string());
+        assert_eq!("0", Pnl::Loss(Money::ZERO).to_string());
+        assert_eq!(Ok(loss), "-3".parse());
+        assert_eq!(Ok(Pnl::Profit(Money::new(125, 1))), "12.5".parse());
+        assert!("--3".parse::<Pnl>().is_err());
+        assert_eq!("\"-3\"", serde_json::to_string(&loss).unwrap());
+        assert_eq!(loss, serde_json::from_str("\"-3\"").unwrap());
+    }
+
+    #[test]
+    fn position_realizes_on_reductions_and_flips() {
+        let mut position = Position::new(Side::Buy);
+        position.apply_fill(&Side::Buy, 10.into(), 100.into());
+        position.apply_fill(&Side::Buy, 10.into(), 120.into());
+        assert_eq!(Some(Price::from(11)), position.average_price());
+        assert_eq!(Pnl::Profit(20.into()), position.unrealized(12.into()));
+
+        // Selling 5 at 13 realizes 5 * (13 - 11).
+        position.apply_fill(&Side::Sell, 5.into(), 65.into());
+        assert_eq!(Pnl::Profit(10.into()), position.realized);
+        assert_eq!(Quantity::from(15), position.units);
+        assert_eq!(Money::from(165), position.cost);
+
+        // Selling 20 at 10 closes the 15, and opens a short position of 5 at 10.
+        position.apply_fill(&Side::Sell, 20.into(), 200.into());
+        assert_eq!(Pnl::Loss(5.into()), position.realized);
+        assert_eq!(Side::Sell, position.side);
+        assert_eq!(Quantity::from(5), position.units);
+        assert_eq!(Some(Price::from(10)), position.average_price());
+        assert_eq!(Pnl::Loss(10.into()), position.unrealized(12.into()));
+
+        // Buying the 5 back at 8 closes the position.
+        position.apply_fill(&Side::Buy, 5.into(), 40.into());
+        assert_eq!(Pnl::Profit(5.into()), position.realized);
+        assert!(position.units.is_zero());
+        assert_eq!(None, position.average_price());
+    }
+}
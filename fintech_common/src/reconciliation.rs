@@ -0,0 +1,615 @@
+use crate::accounts::{AccountSummary, Accounts};
+use crate::errors::AccountingError;
+use crate::fx::CurrencyScales;
+use crate::history::HistorySink;
+use crate::journal::{EntryKind, SystemAccount};
+use crate::tx::Tx;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io;
+
+/// **Why a transaction log can't be replayed**
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReplayError {
+    /// The log doesn't start with the first transaction, or skips some;
+    /// holds the id that was expected, and the one that was found instead.
+    MissingTransactions(u64, u64),
+    /// The transaction with the id can't be applied to the state before it.
+    TransactionFailed(u64, AccountingError),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::MissingTransactions(expected, found) => write!(
+                f,
+                "The log is incomplete: transaction #{} was expected, but #{} was found.",
+                expected, found
+            ),
+            ReplayError::TransactionFailed(id, err) => {
+                write!(f, "Transaction #{} can't be applied: {:?}.", id, err)
+            }
+        }
+    }
+}
+
+/// **Applies the transactions to the accounts, in the same sequence**
+///
+/// Starting from [`Accounts::new`], and with the complete log, this rebuilds the state
+/// that produced the log. The ids of the transactions have to follow one another,
+/// starting with 1, so that none are missing.
+///
+/// # Errors
+/// - A transaction is missing, `ReplayError::MissingTransactions`;
+/// - A transaction can't be applied, `ReplayError::TransactionFailed`.
+pub fn replay<'a>(
+    accounts: Accounts,
+    txs: impl IntoIterator<Item = &'a Tx>,
+) -> Result<Accounts, ReplayError> {
+    replay_from(accounts, 0, txs)
+}
+
+/// **Applies the transactions to the accounts, which are the state after the transaction `last_tx_id`**
+///
+/// The same as [`replay`], except that the ids of the transactions start with the one after `last_tx_id`.
+///
+/// # Errors
+/// - A transaction is missing, `ReplayError::MissingTransactions`;
+/// - A transaction can't be applied, `ReplayError::TransactionFailed`.
+pub fn replay_from<'a>(
+    mut accounts: Accounts,
+    last_tx_id: u64,
+    txs: impl IntoIterator<Item = &'a Tx>,
+) -> Result<Accounts, ReplayError> {
+    for (expected, tx) in (last_tx_id + 1..).zip(txs) {
+        if tx.id() != expected {
+            return Err(ReplayError::MissingTransactions(expected, tx.id()));
+        }
+        apply(&mut accounts, tx).map_err(|err| ReplayError::TransactionFailed(tx.id(), err))?;
+    }
+
+    Ok(accounts)
+}
+
+/// **A transaction log that can be replayed, even when its sink doesn't retain all transactions**
+///
+/// Every transaction that the sink no longer retains, such as the oldest one of a full
+/// [`RingBufferSink`](crate::history::RingBufferSink), or any one with a [`NoOpSink`](crate::history::NoOpSink),
+/// is applied to a checkpoint: the accounts as all transactions up to it left them.
+/// Replaying the log starts from the checkpoint, with the retained transactions.
+pub struct TxLog {
+    sink: Box<dyn HistorySink<Tx>>,
+    /// The accounts as the transactions that aren't retained anymore left them
+    checkpoint: Accounts,
+    /// The id of the last transaction that isn't retained anymore; 0 if there's none
+    checkpoint_tx_id: u64,
+    /// The timestamp of the last transaction that isn't retained anymore
+    checkpoint_timestamp: u64,
+    /// Why a transaction couldn't be applied to the checkpoint; none are applied after it.
+    checkpoint_error: Option<ReplayError>,
+    /// The accounts that the transactions recorded since they were last taken involve
+    touched: BTreeSet<String>,
+}
+
+impl TxLog {
+    /// Creates a log that records into the `sink`, with a checkpoint of empty accounts.
+    ///
+    /// The sink has to be empty, as transactions that are already in it don't start from the checkpoint;
+    /// an [`AppendFileSink`](crate::history::AppendFileSink) always is, as it moves a previous run's file aside.
+    pub fn new(sink: Box<dyn HistorySink<Tx>>) -> Self {
+        Self {
+            sink,
+            checkpoint: Accounts::new(),
+            checkpoint_tx_id: 0,
+            checkpoint_timestamp: 0,
+            checkpoint_error: None,
+            touched: BTreeSet::new(),
+        }
+    }
+
+    /// Sets the currency of the checkpoint's balances; it has to be the one of the live accounts.
+    pub fn set_currency(&mut self, currency: &str) {
+        self.checkpoint.set_currency(currency);
+    }
+
+    /// Sets the scales of the checkpoint's currencies; they have to be the ones of the live accounts.
+    pub fn set_currency_scales(&mut self, scales: CurrencyScales) {
+        self.checkpoint.set_currency_scales(scales);
+    }
+
+    /// **Records a transaction, and applies the one that the sink doesn't retain anymore to the checkpoint**
+    pub fn record(&mut self, tx: Tx) {
+        for account in tx.accounts() {
+            if !self.touched.contains(account) {
+                self.touched.insert(account.to_string());
+            }
+        }
+
+        let Some(evicted) = self.sink.record(tx) else {
+            return;
+        };
+
+        let expected = self.checkpoint_tx_id + 1;
+        (self.checkpoint_tx_id, self.checkpoint_timestamp) = (evicted.id(), evicted.timestamp());
+        if self.checkpoint_error.is_none() {
+            self.checkpoint_error = match evicted.id() == expected {
+                true => apply(&mut self.checkpoint, &evicted)
+                    .err()
+                    .map(|err| ReplayError::TransactionFailed(evicted.id(), err)),
+                false => Some(ReplayError::MissingTransactions(expected, evicted.id())),
+            };
+        }
+    }
+
+    /// Returns all retained transactions, from the oldest to the newest.
+    ///
+    /// It's as costly as its sink makes it; see [`HistorySink::records`].
+    pub fn records(&self) -> Vec<Tx> {
+        self.sink.records()
+    }
+
+    /// Takes the accounts that the transactions recorded since the last call involve.
+    pub fn take_touched(&mut self) -> BTreeSet<String> {
+        std::mem::take(&mut self.touched)
+    }
+
+    /// Returns the number of retained transactions.
+    pub fn len(&self) -> usize {
+        self.sink.len()
+    }
+
+    /// Returns `true` if there are no retained transactions.
+    pub fn is_empty(&self) -> bool {
+        self.sink.is_empty()
+    }
+
+    /// The id and the timestamp of the last transaction that isn't retained anymore, if there's one
+    pub fn last_evicted(&self) -> Option<(u64, u64)> {
+        (self.checkpoint_tx_id > 0).then_some((self.checkpoint_tx_id, self.checkpoint_timestamp))
+    }
+
+    /// **The accounts as the transactions that aren't retained anymore left them**
+    ///
+    /// Returns the id of the last of these transactions along with them; 0 if there's none.
+    ///
+    /// # Errors
+    /// - One of these transactions couldn't be applied, see [`replay`].
+    pub fn checkpoint(&self) -> Result<(&Accounts, u64), ReplayError> {
+        match &self.checkpoint_error {
+            Some(err) => Err(err.clone()),
+            None => Ok((&self.checkpoint, self.checkpoint_tx_id)),
+        }
+    }
+
+    /// **Rebuilds the accounts from the checkpoint and the retained transactions**
+    ///
+    /// # Errors
+    /// - A transaction is missing, `ReplayError::MissingTransactions`;
+    /// - A transaction can't be applied, `ReplayError::TransactionFailed`.
+    pub fn replay(&self) -> Result<Accounts, ReplayError> {
+        let (checkpoint, last_tx_id) = self.checkpoint()?;
+        replay_from(checkpoint.clone(), last_tx_id, &self.sink.records())
+    }
+
+    /// Discards all retained transactions, and the checkpoint, keeping its currency and its scales.
+    ///
+    /// # Errors
+    /// - The transactions are kept outside of memory, and they can't be discarded.
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.sink.clear()?;
+
+        let mut checkpoint = Accounts::new();
+        checkpoint.set_currency(self.checkpoint.currency());
+        checkpoint.set_currency_scales(self.checkpoint.currency_scales().clone());
+        self.checkpoint = checkpoint;
+        self.checkpoint_tx_id = 0;
+        self.checkpoint_timestamp = 0;
+        self.checkpoint_error = None;
+        Ok(())
+    }
+}
+
+/// Applies a single transaction, moving the funds and the units in the way that it records.
+fn apply(accounts: &mut Accounts, tx: &Tx) -> Result<(), AccountingError> {
+    match tx {
+        Tx::Deposit {
+            account,
+            amount,
+            currency,
+            ..
+        } => {
+            // What was recorded is replayed as it is, without checking the currency's scale.
+            let currency = currency_of(accounts, currency);
+            accounts
+                .deposit_in_from(account, &currency, *amount, SystemAccount::CashIn)
+                .map(|_| ())
+        }
+        Tx::Withdraw {
+            account,
+            amount,
+            currency,
+            ..
+        } => {
+            let currency = currency_of(accounts, currency);
+            accounts
+                .withdraw_in_to(account, &currency, *amount, SystemAccount::CashIn)
+                .map(|_| ())
+        }
+        Tx::DepositUnits {
+            account,
+            symbol,
+            units,
+            ..
+        } => accounts.deposit_units(account, symbol, *units).map(|_| ()),
+        Tx::WithdrawUnits {
+            account,
+            symbol,
+            units,
+            ..
+        } => accounts.withdraw_units(account, symbol, *units).map(|_| ()),
+        Tx::Transfer {
+            from,
+            to,
+            amount,
+            memo,
+            currency,
+            ..
+        } => {
+            let currency = currency_of(accounts, currency);
+            accounts
+                .send_in(from, to, &currency, *amount, memo.as_deref())
+                .map(|_| ())
+        }
+        Tx::TransferUnits {
+            from,
+            to,
+            symbol,
+            units,
+            memo,
+            ..
+        } => accounts
+            .send_units(from, to, symbol, *units, memo.as_deref())
+            .map(|_| ()),
+        Tx::TradeSettlement {
+            buyer,
+            seller,
+            symbol,
+            units,
+            value,
+            ..
+        } => {
+            accounts.transfer(buyer, seller, *value, EntryKind::Settlement)?;
+            accounts.transfer_units(seller, buyer, symbol, *units)
+        }
+        Tx::Fee {
+            account,
+            fee_account,
+            amount,
+            ..
+        } => {
+            accounts.open_fee_account(fee_account);
+            accounts.transfer(account, fee_account, *amount, EntryKind::Fee)
+        }
+        Tx::Rebate {
+            account,
+            fee_account,
+            amount,
+            ..
+        } => {
+            accounts.open_fee_account(fee_account);
+            accounts.transfer(fee_account, account, *amount, EntryKind::Rebate)
+        }
+        Tx::AccountClosed { account, .. } => accounts.close(account, None).map(|_| ()),
+        Tx::AccountStatusChanged {
+            account, status, ..
+        } => accounts.set_status(account, *status).map(|_| ()),
+        Tx::MarginAccountOpened { account, .. } => accounts.open_margin(account).map(|_| ()),
+        Tx::MarginLoan {
+            account, amount, ..
+        } => accounts.borrow(account, *amount).map(|_| ()),
+        Tx::MarginRepayment {
+            account, amount, ..
+        } => accounts.repay(account, *amount).map(|_| ()),
+        Tx::Conversion {
+            account,
+            from_currency,
+            to_currency,
+            amount,
+            converted,
+            ..
+        } => accounts.exchange(account, from_currency, to_currency, *amount, *converted),
+        Tx::Reservation {
+            account,
+            amount,
+            currency,
+            leg,
+            ..
+        } => {
+            let currency = currency_of(accounts, currency);
+            accounts
+                .reserve_in(account, &currency, *amount, *leg)
+                .map(|_| ())
+        }
+        Tx::ReservationUnits {
+            account,
+            symbol,
+            units,
+            leg,
+            ..
+        } => accounts
+            .reserve_units(account, symbol, *units, *leg)
+            .map(|_| ()),
+        Tx::Release {
+            account,
+            amount,
+            currency,
+            leg,
+            ..
+        } => {
+            let currency = currency_of(accounts, currency);
+            accounts
+                .release_in(account, &currency, *amount, *leg)
+                .map(|_| ())
+        }
+        Tx::ReleaseUnits {
+            account,
+            symbol,
+            units,
+            leg,
+            ..
+        } => accounts
+            .release_units(account, symbol, *units, *leg)
+            .map(|_| ()),
+        // The trades that follow them move the funds and the units.
+        Tx::MarginCall { .. } | Tx::Liquidation { .. } => Ok(()),
+    }
+}
+
+/// The transaction's currency; the balances' currency if it doesn't have one
+fn currency_of(accounts: &Accounts, currency: &Option<String>) -> String {
+    currency
+        .clone()
+        .unwrap_or_else(|| accounts.currency().to_string())
+}
+
+/// **An account whose state, rebuilt from the transaction log, differs from the live one**
+///
+/// Only the balances, in every currency, the holdings, the statuses and the margin loans are compared,
+/// as holds for resting orders aren't transactions.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Divergence {
+    pub account: String,
+    /// The account rebuilt from the log; `None` if it isn't there
+    pub rebuilt: Option<AccountSummary>,
+    /// The live account; `None` if it isn't there
+    pub live: Option<AccountSummary>,
+}
+
+/// **Compares the balances, the holdings, the statuses and the margin loans of the rebuilt accounts
+/// with the live ones**
+pub fn divergences(rebuilt: &Accounts, live: &Accounts) -> Vec<Divergence> {
+    let unheld = |accounts: &Accounts, signer: &str| {
+        accounts
+            .summary_of(signer)
+            .ok()
+            .map(|summary| AccountSummary {
+                balance: summary.balance,
+                holdings: summary.holdings,
+                status: summary.status,
+                margin: summary.margin,
+                loan: summary.loan,
+                balances: summary.balances,
+                ..Default::default()
+            })
+    };
+
+    rebuilt
+        .accounts
+        .keys()
+        .chain(live.accounts.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter_map(|signer| {
+            let (rebuilt, live) = (unheld(rebuilt, signer), unheld(live, signer));
+            (rebuilt != live).then(|| Divergence {
+                account: signer.clone(),
+                rebuilt,
+                live,
+            })
+        })
+        .collect()
+}
+
+/// **The result of checking a platform's accounts against its transaction log**
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Reconciliation {
+    /// The symbol of the platform's instrument
+    pub symbol: String,
+    /// The number of transactions in the log
+    pub transactions: usize,
+    /// Why the log couldn't be replayed, if it couldn't; there are no divergences then.
+    pub replay_error: Option<String>,
+    pub divergences: Vec<Divergence>,
+}
+
+impl Reconciliation {
+    /// Whether the log could be replayed, and the rebuilt accounts match the live ones
+    pub fn is_consistent(&self) -> bool {
+        self.replay_error.is_none() && self.divergences.is_empty()
+    }
+}
+
+impl fmt::Display for Reconciliation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} transactions", self.symbol, self.transactions)?;
+        if let Some(replay_error) = &self.replay_error {
+            return write!(f, "; {}", replay_error);
+        }
+        if self.divergences.is_empty() {
+            return write!(f, "; consistent.");
+        }
+
+        write!(f, "; {} accounts diverge:", self.divergences.len())?;
+        for divergence in &self.divergences {
+            write!(
+                f,
+                "\n  {}: rebuilt {:?}, live {:?}",
+                divergence.account, divergence.rebuilt, divergence.live
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::AccountError;
+    use crate::history::{AppendFileSink, RingBufferSink};
+    use crate::types::Money;
+
+    #[test]
+    fn replay_rebuilds_the_accounts() {
+        let mut live = Accounts::new();
+        let mut tx_log = vec![
+            live.deposit("Alice", 100.into()).unwrap(),
+            live.deposit_units("Bob", "ABC", 5.into()).unwrap(),
+            live.send("Alice", "Bob", 40.into(), Some("Rent")).unwrap(),
+            live.send_units("Bob", "Alice", "ABC", 2.into(), None)
+                .unwrap(),
+            live.withdraw("Bob", 10.into()).unwrap(),
+        ];
+        tx_log.extend(live.close("Bob", Some("Alice")).unwrap());
+
+        let rebuilt = replay(Accounts::new(), &tx_log).unwrap();
+        assert!(divergences(&rebuilt, &live).is_empty());
+        assert_eq!(&Money::from(90), rebuilt.balance_of("Alice").unwrap());
+    }
+
+    #[test]
+    fn replay_err_missing_or_failing_transactions() {
+        let mut live = Accounts::new();
+        let tx_log = vec![
+            live.deposit("Alice", 100.into()).unwrap(),
+            live.withdraw("Alice", 60.into()).unwrap(),
+            live.withdraw("Alice", 40.into()).unwrap(),
+        ];
+
+        assert_eq!(
+            Err(ReplayError::MissingTransactions(1, 2)),
+            replay(Accounts::new(), &tx_log[1..]).map(|_| ())
+        );
+        assert_eq!(
+            Err(ReplayError::MissingTransactions(2, 3)),
+            replay(Accounts::new(), [&tx_log[0], &tx_log[2]]).map(|_| ())
+        );
+
+        let mut tampered = tx_log.clone();
+        if let Tx::Withdraw { amount, .. } = &mut tampered[2] {
+            *amount = 41.into();
+        }
+        assert_eq!(
+            Err(ReplayError::TransactionFailed(
+                3,
+                AccountingError::Account(AccountError::UnderFunded("Alice".to_string(), 41.into()))
+            )),
+            replay(Accounts::new(), &tampered).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn tx_log_applies_evicted_transactions_to_its_checkpoint() {
+        let mut live = Accounts::new();
+        let mut tx_log = TxLog::new(Box::new(RingBufferSink::new(2)));
+        tx_log.record(live.deposit("Alice", 100.into()).unwrap());
+        tx_log.record(live.deposit("Bob", 50.into()).unwrap());
+        assert!(tx_log.checkpoint().unwrap().0.accounts.is_empty());
+        tx_log.record(live.send("Alice", "Bob", 40.into(), None).unwrap());
+        tx_log.record(live.withdraw("Bob", 10.into()).unwrap());
+
+        let (checkpoint, last_tx_id) = tx_log.checkpoint().unwrap();
+        assert_eq!(2, last_tx_id);
+        assert_eq!(&Money::from(50), checkpoint.balance_of("Bob").unwrap());
+        assert_eq!(2, tx_log.len());
+        assert!(divergences(&tx_log.replay().unwrap(), &live).is_empty());
+
+        // Without the checkpoint, the retained transactions can't be replayed.
+        assert_eq!(
+            Err(ReplayError::MissingTransactions(1, 3)),
+            replay(Accounts::new(), &tx_log.records()).map(|_| ())
+        );
+
+        tx_log.clear().unwrap();
+        assert_eq!(0, tx_log.checkpoint().unwrap().1);
+        assert!(tx_log.is_empty());
+    }
+
+    #[test]
+    fn tx_log_replays_a_reopened_file_from_the_new_run() {
+        let path =
+            std::env::temp_dir().join(format!("fintech_tx_log_{}.jsonl", std::process::id()));
+        let previous =
+            path.with_file_name(format!("fintech_tx_log_{}.1.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&previous);
+
+        let mut first_run = Accounts::new();
+        let mut tx_log = TxLog::new(Box::new(AppendFileSink::open(&path).unwrap()));
+        tx_log.record(first_run.deposit("Alice", 100.into()).unwrap());
+        tx_log.record(first_run.withdraw("Alice", 30.into()).unwrap());
+        drop(tx_log);
+
+        // A restart begins with empty accounts, whose transactions are numbered from 1 again.
+        let mut second_run = Accounts::new();
+        let mut tx_log = TxLog::new(Box::new(AppendFileSink::open(&path).unwrap()));
+        assert!(tx_log.is_empty());
+        tx_log.record(second_run.deposit("Bob", 50.into()).unwrap());
+        assert_eq!(1, tx_log.len());
+        assert!(divergences(&tx_log.replay().unwrap(), &second_run).is_empty());
+
+        // The previous run's transactions are kept in a file of their own, which still replays.
+        let previous_txs: Vec<Tx> = std::fs::read_to_string(&previous)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert!(
+            divergences(&replay(Accounts::new(), &previous_txs).unwrap(), &first_run).is_empty()
+        );
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&previous).unwrap();
+    }
+
+    #[test]
+    fn divergences_report_both_sides() {
+        let mut live = Accounts::new();
+        let tx_log = vec![live.deposit("Alice", 100.into()).unwrap()];
+        live.accounts.insert("Alice".to_string(), 99.into());
+        live.accounts.insert("Bob".to_string(), Money::ZERO);
+
+        let rebuilt = replay(Accounts::new(), &tx_log).unwrap();
+        assert_eq!(
+            vec![
+                Divergence {
+                    account: "Alice".to_string(),
+                    rebuilt: Some(AccountSummary {
+                        balance: 100.into(),
+                        ..Default::default()
+                    }),
+                    live: Some(AccountSummary {
+                        balance: 99.into(),
+                        ..Default::default()
+                    }),
+                },
+                Divergence {
+                    account: "Bob".to_string(),
+                    rebuilt: None,
+                    live: Some(AccountSummary::default()),
+                },
+            ],
+            divergences(&rebuilt, &live)
+        );
+    }
+}
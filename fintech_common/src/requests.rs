@@ -1,5 +1,8 @@
 //! The request types (also called models in warp examples)
 
+use crate::accounts::AccountStatus;
+use crate::positions::MarkPrice;
+use crate::types::{Money, Order, Quantity, Side};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -7,26 +10,123 @@ pub struct AccountBalanceRequest {
     pub signer: String,
 }
 
+/// Deposits or withdraws funds in the `currency`; the ledger's currency if it's `None`.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct AccountUpdateRequest {
     pub signer: String,
-    pub amount: u64,
+    pub amount: Money,
+    #[serde(default)]
+    pub currency: Option<String>,
+}
+
+/// Deposits or withdraws units of the instrument of a symbol's market;
+/// the default market if the symbol is `None`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AccountUnitsUpdateRequest {
+    pub signer: String,
+    pub units: Quantity,
+    pub symbol: Option<String>,
+}
+
+/// Closes an account; its funds and units are swept to the `sweep_to` account, if any.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AccountCloseRequest {
+    pub signer: String,
+    pub sweep_to: Option<String>,
+}
+
+/// Asks for what's left of an account's limits on withdrawals and transfers.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AccountLimitsRequest {
+    pub signer: String,
+}
+
+/// Sets the status of an account, such as frozen; an administrative request.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AccountStatusRequest {
+    pub signer: String,
+    pub status: AccountStatus,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct AccountSendRequest {
     pub sender: String,
     pub recipient: String,
-    pub amount: u64,
+    pub amount: Money,
+    /// An optional note for the recipient
+    #[serde(default)]
+    pub memo: Option<String>,
+    /// The currency of the funds; the ledger's currency if it's `None`
+    #[serde(default)]
+    pub currency: Option<String>,
+}
+
+/// Converts the `amount` of the signer's funds in the `from` currency into the `to` currency.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ConvertRequest {
+    pub signer: String,
+    pub from: String,
+    pub to: String,
+    pub amount: Money,
+}
+
+/// Selects the market of a symbol; the default market if the symbol is `None`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct MarketRequest {
+    pub symbol: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct OrderBookRequest {
     pub sort: Option<bool>,
     pub desc: Option<bool>,
+    pub symbol: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct OrderBookByPriceRequest {
     pub desc: Option<bool>,
+    pub symbol: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct OcoRequest {
+    pub take_profit: Order,
+    pub stop_loss: Order,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BracketRequest {
+    pub entry: Order,
+    pub take_profit: Order,
+    pub stop_loss: Order,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CancelAllRequest {
+    pub signer: String,
+    pub side: Option<Side>,
+    /// All markets if `None`
+    pub symbol: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PositionsRequest {
+    /// All signers if `None`
+    pub signer: Option<String>,
+    /// All markets if `None`
+    pub symbol: Option<String>,
+    /// The last trade's price if `None`
+    pub mark: Option<MarkPrice>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SessionRequest {
+    pub signer: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ResetRequest {
+    /// Whether to respond with an archive of everything that gets cleared
+    pub archive: bool,
 }
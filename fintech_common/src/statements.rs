@@ -0,0 +1,463 @@
+//! Account statements
+//!
+//! A statement lists a single account's transactions from a transaction log, oldest first,
+//! optionally narrowed down to some kinds of transactions, and to a range of times and ids.
+//! Long statements come in pages: a page that isn't the last one has a cursor, the id of its
+//! last transaction, and the next page starts right after it.
+//!
+//! Every entry carries the account's balance right after its transaction, and every page
+//! the balance that it opens and closes with. The balances are worked out backwards from
+//! the live balance, so a log that has dropped its oldest records still gets them right.
+//! They are in the currency of the balance; transactions in other currencies, and of units,
+//! leave them as they are.
+
+use crate::tx::{Leg, Tx, TxKind};
+use crate::types::Money;
+use serde::de;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+/// The number of entries in a page, unless a filter asks for another one
+pub const DEFAULT_STATEMENT_LIMIT: usize = 100;
+
+/// The largest number of entries in a page
+pub const MAX_STATEMENT_LIMIT: usize = 1000;
+
+/// **The kinds of transactions that a statement is narrowed down to**
+///
+/// Displayed, parsed and serialized as comma-separated kinds, such as `"deposit,withdraw"`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TxKinds(pub BTreeSet<TxKind>);
+
+impl TxKinds {
+    /// Whether the transaction is of one of the kinds
+    pub fn contains(&self, tx: &Tx) -> bool {
+        self.0.contains(&tx.kind())
+    }
+}
+
+impl fmt::Display for TxKinds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, kind) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", kind)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for TxKinds {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .filter(|kind| !kind.trim().is_empty())
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map(TxKinds)
+    }
+}
+
+impl Serialize for TxKinds {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for TxKinds {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// **What a statement covers, and which page of it**
+///
+/// Every part is optional; without any, the page starts with the account's oldest transaction.
+/// Serialized as the query of a statement request.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct StatementFilter {
+    /// Only these kinds of transactions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kinds: Option<TxKinds>,
+    /// The earliest time, inclusive, in milliseconds since the Unix epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub since: Option<u64>,
+    /// The latest time, inclusive, in milliseconds since the Unix epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub until: Option<u64>,
+    /// The lowest transaction id, inclusive
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from_id: Option<u64>,
+    /// The highest transaction id, inclusive
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to_id: Option<u64>,
+    /// The cursor of the previous page; this page starts with the transactions after it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<u64>,
+    /// The most entries in the page; [`DEFAULT_STATEMENT_LIMIT`] if `None`,
+    /// and at least one and at most [`MAX_STATEMENT_LIMIT`] otherwise
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+impl StatementFilter {
+    /// Whether the transaction comes before the range, or before the cursor
+    fn is_before(&self, tx: &Tx) -> bool {
+        self.from_id.is_some_and(|id| tx.id() < id)
+            || self.since.is_some_and(|since| tx.timestamp() < since)
+            || self.after.is_some_and(|after| tx.id() <= after)
+    }
+
+    /// **Whether the range, or the page, starts after the transaction with the `id` and the `timestamp`**
+    ///
+    /// Transactions that come later have higher ids, and timestamps that aren't lower.
+    pub fn starts_after(&self, id: u64, timestamp: u64) -> bool {
+        self.from_id.is_some_and(|from_id| from_id > id)
+            || self.since.is_some_and(|since| since > timestamp)
+            || self.after.is_some_and(|after| after >= id)
+    }
+
+    /// Whether the transaction comes after the range
+    fn is_after(&self, tx: &Tx) -> bool {
+        self.to_id.is_some_and(|id| tx.id() > id)
+            || self.until.is_some_and(|until| tx.timestamp() > until)
+    }
+
+    fn limit(&self) -> usize {
+        self.limit
+            .unwrap_or(DEFAULT_STATEMENT_LIMIT)
+            .clamp(1, MAX_STATEMENT_LIMIT)
+    }
+}
+
+impl FromStr for StatementFilter {
+    type Err = String;
+
+    /// Parses space-separated `<part>=<value>` pairs, named after the fields,
+    /// such as `"kinds=deposit,withdraw after=12 limit=20"`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |part: &str| {
+            format!(
+                "Invalid statement filter: \"{part}\"; expected \"<part>=<value>\", where the part is one of \
+                 kinds, since, until, from_id, to_id, after and limit"
+            )
+        };
+        let number = |part: &str, value: &str| value.parse::<u64>().map_err(|_| invalid(part));
+
+        s.split_whitespace()
+            .try_fold(StatementFilter::default(), |mut filter, part| {
+                let (name, value) = part.split_once('=').ok_or_else(|| invalid(part))?;
+                match name {
+                    "kinds" => filter.kinds = Some(value.parse()?),
+                    "since" => filter.since = Some(number(part, value)?),
+                    "until" => filter.until = Some(number(part, value)?),
+                    "from_id" => filter.from_id = Some(number(part, value)?),
+                    "to_id" => filter.to_id = Some(number(part, value)?),
+                    "after" => filter.after = Some(number(part, value)?),
+                    "limit" => {
+                        filter.limit = Some(value.parse::<usize>().map_err(|_| invalid(part))?)
+                    }
+                    _ => return Err(invalid(part)),
+                }
+                Ok(filter)
+            })
+    }
+}
+
+/// **A transaction in a statement, with the account's balance right after it**
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct StatementEntry {
+    pub tx: Tx,
+    pub balance: Money,
+}
+
+/// **A page of an account's statement**
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Statement {
+    pub account: String,
+    /// The code of the balance's currency
+    pub currency: String,
+    /// The balance before the page; before the range on the first page
+    pub opening_balance: Money,
+    /// The balance after the page; after the range on the last page
+    pub closing_balance: Money,
+    pub entries: Vec<StatementEntry>,
+    /// The cursor of the next page, which goes into its filter's `after`; `None` on the last page
+    pub next_cursor: Option<u64>,
+}
+
+impl Statement {
+    /// **Builds a page of the `account`'s statement from a transaction log, oldest first**
+    ///
+    /// The account's `balance` in the `currency` is the one after the last transaction of the log.
+    pub fn new(
+        txs: &[Tx],
+        account: &str,
+        currency: &str,
+        balance: Money,
+        filter: &StatementFilter,
+    ) -> Self {
+        // The balance before each transaction, and after all of them at the end.
+        let mut balances = vec![balance; txs.len() + 1];
+        for (i, tx) in txs.iter().enumerate().rev() {
+            let (credit, debit) = cash_flow(tx, account, currency);
+            balances[i] = balances[i + 1].saturating_sub(credit).saturating_add(debit);
+        }
+
+        let start = txs.partition_point(|tx| filter.is_before(tx));
+        let end = txs.partition_point(|tx| !filter.is_after(tx)).max(start);
+        let limit = filter.limit();
+        let mut matches = (start..end).filter(|&i| {
+            txs[i].involves(account)
+                && filter
+                    .kinds
+                    .as_ref()
+                    .is_none_or(|kinds| kinds.contains(&txs[i]))
+        });
+
+        let page: Vec<usize> = matches.by_ref().take(limit).collect();
+        let (close, next_cursor) = match (page.last(), matches.next()) {
+            (Some(&last), Some(_)) => (last + 1, Some(txs[last].id())),
+            _ => (end, None),
+        };
+
+        Statement {
+            account: account.to_string(),
+            currency: currency.to_string(),
+            opening_balance: balances[start],
+            closing_balance: balances[close],
+            entries: page
+                .into_iter()
+                .map(|i| StatementEntry {
+                    tx: txs[i].clone(),
+                    balance: balances[i + 1],
+                })
+                .collect(),
+            next_cursor,
+        }
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "The statement of \"{}\", in {}; opening balance {}",
+            self.account, self.currency, self.opening_balance
+        )?;
+        for entry in &self.entries {
+            writeln!(f, "{}; balance {}", entry.tx, entry.balance)?;
+        }
+        write!(f, "Closing balance {}", self.closing_balance)?;
+        match self.next_cursor {
+            Some(cursor) => write!(f, "; more after #{}.", cursor),
+            None => write!(f, "."),
+        }
+    }
+}
+
+/// What the transaction adds to the account's balance in the `currency`, and what it takes out of it
+fn cash_flow(tx: &Tx, account: &str, currency: &str) -> (Money, Money) {
+    let (mut credit, mut debit) = (Money::ZERO, Money::ZERO);
+    let mut add = |to: &str, from: &str, amount: Money| {
+        if to == account {
+            credit = credit.saturating_add(amount);
+        }
+        if from == account {
+            debit = debit.saturating_add(amount);
+        }
+    };
+
+    match tx {
+        Tx::Deposit {
+            account: to,
+            amount,
+            currency: None,
+            ..
+        }
+        | Tx::Reservation {
+            account: to,
+            amount,
+            currency: None,
+            leg: Leg::In,
+            ..
+        }
+        | Tx::Release {
+            account: to,
+            amount,
+            currency: None,
+            leg: Leg::In,
+            ..
+        }
+        | Tx::MarginLoan {
+            account: to,
+            amount,
+            ..
+        } => add(to, "", *amount),
+        Tx::Withdraw {
+            account: from,
+            amount,
+            currency: None,
+            ..
+        }
+        | Tx::Reservation {
+            account: from,
+            amount,
+            currency: None,
+            leg: Leg::Out,
+            ..
+        }
+        | Tx::Release {
+            account: from,
+            amount,
+            currency: None,
+            leg: Leg::Out,
+            ..
+        }
+        | Tx::MarginRepayment {
+            account: from,
+            amount,
+            ..
+        } => add("", from, *amount),
+        Tx::Transfer {
+            from,
+            to,
+            amount,
+            currency: None,
+            ..
+        } => add(to, from, *amount),
+        Tx::TradeSettlement {
+            buyer,
+            seller,
+            value,
+            ..
+        } => add(seller, buyer, *value),
+        Tx::Fee {
+            account: from,
+            fee_account: to,
+            amount,
+            ..
+        }
+        | Tx::Rebate {
+            account: to,
+            fee_account: from,
+            amount,
+            ..
+        } => add(to, from, *amount),
+        Tx::Conversion {
+            account,
+            from_currency,
+            to_currency,
+            amount,
+            converted,
+            ..
+        } => {
+            if from_currency == currency {
+                add("", account, *amount);
+            }
+            if to_currency == currency {
+                add(account, "", *converted);
+            }
+        }
+        _ => {}
+    }
+
+    (credit, debit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accounts::Accounts;
+
+    #[test]
+    fn filter_from_str_and_kinds_round_trip() {
+        let filter: StatementFilter = "kinds=withdraw,deposit after=12 limit=20".parse().unwrap();
+        assert_eq!(
+            StatementFilter {
+                kinds: Some(TxKinds(BTreeSet::from([TxKind::Deposit, TxKind::Withdraw]))),
+                after: Some(12),
+                limit: Some(20),
+                ..Default::default()
+            },
+            filter
+        );
+        assert_eq!("deposit,withdraw", filter.kinds.unwrap().to_string());
+        assert_eq!(Ok(StatementFilter::default()), "".parse());
+        for bad in ["kinds=deposits", "after", "after=-1", "page=2"] {
+            assert!(bad.parse::<StatementFilter>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn statement_pages_with_running_balances() {
+        let mut accounts = Accounts::new();
+        let txs = vec![
+            accounts.deposit("Alice", 100.into()).unwrap(),
+            accounts.deposit("Bob", 50.into()).unwrap(),
+            accounts.send("Alice", "Bob", 30.into(), None).unwrap(),
+            accounts.deposit_units("Alice", "ABC", 5.into()).unwrap(),
+            accounts.withdraw("Alice", 20.into()).unwrap(),
+            accounts.send("Bob", "Alice", 5.into(), None).unwrap(),
+        ];
+        let balance = *accounts.balance_of("Alice").unwrap();
+        let statement =
+            |filter: &str| Statement::new(&txs, "Alice", "USD", balance, &filter.parse().unwrap());
+
+        let first = statement("limit=2");
+        assert_eq!(Money::ZERO, first.opening_balance);
+        assert_eq!(
+            vec![(1, Money::from(100)), (3, Money::from(70))],
+            first
+                .entries
+                .iter()
+                .map(|entry| (entry.tx.id(), entry.balance))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Money::from(70), first.closing_balance);
+        assert_eq!(Some(3), first.next_cursor);
+
+        let second = statement("limit=2 after=3");
+        assert_eq!(Money::from(70), second.opening_balance);
+        assert_eq!(
+            vec![4, 5],
+            second
+                .entries
+                .iter()
+                .map(|entry| entry.tx.id())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Some(5), second.next_cursor);
+        let last = statement("limit=2 after=5");
+        assert_eq!(Money::from(50), last.opening_balance);
+        assert_eq!(Money::from(55), last.closing_balance);
+        assert_eq!(None, last.next_cursor);
+
+        // The balances cover the whole range, even with the other kinds filtered out.
+        let transfers = statement("kinds=transfer from_id=2 to_id=5");
+        assert_eq!(Money::from(100), transfers.opening_balance);
+        assert_eq!(1, transfers.entries.len());
+        assert_eq!(Money::from(50), transfers.closing_balance);
+
+        // A log without its oldest records still has the balances right.
+        let truncated = Statement::new(
+            &txs[2..],
+            "Alice",
+            "USD",
+            balance,
+            &StatementFilter::default(),
+        );
+        assert_eq!(Money::from(100), truncated.opening_balance);
+        assert_eq!(Money::from(55), truncated.closing_balance);
+
+        let nothing = statement("since=0 until=0 from_id=7");
+        assert!(nothing.entries.is_empty());
+        assert_eq!(Money::from(55), nothing.opening_balance);
+    }
+}
@@ -1,24 +1,421 @@
-use crate::accounts::Accounts;
-use crate::core::types::{Order, PartialOrder, Receipt, Side};
-use crate::core::MatchingEngine;
-use crate::errors::AccountingError;
-use crate::tx::Tx;
+use crate::accounts::{AccountStatus, AccountSummary, Accounts};
+use crate::core::types::{
+    InstrumentSpec, Money, Order, PartialOrder, Price, Quantity, Receipt, Rounding, Side,
+};
+use crate::core::{ExecutionPriceRule, MatchingEngine};
+use crate::errors::{AccountError, AccountingError, MarginError, OrderError};
+use crate::fees::{Fee, FeeSchedule};
+use crate::fx::{CurrencyScales, FxRates};
+use crate::history::{HistoryConfig, HistorySink, RingBufferSink, DEFAULT_HISTORY_CAPACITY};
+use crate::journal::EntryKind;
+use crate::limits::{Allowance, Clock, LimitSchedule};
+use crate::margin::{MarginConfig, MarginSummary};
+use crate::order_groups::{
+    validate_bracket, validate_exits, LegRole, LegStatus, OrderGroup, OrderGroupStatus,
+};
+use crate::positions::{MarkPrice, Position, PositionSummary};
+use crate::reconciliation::{divergences, Reconciliation, TxLog};
+use crate::statements::{Statement, StatementFilter};
+use crate::tx::{Leg, Tx};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io;
+use std::sync::Arc;
+
+/// The symbol of the traded instrument, unless configured otherwise
+pub const DEFAULT_SYMBOL: &str = "FINTECH";
+
+/// **Everything that a platform reset discards, kept for the record**
+///
+/// See [`TradingPlatform::archive`].
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PlatformArchive {
+    /// The symbol of the traded instrument
+    pub symbol: String,
+    /// All accounts, by their signer
+    pub accounts: BTreeMap<String, AccountSummary>,
+    /// The retained transaction log
+    pub tx_log: Vec<Tx>,
+    /// The resting orders, ordered by their ordinal sequence number
+    pub order_book: Vec<PartialOrder>,
+    /// All order groups ever placed, ordered by their id
+    pub order_groups: Vec<OrderGroup>,
+    /// The retained receipts
+    pub receipts: Vec<Receipt>,
+    /// All positions, valued at the last trade price
+    #[serde(default)]
+    pub positions: Vec<PositionSummary>,
+}
+
+/// **What may have changed on the platform since the changes were last taken**
+///
+/// See [`TradingPlatform::take_changes`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Changes {
+    /// Everything may have changed, such as after a reset.
+    pub all: bool,
+    /// The accounts whose balances, holdings, holds or status may have changed
+    pub accounts: BTreeSet<String>,
+    /// Whether the order book may have changed
+    pub order_book: bool,
+}
+
+impl Changes {
+    /// Whether nothing has changed
+    pub fn is_empty(&self) -> bool {
+        !self.all && self.accounts.is_empty() && !self.order_book
+    }
+}
 
 /// Manages accounts, validates, and orchestrates the processing of each order.
 pub struct TradingPlatform {
     matching_engine: MatchingEngine,
     pub accounts: Accounts,
-    pub tx_log: Vec<Tx>,
+    /// The log of all transactions; where they go depends on its sink.
+    pub tx_log: TxLog,
+    /// All order groups ever placed, by their id
+    order_groups: BTreeMap<u64, OrderGroup>,
+    /// Maps the ordinal of a submitted leg to its group id and its index inside of the group
+    order_group_legs: BTreeMap<u64, (u64, usize)>,
+    /// The id of the most recently placed order group
+    order_group_id: u64,
+    /// The price of the most recent trade; stop-losses are triggered by it
+    last_trade_price: Option<Price>,
+    /// The symbol of the traded instrument; sellers need to hold its units.
+    symbol: String,
+    /// The decimal scales of the traded instrument
+    instrument: InstrumentSpec,
+    /// The maker and taker fees that are charged on every match
+    fees: FeeSchedule,
+    /// The initial and the maintenance margin; margin accounts can only be opened with it.
+    margin: Option<MarginConfig>,
+    /// The margin accounts that have been called, and haven't recovered yet
+    margin_called: BTreeSet<String>,
+    /// Every signer's position in the traded instrument, built from their fills
+    positions: BTreeMap<String, Position>,
+    /// What has changed apart from the accounts that the transaction log records
+    changes: Changes,
+}
+
+impl Default for TradingPlatform {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TradingPlatform {
     /// **Creates a new instance without any data.**
+    ///
+    /// Both the transaction log and the receipts are kept in memory,
+    /// each in a ring buffer of [`DEFAULT_HISTORY_CAPACITY`] records.
     pub fn new() -> Self {
+        Self::with_history(
+            Box::new(RingBufferSink::new(DEFAULT_HISTORY_CAPACITY)),
+            Box::new(RingBufferSink::new(DEFAULT_HISTORY_CAPACITY)),
+        )
+    }
+
+    /// **Creates a new instance whose transaction log and receipts are built from `config`**
+    ///
+    /// The transaction log is called `tx_log`, and the receipts are called `receipts`;
+    /// see [`HistoryConfig::build`].
+    ///
+    /// # Errors
+    /// - A sink can't be created, for example, because its file can't be opened.
+    pub fn with_history_config(config: &HistoryConfig) -> std::io::Result<Self> {
+        Ok(Self::with_history(
+            config.build("tx_log")?,
+            config.build("receipts")?,
+        ))
+    }
+
+    /// **Creates a new instance without any data, recording into the provided sinks**
+    pub fn with_history(
+        tx_log: Box<dyn HistorySink<Tx>>,
+        receipts: Box<dyn HistorySink<Receipt>>,
+    ) -> Self {
         TradingPlatform {
-            matching_engine: MatchingEngine::new(),
+            matching_engine: MatchingEngine::with_history(receipts),
             accounts: Accounts::new(),
-            tx_log: vec![],
+            tx_log: TxLog::new(tx_log),
+            order_groups: BTreeMap::new(),
+            order_group_legs: BTreeMap::new(),
+            order_group_id: 0,
+            last_trade_price: None,
+            symbol: DEFAULT_SYMBOL.to_string(),
+            instrument: InstrumentSpec::default(),
+            fees: FeeSchedule::default(),
+            margin: None,
+            margin_called: BTreeSet::new(),
+            positions: BTreeMap::new(),
+            changes: Changes::default(),
+        }
+    }
+
+    /// **Sets the symbol of the traded instrument**
+    ///
+    /// Sellers need to hold units of the instrument with this symbol,
+    /// and fills move them from the seller to the buyer.
+    /// By default, it's [`DEFAULT_SYMBOL`].
+    pub fn with_symbol(mut self, symbol: &str) -> Self {
+        self.symbol = symbol.to_string();
+        self
+    }
+
+    /// The symbol of the traded instrument
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    /// **Sets the code of the currency that the instrument is quoted in**
+    ///
+    /// Orders are priced in it, trades settle in it, and it's the currency of the balances.
+    /// Funds in other currencies are kept apart, see [`deposit_in`](Self::deposit_in).
+    /// By default, it's [`DEFAULT_CURRENCY`](crate::fx::DEFAULT_CURRENCY).
+    pub fn with_currency(mut self, currency: &str) -> Self {
+        self.accounts.set_currency(currency);
+        self.tx_log.set_currency(currency);
+        self
+    }
+
+    /// The code of the currency that the instrument is quoted in
+    pub fn currency(&self) -> &str {
+        self.accounts.currency()
+    }
+
+    /// **Sets the rates that funds are converted at**
+    ///
+    /// By default, there are none, so nothing can be converted. See [`FxRates`].
+    pub fn with_fx_rates(mut self, fx_rates: FxRates) -> Self {
+        self.accounts.set_fx_rates(fx_rates);
+        self
+    }
+
+    /// The rates that funds are converted at
+    pub fn fx_rates(&self) -> &FxRates {
+        self.accounts.fx_rates()
+    }
+
+    /// **Sets the number of decimals of each currency's amounts**
+    ///
+    /// The values of trades are rounded down to the decimals of the instrument's currency,
+    /// and amounts with more decimals than their currency are refused.
+    /// By default, every currency has [`DEFAULT_CURRENCY_SCALE`](crate::fx::DEFAULT_CURRENCY_SCALE).
+    pub fn with_currency_scales(mut self, scales: CurrencyScales) -> Self {
+        self.tx_log.set_currency_scales(scales.clone());
+        self.accounts.set_currency_scales(scales);
+        self
+    }
+
+    /// The number of decimals of each currency's amounts
+    pub fn currency_scales(&self) -> &CurrencyScales {
+        self.accounts.currency_scales()
+    }
+
+    /// **Sets the decimal scales of the traded instrument**
+    ///
+    /// Orders whose price or amount have more decimals than the instrument allows are rejected.
+    /// By default, both prices and amounts are whole numbers.
+    pub fn with_instrument(mut self, instrument: InstrumentSpec) -> Self {
+        self.instrument = instrument;
+        self
+    }
+
+    /// The decimal scales of the traded instrument
+    pub fn instrument(&self) -> &InstrumentSpec {
+        &self.instrument
+    }
+
+    /// **Sets the rule that sets the price of every match**
+    ///
+    /// Funds move at the price of the match, so the rule decides how much the buyer pays.
+    /// By default, matches are executed at the maker's price.
+    pub fn with_price_rule(mut self, price_rule: ExecutionPriceRule) -> Self {
+        self.matching_engine =
+            std::mem::take(&mut self.matching_engine).with_price_rule(price_rule);
+        self
+    }
+
+    /// **Sets the fees that are charged on every match**
+    ///
+    /// The schedule's fee account is opened, with a zero balance, if it doesn't exist yet.
+    /// By default, trading is free.
+    pub fn with_fees(mut self, fees: FeeSchedule) -> Self {
+        self.accounts.open_fee_account(fees.fee_account());
+        self.fees = fees;
+        self
+    }
+
+    /// The fees that are charged on every match
+    pub fn fees(&self) -> &FeeSchedule {
+        &self.fees
+    }
+
+    /// **Sets the limits on withdrawals and transfers**
+    ///
+    /// By default, nothing is limited. See [`LimitSchedule`].
+    pub fn with_limits(mut self, limits: LimitSchedule) -> Self {
+        self.accounts.set_limits(limits);
+        self
+    }
+
+    /// The limits on withdrawals and transfers
+    pub fn limits(&self) -> &LimitSchedule {
+        self.accounts.limits()
+    }
+
+    /// **Sets the clock that timestamps the transactions and that limits are evaluated at**
+    ///
+    /// By default, it's the [`SystemClock`](crate::limits::SystemClock).
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.accounts.set_clock(clock);
+        self
+    }
+
+    /// **Retrieves what's left of the signer's limits on withdrawals and transfers**
+    pub fn allowance_of(&self, signer: &str) -> Allowance {
+        self.accounts.allowance_of(signer)
+    }
+
+    /// **Enables margin trading, with the initial and the maintenance margin**
+    ///
+    /// By default, margin accounts can't be opened. See [`crate::margin`].
+    pub fn with_margin(mut self, margin: MarginConfig) -> Self {
+        self.margin = Some(margin);
+        self
+    }
+
+    /// The initial and the maintenance margin, if margin trading is enabled
+    pub fn margin(&self) -> Option<&MarginConfig> {
+        self.margin.as_ref()
+    }
+
+    /// The price of the most recent trade, which margin accounts are valued at
+    pub fn last_trade_price(&self) -> Option<Price> {
+        self.last_trade_price
+    }
+
+    /// **The price that open positions are valued at, if there is one**
+    ///
+    /// There's no last trade price before the first trade, and no mid price
+    /// unless both sides of the order book have orders.
+    pub fn mark_price(&self, mark: MarkPrice) -> Option<Price> {
+        match mark {
+            MarkPrice::LastTrade => self.last_trade_price,
+            MarkPrice::Mid => {
+                let best_bid = self.matching_engine.bids.keys().next_back()?;
+                let best_ask = self.matching_engine.asks.keys().next()?;
+                Some(best_bid.midpoint(*best_ask))
+            }
+        }
+    }
+
+    /// **Retrieves a signer's position in the traded instrument, built from their fills**
+    pub fn position_of(&self, signer: &str) -> Option<&Position> {
+        self.positions.get(signer)
+    }
+
+    /// **Values the positions of all signers, or of the one `signer`, at the `mark` price**
+    ///
+    /// Signers who haven't traded have no position. Positions are ordered by their signer.
+    pub fn positions(&self, signer: Option<&str>, mark: MarkPrice) -> Vec<PositionSummary> {
+        let mark_price = self.mark_price(mark);
+        self.positions
+            .iter()
+            .filter(|(owner, _)| signer.is_none_or(|signer| signer == owner.as_str()))
+            .map(|(owner, position)| {
+                PositionSummary::new(owner, &self.symbol, position, mark_price)
+            })
+            .collect()
+    }
+
+    /// **Lets the signer buy with borrowed funds**
+    ///
+    /// A buy order of a margin account that its available funds don't cover borrows the rest,
+    /// as long as the equity covers the initial margin afterwards. See [`Accounts::open_margin`].
+    ///
+    /// # Errors
+    /// - Margin trading isn't enabled, `MarginError::Disabled`;
+    /// - The account's status doesn't let it buy, see [`Accounts::check_order`];
+    /// - Account doesn't exist, `AccountError::NotFound`.
+    pub fn open_margin_account(&mut self, signer: &str) -> Result<Tx, AccountingError> {
+        self.margin.ok_or(MarginError::Disabled)?;
+        self.accounts.check_order(signer, &Side::Buy)?;
+        let result = self.accounts.open_margin(signer)?;
+        self.tx_log.record(result.clone());
+        Ok(result)
+    }
+
+    /// **Values a margin account at the price of the most recent trade**
+    ///
+    /// # Errors
+    /// - Margin trading isn't enabled, `MarginError::Disabled`;
+    /// - Account isn't a margin account, `MarginError::AccountNotFound`.
+    pub fn margin_of(&self, signer: &str) -> Result<MarginSummary, AccountingError> {
+        let margin = self.margin.ok_or(MarginError::Disabled)?;
+        if !self.accounts.is_margin_account(signer) {
+            return Err(MarginError::AccountNotFound(signer.to_string()).into());
+        }
+
+        Ok(MarginSummary::new(
+            &margin,
+            *self.accounts.balance_of(signer)?,
+            self.accounts.loan_of(signer),
+            self.accounts.units_of(signer, &self.symbol)?,
+            self.last_trade_price,
+            self.accounts.cash_scale(),
+        ))
+    }
+
+    /// **Pays back a part of a margin account's loan, from its available funds**
+    ///
+    /// # Errors
+    /// - The account's status doesn't let funds out, see [`Accounts::check_outflow`];
+    /// - Any of the errors from [`Accounts::repay`].
+    pub fn repay_loan(&mut self, signer: &str, amount: Money) -> Result<Tx, AccountingError> {
+        self.accounts.check_outflow(signer)?;
+        let result = self.accounts.repay(signer, amount)?;
+        self.tx_log.record(result.clone());
+        Ok(result)
+    }
+
+    /// Makes sure that a margin account that owes a loan, or is about to take one,
+    /// still covers the initial margin after it takes on the `exposure`, the value
+    /// of the units that it's buying, and spends the `cost`. Other accounts don't need to.
+    ///
+    /// Funds that are held for resting buy orders count as exposure, too.
+    ///
+    /// # Errors
+    /// - The equity wouldn't cover the initial margin, `MarginError::Insufficient`.
+    fn check_initial_margin(
+        &self,
+        signer: &str,
+        borrowing: bool,
+        exposure: Money,
+        cost: Money,
+    ) -> Result<(), AccountingError> {
+        let Some(margin) = self.margin else {
+            return Ok(());
+        };
+        if !self.accounts.is_margin_account(signer)
+            || (!borrowing && self.accounts.loan_of(signer).is_zero())
+        {
+            return Ok(());
         }
+
+        let summary = self.margin_of(signer)?;
+        let requirement = margin.initial_requirement(
+            summary
+                .position_value
+                .saturating_add(self.accounts.held_of(signer))
+                .saturating_add(exposure),
+            self.accounts.cash_scale(),
+        );
+        if summary.equity.saturating_sub(cost) < requirement {
+            return Err(MarginError::Insufficient(signer.to_string(), requirement).into());
+        }
+        Ok(())
     }
 
     /// **Fetches the complete order book**
@@ -68,9 +465,9 @@ impl TradingPlatform {
         let mut book = self.order_book(true, false);
 
         if !desc {
-            book.sort_by(|a, b| (a.price).cmp(&b.price));
+            book.sort_by_key(|po| po.price);
         } else {
-            book.sort_by(|a, b| (b.price).cmp(&a.price));
+            book.sort_by_key(|po| Reverse(po.price));
         }
 
         book
@@ -79,149 +476,1484 @@ impl TradingPlatform {
     /// **Retrieves the balance of an account**
     ///
     /// # Errors
-    /// - Account doesn't exist, `AccountingError::AccountNotFound`
-    pub fn balance_of(&mut self, signer: &str) -> Result<&u64, AccountingError> {
+    /// - Account doesn't exist, `AccountError::NotFound`
+    pub fn balance_of(&mut self, signer: &str) -> Result<&Money, AccountingError> {
         self.accounts.balance_of(signer)
     }
 
     /// **Deposit funds**
     ///
     /// # Errors
-    /// - Attempted overflow (account over-funded), `AccountingError::AccountOverFunded`
-    pub fn deposit(&mut self, signer: &str, amount: u64) -> Result<Tx, AccountingError> {
+    /// - The account's status doesn't let funds in, see [`Accounts::check_inflow`];
+    /// - Attempted overflow (account over-funded), `AccountError::OverFunded`.
+    pub fn deposit(&mut self, signer: &str, amount: Money) -> Result<Tx, AccountingError> {
+        self.accounts.check_inflow(signer)?;
         let result = self.accounts.deposit(signer, amount)?;
-        self.tx_log.push(result.clone());
+        self.tx_log.record(result.clone());
         Ok(result)
     }
 
     /// **Withdraw funds**
     ///
     /// # Errors
-    /// - Account doesn't exist, `AccountingError::AccountNotFound`;
-    /// - Attempted overflow (account under-funded), `AccountingError::AccountUnderFunded`.
-    pub fn withdraw(&mut self, signer: &str, amount: u64) -> Result<Tx, AccountingError> {
+    /// - The account's status doesn't let funds out, see [`Accounts::check_outflow`];
+    /// - Account doesn't exist, `AccountError::NotFound`;
+    /// - A margin account with a loan wouldn't cover the initial margin afterwards,
+    ///   `MarginError::Insufficient`;
+    /// - The withdrawal would exceed a limit, `LimitError::Exceeded`;
+    /// - Attempted overflow (account under-funded), `AccountError::UnderFunded`.
+    pub fn withdraw(&mut self, signer: &str, amount: Money) -> Result<Tx, AccountingError> {
+        self.accounts.check_outflow(signer)?;
+        self.check_initial_margin(signer, false, Money::ZERO, amount)?;
         let result = self.accounts.withdraw(signer, amount)?;
-        self.tx_log.push(result.clone());
+        self.tx_log.record(result.clone());
+        Ok(result)
+    }
+
+    /// **Deposit units of the instrument with the `symbol`**
+    ///
+    /// The account is created, with a zero balance, if it doesn't exist.
+    ///
+    /// # Errors
+    /// - The account's status doesn't let units in, see [`Accounts::check_inflow`];
+    /// - Attempted overflow (too many units), `AccountError::HoldingsOverflow`.
+    pub fn deposit_units(
+        &mut self,
+        signer: &str,
+        symbol: &str,
+        units: Quantity,
+    ) -> Result<Tx, AccountingError> {
+        self.accounts.check_inflow(signer)?;
+        let result = self.accounts.deposit_units(signer, symbol, units)?;
+        self.tx_log.record(result.clone());
+        Ok(result)
+    }
+
+    /// **Withdraw units of the instrument with the `symbol`**
+    ///
+    /// # Errors
+    /// - The account's status doesn't let units out, see [`Accounts::check_outflow`];
+    /// - Account doesn't exist, `AccountError::NotFound`;
+    /// - A margin account with a loan wouldn't cover the initial margin afterwards,
+    ///   `MarginError::Insufficient`;
+    /// - Not enough units held, `AccountError::HoldingsInsufficient`.
+    pub fn withdraw_units(
+        &mut self,
+        signer: &str,
+        symbol: &str,
+        units: Quantity,
+    ) -> Result<Tx, AccountingError> {
+        self.accounts.check_outflow(signer)?;
+        if symbol == self.symbol {
+            let value = self
+                .last_trade_price
+                .map(|price| {
+                    price
+                        .notional(units, self.accounts.cash_scale(), Rounding::Down)
+                        .unwrap_or(Money::MAX)
+                })
+                .unwrap_or_default();
+            self.check_initial_margin(signer, false, Money::ZERO, value)?;
+        }
+        let result = self.accounts.withdraw_units(signer, symbol, units)?;
+        self.tx_log.record(result.clone());
         Ok(result)
     }
 
     /// **Transfer funds between sender and recipient**
     ///
+    /// The sender can add a `memo` for the recipient.
+    ///
     /// # Errors
-    /// - Any of the two accounts doesn't exist, `AccountingError::AccountNotFound`;
-    /// - Attempted overflow (sender's account under-funded), `AccountingError::AccountUnderFunded`;
-    /// - Attempted overflow (recipient's account over-funded), `AccountingError::AccountOverFunded`.
+    /// - The sender's status doesn't let funds out, or the recipient's doesn't let them in,
+    ///   see [`Accounts::check_outflow`] and [`Accounts::check_inflow`];
+    /// - Any of the two accounts doesn't exist, `AccountError::NotFound`;
+    /// - A margin sender with a loan wouldn't cover the initial margin afterwards,
+    ///   `MarginError::Insufficient`;
+    /// - The transfer would exceed a limit of the sender, `LimitError::Exceeded`;
+    /// - Attempted overflow (sender's account under-funded), `AccountError::UnderFunded`;
+    /// - Attempted overflow (recipient's account over-funded), `AccountError::OverFunded`.
     pub fn send(
         &mut self,
         sender: &str,
         recipient: &str,
-        amount: u64,
-    ) -> Result<(Tx, Tx), AccountingError> {
-        let result = self.accounts.send(sender, recipient, amount)?;
-        let result_copy = result.clone();
-        let tx_withdraw = result_copy.0;
-        let tx_deposit = result_copy.1;
-        self.tx_log.push(tx_withdraw);
-        self.tx_log.push(tx_deposit);
+        amount: Money,
+        memo: Option<&str>,
+    ) -> Result<Tx, AccountingError> {
+        self.accounts.check_outflow(sender)?;
+        self.accounts.check_inflow(recipient)?;
+        self.check_initial_margin(sender, false, Money::ZERO, amount)?;
+        let result = self.accounts.send(sender, recipient, amount, memo)?;
+        self.tx_log.record(result.clone());
+        Ok(result)
+    }
+
+    /// **Deposit funds in the `currency`**
+    ///
+    /// Funds in the quote currency are the balance, the same as [`deposit`](Self::deposit).
+    ///
+    /// # Errors
+    /// - The account's status doesn't let funds in, see [`Accounts::check_inflow`];
+    /// - The currency's code is invalid, `FxError::CurrencyInvalid`;
+    /// - Attempted overflow (account over-funded), `AccountError::OverFunded`
+    ///   or `FxError::OverFunded`.
+    pub fn deposit_in(
+        &mut self,
+        signer: &str,
+        currency: &str,
+        amount: Money,
+    ) -> Result<Tx, AccountingError> {
+        if currency == self.currency() {
+            return self.deposit(signer, amount);
+        }
+
+        self.accounts.check_inflow(signer)?;
+        let result = self.accounts.deposit_in(signer, currency, amount)?;
+        self.tx_log.record(result.clone());
+        Ok(result)
+    }
+
+    /// **Withdraw funds in the `currency`**
+    ///
+    /// Funds in the quote currency are the balance, the same as [`withdraw`](Self::withdraw).
+    ///
+    /// # Errors
+    /// - The account's status doesn't let funds out, see [`Accounts::check_outflow`];
+    /// - Any of the errors from [`Accounts::withdraw_in`], or from `withdraw`.
+    pub fn withdraw_in(
+        &mut self,
+        signer: &str,
+        currency: &str,
+        amount: Money,
+    ) -> Result<Tx, AccountingError> {
+        if currency == self.currency() {
+            return self.withdraw(signer, amount);
+        }
+
+        self.accounts.check_outflow(signer)?;
+        let result = self.accounts.withdraw_in(signer, currency, amount)?;
+        self.tx_log.record(result.clone());
+        Ok(result)
+    }
+
+    /// **Transfer funds in the `currency` between sender and recipient**
+    ///
+    /// Funds in the quote currency are the balance, the same as [`send`](Self::send).
+    ///
+    /// # Errors
+    /// - The sender's status doesn't let funds out, or the recipient's doesn't let them in,
+    ///   see [`Accounts::check_outflow`] and [`Accounts::check_inflow`];
+    /// - Any of the errors from [`Accounts::send_in`], or from `send`.
+    pub fn send_in(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        currency: &str,
+        amount: Money,
+        memo: Option<&str>,
+    ) -> Result<Tx, AccountingError> {
+        if currency == self.currency() {
+            return self.send(sender, recipient, amount, memo);
+        }
+
+        self.accounts.check_outflow(sender)?;
+        self.accounts.check_inflow(recipient)?;
+        let result = self
+            .accounts
+            .send_in(sender, recipient, currency, amount, memo)?;
+        self.tx_log.record(result.clone());
+        Ok(result)
+    }
+
+    /// **Convert funds from one currency into another, at the platform's rates**
+    ///
+    /// Only the available funds can be converted. See [`Accounts::convert`].
+    ///
+    /// # Errors
+    /// - The account's status doesn't let funds out, see [`Accounts::check_outflow`];
+    /// - A margin account with a loan wouldn't cover the initial margin after converting
+    ///   funds out of the quote currency, `MarginError::Insufficient`;
+    /// - Any of the errors from [`Accounts::convert`].
+    pub fn convert(
+        &mut self,
+        signer: &str,
+        from: &str,
+        to: &str,
+        amount: Money,
+    ) -> Result<Tx, AccountingError> {
+        self.accounts.check_outflow(signer)?;
+        if from == self.currency() {
+            self.check_initial_margin(signer, false, Money::ZERO, amount)?;
+        }
+        let result = self.accounts.convert(signer, from, to, amount)?;
+        self.tx_log.record(result.clone());
         Ok(result)
     }
 
+    /// **Close an account**
+    ///
+    /// The signer can't have any resting orders, nor order groups that may still submit some.
+    /// A frozen account can't be closed, as nothing can leave it. See [`Accounts::close`].
+    ///
+    /// # Errors
+    /// - The signer's status doesn't let funds out, or the recipient's doesn't let them in,
+    ///   see [`Accounts::check_outflow`] and [`Accounts::check_inflow`];
+    /// - The signer has open orders, `AccountError::HasOpenOrders`;
+    /// - Any of the errors from [`Accounts::close`].
+    pub fn close_account(
+        &mut self,
+        signer: &str,
+        sweep_to: Option<&str>,
+    ) -> Result<Vec<Tx>, AccountingError> {
+        self.accounts.check_outflow(signer)?;
+        if let Some(recipient) = sweep_to {
+            self.accounts.check_inflow(recipient)?;
+        }
+        if self.has_open_orders(signer) {
+            return Err(AccountError::HasOpenOrders(signer.to_string()).into());
+        }
+
+        let txs = self.accounts.close(signer, sweep_to)?;
+        for tx in &txs {
+            self.tx_log.record(tx.clone());
+        }
+        Ok(txs)
+    }
+
+    /// **Set the status of an account**
+    ///
+    /// Resting orders that the account can't place anymore are cancelled, such as all of them
+    /// when it's frozen, and its buy orders when it's withdraw-only.
+    /// Closing the account is the same as [`close_account`](Self::close_account) without a sweep.
+    /// See [`Accounts::set_status`].
+    ///
+    /// # Errors
+    /// - Any of the errors from [`Accounts::set_status`], or from `close_account` when closing it.
+    pub fn set_account_status(
+        &mut self,
+        signer: &str,
+        status: AccountStatus,
+    ) -> Result<Vec<Tx>, AccountingError> {
+        if status == AccountStatus::Closed {
+            return self.close_account(signer, None);
+        }
+
+        let txs = self.accounts.set_status(signer, status)?;
+        for tx in &txs {
+            self.tx_log.record(tx.clone());
+        }
+        for side in [Side::Buy, Side::Sell] {
+            if !status.allows_order(&side) {
+                self.cancel_all(signer, Some(side));
+            }
+        }
+        Ok(txs)
+    }
+
+    /// Whether the signer has resting orders, or order group legs that are waiting or armed
+    pub fn has_open_orders(&self, signer: &str) -> bool {
+        let resting = self
+            .matching_engine
+            .asks
+            .values()
+            .chain(self.matching_engine.bids.values())
+            .flatten()
+            .any(|po| po.signer == signer);
+        let pending = self
+            .order_groups
+            .values()
+            .flat_map(|group| &group.legs)
+            .any(|leg| {
+                leg.order.signer == signer
+                    && matches!(leg.status, LegStatus::Waiting | LegStatus::Armed)
+            });
+
+        resting || pending
+    }
+
+    /// **Builds a page of an account's statement from the transaction log**
+    ///
+    /// The balances are in the platform's currency. An account that was closed still has
+    /// a statement, as long as the log has its transactions. See [`Statement::new`].
+    ///
+    /// # Errors
+    /// - Account doesn't exist, and never did in the log, `AccountError::NotFound`;
+    /// - The range starts before the oldest transaction that the log retains, which means that
+    ///   the statement would miss some, `AccountingError::HistoryUnavailable`.
+    pub fn statement(
+        &self,
+        signer: &str,
+        filter: &StatementFilter,
+    ) -> Result<Statement, AccountingError> {
+        if let Some((id, timestamp)) = self.tx_log.last_evicted() {
+            if !filter.starts_after(id, timestamp) {
+                return Err(AccountingError::HistoryUnavailable(id));
+            }
+        }
+
+        let tx_log = self.tx_log.records();
+        let balance = match self.accounts.balance_of(signer) {
+            Ok(balance) => *balance,
+            Err(_) if tx_log.iter().any(|tx| tx.involves(signer)) => Money::ZERO,
+            Err(err) => return Err(err),
+        };
+        Ok(Statement::new(
+            &tx_log,
+            signer,
+            self.currency(),
+            balance,
+            filter,
+        ))
+    }
+
+    /// **Takes a copy of all accounts, transactions, orders and receipts**
+    ///
+    /// Meant to be taken right before a [`reset`](Self::reset), so that nothing is lost for good.
+    pub fn archive(&self) -> PlatformArchive {
+        PlatformArchive {
+            symbol: self.symbol.clone(),
+            accounts: self.accounts.summaries(),
+            tx_log: self.tx_log.records(),
+            order_book: self.order_book(true, false),
+            order_groups: self.order_groups(),
+            receipts: self.matching_engine.history.records(),
+            positions: self.positions(None, MarkPrice::LastTrade),
+        }
+    }
+
+    /// **Rebuilds the accounts from the transaction log, and compares them with the live ones**
+    ///
+    /// The accounts are rebuilt from the log's checkpoint, so a sink that doesn't retain
+    /// every transaction, such as a full ring buffer, can still be replayed; see [`TxLog`].
+    /// The fee account is opened without a transaction, so the rebuilt accounts get it
+    /// if the live ones have it.
+    pub fn reconcile(&self) -> Reconciliation {
+        let (replay_error, divergences) = match self.tx_log.replay() {
+            Ok(mut rebuilt) => {
+                if self.accounts.balance_of(self.fees.fee_account()).is_ok() {
+                    rebuilt.open_fee_account(self.fees.fee_account());
+                }
+                (None, divergences(&rebuilt, &self.accounts))
+            }
+            Err(err) => (Some(err.to_string()), vec![]),
+        };
+        Reconciliation {
+            symbol: self.symbol.clone(),
+            transactions: self.tx_log.len(),
+            replay_error,
+            divergences,
+        }
+    }
+
+    /// **Clears everything: all accounts, the entire transaction log, the order book and its history**
+    ///
+    /// Order groups, positions, margin calls and the price of the most recent trade go as well.
+    /// The fee account is opened again, with a zero balance. The configuration, meaning the symbol, the instrument, the currency, the exchange rates, the currency scales, the fees, the price rule, the limits, the clock and the margin, stays,
+    /// and so do the ordinal and order group id sequences, so that they are never reused.
+    ///
+    /// Nothing is recorded for this; take an [`archive`](Self::archive) first to keep a copy.
+    ///
+    /// # Errors
+    /// - The transaction log or the receipts can't be cleared, see [`HistorySink::clear`].
+    ///   Nothing in memory is cleared in that case.
+    pub fn reset(&mut self) -> io::Result<()> {
+        self.tx_log.clear()?;
+        self.matching_engine.history.clear()?;
+
+        let mut accounts = Accounts::new();
+        accounts.open_fee_account(self.fees.fee_account());
+        accounts.set_limits(self.accounts.limits().clone());
+        accounts.set_clock(self.accounts.clock());
+        accounts.set_currency(self.accounts.currency());
+        accounts.set_fx_rates(self.accounts.fx_rates().clone());
+        accounts.set_currency_scales(self.accounts.currency_scales().clone());
+        self.accounts = accounts;
+        self.matching_engine.asks.clear();
+        self.matching_engine.bids.clear();
+        self.order_groups.clear();
+        self.order_group_legs.clear();
+        self.last_trade_price = None;
+        self.margin_called.clear();
+        self.positions.clear();
+        self.changes.all = true;
+
+        Ok(())
+    }
+
+    /// **Takes what may have changed since the last call**
+    ///
+    /// The accounts that the recorded transactions involve, or whose holds moved, and whether
+    /// the order book moved, so that a copy of them only needs to be updated where they did.
+    pub fn take_changes(&mut self) -> Changes {
+        let mut changes = std::mem::take(&mut self.changes);
+        changes.accounts.append(&mut self.tx_log.take_touched());
+        changes
+    }
+
     /// **Process a given order and apply the outcome to the accounts involved.**
     ///
+    /// Fills that the order produces are propagated to the order groups
+    /// whose legs took part in them, which may cancel or activate linked legs.
+    ///
+    /// Only the available funds and units can back a new order. Whatever its resting part
+    /// may still need is held for it, and released as it gets filled or cancelled.
+    /// A margin account borrows what its available funds don't cover of a buy order.
+    ///
+    /// Once the order has been matched, margin accounts that have fallen below the maintenance
+    /// margin are called, and their units are sold into the bids to repay their loans.
+    ///
     /// **Note** that there are very few safeguards in place.
     ///
     /// The account from the order is expected to exist, regardless of its side.
-    /// If it doesn't exist, the [`AccountingError::AccountNotFound`] error is returned,
+    /// If it doesn't exist, the [`AccountError::NotFound`] error is returned,
     /// containing the order signer's account (name).
     ///
     /// # Errors
-    /// - Account not found, `AccountingError::AccountNotFound`;
-    /// - Account has insufficient funds, `AccountingError::AccountUnderFunded`;
-    /// - Account would be over-funded, `AccountingError::AccountOverFunded`.
+    /// - Price or amount don't fit the instrument's scales, `OrderError::Invalid`;
+    /// - The account's status doesn't let it place the order, see [`Accounts::check_order`];
+    /// - Account not found, `AccountError::NotFound`;
+    /// - Buyer can't afford the order, `OrderError::UnderFunded`;
+    /// - Margin buyer's equity wouldn't cover the initial margin, `MarginError::Insufficient`;
+    /// - Seller doesn't hold enough units of the instrument, `OrderError::HoldingsInsufficient`;
+    /// - Settling a match would under- or over-fund an account, `AccountError::UnderFunded`
+    ///   or `AccountError::OverFunded`;
+    /// - The value of the order is too large, `AccountingError::ArithmeticOverflow`.
     pub fn process_order(&mut self, order: Order) -> Result<Receipt, AccountingError> {
+        let receipt = self.execute_order(order)?;
+        self.update_order_groups(receipt.clone());
+        self.update_holds(None);
+        Ok(receipt)
+    }
+
+    /// Validates an order, runs the matching and settles the matches,
+    /// without looking at order groups.
+    fn execute_order(&mut self, order: Order) -> Result<Receipt, AccountingError> {
+        self.execute_order_as(order, true)
+    }
+
+    /// Same as [`execute_order`](Self::execute_order), but only checks whether the signer's account
+    /// allows the order if `check_status` is `true`; a liquidation sells regardless of the status.
+    fn execute_order_as(
+        &mut self,
+        order: Order,
+        check_status: bool,
+    ) -> Result<Receipt, AccountingError> {
+        // Bring the price and the amount to the instrument's scales.
+        let order = order.normalize(&self.instrument)?;
+
         let order_signer = &order.signer.clone();
+        if check_status {
+            self.accounts.check_order(order_signer, &order.side)?;
+        }
 
-        // Make sure that the Order struct’s signer has an account
-        let account_balance = *self.balance_of(order_signer)?;
+        // Make sure that the Order struct’s signer has an account,
+        // and find the part of its balance that isn't held for other resting orders
+        let available = self.accounts.available_of(order_signer)?;
 
         let order_side = order.side.clone();
 
         // For Buy orders, guard for solvency, i.e., make sure the account has
-        // a sufficiently high balance to buy amount * price.
+        // a sufficiently high available balance to buy amount * price, and to pay the taker fee on it.
         // A buyer puts the highest price that they are willing to pay,
         // and if they find a cheaper deal, good for them.
         // What matters is that they have enough funds in the worst case,
         // and that's what we're checking here.
+        // A margin account may borrow the rest, if its equity covers the initial margin.
+        let mut loan = Money::ZERO;
         if order_side == Side::Buy {
-            let required_amount = order.get_initial_amount() * order.price;
-            if account_balance < required_amount {
-                return Err(AccountingError::AccountUnderFunded(
-                    order_signer.to_string(),
-                    required_amount,
-                ));
+            let value = order.price.notional(
+                order.get_initial_amount(),
+                self.accounts.cash_scale(),
+                Rounding::Down,
+            )?;
+            let taker_fee = self
+                .fees
+                .tier_of(order_signer)
+                .taker_fee(value, self.accounts.cash_scale());
+            let required_amount = value.checked_add(taker_fee).ok_or_else(|| {
+                AccountingError::ArithmeticOverflow(format!(
+                    "The value of {} at {}, with a fee of {}, is too large.",
+                    order.get_initial_amount(),
+                    order.price,
+                    taker_fee
+                ))
+            })?;
+            if available < required_amount {
+                if self.margin.is_none() || !self.accounts.is_margin_account(order_signer) {
+                    return Err(
+                        OrderError::UnderFunded(order_signer.to_string(), required_amount).into(),
+                    );
+                }
+                loan = required_amount.saturating_sub(available);
+            }
+            self.check_initial_margin(order_signer, !loan.is_zero(), value, taker_fee)?;
+        }
+
+        // For Sell orders, make sure the seller holds the units they wish to sell,
+        // and that they aren't held for other resting orders.
+        if order_side == Side::Sell
+            && self
+                .accounts
+                .units_available_of(order_signer, &self.symbol)?
+                < order.get_initial_amount()
+        {
+            return Err(OrderError::HoldingsInsufficient(
+                order_signer.to_string(),
+                self.symbol.clone(),
+                order.get_initial_amount(),
+            )
+            .into());
+        }
+
+        // Run the matching, without recording it yet, so that it can be rolled back.
+        let (receipt, rollback) = self.matching_engine.process_unrecorded(order)?;
+
+        // Work out the settlement and the fees of every match, and make sure that all of them
+        // can be applied before applying any. Otherwise, the order book is rolled back,
+        // and the order is rejected as if it had never been matched.
+        let fills = self
+            .fills(order_signer, &order_side, &receipt)
+            .and_then(|fills| self.check_fills(order_signer, loan, &fills).map(|_| fills));
+        let fills = match fills {
+            Ok(fills) => fills,
+            Err(err) => {
+                self.matching_engine.rollback(rollback);
+                return Err(err);
             }
+        };
+        self.matching_engine.history.record(receipt.clone());
+
+        // The loan has been checked along with the fills, so it doesn't fail either.
+        if !loan.is_zero() {
+            let tx = self.accounts.borrow(order_signer, loan)?;
+            self.tx_log.record(tx);
+        }
+
+        // Move funds and units in accordance with the trade requirements, and charge the fees.
+        // The resting orders' part is held for them, so it's spent, too.
+        // All of it has been checked, so none of it fails.
+        for fill in &fills {
+            let trade_id = self.settle(receipt.ordinal, fill)?;
+            self.charge_fees(trade_id, order_signer, fill)?;
         }
 
-        // Run the matching
-        let receipt = self.matching_engine.process(order)?;
+        Ok(receipt)
+    }
 
-        // This is the total value of the order that was realized.
-        // Namely, in the Buy case, it can be lower than the worst case, which is good for the buyer.
-        // Conversely, in the Sell case, it can be higher than the worst case, which is good for the seller.
-        // It is not used anywhere, though.
-        let _total_realized: u64 = receipt
+    /// Works out the settlement and the fees of each of the receipt's matches.
+    ///
+    /// # Errors
+    /// - The value of a match is too large, `AccountingError::ArithmeticOverflow`.
+    fn fills<'a>(
+        &self,
+        taker: &'a str,
+        taker_side: &Side,
+        receipt: &'a Receipt,
+    ) -> Result<Vec<Fill<'a>>, AccountingError> {
+        receipt
             .matches
             .iter()
             .map(|po| {
-                po.current_amount
+                let units = po
+                    .current_amount
                     .checked_sub(po.remaining_amount)
-                    .expect("Current amount of a partial order is less than its remaining amount!")
-                    .checked_mul(po.price)
-                    .expect("Product overflowed!")
+                    .ok_or_else(|| {
+                        AccountingError::ArithmeticOverflow(format!(
+                            "The matched order #{} has {} remaining of {}.",
+                            po.ordinal, po.remaining_amount, po.current_amount
+                        ))
+                    })?;
+                // Rounded down, the fills of an order never cost more than its worst case.
+                let value = po
+                    .price
+                    .notional(units, self.accounts.cash_scale(), Rounding::Down)?;
+
+                let (buyer, seller) = match taker_side {
+                    Side::Buy => (taker, po.signer.as_str()),
+                    Side::Sell => (po.signer.as_str(), taker),
+                };
+
+                // The rebate is capped at the taker's fee, so that the fee account can always pay it.
+                let scale = self.accounts.cash_scale();
+                let taker_fee = self.fees.tier_of(taker).taker_fee(value, scale);
+                let maker_fee = match self.fees.tier_of(&po.signer).maker_fee(value, scale) {
+                    Fee::Rebate(amount) => Fee::Rebate(amount.min(taker_fee)),
+                    charge => charge,
+                };
+
+                Ok(Fill {
+                    maker: po,
+                    buyer,
+                    seller,
+                    units,
+                    value,
+                    taker_fee,
+                    maker_fee,
+                })
             })
-            .sum();
-
-        // Move funds in accordance with the trade requirements
-        match order_side {
-            Side::Buy => {
-                for po in &receipt.matches {
-                    self.send(
-                        order_signer,
-                        po.signer.as_str(),
-                        po.current_amount
-                            .checked_sub(po.remaining_amount)
-                            .expect(
-                                "Current amount of a partial order is less than its remaining amount!",
-                            )
-                            .checked_mul(po.price)
-                            .expect("Product overflowed!"),
-                    )?;
-                }
+            .collect()
+    }
+
+    /// Makes sure that the fills can be settled, and their fees charged, one after another,
+    /// after the taker has borrowed the `loan`.
+    ///
+    /// # Errors
+    /// - Any of the errors that borrowing, settling or charging a fee would fail with, see [`DryRun`].
+    fn check_fills(&self, taker: &str, loan: Money, fills: &[Fill]) -> Result<(), AccountingError> {
+        let fee_account = self.fees.fee_account();
+        let mut dry_run = DryRun::new(&self.accounts, &self.symbol);
+
+        if !loan.is_zero() {
+            dry_run.borrow(taker, loan)?;
+        }
+
+        for fill in fills {
+            dry_run.transfer(fill.buyer, fill.seller, fill.value)?;
+            dry_run.transfer_units(fill.seller, fill.buyer, fill.units)?;
+
+            if !fill.taker_fee.is_zero() {
+                dry_run.transfer(taker, fee_account, fill.taker_fee)?;
             }
-            Side::Sell => {
-                for po in &receipt.matches {
-                    self.send(
-                        po.signer.as_str(),
-                        order_signer,
-                        po.current_amount
-                            .checked_sub(po.remaining_amount)
-                            .expect(
-                                "Current amount of a partial order is less than its remaining amount!",
-                            )
-                            .checked_mul(po.price)
-                            .expect("Product overflowed!"),
-                    )?;
+            match &fill.maker_fee {
+                Fee::Charge(amount) if !amount.is_zero() => {
+                    dry_run.transfer(&fill.maker.signer, fee_account, *amount)?
                 }
+                Fee::Rebate(amount) if !amount.is_zero() => {
+                    dry_run.transfer(fee_account, &fill.maker.signer, *amount)?
+                }
+                _ => {}
             }
         }
 
-        Ok(receipt)
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Moves the value of a match from the buyer to the seller, and the units the other way,
+    /// including the held funds and units, records the trade in the transaction log,
+    /// and adds it to both positions.
+    ///
+    /// Returns the id of the trade's transaction.
+    fn settle(&mut self, taker_ordinal: u64, fill: &Fill) -> Result<u64, AccountingError> {
+        self.accounts
+            .transfer(fill.buyer, fill.seller, fill.value, EntryKind::Settlement)?;
+        self.accounts
+            .transfer_units(fill.seller, fill.buyer, &self.symbol, fill.units)?;
+        for (signer, side) in [(fill.buyer, Side::Buy), (fill.seller, Side::Sell)] {
+            self.positions
+                .entry(signer.to_string())
+                .or_insert_with(|| Position::new(side.clone()))
+                .apply_fill(&side, fill.units, fill.value);
+        }
+
+        let (id, timestamp) = self.accounts.next_tx();
+        self.tx_log.record(Tx::TradeSettlement {
+            id,
+            timestamp,
+            taker_ordinal,
+            maker_ordinal: fill.maker.ordinal,
+            buyer: fill.buyer.to_string(),
+            seller: fill.seller.to_string(),
+            symbol: self.symbol.clone(),
+            price: fill.maker.price,
+            units: fill.units,
+            value: fill.value,
+        });
+        Ok(id)
+    }
+
+    /// Charges the taker's and the maker's fee of a fill, which is the trade with the `trade_id`,
+    /// or pays the maker's rebate, and records them in the transaction log.
+    fn charge_fees(
+        &mut self,
+        trade_id: u64,
+        taker: &str,
+        fill: &Fill,
+    ) -> Result<(), AccountingError> {
+        let fee_account = self.fees.fee_account().to_string();
+        let maker = fill.maker.signer.as_str();
+
+        if !fill.taker_fee.is_zero() {
+            self.accounts
+                .transfer(taker, &fee_account, fill.taker_fee, EntryKind::Fee)?;
+            let (id, timestamp) = self.accounts.next_tx();
+            self.tx_log.record(Tx::Fee {
+                id,
+                timestamp,
+                trade_id,
+                account: taker.to_string(),
+                fee_account: fee_account.clone(),
+                amount: fill.taker_fee,
+            });
+        }
+
+        match fill.maker_fee {
+            Fee::Charge(amount) if !amount.is_zero() => {
+                self.accounts
+                    .transfer(maker, &fee_account, amount, EntryKind::Fee)?;
+                let (id, timestamp) = self.accounts.next_tx();
+                self.tx_log.record(Tx::Fee {
+                    id,
+                    timestamp,
+                    trade_id,
+                    account: maker.to_string(),
+                    fee_account,
+                    amount,
+                });
+            }
+            Fee::Rebate(amount) if !amount.is_zero() => {
+                self.accounts
+                    .transfer(&fee_account, maker, amount, EntryKind::Rebate)?;
+                let (id, timestamp) = self.accounts.next_tx();
+                self.tx_log.record(Tx::Rebate {
+                    id,
+                    timestamp,
+                    trade_id,
+                    account: maker.to_string(),
+                    fee_account,
+                    amount,
+                });
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// **The funds that each signer's open buy orders may still need**
+    ///
+    /// Counts the remaining amounts of resting buy orders, and the buy legs of order groups
+    /// that are still waiting or armed, each at its worst-case cost;
+    /// see [`FeeSchedule::worst_case_cost`]. Only one of the exits of an order group
+    /// can be filled, so only the one that may need the most is counted.
+    /// Signers without open buy orders aren't included.
+    pub fn committed_funds(&self) -> BTreeMap<String, Money> {
+        self.commitments(None).0
+    }
+
+    /// The funds and the units that open orders may still need, by signer,
+    /// leaving out the order group with the `excluded_group` id.
+    ///
+    /// See [`committed_funds`](Self::committed_funds) and [`committed_units`](Self::committed_units).
+    fn commitments(
+        &self,
+        excluded_group: Option<u64>,
+    ) -> (BTreeMap<String, Money>, BTreeMap<String, Quantity>) {
+        // Orders as (signer, side, price, amount); exits are collected by their group.
+        let mut open = vec![];
+        let mut exits: BTreeMap<u64, Vec<(&String, &Side, Price, Quantity)>> = BTreeMap::new();
+
+        let resting = self
+            .matching_engine
+            .bids
+            .values()
+            .chain(self.matching_engine.asks.values())
+            .flatten();
+        for po in resting {
+            let order = (&po.signer, &po.side, po.price, po.remaining_amount);
+            match self.order_group_legs.get(&po.ordinal) {
+                Some(&(id, _)) if Some(id) == excluded_group => {}
+                Some(&(id, leg_idx))
+                    if self.order_groups[&id].legs[leg_idx].role != LegRole::Entry =>
+                {
+                    exits.entry(id).or_default().push(order)
+                }
+                _ => open.push(order),
+            }
+        }
+
+        let groups = self
+            .order_groups
+            .values()
+            .filter(|group| Some(group.id) != excluded_group);
+        for group in groups {
+            let pending = group
+                .legs
+                .iter()
+                .filter(|leg| matches!(leg.status, LegStatus::Waiting | LegStatus::Armed));
+            for leg in pending {
+                let order = (
+                    &leg.order.signer,
+                    &leg.order.side,
+                    leg.order.price,
+                    leg.order.get_initial_amount(),
+                );
+                match leg.role {
+                    LegRole::Entry => open.push(order),
+                    _ => exits.entry(group.id).or_default().push(order),
+                }
+            }
+        }
+
+        // The exits share their side and amount, so the one with the highest price may need the most.
+        open.extend(
+            exits
+                .into_values()
+                .filter_map(|exits| exits.into_iter().max_by_key(|exit| exit.2)),
+        );
+
+        let mut funds: BTreeMap<String, Money> = BTreeMap::new();
+        let mut units: BTreeMap<String, Quantity> = BTreeMap::new();
+        for (signer, side, price, amount) in open {
+            match side {
+                // Open orders have been accepted, so they fit into the signer's account;
+                // a commitment that doesn't fit is as good as committing everything.
+                Side::Buy => {
+                    let cost = self
+                        .fees
+                        .worst_case_cost(signer, price, amount, self.accounts.cash_scale())
+                        .unwrap_or(Money::MAX);
+                    let sum = funds.entry(signer.clone()).or_default();
+                    *sum = sum.saturating_add(cost);
+                }
+                Side::Sell => {
+                    let sum = units.entry(signer.clone()).or_default();
+                    *sum = sum.saturating_add(amount);
+                }
+            }
+        }
+
+        (funds, units)
+    }
+
+    /// Holds the funds and the units that open orders may still need, and releases the rest;
+    /// see [`commitments`](Self::commitments).
+    ///
+    /// Called after every change to the open orders, so that the holds follow them.
+    /// The accounts whose holds move, and the book, are recorded as changes; see [`take_changes`](Self::take_changes).
+    fn update_holds(&mut self, excluded_group: Option<u64>) {
+        let (funds, units) = self.commitments(excluded_group);
+
+        // The holds of the signers that have gained, lost or changed one are published anew.
+        let changes = &mut self.changes;
+        changes.order_book = true;
+        let held = &self.accounts.held;
+        let held_units = |signer: &String| {
+            self.accounts
+                .held_units
+                .get(signer)
+                .and_then(|held_units| held_units.get(&self.symbol))
+        };
+        for signer in held.keys().chain(funds.keys()) {
+            if held.get(signer) != funds.get(signer) {
+                changes.accounts.insert(signer.clone());
+            }
+        }
+        for signer in self.accounts.held_units.keys().chain(units.keys()) {
+            if held_units(signer) != units.get(signer) {
+                changes.accounts.insert(signer.clone());
+            }
+        }
+
+        self.accounts.held = funds;
+        for held_units in self.accounts.held_units.values_mut() {
+            held_units.remove(&self.symbol);
+        }
+        for (signer, units) in units {
+            self.accounts
+                .held_units
+                .entry(signer)
+                .or_default()
+                .insert(self.symbol.clone(), units);
+        }
+    }
+
+    /// **Withdraws all funds that aren't committed to open buy orders**
+    ///
+    /// Every account keeps only its [`committed_funds`](Self::committed_funds),
+    /// and the rest is released, as a [`Tx::Release`] out of the account, since it isn't spent,
+    /// but only moved elsewhere by the caller. The journal posts it to the suspense account,
+    /// until it arrives there.
+    ///
+    /// Returns the released amounts, by account; accounts without anything to release are left out.
+    pub fn release_uncommitted(&mut self) -> Vec<(String, Money)> {
+        let committed = self.committed_funds();
+        let released: Vec<(String, Money)> = self
+            .accounts
+            .accounts
+            .iter()
+            .map(|(signer, balance)| {
+                let committed = committed.get(signer).copied().unwrap_or_default();
+                (signer.clone(), balance.saturating_sub(committed))
+            })
+            .filter(|(_, excess)| !excess.is_zero())
+            .collect();
+
+        let currency = self.currency().to_string();
+        for (signer, excess) in &released {
+            // The excess is a part of the balance, so it can certainly be released.
+            if let Ok(tx) = self
+                .accounts
+                .release_in(signer, &currency, *excess, Leg::Out)
+            {
+                self.tx_log.record(tx);
+            }
+        }
+
+        released
+    }
+
+    /// **The units that each signer's open sell orders may still need**
+    ///
+    /// Counts the remaining amounts of resting sell orders, and the amounts of the sell legs
+    /// of order groups that are still waiting or armed. Only one of the exits
+    /// of an order group can be filled, so only one of them is counted.
+    /// Signers without open sell orders aren't included.
+    pub fn committed_units(&self) -> BTreeMap<String, Quantity> {
+        self.commitments(None).1
+    }
+
+    /// **Withdraws all units of the traded instrument that aren't committed to open sell orders**
+    ///
+    /// The same as [`release_uncommitted`](Self::release_uncommitted), for the units
+    /// of the instrument with this platform's symbol; see [`committed_units`](Self::committed_units).
+    pub fn release_uncommitted_units(&mut self) -> Vec<(String, Quantity)> {
+        let committed = self.committed_units();
+        let released: Vec<(String, Quantity)> = self
+            .accounts
+            .holdings
+            .iter()
+            .filter_map(|(signer, holdings)| {
+                let committed = committed.get(signer).copied().unwrap_or_default();
+                holdings
+                    .get(&self.symbol)
+                    .map(|units| (signer.clone(), units.saturating_sub(committed)))
+            })
+            .filter(|(_, excess)| !excess.is_zero())
+            .collect();
+
+        for (signer, excess) in &released {
+            // The excess is a part of the holdings, so it can certainly be released.
+            if let Ok(tx) = self
+                .accounts
+                .release_units(signer, &self.symbol, *excess, Leg::Out)
+            {
+                self.tx_log.record(tx);
+            }
+        }
+
+        released
+    }
+
+    /// **Places a one-cancels-other pair of a take-profit and a stop-loss order**
+    ///
+    /// The take-profit is submitted to the order book right away, while the stop-loss
+    /// is armed and only submitted once the last trade price reaches its price.
+    /// A fill on either leg cancels the other one.
+    ///
+    /// # Errors
+    /// - A leg doesn't fit the instrument's scales, `OrderError::Invalid`;
+    /// - Legs don't fit together, `OrderError::GroupInvalid`;
+    /// - Any of the errors from [`TradingPlatform::process_order`], for the take-profit.
+    pub fn place_oco(
+        &mut self,
+        take_profit: Order,
+        stop_loss: Order,
+    ) -> Result<OrderGroup, AccountingError> {
+        let take_profit = take_profit.normalize(&self.instrument)?;
+        let stop_loss = stop_loss.normalize(&self.instrument)?;
+        validate_exits(&take_profit, &stop_loss)?;
+
+        let receipt = self.execute_order(take_profit.clone())?;
+
+        self.order_group_id += 1;
+        let id = self.order_group_id;
+        let mut group = OrderGroup::new_oco(id, take_profit, stop_loss);
+        group.legs[0].ordinal = Some(receipt.ordinal);
+        self.order_groups.insert(id, group);
+        self.order_group_legs.insert(receipt.ordinal, (id, 0));
+
+        self.update_order_groups(receipt);
+        self.update_holds(None);
+
+        self.order_group(id).cloned()
+    }
+
+    /// **Places an entry order with a bracket of a take-profit and a stop-loss order**
+    ///
+    /// The entry is submitted to the order book right away. Once it has been completely filled,
+    /// the take-profit and the stop-loss become active as a one-cancels-other pair.
+    ///
+    /// # Errors
+    /// - A leg doesn't fit the instrument's scales, `OrderError::Invalid`;
+    /// - Legs don't fit together, `OrderError::GroupInvalid`;
+    /// - Any of the errors from [`TradingPlatform::process_order`], for the entry.
+    pub fn place_bracket(
+        &mut self,
+        entry: Order,
+        take_profit: Order,
+        stop_loss: Order,
+    ) -> Result<OrderGroup, AccountingError> {
+        let entry = entry.normalize(&self.instrument)?;
+        let take_profit = take_profit.normalize(&self.instrument)?;
+        let stop_loss = stop_loss.normalize(&self.instrument)?;
+        validate_bracket(&entry, &take_profit, &stop_loss)?;
+
+        let receipt = self.execute_order(entry.clone())?;
+
+        self.order_group_id += 1;
+        let id = self.order_group_id;
+        let mut group = OrderGroup::new_bracket(id, entry, take_profit, stop_loss);
+        group.legs[0].ordinal = Some(receipt.ordinal);
+        self.order_groups.insert(id, group);
+        self.order_group_legs.insert(receipt.ordinal, (id, 0));
+
+        self.update_order_groups(receipt);
+        self.update_holds(None);
+
+        self.order_group(id).cloned()
+    }
+
+    /// **Retrieves an order group by its id**
+    ///
+    /// # Errors
+    /// - Group doesn't exist, `OrderError::GroupNotFound`
+    pub fn order_group(&self, id: u64) -> Result<&OrderGroup, AccountingError> {
+        self.order_groups
+            .get(&id)
+            .ok_or(AccountingError::Order(OrderError::GroupNotFound(id)))
+    }
+
+    /// **Fetches all order groups ever placed, ordered by their id**
+    pub fn order_groups(&self) -> Vec<OrderGroup> {
+        self.order_groups.values().cloned().collect()
+    }
+
+    /// **Cancels all open legs of an order group**
+    ///
+    /// Working legs are removed from the order book, while waiting and armed legs
+    /// will never be submitted. Fills that have already happened stay in place.
+    ///
+    /// Cancelling a group that isn't active anymore doesn't change it.
+    ///
+    /// # Errors
+    /// - Group doesn't exist, `OrderError::GroupNotFound`
+    pub fn cancel_order_group(&mut self, id: u64) -> Result<OrderGroup, AccountingError> {
+        let group = self
+            .order_groups
+            .get_mut(&id)
+            .ok_or(OrderError::GroupNotFound(id))?;
+
+        if group.status == OrderGroupStatus::Active {
+            for leg in group.legs.iter_mut().filter(|leg| leg.is_open()) {
+                if let Some(ordinal) = leg.ordinal {
+                    self.matching_engine.cancel(ordinal);
+                }
+                leg.status = LegStatus::Cancelled;
+            }
+            group.status = OrderGroupStatus::Cancelled;
+        }
+
+        let group = group.clone();
+        self.update_holds(None);
+        Ok(group)
+    }
+
+    /// **Cancels all resting orders of a signer, optionally only on one side of the order book**
+    ///
+    /// Cancelling an order that is a leg of an order group cancels the whole group,
+    /// so that none of its other legs gets submitted later on.
+    ///
+    /// Returns the removed orders, ordered by their ordinal sequence number.
+    pub fn cancel_all(&mut self, signer: &str, side: Option<Side>) -> Vec<PartialOrder> {
+        let cancelled = self.matching_engine.cancel_all(signer, side);
+
+        for po in &cancelled {
+            if let Some(&(group_id, _)) = self.order_group_legs.get(&po.ordinal) {
+                // The group certainly exists, as it's the one that the leg was recorded for.
+                let _ = self.cancel_order_group(group_id);
+            }
+        }
+        self.update_holds(None);
+
+        cancelled
+    }
+
+    /// Propagates the fills from a receipt to the order groups.
+    ///
+    /// Activating a leg submits a new order, which produces a new receipt,
+    /// so receipts are processed from a queue until there is nothing left to propagate.
+    fn update_order_groups(&mut self, receipt: Receipt) {
+        let mut receipts = VecDeque::from([receipt]);
+
+        while let Some(receipt) = receipts.pop_front() {
+            if let Some(last_match) = receipt.matches.last() {
+                self.last_trade_price = Some(last_match.price);
+            }
+
+            // Both the incoming order and every matched resting order may be group legs.
+            let incoming_filled = receipt.matches.iter().fold(Quantity::ZERO, |sum, po| {
+                sum.saturating_add(po.current_amount.saturating_sub(po.remaining_amount))
+            });
+            let fills = std::iter::once((receipt.ordinal, incoming_filled)).chain(
+                receipt.matches.iter().map(|po| {
+                    (
+                        po.ordinal,
+                        po.current_amount.saturating_sub(po.remaining_amount),
+                    )
+                }),
+            );
+
+            for (ordinal, filled) in fills.collect::<Vec<_>>() {
+                if filled.is_zero() {
+                    continue;
+                }
+                if let Some(&(id, leg_idx)) = self.order_group_legs.get(&ordinal) {
+                    self.on_leg_filled(id, leg_idx, filled, &mut receipts);
+                }
+            }
+
+            self.trigger_stop_losses(&mut receipts);
+            self.enforce_margin(&mut receipts);
+        }
+    }
+
+    /// Records a fill on a leg, and cancels or activates the linked legs.
+    fn on_leg_filled(
+        &mut self,
+        id: u64,
+        leg_idx: usize,
+        filled: Quantity,
+        receipts: &mut VecDeque<Receipt>,
+    ) {
+        let Some(group) = self.order_groups.get_mut(&id) else {
+            return;
+        };
+
+        let leg = &mut group.legs[leg_idx];
+        leg.filled = leg.filled.saturating_add(filled);
+        if leg.filled >= leg.order.get_initial_amount() {
+            leg.status = LegStatus::Filled;
+        }
+        let role = leg.role.clone();
+        let leg_is_filled = leg.status == LegStatus::Filled;
+
+        match role {
+            LegRole::Entry => {
+                // Only a completely filled entry activates the exits, which cover its entire amount.
+                if leg_is_filled {
+                    let mut to_submit = vec![];
+                    for (exit_idx, exit) in group.legs.iter_mut().enumerate() {
+                        if exit.status == LegStatus::Waiting {
+                            match exit.role {
+                                LegRole::StopLoss => exit.status = LegStatus::Armed,
+                                _ => to_submit.push(exit_idx),
+                            }
+                        }
+                    }
+                    for exit_idx in to_submit {
+                        self.submit_leg(id, exit_idx, receipts);
+                    }
+                }
+            }
+            LegRole::TakeProfit | LegRole::StopLoss => {
+                let mut to_cancel = vec![];
+                for (other_idx, other) in group.legs.iter_mut().enumerate() {
+                    if other_idx != leg_idx && other.role != LegRole::Entry && other.is_open() {
+                        other.status = LegStatus::Cancelled;
+                        to_cancel.extend(other.ordinal);
+                    }
+                }
+                for ordinal in to_cancel {
+                    self.matching_engine.cancel(ordinal);
+                }
+            }
+        }
+
+        if let Some(group) = self.order_groups.get_mut(&id) {
+            group.update_status();
+        }
+    }
+
+    /// Submits armed stop-losses whose trigger price has been reached by the last trade price.
+    ///
+    /// A sell stop-loss is triggered when the price falls to or below its price,
+    /// and a buy stop-loss when the price rises to or above its price.
+    fn trigger_stop_losses(&mut self, receipts: &mut VecDeque<Receipt>) {
+        let Some(last_trade_price) = self.last_trade_price else {
+            return;
+        };
+
+        let triggered: Vec<(u64, usize)> = self
+            .order_groups
+            .values()
+            .filter(|group| group.status == OrderGroupStatus::Active)
+            .flat_map(|group| {
+                group
+                    .legs
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, leg)| {
+                        leg.status == LegStatus::Armed
+                            && match leg.order.side {
+                                Side::Sell => last_trade_price <= leg.order.price,
+                                Side::Buy => last_trade_price >= leg.order.price,
+                            }
+                    })
+                    .map(|(leg_idx, _)| (group.id, leg_idx))
+            })
+            .collect();
+
+        for (id, leg_idx) in triggered {
+            self.submit_leg(id, leg_idx, receipts);
+        }
+    }
+
+    /// Calls the margin accounts whose equity has fallen below the maintenance margin,
+    /// at the last trade price, and liquidates them.
+    ///
+    /// An account is called once, until it has recovered, but it's liquidated for as long
+    /// as it stays below the maintenance margin, and there are bids to sell its units into.
+    fn enforce_margin(&mut self, receipts: &mut VecDeque<Receipt>) {
+        if self.margin.is_none() || self.last_trade_price.is_none() {
+            return;
+        }
+
+        let signers: Vec<String> = self.accounts.loans().keys().cloned().collect();
+        for signer in signers {
+            let Ok(summary) = self.margin_of(&signer) else {
+                continue;
+            };
+            if !summary.is_below_maintenance() {
+                self.margin_called.remove(&signer);
+                continue;
+            }
+
+            if self.margin_called.insert(signer.clone()) {
+                let (id, timestamp) = self.accounts.next_tx();
+                self.tx_log.record(Tx::MarginCall {
+                    id,
+                    timestamp,
+                    account: signer.clone(),
+                    equity: summary.equity,
+                    requirement: summary.maintenance_requirement,
+                });
+            }
+            self.liquidate(&signer, receipts);
+        }
+    }
+
+    /// Cancels the signer's orders, sells all of their units down to the lowest bid,
+    /// and repays as much of their loan as the funds allow.
+    ///
+    /// Whatever the bids don't take is taken off the order book again.
+    fn liquidate(&mut self, signer: &str, receipts: &mut VecDeque<Receipt>) {
+        let Some(&lowest_bid) = self.matching_engine.bids.keys().next() else {
+            return;
+        };
+
+        self.cancel_all(signer, None);
+        let units = self
+            .accounts
+            .units_available_of(signer, &self.symbol)
+            .unwrap_or_default();
+        if !units.is_zero() {
+            let (id, timestamp) = self.accounts.next_tx();
+            self.tx_log.record(Tx::Liquidation {
+                id,
+                timestamp,
+                account: signer.to_string(),
+                symbol: self.symbol.clone(),
+                units,
+            });
+
+            // A frozen or withdraw-only account is liquidated all the same, as its loan is at risk.
+            let order = Order::new(lowest_bid, units, Side::Sell, signer.to_string());
+            if let Ok(receipt) = self.execute_order_as(order, false) {
+                self.matching_engine.cancel(receipt.ordinal);
+                // Only fills need to be propagated; this also keeps the liquidations from looping.
+                if !receipt.matches.is_empty() {
+                    receipts.push_back(receipt);
+                }
+            }
+            self.update_holds(None);
+        }
+
+        let repayment = self
+            .accounts
+            .loan_of(signer)
+            .min(self.accounts.available_of(signer).unwrap_or_default());
+        if !repayment.is_zero() {
+            if let Ok(tx) = self.accounts.repay(signer, repayment) {
+                self.tx_log.record(tx);
+            }
+        }
+    }
+
+    /// Submits a leg's order to the order book.
+    ///
+    /// The resulting receipt is queued, so that its fills get propagated, too.
+    /// A leg whose order can't be processed is marked as rejected.
+    fn submit_leg(&mut self, id: u64, leg_idx: usize, receipts: &mut VecDeque<Receipt>) {
+        let Some(order) = self
+            .order_groups
+            .get(&id)
+            .map(|group| group.legs[leg_idx].order.clone())
+        else {
+            return;
+        };
+
+        // The leg's own holds, which it may share with the other exit, are there for it to spend.
+        self.update_holds(Some(id));
+        let result = self.execute_order(order);
+
+        if let Some(group) = self.order_groups.get_mut(&id) {
+            let leg = &mut group.legs[leg_idx];
+            match result {
+                Ok(receipt) => {
+                    leg.status = LegStatus::Working;
+                    leg.ordinal = Some(receipt.ordinal);
+                    self.order_group_legs.insert(receipt.ordinal, (id, leg_idx));
+                    receipts.push_back(receipt);
+                }
+                Err(_) => leg.status = LegStatus::Rejected,
+            }
+            group.update_status();
+        }
+    }
+}
+
+/// The settlement of a single match, and its fees, worked out in advance
+struct Fill<'a> {
+    /// The resting order that was matched
+    maker: &'a PartialOrder,
+    buyer: &'a str,
+    seller: &'a str,
+    units: Quantity,
+    value: Money,
+    taker_fee: Money,
+    /// With a rebate capped at the taker's fee
+    maker_fee: Fee,
+}
+
+/// **Transfers that are checked against the accounts without changing them**
+///
+/// The balances and the holdings that the transfers change are tracked on the side,
+/// so that a sequence of transfers fails exactly where applying it to the accounts would.
+struct DryRun<'a> {
+    accounts: &'a Accounts,
+    symbol: &'a str,
+    balances: BTreeMap<String, Money>,
+    units: BTreeMap<String, Quantity>,
+}
+
+impl<'a> DryRun<'a> {
+    fn new(accounts: &'a Accounts, symbol: &'a str) -> Self {
+        DryRun {
+            accounts,
+            symbol,
+            balances: BTreeMap::new(),
+            units: BTreeMap::new(),
+        }
+    }
+
+    fn balance_of(&self, signer: &str) -> Result<Money, AccountingError> {
+        match self.balances.get(signer) {
+            Some(balance) => Ok(*balance),
+            None => self.accounts.balance_of(signer).copied(),
+        }
+    }
+
+    fn units_of(&self, signer: &str) -> Result<Quantity, AccountingError> {
+        match self.units.get(signer) {
+            Some(units) => Ok(*units),
+            None => self.accounts.units_of(signer, self.symbol),
+        }
+    }
+
+    /// Checks the loan in the same order as [`Accounts::borrow`].
+    fn borrow(&mut self, signer: &str, amount: Money) -> Result<(), AccountingError> {
+        let balance = self
+            .balance_of(signer)?
+            .checked_add(amount)
+            .ok_or(AccountError::OverFunded(signer.to_string(), amount))?;
+        self.balances.insert(signer.to_string(), balance);
+        Ok(())
+    }
+
+    /// Checks the transfer of funds in the same order as [`Accounts::transfer`].
+    fn transfer(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Money,
+    ) -> Result<(), AccountingError> {
+        self.balance_of(recipient)?
+            .checked_add(amount)
+            .ok_or(AccountError::OverFunded(recipient.to_string(), amount))?;
+        let sender_balance = self
+            .balance_of(sender)?
+            .checked_sub(amount)
+            .ok_or(AccountError::UnderFunded(sender.to_string(), amount))?;
+        self.balances.insert(sender.to_string(), sender_balance);
+
+        // The recipient's balance is read again, as it's the sender's, when they are the same.
+        let recipient_balance = self
+            .balance_of(recipient)?
+            .checked_add(amount)
+            .ok_or(AccountError::OverFunded(recipient.to_string(), amount))?;
+        self.balances
+            .insert(recipient.to_string(), recipient_balance);
+        Ok(())
+    }
+
+    /// Checks the transfer of units in the same order as [`Accounts::transfer_units`].
+    fn transfer_units(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        units: Quantity,
+    ) -> Result<(), AccountingError> {
+        let overflow = || {
+            AccountingError::Account(AccountError::HoldingsOverflow(
+                recipient.to_string(),
+                self.symbol.to_string(),
+                units,
+            ))
+        };
+        self.units_of(recipient)?
+            .checked_add(units)
+            .ok_or_else(overflow)?;
+        let sender_units =
+            self.units_of(sender)?
+                .checked_sub(units)
+                .ok_or(AccountError::HoldingsInsufficient(
+                    sender.to_string(),
+                    self.symbol.to_string(),
+                    units,
+                ))?;
+        self.units.insert(sender.to_string(), sender_units);
+
+        let recipient_units = self
+            .units_of(recipient)?
+            .checked_add(units)
+            .ok_or_else(overflow)?;
+        self.units.insert(recipient.to_string(), recipient_units);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::{FxError, LimitError};
+    use crate::fees::{FeeTier, DEFAULT_FEE_ACCOUNT, MAX_FEE_BPS};
+    use crate::history::NoOpSink;
+    use crate::journal::{Asset, LedgerAccount, SystemAccount};
+    use crate::limits::{Limit, ManualClock};
+    use crate::order_groups::OrderGroupKind;
+    use crate::positions::Pnl;
+    use crate::tx::TxKind;
+    use crate::types::Amount;
+
+    /// Gives the signers plenty of units to sell, without recording it in the transaction log.
+    fn give_units(trading_platform: &mut TradingPlatform, signers: &[&str]) {
+        for signer in signers {
+            trading_platform
+                .accounts
+                .deposit_units(signer, DEFAULT_SYMBOL, 1000.into())
+                .unwrap();
+        }
+    }
 
     /// The implementation of the `order_book` function works first with asks (sells) and then with bids (buys),
     /// so we are also testing here when a bid comes first and then an ask from the same signer, Bob.
@@ -232,11 +1964,28 @@ mod tests {
         let mut trading_platform = TradingPlatform::new();
 
         // Set up accounts
-        assert!(trading_platform.accounts.deposit("Alice", 100).is_ok());
-        assert!(trading_platform.accounts.deposit("Bob", 100).is_ok());
-        assert!(trading_platform.accounts.deposit("Charlie", 100).is_ok());
-        assert!(trading_platform.accounts.deposit("Donna", 100).is_ok());
-        assert!(trading_platform.accounts.deposit("Eleanor", 100).is_ok());
+        assert!(trading_platform
+            .accounts
+            .deposit("Alice", 100.into())
+            .is_ok());
+        assert!(trading_platform.accounts.deposit("Bob", 100.into()).is_ok());
+        assert!(trading_platform
+            .accounts
+            .deposit("Charlie", 100.into())
+            .is_ok());
+        assert!(trading_platform
+            .accounts
+            .deposit("Donna", 100.into())
+            .is_ok());
+        assert!(trading_platform
+            .accounts
+            .deposit("Eleanor", 100.into())
+            .is_ok());
+
+        give_units(
+            &mut trading_platform,
+            &["Alice", "Bob", "Charlie", "Eleanor"],
+        );
 
         trading_platform
             .process_order(Order::new(15, 1, Side::Sell, String::from("Alice")))
@@ -316,11 +2065,28 @@ mod tests {
         let mut trading_platform = TradingPlatform::new();
 
         // Set up accounts
-        assert!(trading_platform.accounts.deposit("Alice", 100).is_ok());
-        assert!(trading_platform.accounts.deposit("Bob", 100).is_ok());
-        assert!(trading_platform.accounts.deposit("Charlie", 100).is_ok());
-        assert!(trading_platform.accounts.deposit("Donna", 100).is_ok());
-        assert!(trading_platform.accounts.deposit("Eleanor", 100).is_ok());
+        assert!(trading_platform
+            .accounts
+            .deposit("Alice", 100.into())
+            .is_ok());
+        assert!(trading_platform.accounts.deposit("Bob", 100.into()).is_ok());
+        assert!(trading_platform
+            .accounts
+            .deposit("Charlie", 100.into())
+            .is_ok());
+        assert!(trading_platform
+            .accounts
+            .deposit("Donna", 100.into())
+            .is_ok());
+        assert!(trading_platform
+            .accounts
+            .deposit("Eleanor", 100.into())
+            .is_ok());
+
+        give_units(
+            &mut trading_platform,
+            &["Alice", "Bob", "Charlie", "Eleanor"],
+        );
 
         trading_platform
             .process_order(Order::new(15, 1, Side::Sell, String::from("Alice")))
@@ -393,106 +2159,121 @@ mod tests {
     fn deposit_works() {
         let mut trading_platform = TradingPlatform::new();
 
+        let tx = trading_platform.deposit("Alice", 100.into());
         assert_eq!(
             Ok(Tx::Deposit {
+                id: 1,
+                timestamp: tx.as_ref().unwrap().timestamp(),
                 account: "Alice".to_string(),
-                amount: 100
+                amount: 100.into(),
+                currency: None,
             }),
-            trading_platform.deposit("Alice", 100)
+            tx
         );
 
         // Check the account balance
-        assert_eq!(Ok(&100), trading_platform.balance_of("Alice"));
+        assert_eq!(Ok(&Money::from(100)), trading_platform.balance_of("Alice"));
     }
 
     #[test]
     fn deposit_overflows() {
         let mut trading_platform = TradingPlatform::new();
 
-        assert!(trading_platform.deposit("Alice", u64::MAX).is_ok());
+        assert!(trading_platform.deposit("Alice", u64::MAX.into()).is_ok());
 
         assert_eq!(
-            AccountingError::AccountOverFunded("Alice".to_string(), 1),
-            trading_platform.deposit("Alice", 1).unwrap_err()
+            AccountingError::Account(AccountError::OverFunded("Alice".to_string(), 1.into())),
+            trading_platform.deposit("Alice", 1.into()).unwrap_err()
         );
 
         // Check the account balance
-        assert_eq!(Ok(&u64::MAX), trading_platform.balance_of("Alice"));
+        assert_eq!(
+            Ok(&Money::from(u64::MAX)),
+            trading_platform.balance_of("Alice")
+        );
     }
 
     #[test]
     fn deposit_multiple_works() {
         let mut trading_platform = TradingPlatform::new();
 
-        assert!(trading_platform.deposit("Alice", 100).is_ok());
-        assert!(trading_platform.deposit("Alice", 20).is_ok());
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
+        assert!(trading_platform.deposit("Alice", 20.into()).is_ok());
 
         // Check the account balance
-        assert_eq!(Ok(&120), trading_platform.balance_of("Alice"));
+        assert_eq!(Ok(&Money::from(120)), trading_platform.balance_of("Alice"));
     }
 
     #[test]
     fn withdraw_works() {
         let mut trading_platform = TradingPlatform::new();
 
-        assert!(trading_platform.deposit("Alice", 100).is_ok());
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
+        let tx = trading_platform.withdraw("Alice", 30.into());
         assert_eq!(
             Ok(Tx::Withdraw {
+                id: 2,
+                timestamp: tx.as_ref().unwrap().timestamp(),
                 account: "Alice".to_string(),
-                amount: 30
+                amount: 30.into(),
+                currency: None,
             }),
-            trading_platform.withdraw("Alice", 30)
+            tx
         );
 
         // Check the account balance
-        assert_eq!(Ok(&70), trading_platform.balance_of("Alice"));
+        assert_eq!(Ok(&Money::from(70)), trading_platform.balance_of("Alice"));
     }
 
     #[test]
     fn withdraw_multiple_works() {
         let mut trading_platform = TradingPlatform::new();
 
-        assert!(trading_platform.deposit("Alice", 100).is_ok());
-        assert!(trading_platform.withdraw("Alice", 30).is_ok());
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
+        assert!(trading_platform.withdraw("Alice", 30.into()).is_ok());
+        let tx = trading_platform.withdraw("Alice", 20.into());
         assert_eq!(
             Ok(Tx::Withdraw {
+                id: 3,
+                timestamp: tx.as_ref().unwrap().timestamp(),
                 account: "Alice".to_string(),
-                amount: 20
+                amount: 20.into(),
+                currency: None,
             }),
-            trading_platform.withdraw("Alice", 20)
+            tx
         );
 
         // Check the account balance
-        assert_eq!(Ok(&50), trading_platform.balance_of("Alice"));
+        assert_eq!(Ok(&Money::from(50)), trading_platform.balance_of("Alice"));
     }
 
     #[test]
     fn deposit_withdraw_multiple_works() {
         let mut trading_platform = TradingPlatform::new();
 
-        assert!(trading_platform.deposit("Alice", 100).is_ok());
-        assert!(trading_platform.withdraw("Alice", 30).is_ok());
-        assert!(trading_platform.deposit("Alice", 10).is_ok());
-        assert!(trading_platform.withdraw("Alice", 20).is_ok());
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
+        assert!(trading_platform.withdraw("Alice", 30.into()).is_ok());
+        assert!(trading_platform.deposit("Alice", 10.into()).is_ok());
+        assert!(trading_platform.withdraw("Alice", 20.into()).is_ok());
 
         // Check the account balance
-        assert_eq!(Ok(&60), trading_platform.balance_of("Alice"));
+        assert_eq!(Ok(&Money::from(60)), trading_platform.balance_of("Alice"));
     }
 
     #[test]
     fn withdraw_err_doesnt_exist() {
         let mut trading_platform = TradingPlatform::new();
 
-        let tx = trading_platform.withdraw("Alice", 30);
+        let tx = trading_platform.withdraw("Alice", 30.into());
 
         assert!(tx.is_err());
         assert_eq!(
-            AccountingError::AccountNotFound("Alice".to_string()),
+            AccountingError::Account(AccountError::NotFound("Alice".to_string())),
             tx.unwrap_err()
         );
 
         assert_eq!(
-            AccountingError::AccountNotFound("Alice".to_string()),
+            AccountingError::Account(AccountError::NotFound("Alice".to_string())),
             trading_platform.balance_of("Alice").unwrap_err()
         );
     }
@@ -501,70 +2282,70 @@ mod tests {
     fn withdraw_err_under_funded() {
         let mut trading_platform = TradingPlatform::new();
 
-        assert!(trading_platform.deposit("Alice", 100).is_ok());
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
 
-        let tx = trading_platform.withdraw("Alice", 130);
+        let tx = trading_platform.withdraw("Alice", 130.into());
 
         assert!(tx.is_err());
         assert_eq!(
-            AccountingError::AccountUnderFunded("Alice".to_string(), 130),
+            AccountingError::Account(AccountError::UnderFunded("Alice".to_string(), 130.into())),
             tx.unwrap_err()
         );
 
-        assert_eq!(Ok(&100), trading_platform.balance_of("Alice"));
+        assert_eq!(Ok(&Money::from(100)), trading_platform.balance_of("Alice"));
     }
 
     #[test]
     fn send_ok() {
         let mut trading_platform = TradingPlatform::new();
 
-        assert!(trading_platform.deposit("Alice", 100).is_ok());
-        assert!(trading_platform.deposit("Bob", 50).is_ok());
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
+        assert!(trading_platform.deposit("Bob", 50.into()).is_ok());
 
-        let status = trading_platform.send("Alice", "Bob", 10);
+        let status = trading_platform.send("Alice", "Bob", 10.into(), None);
 
         assert!(status.is_ok());
 
-        assert_eq!(Ok(&90), trading_platform.balance_of("Alice"));
-        assert_eq!(Ok(&60), trading_platform.balance_of("Bob"));
+        assert_eq!(Ok(&Money::from(90)), trading_platform.balance_of("Alice"));
+        assert_eq!(Ok(&Money::from(60)), trading_platform.balance_of("Bob"));
     }
 
     #[test]
     fn send_err_sender_doesnt_exist() {
         let mut trading_platform = TradingPlatform::new();
 
-        assert!(trading_platform.deposit("Bob", 50).is_ok());
+        assert!(trading_platform.deposit("Bob", 50.into()).is_ok());
 
-        let status = trading_platform.send("Alice", "Bob", 10);
+        let status = trading_platform.send("Alice", "Bob", 10.into(), None);
 
         assert_eq!(
-            AccountingError::AccountNotFound("Alice".to_string()),
+            AccountingError::Account(AccountError::NotFound("Alice".to_string())),
             status.unwrap_err()
         );
 
         assert_eq!(
-            AccountingError::AccountNotFound("Alice".to_string()),
+            AccountingError::Account(AccountError::NotFound("Alice".to_string())),
             trading_platform.balance_of("Alice").unwrap_err()
         );
-        assert_eq!(Ok(&50), trading_platform.balance_of("Bob"));
+        assert_eq!(Ok(&Money::from(50)), trading_platform.balance_of("Bob"));
     }
 
     #[test]
     fn send_err_recipient_doesnt_exist() {
         let mut trading_platform = TradingPlatform::new();
 
-        assert!(trading_platform.deposit("Alice", 100).is_ok());
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
 
-        let status = trading_platform.send("Alice", "Bob", 10);
+        let status = trading_platform.send("Alice", "Bob", 10.into(), None);
 
         assert_eq!(
-            AccountingError::AccountNotFound("Bob".to_string()),
+            AccountingError::Account(AccountError::NotFound("Bob".to_string())),
             status.unwrap_err()
         );
 
-        assert_eq!(Ok(&100), trading_platform.balance_of("Alice"));
+        assert_eq!(Ok(&Money::from(100)), trading_platform.balance_of("Alice"));
         assert_eq!(
-            AccountingError::AccountNotFound("Bob".to_string()),
+            AccountingError::Account(AccountError::NotFound("Bob".to_string())),
             trading_platform.balance_of("Bob").unwrap_err()
         );
     }
@@ -573,19 +2354,19 @@ mod tests {
     fn send_err_no_one_exists() {
         let mut trading_platform = TradingPlatform::new();
 
-        let status = trading_platform.send("Alice", "Bob", 10);
+        let status = trading_platform.send("Alice", "Bob", 10.into(), None);
 
         assert_eq!(
-            AccountingError::AccountNotFound("Bob".to_string()),
+            AccountingError::Account(AccountError::NotFound("Bob".to_string())),
             status.unwrap_err()
         );
 
         assert_eq!(
-            AccountingError::AccountNotFound("Alice".to_string()),
+            AccountingError::Account(AccountError::NotFound("Alice".to_string())),
             trading_platform.balance_of("Alice").unwrap_err()
         );
         assert_eq!(
-            AccountingError::AccountNotFound("Bob".to_string()),
+            AccountingError::Account(AccountError::NotFound("Bob".to_string())),
             trading_platform.balance_of("Bob").unwrap_err()
         );
     }
@@ -594,36 +2375,39 @@ mod tests {
     fn send_err_sender_under_funded() {
         let mut trading_platform = TradingPlatform::new();
 
-        assert!(trading_platform.deposit("Alice", 100).is_ok());
-        assert!(trading_platform.deposit("Bob", 50).is_ok());
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
+        assert!(trading_platform.deposit("Bob", 50.into()).is_ok());
 
-        let status = trading_platform.send("Alice", "Bob", 200);
+        let status = trading_platform.send("Alice", "Bob", 200.into(), None);
 
         assert_eq!(
-            AccountingError::AccountUnderFunded("Alice".to_string(), 200),
+            AccountingError::Account(AccountError::UnderFunded("Alice".to_string(), 200.into())),
             status.unwrap_err()
         );
 
-        assert_eq!(Ok(&100), trading_platform.balance_of("Alice"));
-        assert_eq!(Ok(&50), trading_platform.balance_of("Bob"));
+        assert_eq!(Ok(&Money::from(100)), trading_platform.balance_of("Alice"));
+        assert_eq!(Ok(&Money::from(50)), trading_platform.balance_of("Bob"));
     }
 
     #[test]
     fn send_err_recipient_over_funded() {
         let mut trading_platform = TradingPlatform::new();
 
-        assert!(trading_platform.deposit("Alice", 100).is_ok());
-        assert!(trading_platform.deposit("Bob", u64::MAX).is_ok());
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
+        assert!(trading_platform.deposit("Bob", u64::MAX.into()).is_ok());
 
-        let status = trading_platform.send("Alice", "Bob", 10);
+        let status = trading_platform.send("Alice", "Bob", 10.into(), None);
 
         assert_eq!(
-            AccountingError::AccountOverFunded("Bob".to_string(), 10),
+            AccountingError::Account(AccountError::OverFunded("Bob".to_string(), 10.into())),
             status.unwrap_err()
         );
 
-        assert_eq!(Ok(&100), trading_platform.balance_of("Alice"));
-        assert_eq!(Ok(&u64::MAX), trading_platform.balance_of("Bob"));
+        assert_eq!(Ok(&Money::from(100)), trading_platform.balance_of("Alice"));
+        assert_eq!(
+            Ok(&Money::from(u64::MAX)),
+            trading_platform.balance_of("Bob")
+        );
     }
 
     #[test]
@@ -632,7 +2416,9 @@ mod tests {
 
         assert_eq!(
             trading_platform.process_order(Order::new(10, 1, Side::Sell, String::from("Alice"))),
-            Err(AccountingError::AccountNotFound("Alice".to_string()))
+            Err(AccountingError::Account(AccountError::NotFound(
+                "Alice".to_string()
+            )))
         );
         assert!(trading_platform.matching_engine.asks.is_empty());
         assert!(trading_platform.matching_engine.bids.is_empty());
@@ -644,7 +2430,9 @@ mod tests {
 
         assert_eq!(
             trading_platform.process_order(Order::new(10, 1, Side::Buy, String::from("Alice"))),
-            Err(AccountingError::AccountNotFound("Alice".to_string()))
+            Err(AccountingError::Account(AccountError::NotFound(
+                "Alice".to_string()
+            )))
         );
         assert!(trading_platform.matching_engine.asks.is_empty());
         assert!(trading_platform.matching_engine.bids.is_empty());
@@ -654,426 +2442,2647 @@ mod tests {
     fn process_order_checks_for_balance_in_buy_case_underfunded() {
         let mut trading_platform = TradingPlatform::new();
 
-        assert!(trading_platform.deposit("Alice", 100).is_ok());
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
 
         let alice_receipt =
             trading_platform.process_order(Order::new(10, 11, Side::Buy, String::from("Alice")));
         assert_eq!(
-            AccountingError::AccountUnderFunded("Alice".to_string(), 110),
+            AccountingError::Order(OrderError::UnderFunded("Alice".to_string(), 110.into())),
             alice_receipt.unwrap_err()
         );
     }
 
     #[test]
-    fn process_order_partially_match_order_updates_accounts_seller_first_1() {
-        let mut trading_platform = TradingPlatform::new();
-
-        // Set up accounts
-        assert!(trading_platform.deposit("Alice", 100).is_ok());
-        assert!(trading_platform.deposit("Bob", 100).is_ok());
-
-        let alice_receipt = trading_platform
-            .process_order(Order::new(10, 1, Side::Sell, String::from("Alice")))
-            .unwrap();
-        assert_eq!(1, alice_receipt.ordinal);
-        assert!(alice_receipt.matches.is_empty());
+    fn process_order_checks_for_holdings_in_sell_case() {
+        let mut trading_platform = TradingPlatform::new().with_symbol("ABC");
+
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
+        assert!(trading_platform
+            .deposit_units("Alice", "ABC", 2.into())
+            .is_ok());
+        // Units of another instrument don't count.
+        assert!(trading_platform
+            .deposit_units("Alice", "XYZ", 10.into())
+            .is_ok());
 
-        let bob_receipt = trading_platform
-            .process_order(Order::new(10, 2, Side::Buy, String::from("Bob")))
-            .unwrap();
-        assert_eq!(2, bob_receipt.ordinal);
         assert_eq!(
-            vec![PartialOrder {
-                price: 10,
-                current_amount: 1,
-                remaining_amount: 0,
-                side: Side::Sell,
-                signer: "Alice".to_string(),
-                ordinal: 1
-            }],
-            bob_receipt.matches,
+            AccountingError::Order(OrderError::HoldingsInsufficient(
+                "Alice".to_string(),
+                "ABC".to_string(),
+                3.into()
+            )),
+            trading_platform
+                .process_order(Order::new(10, 3, Side::Sell, String::from("Alice")))
+                .unwrap_err()
         );
+        assert!(trading_platform.order_book(false, false).is_empty());
 
-        assert_eq!(0, trading_platform.matching_engine.asks.len());
-        assert_eq!(1, trading_platform.matching_engine.bids.len());
-
-        // Check the account balances
-        assert_eq!(Ok(&110), trading_platform.balance_of("Alice"));
-        assert_eq!(Ok(&90), trading_platform.balance_of("Bob"));
+        assert!(trading_platform
+            .process_order(Order::new(10, 2, Side::Sell, String::from("Alice")))
+            .is_ok());
     }
 
     #[test]
-    fn process_order_partially_match_order_updates_accounts_seller_first_2() {
+    fn process_order_moves_units_to_buyer() {
         let mut trading_platform = TradingPlatform::new();
 
-        // Set up accounts
-        assert!(trading_platform.accounts.deposit("Alice", 100).is_ok());
-        assert!(trading_platform.accounts.deposit("Bob", 100).is_ok());
+        assert!(trading_platform
+            .deposit_units("Alice", DEFAULT_SYMBOL, 5.into())
+            .is_ok());
+        assert!(trading_platform.deposit("Bob", 100.into()).is_ok());
 
-        let alice_receipt = trading_platform
-            .process_order(Order::new(10, 2, Side::Sell, String::from("Alice")))
+        trading_platform
+            .process_order(Order::new(10, 5, Side::Sell, String::from("Alice")))
             .unwrap();
-        assert_eq!(1, alice_receipt.ordinal);
-        assert!(alice_receipt.matches.is_empty());
-
-        let bob_receipt = trading_platform
-            .process_order(Order::new(10, 1, Side::Buy, String::from("Bob")))
+        trading_platform
+            .process_order(Order::new(10, 3, Side::Buy, String::from("Bob")))
             .unwrap();
-        assert_eq!(2, bob_receipt.ordinal);
+
+        let accounts = &trading_platform.accounts;
         assert_eq!(
-            vec![PartialOrder {
-                price: 10,
-                current_amount: 2,
-                remaining_amount: 1,
-                side: Side::Sell,
-                signer: "Alice".to_string(),
-                ordinal: 1
-            }],
-            bob_receipt.matches,
+            Ok(Quantity::from(2)),
+            accounts.units_of("Alice", DEFAULT_SYMBOL)
+        );
+        assert_eq!(
+            Ok(Quantity::from(3)),
+            accounts.units_of("Bob", DEFAULT_SYMBOL)
+        );
+        assert_eq!(&Money::from(30), accounts.balance_of("Alice").unwrap());
+        let settlement = trading_platform.tx_log.records().pop().unwrap();
+        assert_eq!(
+            Tx::TradeSettlement {
+                id: 3,
+                timestamp: settlement.timestamp(),
+                taker_ordinal: 2,
+                maker_ordinal: 1,
+                buyer: "Bob".to_string(),
+                seller: "Alice".to_string(),
+                symbol: DEFAULT_SYMBOL.to_string(),
+                price: 10.into(),
+                units: 3.into(),
+                value: 30.into(),
+            },
+            settlement
         );
 
-        assert_eq!(1, trading_platform.matching_engine.asks.len());
-        assert_eq!(0, trading_platform.matching_engine.bids.len());
-
-        // Check the account balances
-        assert_eq!(Ok(&110), trading_platform.accounts.balance_of("Alice"));
-        assert_eq!(Ok(&90), trading_platform.accounts.balance_of("Bob"));
+        // Only the units of the resting sell order are kept.
+        assert_eq!(
+            vec![(String::from("Bob"), Quantity::from(3))],
+            trading_platform.release_uncommitted_units()
+        );
+        assert_eq!(
+            BTreeMap::from([(String::from("Alice"), Quantity::from(2))]),
+            trading_platform.committed_units()
+        );
+    }
+
+    #[test]
+    fn process_order_holds_funds_of_resting_buy_orders() {
+        let mut trading_platform = TradingPlatform::new();
+
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
+        assert!(trading_platform.deposit("Bob", 100.into()).is_ok());
+
+        trading_platform
+            .process_order(Order::new(10, 5, Side::Buy, String::from("Alice")))
+            .unwrap();
+        assert_eq!(Money::from(50), trading_platform.accounts.held_of("Alice"));
+
+        // The same funds can't back another order, nor be withdrawn or sent.
+        assert_eq!(
+            AccountingError::Order(OrderError::UnderFunded("Alice".to_string(), 60.into())),
+            trading_platform
+                .process_order(Order::new(10, 6, Side::Buy, String::from("Alice")))
+                .unwrap_err()
+        );
+        assert_eq!(
+            Err(AccountingError::Account(AccountError::UnderFunded(
+                "Alice".to_string(),
+                51.into()
+            ))),
+            trading_platform.withdraw("Alice", 51.into())
+        );
+        assert!(trading_platform
+            .send("Alice", "Bob", 51.into(), None)
+            .is_err());
+        assert!(trading_platform.withdraw("Alice", 50.into()).is_ok());
+
+        // Cancelling the order releases its funds.
+        trading_platform.cancel_all("Alice", None);
+        assert!(trading_platform.accounts.held_of("Alice").is_zero());
+        assert_eq!(
+            Ok(Money::from(50)),
+            trading_platform.accounts.available_of("Alice")
+        );
+    }
+
+    #[test]
+    fn process_order_fills_release_held_funds_and_units() {
+        let mut trading_platform = TradingPlatform::new();
+
+        assert!(trading_platform
+            .deposit_units("Alice", DEFAULT_SYMBOL, 5.into())
+            .is_ok());
+        assert!(trading_platform.deposit("Bob", 100.into()).is_ok());
+
+        trading_platform
+            .process_order(Order::new(10, 5, Side::Sell, String::from("Alice")))
+            .unwrap();
+        assert_eq!(
+            Err(AccountingError::Account(
+                AccountError::HoldingsInsufficient(
+                    "Alice".to_string(),
+                    DEFAULT_SYMBOL.to_string(),
+                    1.into()
+                )
+            )),
+            trading_platform.withdraw_units("Alice", DEFAULT_SYMBOL, 1.into())
+        );
+
+        // Bob's resting bid is held, and Alice's fill spends the held units.
+        trading_platform
+            .process_order(Order::new(10, 3, Side::Buy, String::from("Bob")))
+            .unwrap();
+
+        let alice = trading_platform.accounts.summary_of("Alice").unwrap();
+        assert_eq!(
+            BTreeMap::from([(DEFAULT_SYMBOL.to_string(), Quantity::from(2))]),
+            alice.held_units
+        );
+        assert_eq!(Money::from(30), alice.balance);
+        assert!(alice.held.is_zero());
+        assert!(trading_platform.accounts.held_of("Bob").is_zero());
+    }
+
+    #[test]
+    fn close_account_err_open_orders() {
+        let mut trading_platform = TradingPlatform::new();
+
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
+        assert!(trading_platform.deposit("Bob", 0.into()).is_ok());
+        // A buy at 0 doesn't hold anything, but it's still an open order.
+        trading_platform
+            .process_order(Order::new(0, 1, Side::Buy, String::from("Alice")))
+            .unwrap();
+
+        assert_eq!(
+            Err(AccountingError::Account(AccountError::HasOpenOrders(
+                "Alice".to_string()
+            ))),
+            trading_platform.close_account("Alice", Some("Bob"))
+        );
+
+        trading_platform.cancel_all("Alice", None);
+        assert!(trading_platform.close_account("Alice", Some("Bob")).is_ok());
+        assert_eq!(Ok(&Money::from(100)), trading_platform.balance_of("Bob"));
+        assert!(matches!(
+            trading_platform.tx_log.records().last(),
+            Some(Tx::AccountClosed { account, .. }) if account == "Alice"
+        ));
+    }
+
+    #[test]
+    fn account_status_frozen_and_withdraw_only() {
+        let mut trading_platform = TradingPlatform::new();
+        let alice = "Alice";
+
+        assert!(trading_platform.deposit(alice, 100.into()).is_ok());
+        assert!(trading_platform.deposit("Bob", 100.into()).is_ok());
+        assert!(trading_platform
+            .deposit_units(alice, DEFAULT_SYMBOL, 10.into())
+            .is_ok());
+        trading_platform
+            .process_order(Order::new(10, 1, Side::Buy, alice.to_string()))
+            .unwrap();
+        trading_platform
+            .process_order(Order::new(50, 1, Side::Sell, alice.to_string()))
+            .unwrap();
+
+        // Freezing cancels all resting orders, and nothing can leave the account anymore.
+        let txs = trading_platform
+            .set_account_status(alice, AccountStatus::Frozen)
+            .unwrap();
+        assert!(matches!(
+            &txs[..],
+            [Tx::AccountStatusChanged { account, status: AccountStatus::Frozen, .. }] if account == alice
+        ));
+        assert!(!trading_platform.has_open_orders(alice));
+        let frozen = Err(AccountingError::Account(AccountError::Frozen(
+            alice.to_string(),
+        )));
+        assert_eq!(frozen, trading_platform.withdraw(alice, 1.into()));
+        assert_eq!(
+            frozen,
+            trading_platform.withdraw_units(alice, DEFAULT_SYMBOL, 1.into())
+        );
+        assert_eq!(frozen, trading_platform.send(alice, "Bob", 1.into(), None));
+        assert_eq!(
+            Err(AccountingError::Account(AccountError::Frozen(
+                alice.to_string()
+            ))),
+            trading_platform.process_order(Order::new(50, 1, Side::Sell, alice.to_string()))
+        );
+        assert_eq!(
+            Err(AccountingError::Account(AccountError::Frozen(
+                alice.to_string()
+            ))),
+            trading_platform.close_account(alice, Some("Bob"))
+        );
+        // It can still receive.
+        assert!(trading_platform.send("Bob", alice, 1.into(), None).is_ok());
+        assert_eq!(
+            AccountStatus::Frozen,
+            trading_platform.accounts.summary_of(alice).unwrap().status
+        );
+
+        // A withdraw-only account can reduce its exposure, but it can't add to it.
+        assert!(trading_platform
+            .set_account_status(alice, AccountStatus::WithdrawOnly)
+            .is_ok());
+        let withdraw_only = Err(AccountingError::Account(AccountError::WithdrawOnly(
+            alice.to_string(),
+        )));
+        assert_eq!(withdraw_only, trading_platform.deposit(alice, 1.into()));
+        assert_eq!(
+            withdraw_only,
+            trading_platform.send("Bob", alice, 1.into(), None)
+        );
+        assert_eq!(
+            Err(AccountingError::Account(AccountError::WithdrawOnly(
+                alice.to_string()
+            ))),
+            trading_platform.process_order(Order::new(10, 1, Side::Buy, alice.to_string()))
+        );
+        assert!(trading_platform
+            .process_order(Order::new(50, 1, Side::Sell, alice.to_string()))
+            .is_ok());
+        assert!(trading_platform.withdraw(alice, 1.into()).is_ok());
+
+        assert!(trading_platform
+            .set_account_status(alice, AccountStatus::Active)
+            .is_ok());
+        assert!(trading_platform.deposit(alice, 1.into()).is_ok());
+
+        let reconciliation = trading_platform.reconcile();
+        assert!(reconciliation.is_consistent(), "{}", reconciliation);
+    }
+
+    #[test]
+    fn account_status_closed_until_reopened() {
+        let mut trading_platform = TradingPlatform::new();
+
+        assert!(trading_platform.deposit("Alice", 0.into()).is_ok());
+        assert!(matches!(
+            &trading_platform
+                .set_account_status("Alice", AccountStatus::Closed)
+                .unwrap()[..],
+            [Tx::AccountClosed { .. }]
+        ));
+        assert_eq!(
+            Err(AccountingError::Account(AccountError::Closed(
+                "Alice".to_string()
+            ))),
+            trading_platform.deposit("Alice", 1.into())
+        );
+        assert_eq!(
+            Err(AccountingError::Account(AccountError::Closed(
+                "Alice".to_string()
+            ))),
+            trading_platform.set_account_status("Alice", AccountStatus::Frozen)
+        );
+        assert_eq!(
+            Err(AccountingError::Account(AccountError::NotFound(
+                "Nobody".to_string()
+            ))),
+            trading_platform.set_account_status("Nobody", AccountStatus::Frozen)
+        );
+
+        assert!(trading_platform
+            .set_account_status("Alice", AccountStatus::Active)
+            .is_ok());
+        assert!(trading_platform.deposit("Alice", 1.into()).is_ok());
+
+        let reconciliation = trading_platform.reconcile();
+        assert!(reconciliation.is_consistent(), "{}", reconciliation);
+    }
+
+    #[test]
+    fn limits_outlive_a_reset_and_dont_affect_the_replay() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut trading_platform = TradingPlatform::new()
+            .with_clock(clock.clone())
+            .with_limits(LimitSchedule::new("50:80:".parse().unwrap()));
+
+        assert!(trading_platform.deposit("Alice", 200.into()).is_ok());
+        assert!(trading_platform.deposit("Bob", 200.into()).is_ok());
+        assert_eq!(
+            Err(AccountingError::Limit(LimitError::Exceeded(
+                "Alice".to_string(),
+                Limit::PerTransaction
+            ))),
+            trading_platform.withdraw("Alice", 51.into())
+        );
+        assert!(trading_platform.withdraw("Alice", 50.into()).is_ok());
+        assert_eq!(
+            Err(AccountingError::Limit(LimitError::Exceeded(
+                "Alice".to_string(),
+                Limit::Daily
+            ))),
+            trading_platform.send("Alice", "Bob", 31.into(), None)
+        );
+        assert!(trading_platform
+            .send("Alice", "Bob", 30.into(), None)
+            .is_ok());
+        assert_eq!(
+            Some(Money::ZERO),
+            trading_platform.allowance_of("Alice").daily_remaining
+        );
+        assert_eq!(1_000, trading_platform.tx_log.records()[0].timestamp());
+
+        let reconciliation = trading_platform.reconcile();
+        assert!(reconciliation.is_consistent(), "{}", reconciliation);
+
+        trading_platform.reset().unwrap();
+        clock.advance(1_000);
+        assert!(trading_platform.deposit("Alice", 200.into()).is_ok());
+        assert_eq!(
+            Err(AccountingError::Limit(LimitError::Exceeded(
+                "Alice".to_string(),
+                Limit::PerTransaction
+            ))),
+            trading_platform.withdraw("Alice", 51.into())
+        );
+        assert_eq!(2_000, trading_platform.tx_log.records()[0].timestamp());
+    }
+
+    #[test]
+    fn failed_closing_leaves_the_accounts_and_the_log_unchanged() {
+        let mut trading_platform = TradingPlatform::new();
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
+        assert!(trading_platform
+            .deposit_in("Alice", "EUR", 50.into())
+            .is_ok());
+        assert!(trading_platform
+            .deposit_units("Alice", DEFAULT_SYMBOL, 5.into())
+            .is_ok());
+        assert!(trading_platform
+            .deposit_units("Bob", DEFAULT_SYMBOL, Quantity::MAX)
+            .is_ok());
+        let summaries = trading_platform.accounts.summaries();
+        let logged = trading_platform.tx_log.len();
+
+        // The units are swept last, and the recipient can't take them.
+        assert_eq!(
+            Err(AccountingError::Account(AccountError::HoldingsOverflow(
+                "Bob".to_string(),
+                DEFAULT_SYMBOL.to_string(),
+                5.into()
+            ))),
+            trading_platform.close_account("Alice", Some("Bob"))
+        );
+        assert_eq!(summaries, trading_platform.accounts.summaries());
+        assert_eq!(logged, trading_platform.tx_log.len());
+        assert_eq!(
+            AccountStatus::Active,
+            trading_platform.accounts.status_of("Alice")
+        );
+
+        let reconciliation = trading_platform.reconcile();
+        assert!(reconciliation.is_consistent(), "{}", reconciliation);
+    }
+
+    #[test]
+    fn closing_sweeps_every_currency_regardless_of_the_limits() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut trading_platform = TradingPlatform::new()
+            .with_clock(clock.clone())
+            .with_limits(LimitSchedule::new("::1/60".parse().unwrap()));
+
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
+        assert!(trading_platform
+            .deposit_in("Alice", "EUR", 50.into())
+            .is_ok());
+        assert!(trading_platform.deposit("Bob", 1.into()).is_ok());
+        assert!(trading_platform.withdraw("Alice", 10.into()).is_ok());
+        assert_eq!(
+            Err(AccountingError::Limit(LimitError::Exceeded(
+                "Alice".to_string(),
+                Limit::Velocity
+            ))),
+            trading_platform.send("Alice", "Bob", 1.into(), None)
+        );
+
+        let txs = trading_platform
+            .close_account("Alice", Some("Bob"))
+            .unwrap();
+        assert_eq!(
+            vec![TxKind::Transfer, TxKind::Transfer, TxKind::AccountClosed],
+            txs.iter().map(Tx::kind).collect::<Vec<_>>()
+        );
+        assert_eq!(Ok(&Money::from(91)), trading_platform.balance_of("Bob"));
+        assert_eq!(
+            Ok(Money::from(50)),
+            trading_platform.accounts.balance_in("Bob", "EUR")
+        );
+        // The sweep doesn't count towards the limits, either.
+        assert_eq!(
+            Some(1),
+            trading_platform.allowance_of("Bob").velocity_remaining
+        );
+
+        let reconciliation = trading_platform.reconcile();
+        assert!(reconciliation.is_consistent(), "{}", reconciliation);
+    }
+
+    #[test]
+    fn margin_buy_borrows_within_the_initial_margin() {
+        let mut trading_platform = TradingPlatform::new();
+        assert!(trading_platform.deposit("Alice", 500.into()).is_ok());
+        assert_eq!(
+            Err(AccountingError::Margin(MarginError::Disabled)),
+            trading_platform.open_margin_account("Alice")
+        );
+
+        let mut trading_platform =
+            trading_platform.with_margin(MarginConfig::new(5000, 2500).unwrap());
+        assert!(trading_platform
+            .deposit_units("Bob", DEFAULT_SYMBOL, 150.into())
+            .is_ok());
+        trading_platform
+            .process_order(Order::new(10, 150, Side::Sell, String::from("Bob")))
+            .unwrap();
+
+        // Without a margin account, the buyer can only spend their own funds.
+        assert_eq!(
+            Err(AccountingError::Order(OrderError::UnderFunded(
+                "Alice".to_string(),
+                1000.into()
+            ))),
+            trading_platform.process_order(Order::new(10, 100, Side::Buy, String::from("Alice")))
+        );
+        assert!(trading_platform.open_margin_account("Alice").is_ok());
+
+        // 500 of equity covers the initial margin of 50% of 1000, but not of 1010.
+        assert_eq!(
+            Err(AccountingError::Margin(MarginError::Insufficient(
+                "Alice".to_string(),
+                505.into()
+            ))),
+            trading_platform.process_order(Order::new(10, 101, Side::Buy, String::from("Alice")))
+        );
+        trading_platform
+            .process_order(Order::new(10, 100, Side::Buy, String::from("Alice")))
+            .unwrap();
+        assert!(matches!(
+            trading_platform.tx_log.records().as_slice(),
+            [.., Tx::MarginLoan { account, amount, .. }, Tx::TradeSettlement { .. }]
+                if account == "Alice" && *amount == 500.into()
+        ));
+
+        let summary = trading_platform.margin_of("Alice").unwrap();
+        assert_eq!(Money::ZERO, summary.balance);
+        assert_eq!(Money::from(500), summary.loan);
+        assert_eq!(Some(Price::from(10)), summary.mark_price);
+        assert_eq!(Money::from(1000), summary.position_value);
+        assert_eq!(Money::from(500), summary.equity);
+
+        // Nothing can leave the account while it only just covers the initial margin.
+        assert_eq!(
+            Err(AccountingError::Margin(MarginError::Insufficient(
+                "Alice".to_string(),
+                500.into()
+            ))),
+            trading_platform.withdraw_units("Alice", DEFAULT_SYMBOL, 1.into())
+        );
+        assert_eq!(
+            Err(AccountingError::Margin(MarginError::LoanOutstanding(
+                "Alice".to_string()
+            ))),
+            trading_platform.close_account("Alice", Some("Bob"))
+        );
+
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
+        assert_eq!(
+            Err(AccountingError::Margin(MarginError::LoanExceeded(
+                "Alice".to_string(),
+                501.into()
+            ))),
+            trading_platform.repay_loan("Alice", 501.into())
+        );
+        assert!(trading_platform.repay_loan("Alice", 100.into()).is_ok());
+        assert_eq!(Money::from(400), trading_platform.accounts.loan_of("Alice"));
+
+        let reconciliation = trading_platform.reconcile();
+        assert!(reconciliation.is_consistent(), "{}", reconciliation);
+        assert!(trading_platform
+            .accounts
+            .journal()
+            .trial_balance()
+            .is_balanced());
+    }
+
+    #[test]
+    fn margin_call_liquidates_below_maintenance() {
+        let mut trading_platform =
+            TradingPlatform::new().with_margin(MarginConfig::new(5000, 2500).unwrap());
+        assert!(trading_platform.deposit("Alice", 500.into()).is_ok());
+        assert!(trading_platform.deposit("Eve", 1000.into()).is_ok());
+        for signer in ["Bob", "Dave"] {
+            assert!(trading_platform
+                .deposit_units(signer, DEFAULT_SYMBOL, 100.into())
+                .is_ok());
+        }
+        assert!(trading_platform.open_margin_account("Alice").is_ok());
+
+        trading_platform
+            .process_order(Order::new(10, 100, Side::Sell, String::from("Bob")))
+            .unwrap();
+        trading_platform
+            .process_order(Order::new(10, 100, Side::Buy, String::from("Alice")))
+            .unwrap();
+        // A resting sell order of the called account is cancelled, so that its units can be sold.
+        trading_platform
+            .process_order(Order::new(20, 10, Side::Sell, String::from("Alice")))
+            .unwrap();
+        trading_platform
+            .process_order(Order::new(6, 100, Side::Buy, String::from("Eve")))
+            .unwrap();
+        assert!(!trading_platform.margin_called.contains("Alice"));
+
+        // At 6, the equity of 600 - 500 is below the maintenance margin of 25% of 600.
+        trading_platform
+            .process_order(Order::new(6, 1, Side::Sell, String::from("Dave")))
+            .unwrap();
+
+        let tx_log = trading_platform.tx_log.records();
+        let call = tx_log
+            .iter()
+            .position(|tx| {
+                matches!(tx, Tx::MarginCall { account, equity, requirement, .. }
+                if account == "Alice" && *equity == 100.into() && *requirement == 150.into())
+            })
+            .unwrap();
+        assert!(matches!(
+            &tx_log[call + 1],
+            Tx::Liquidation { account, units, .. } if account == "Alice" && *units == 100.into()
+        ));
+        assert!(matches!(
+            tx_log.last(),
+            Some(Tx::MarginRepayment { account, amount, .. })
+                if account == "Alice" && *amount == 500.into()
+        ));
+
+        // The bids took 99 of the units, and the rest isn't offered anymore.
+        let summary = trading_platform.accounts.summary_of("Alice").unwrap();
+        assert_eq!(Money::from(94), summary.balance);
+        assert_eq!(Money::ZERO, summary.loan);
+        assert_eq!(
+            Some(&Quantity::from(1)),
+            summary.holdings.get(DEFAULT_SYMBOL)
+        );
+        assert!(!trading_platform.has_open_orders("Alice"));
+        assert!(!trading_platform.margin_called.contains("Alice"));
+
+        let reconciliation = trading_platform.reconcile();
+        assert!(reconciliation.is_consistent(), "{}", reconciliation);
+    }
+
+    #[test]
+    fn margin_call_liquidates_a_frozen_account() {
+        let mut trading_platform =
+            TradingPlatform::new().with_margin(MarginConfig::new(5000, 2500).unwrap());
+        assert!(trading_platform.deposit("Alice", 500.into()).is_ok());
+        assert!(trading_platform.deposit("Eve", 1000.into()).is_ok());
+        for signer in ["Bob", "Dave"] {
+            assert!(trading_platform
+                .deposit_units(signer, DEFAULT_SYMBOL, 100.into())
+                .is_ok());
+        }
+        assert!(trading_platform.open_margin_account("Alice").is_ok());
+
+        trading_platform
+            .process_order(Order::new(10, 100, Side::Sell, String::from("Bob")))
+            .unwrap();
+        trading_platform
+            .process_order(Order::new(10, 100, Side::Buy, String::from("Alice")))
+            .unwrap();
+        trading_platform
+            .process_order(Order::new(6, 100, Side::Buy, String::from("Eve")))
+            .unwrap();
+        assert!(trading_platform
+            .set_account_status("Alice", AccountStatus::Frozen)
+            .is_ok());
+
+        // At 6, Alice is below the maintenance margin, and her units are sold although she's frozen.
+        trading_platform
+            .process_order(Order::new(6, 1, Side::Sell, String::from("Dave")))
+            .unwrap();
+
+        let summary = trading_platform.accounts.summary_of("Alice").unwrap();
+        assert_eq!(AccountStatus::Frozen, summary.status);
+        assert_eq!(Money::from(94), summary.balance);
+        assert_eq!(Money::ZERO, summary.loan);
+        assert_eq!(
+            Some(&Quantity::from(1)),
+            summary.holdings.get(DEFAULT_SYMBOL)
+        );
+
+        let reconciliation = trading_platform.reconcile();
+        assert!(reconciliation.is_consistent(), "{}", reconciliation);
+    }
+
+    #[test]
+    fn positions_follow_the_fills() {
+        let mut trading_platform = TradingPlatform::new();
+        give_units(&mut trading_platform, &["Bob"]);
+        assert!(trading_platform.deposit("Alice", 1000.into()).is_ok());
+        assert!(trading_platform.deposit("Charlie", 1000.into()).is_ok());
+        assert!(trading_platform
+            .positions(None, MarkPrice::LastTrade)
+            .is_empty());
+
+        trading_platform
+            .process_order(Order::new(10, 10, Side::Sell, String::from("Bob")))
+            .unwrap();
+        trading_platform
+            .process_order(Order::new(10, 10, Side::Buy, String::from("Alice")))
+            .unwrap();
+        trading_platform
+            .process_order(Order::new(12, 4, Side::Buy, String::from("Charlie")))
+            .unwrap();
+        trading_platform
+            .process_order(Order::new(12, 4, Side::Sell, String::from("Alice")))
+            .unwrap();
+
+        // Alice sold 4 of her 10 at 12, and Bob sold units that he didn't buy.
+        let alice = trading_platform.position_of("Alice").unwrap();
+        assert_eq!(Side::Buy, alice.side);
+        assert_eq!(Quantity::from(6), alice.units);
+        assert_eq!(Pnl::Profit(8.into()), alice.realized);
+        assert_eq!(
+            vec![
+                PositionSummary {
+                    signer: "Alice".to_string(),
+                    symbol: DEFAULT_SYMBOL.to_string(),
+                    side: Side::Buy,
+                    units: 6.into(),
+                    average_price: Some(10.into()),
+                    mark_price: Some(12.into()),
+                    realized: Pnl::Profit(8.into()),
+                    unrealized: Some(Pnl::Profit(12.into())),
+                },
+                PositionSummary {
+                    signer: "Bob".to_string(),
+                    symbol: DEFAULT_SYMBOL.to_string(),
+                    side: Side::Sell,
+                    units: 10.into(),
+                    average_price: Some(10.into()),
+                    mark_price: Some(12.into()),
+                    realized: Pnl::default(),
+                    unrealized: Some(Pnl::Loss(20.into())),
+                },
+            ],
+            trading_platform
+                .positions(Some("Alice"), MarkPrice::LastTrade)
+                .into_iter()
+                .chain(trading_platform.positions(Some("Bob"), MarkPrice::LastTrade))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            3,
+            trading_platform.positions(None, MarkPrice::LastTrade).len()
+        );
+
+        // The mid price needs both a bid and an ask.
+        assert_eq!(None, trading_platform.mark_price(MarkPrice::Mid));
+        trading_platform
+            .process_order(Order::new(9, 1, Side::Buy, String::from("Charlie")))
+            .unwrap();
+        trading_platform
+            .process_order(Order::new(10, 1, Side::Sell, String::from("Bob")))
+            .unwrap();
+        let alice = &trading_platform.positions(Some("Alice"), MarkPrice::Mid)[0];
+        assert_eq!(Some(Price::new(95, 1)), alice.mark_price);
+        assert_eq!(Some(Pnl::Loss(3.into())), alice.unrealized);
+
+        assert_eq!(3, trading_platform.archive().positions.len());
+        trading_platform.reset().unwrap();
+        assert_eq!(None, trading_platform.position_of("Alice"));
+    }
+
+    #[test]
+    fn reconcile_replays_trades_fees_and_releases() {
+        let mut trading_platform = TradingPlatform::new()
+            .with_fees(FeeSchedule::new(FeeTier::new(-5, 10).unwrap()).with_fee_account("Fees"));
+
+        assert!(trading_platform.deposit("Alice", 1000.into()).is_ok());
+        assert!(trading_platform.deposit("Bob", 2000.into()).is_ok());
+        assert!(trading_platform
+            .deposit_units("Alice", DEFAULT_SYMBOL, 100.into())
+            .is_ok());
+        trading_platform
+            .process_order(Order::new(10, 60, Side::Sell, String::from("Alice")))
+            .unwrap();
+        trading_platform
+            .process_order(Order::new(10, 50, Side::Buy, String::from("Bob")))
+            .unwrap();
+        assert!(trading_platform
+            .send("Bob", "Alice", 5.into(), None)
+            .is_ok());
+        trading_platform.release_uncommitted();
+        trading_platform.release_uncommitted_units();
+
+        let reconciliation = trading_platform.reconcile();
+        assert!(reconciliation.is_consistent(), "{}", reconciliation);
+        assert_eq!(trading_platform.tx_log.len(), reconciliation.transactions);
+
+        // A change that isn't in the log shows up as a divergence.
+        trading_platform
+            .accounts
+            .accounts
+            .insert("Bob".to_string(), 1.into());
+        let reconciliation = trading_platform.reconcile();
+        assert_eq!(
+            vec!["Bob"],
+            reconciliation
+                .divergences
+                .iter()
+                .map(|divergence| divergence.account.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        // So does a record that is missing from the log.
+        give_units(&mut trading_platform, &["Carol"]);
+        assert!(trading_platform.deposit("Carol", 1.into()).is_ok());
+        assert!(trading_platform.reconcile().replay_error.is_some());
+    }
+
+    #[test]
+    fn reconcile_replays_from_the_checkpoint_of_evicted_transactions() {
+        for tx_log in [
+            Box::new(RingBufferSink::new(3)) as Box<dyn HistorySink<Tx>>,
+            Box::new(NoOpSink),
+        ] {
+            let mut trading_platform =
+                TradingPlatform::with_history(tx_log, Box::new(RingBufferSink::new(3)))
+                    .with_currency("EUR")
+                    .with_currency_scales("EUR=4".parse().unwrap())
+                    .with_fees(
+                        FeeSchedule::new(FeeTier::new(-5, 10).unwrap()).with_fee_account("Fees"),
+                    );
+
+            assert!(trading_platform.deposit("Alice", 1000.into()).is_ok());
+            assert!(trading_platform
+                .deposit("Bob", "2000.0001".parse().unwrap())
+                .is_ok());
+            assert!(trading_platform
+                .deposit_units("Alice", DEFAULT_SYMBOL, 100.into())
+                .is_ok());
+            for _ in 0..5 {
+                trading_platform
+                    .process_order(Order::new(10, 6, Side::Sell, String::from("Alice")))
+                    .unwrap();
+                trading_platform
+                    .process_order(Order::new(10, 5, Side::Buy, String::from("Bob")))
+                    .unwrap();
+            }
+            trading_platform.release_uncommitted();
+            trading_platform.release_uncommitted_units();
+            assert!(trading_platform.tx_log.len() <= 3);
+
+            let reconciliation = trading_platform.reconcile();
+            assert!(reconciliation.is_consistent(), "{}", reconciliation);
+
+            trading_platform
+                .accounts
+                .accounts
+                .insert("Bob".to_string(), 1.into());
+            assert!(!trading_platform.reconcile().is_consistent());
+
+            // The checkpoint goes with the reset, along with the accounts.
+            trading_platform.reset().unwrap();
+            assert!(trading_platform.deposit("Carol", 1.into()).is_ok());
+            let reconciliation = trading_platform.reconcile();
+            assert!(reconciliation.is_consistent(), "{}", reconciliation);
+        }
+    }
+
+    #[test]
+    fn reset_clears_everything_but_the_configuration() {
+        let mut trading_platform = TradingPlatform::new()
+            .with_symbol("ACME")
+            .with_fees(FeeSchedule::new(FeeTier::new(0, 10).unwrap()));
+
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
+        assert!(trading_platform
+            .deposit_units("Bob", "ACME", 5.into())
+            .is_ok());
+        trading_platform
+            .process_order(Order::new(10, 2, Side::Buy, String::from("Alice")))
+            .unwrap();
+        trading_platform
+            .process_order(Order::new(10, 1, Side::Sell, String::from("Bob")))
+            .unwrap();
+
+        let archive = trading_platform.archive();
+        assert_eq!("ACME", archive.symbol);
+        assert_eq!(3, archive.accounts.len());
+        assert_eq!(trading_platform.tx_log.records(), archive.tx_log);
+        assert_eq!(trading_platform.order_book(true, false), archive.order_book);
+        assert_eq!(2, archive.receipts.len());
+
+        trading_platform.reset().unwrap();
+
+        assert_eq!(
+            vec![DEFAULT_FEE_ACCOUNT],
+            trading_platform
+                .accounts
+                .summaries()
+                .keys()
+                .collect::<Vec<_>>()
+        );
+        assert!(trading_platform.tx_log.is_empty());
+        assert!(trading_platform.order_book(false, false).is_empty());
+        assert!(trading_platform.order_groups().is_empty());
+        assert!(trading_platform.archive().receipts.is_empty());
+        assert_eq!("ACME", trading_platform.symbol());
+
+        // Ordinals keep counting, so that they are never reused.
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
+        let receipt = trading_platform
+            .process_order(Order::new(10, 1, Side::Buy, String::from("Alice")))
+            .unwrap();
+        assert_eq!(3, receipt.ordinal);
+    }
+
+    #[test]
+    fn take_changes_reports_the_accounts_and_the_book_that_may_have_changed() {
+        let mut trading_platform = TradingPlatform::new();
+        let symbol = trading_platform.symbol().to_string();
+        let accounts = |signers: &[&str]| signers.iter().map(|s| s.to_string()).collect();
+
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
+        assert!(trading_platform
+            .deposit_units("Bob", &symbol, 5.into())
+            .is_ok());
+        assert_eq!(
+            Changes {
+                accounts: accounts(&["Alice", "Bob"]),
+                ..Default::default()
+            },
+            trading_platform.take_changes()
+        );
+        assert!(trading_platform.take_changes().is_empty());
+
+        // A resting order only moves the holds of its signer.
+        trading_platform
+            .process_order(Order::new(10, 2, Side::Buy, String::from("Alice")))
+            .unwrap();
+        assert_eq!(
+            Changes {
+                accounts: accounts(&["Alice"]),
+                order_book: true,
+                ..Default::default()
+            },
+            trading_platform.take_changes()
+        );
+
+        trading_platform
+            .process_order(Order::new(10, 1, Side::Sell, String::from("Bob")))
+            .unwrap();
+        assert_eq!(
+            accounts(&["Alice", "Bob"]),
+            trading_platform.take_changes().accounts
+        );
+
+        trading_platform.reset().unwrap();
+        assert!(trading_platform.take_changes().all);
+    }
+
+    #[test]
+    fn quote_currency_conversions_and_reconcile() {
+        let mut trading_platform = TradingPlatform::new()
+            .with_currency("EUR")
+            .with_fx_rates("USD/EUR=0.9".parse().unwrap());
+
+        assert!(trading_platform
+            .deposit_in("Alice", "USD", 100.into())
+            .is_ok());
+        assert!(trading_platform
+            .deposit_units("Bob", DEFAULT_SYMBOL, 10.into())
+            .is_ok());
+        // Orders are priced in the quote currency, which Alice doesn't have yet.
+        assert_eq!(
+            Err(AccountingError::Order(OrderError::UnderFunded(
+                "Alice".to_string(),
+                20.into()
+            ))),
+            trading_platform
+                .process_order(Order::new(10, 2, Side::Buy, String::from("Alice")))
+                .map(|_| ())
+        );
+        assert!(trading_platform
+            .convert("Alice", "USD", "EUR", 100.into())
+            .is_ok());
+        trading_platform
+            .process_order(Order::new(10, 2, Side::Buy, String::from("Alice")))
+            .unwrap();
+        trading_platform
+            .process_order(Order::new(10, 2, Side::Sell, String::from("Bob")))
+            .unwrap();
+        assert_eq!(Ok(&Money::from(70)), trading_platform.balance_of("Alice"));
+
+        // Frozen accounts can't convert, and there's no rate back.
+        assert!(trading_platform
+            .set_account_status("Bob", AccountStatus::Frozen)
+            .is_ok());
+        assert_eq!(
+            Err(AccountingError::Account(AccountError::Frozen(
+                "Bob".to_string()
+            ))),
+            trading_platform.convert("Bob", "EUR", "USD", 1.into())
+        );
+        assert_eq!(
+            Err(AccountingError::Fx(FxError::RateNotFound(
+                "EUR".to_string(),
+                "USD".to_string()
+            ))),
+            trading_platform.convert("Alice", "EUR", "USD", 1.into())
+        );
+
+        let reconciliation = trading_platform.reconcile();
+        assert!(reconciliation.is_consistent(), "{}", reconciliation);
+
+        trading_platform.reset().unwrap();
+        assert_eq!("EUR", trading_platform.currency());
+        assert!(!trading_platform.fx_rates().is_empty());
+    }
+
+    #[test]
+    fn statement_follows_trades_conversions_and_closed_accounts() {
+        let mut trading_platform = TradingPlatform::new()
+            .with_currency("EUR")
+            .with_fx_rates("USD/EUR=0.9".parse().unwrap());
+
+        assert!(trading_platform.deposit("Alice", 50.into()).is_ok());
+        assert!(trading_platform
+            .deposit_in("Alice", "USD", 100.into())
+            .is_ok());
+        assert!(trading_platform
+            .convert("Alice", "USD", "EUR", 100.into())
+            .is_ok());
+        assert!(trading_platform
+            .deposit_units("Bob", DEFAULT_SYMBOL, 10.into())
+            .is_ok());
+        trading_platform
+            .process_order(Order::new(10, 2, Side::Sell, String::from("Bob")))
+            .unwrap();
+        trading_platform
+            .process_order(Order::new(10, 2, Side::Buy, String::from("Alice")))
+            .unwrap();
+
+        let statement = trading_platform
+            .statement("Alice", &StatementFilter::default())
+            .unwrap();
+        assert_eq!("EUR", statement.currency);
+        assert_eq!(Money::ZERO, statement.opening_balance);
+        assert_eq!(
+            vec![
+                (TxKind::Deposit, Money::from(50)),
+                (TxKind::Deposit, Money::from(50)),
+                (TxKind::Conversion, Money::from(140)),
+                (TxKind::TradeSettlement, Money::from(120)),
+            ],
+            statement
+                .entries
+                .iter()
+                .map(|entry| (entry.tx.kind(), entry.balance))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            trading_platform.balance_of("Alice").cloned(),
+            Ok(statement.closing_balance)
+        );
+
+        let trades = trading_platform
+            .statement("Bob", &"kinds=trade_settlement limit=1".parse().unwrap())
+            .unwrap();
+        assert_eq!(1, trades.entries.len());
+        assert_eq!(Money::from(20), trades.closing_balance);
+        assert_eq!(None, trades.next_cursor);
+
+        assert!(trading_platform.close_account("Bob", Some("Alice")).is_ok());
+        let closed = trading_platform
+            .statement("Bob", &StatementFilter::default())
+            .unwrap();
+        assert_eq!(Money::ZERO, closed.closing_balance);
+        assert_eq!(
+            Some(Money::from(20)),
+            closed.entries.iter().map(|entry| entry.balance).max()
+        );
+        assert_eq!(
+            Err(AccountingError::Account(AccountError::NotFound(
+                "Carol".to_string()
+            ))),
+            trading_platform
+                .statement("Carol", &StatementFilter::default())
+                .map(|_| ())
+        );
+    }
+
+    #[test]
+    fn statement_refuses_ranges_that_the_log_no_longer_retains() {
+        let mut trading_platform = TradingPlatform::with_history(
+            Box::new(RingBufferSink::new(3)),
+            Box::new(RingBufferSink::new(3)),
+        );
+        for amount in [100, 20, 30, 40, 50] {
+            assert!(trading_platform.deposit("Alice", amount.into()).is_ok());
+        }
+
+        for filter in ["", "from_id=2", "after=1", "to_id=1", "since=0"] {
+            assert_eq!(
+                Err(AccountingError::HistoryUnavailable(2)),
+                trading_platform
+                    .statement("Alice", &filter.parse().unwrap())
+                    .map(|_| ()),
+                "{filter}"
+            );
+        }
+
+        let statement = trading_platform
+            .statement("Alice", &"after=2".parse().unwrap())
+            .unwrap();
+        assert_eq!(Money::from(120), statement.opening_balance);
+        assert_eq!(3, statement.entries.len());
+        assert_eq!(Money::from(240), statement.closing_balance);
+        assert!(trading_platform
+            .statement("Alice", &"from_id=3".parse().unwrap())
+            .is_ok());
+    }
+
+    #[test]
+    fn process_order_partially_match_order_updates_accounts_seller_first_1() {
+        let mut trading_platform = TradingPlatform::new();
+
+        // Set up accounts
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
+        assert!(trading_platform.deposit("Bob", 100.into()).is_ok());
+
+        give_units(&mut trading_platform, &["Alice"]);
+
+        let alice_receipt = trading_platform
+            .process_order(Order::new(10, 1, Side::Sell, String::from("Alice")))
+            .unwrap();
+        assert_eq!(1, alice_receipt.ordinal);
+        assert!(alice_receipt.matches.is_empty());
+
+        let bob_receipt = trading_platform
+            .process_order(Order::new(10, 2, Side::Buy, String::from("Bob")))
+            .unwrap();
+        assert_eq!(2, bob_receipt.ordinal);
+        assert_eq!(
+            vec![PartialOrder {
+                price: 10.into(),
+                current_amount: 1.into(),
+                remaining_amount: 0.into(),
+                side: Side::Sell,
+                signer: "Alice".to_string(),
+                ordinal: 1
+            }],
+            bob_receipt.matches,
+        );
+
+        assert_eq!(0, trading_platform.matching_engine.asks.len());
+        assert_eq!(1, trading_platform.matching_engine.bids.len());
+
+        // Check the account balances
+        assert_eq!(Ok(&Money::from(110)), trading_platform.balance_of("Alice"));
+        assert_eq!(Ok(&Money::from(90)), trading_platform.balance_of("Bob"));
+    }
+
+    #[test]
+    fn process_order_partially_match_order_updates_accounts_seller_first_2() {
+        let mut trading_platform = TradingPlatform::new();
+
+        // Set up accounts
+        assert!(trading_platform
+            .accounts
+            .deposit("Alice", 100.into())
+            .is_ok());
+        assert!(trading_platform.accounts.deposit("Bob", 100.into()).is_ok());
+
+        give_units(&mut trading_platform, &["Alice"]);
+
+        let alice_receipt = trading_platform
+            .process_order(Order::new(10, 2, Side::Sell, String::from("Alice")))
+            .unwrap();
+        assert_eq!(1, alice_receipt.ordinal);
+        assert!(alice_receipt.matches.is_empty());
+
+        let bob_receipt = trading_platform
+            .process_order(Order::new(10, 1, Side::Buy, String::from("Bob")))
+            .unwrap();
+        assert_eq!(2, bob_receipt.ordinal);
+        assert_eq!(
+            vec![PartialOrder {
+                price: 10.into(),
+                current_amount: 2.into(),
+                remaining_amount: 1.into(),
+                side: Side::Sell,
+                signer: "Alice".to_string(),
+                ordinal: 1
+            }],
+            bob_receipt.matches,
+        );
+
+        assert_eq!(1, trading_platform.matching_engine.asks.len());
+        assert_eq!(0, trading_platform.matching_engine.bids.len());
+
+        // Check the account balances
+        assert_eq!(
+            Ok(&Money::from(110)),
+            trading_platform.accounts.balance_of("Alice")
+        );
+        assert_eq!(
+            Ok(&Money::from(90)),
+            trading_platform.accounts.balance_of("Bob")
+        );
     }
 
     #[test]
     fn process_order_partially_match_order_updates_accounts_buyer_first_1() {
         let mut trading_platform = TradingPlatform::new();
 
-        // Set up accounts
-        assert!(trading_platform.accounts.deposit("Alice", 100).is_ok());
-        assert!(trading_platform.accounts.deposit("Bob", 100).is_ok());
+        // Set up accounts
+        assert!(trading_platform
+            .accounts
+            .deposit("Alice", 100.into())
+            .is_ok());
+        assert!(trading_platform.accounts.deposit("Bob", 100.into()).is_ok());
+
+        give_units(&mut trading_platform, &["Bob"]);
+
+        let alice_receipt = trading_platform
+            .process_order(Order::new(10, 1, Side::Buy, String::from("Alice")))
+            .unwrap();
+        assert_eq!(1, alice_receipt.ordinal);
+        assert!(alice_receipt.matches.is_empty());
+
+        let bob_receipt = trading_platform
+            .process_order(Order::new(10, 2, Side::Sell, String::from("Bob")))
+            .unwrap();
+        assert_eq!(2, bob_receipt.ordinal);
+        assert_eq!(
+            vec![PartialOrder {
+                price: 10.into(),
+                current_amount: 1.into(),
+                remaining_amount: 0.into(),
+                side: Side::Buy,
+                signer: "Alice".to_string(),
+                ordinal: 1
+            }],
+            bob_receipt.matches,
+        );
+
+        assert_eq!(1, trading_platform.matching_engine.asks.len());
+        assert_eq!(0, trading_platform.matching_engine.bids.len());
+
+        // Check the account balances
+        assert_eq!(
+            Ok(&Money::from(90)),
+            trading_platform.accounts.balance_of("Alice")
+        );
+        assert_eq!(
+            Ok(&Money::from(110)),
+            trading_platform.accounts.balance_of("Bob")
+        );
+    }
+
+    #[test]
+    fn process_order_partially_match_order_updates_accounts_buyer_first_2() {
+        let mut trading_platform = TradingPlatform::new();
+
+        // Set up accounts
+        assert!(trading_platform
+            .accounts
+            .deposit("Alice", 100.into())
+            .is_ok());
+        assert!(trading_platform.accounts.deposit("Bob", 100.into()).is_ok());
+
+        give_units(&mut trading_platform, &["Bob"]);
+
+        let alice_receipt = trading_platform
+            .process_order(Order::new(10, 2, Side::Buy, String::from("Alice")))
+            .unwrap();
+        assert_eq!(1, alice_receipt.ordinal);
+        assert!(alice_receipt.matches.is_empty());
+
+        let bob_receipt = trading_platform
+            .process_order(Order::new(10, 1, Side::Sell, String::from("Bob")))
+            .unwrap();
+        assert_eq!(2, bob_receipt.ordinal);
+        assert_eq!(
+            vec![PartialOrder {
+                price: 10.into(),
+                current_amount: 2.into(),
+                remaining_amount: 1.into(),
+                side: Side::Buy,
+                signer: "Alice".to_string(),
+                ordinal: 1
+            }],
+            bob_receipt.matches,
+        );
+
+        assert_eq!(0, trading_platform.matching_engine.asks.len());
+        assert_eq!(1, trading_platform.matching_engine.bids.len());
+
+        // Check the account balances
+        assert_eq!(
+            Ok(&Money::from(90)),
+            trading_platform.accounts.balance_of("Alice")
+        );
+        assert_eq!(
+            Ok(&Money::from(110)),
+            trading_platform.accounts.balance_of("Bob")
+        );
+    }
+
+    #[test]
+    fn process_order_fully_match_order_updates_accounts_seller_first() {
+        let mut trading_platform = TradingPlatform::new();
+
+        // Set up accounts
+        assert!(trading_platform
+            .accounts
+            .deposit("Alice", 100.into())
+            .is_ok());
+        assert!(trading_platform.accounts.deposit("Bob", 100.into()).is_ok());
+
+        give_units(&mut trading_platform, &["Alice"]);
+
+        let alice_receipt = trading_platform
+            .process_order(Order::new(10, 2, Side::Sell, String::from("Alice")))
+            .unwrap();
+        assert_eq!(1, alice_receipt.ordinal);
+        assert!(alice_receipt.matches.is_empty());
+
+        let bob_receipt = trading_platform
+            .process_order(Order::new(10, 2, Side::Buy, String::from("Bob")))
+            .unwrap();
+        assert_eq!(2, bob_receipt.ordinal);
+        assert_eq!(
+            vec![PartialOrder {
+                price: 10.into(),
+                current_amount: 2.into(),
+                remaining_amount: 0.into(),
+                side: Side::Sell,
+                signer: "Alice".to_string(),
+                ordinal: 1
+            }],
+            bob_receipt.matches,
+        );
+
+        // A fully matched order doesn't remain in the book
+        assert!(trading_platform.matching_engine.asks.is_empty());
+        assert!(trading_platform.matching_engine.bids.is_empty());
+
+        // Check the account balances
+        assert_eq!(
+            Ok(&Money::from(120)),
+            trading_platform.accounts.balance_of("Alice")
+        );
+        assert_eq!(
+            Ok(&Money::from(80)),
+            trading_platform.accounts.balance_of("Bob")
+        );
+    }
+
+    #[test]
+    fn process_order_fully_match_order_updates_accounts_buyer_first() {
+        let mut trading_platform = TradingPlatform::new();
+
+        // Set up accounts
+        assert!(trading_platform
+            .accounts
+            .deposit("Alice", 100.into())
+            .is_ok());
+        assert!(trading_platform.accounts.deposit("Bob", 100.into()).is_ok());
+
+        give_units(&mut trading_platform, &["Bob"]);
+
+        let alice_receipt = trading_platform
+            .process_order(Order::new(10, 2, Side::Buy, String::from("Alice")))
+            .unwrap();
+        assert_eq!(1, alice_receipt.ordinal);
+        assert!(alice_receipt.matches.is_empty());
+
+        let bob_receipt = trading_platform
+            .process_order(Order::new(10, 2, Side::Sell, String::from("Bob")))
+            .unwrap();
+        assert_eq!(2, bob_receipt.ordinal);
+        assert_eq!(
+            vec![PartialOrder {
+                price: 10.into(),
+                current_amount: 2.into(),
+                remaining_amount: 0.into(),
+                side: Side::Buy,
+                signer: "Alice".to_string(),
+                ordinal: 1
+            }],
+            bob_receipt.matches,
+        );
+
+        // A fully matched order doesn't remain in the book
+        assert!(trading_platform.matching_engine.asks.is_empty());
+        assert!(trading_platform.matching_engine.bids.is_empty());
+
+        // Check the account balances
+        assert_eq!(
+            Ok(&Money::from(80)),
+            trading_platform.accounts.balance_of("Alice")
+        );
+        assert_eq!(
+            Ok(&Money::from(120)),
+            trading_platform.accounts.balance_of("Bob")
+        );
+    }
+
+    #[test]
+    fn process_order_fully_match_order_multi_match_updates_accounts_sellers_first() {
+        let mut trading_platform = TradingPlatform::new();
+
+        // Set up accounts
+        assert!(trading_platform
+            .accounts
+            .deposit("Alice", 100.into())
+            .is_ok());
+        assert!(trading_platform.accounts.deposit("Bob", 100.into()).is_ok());
+        assert!(trading_platform
+            .accounts
+            .deposit("Charlie", 100.into())
+            .is_ok());
+
+        give_units(&mut trading_platform, &["Alice", "Charlie"]);
+
+        let alice_receipt = trading_platform
+            .process_order(Order::new(10, 1, Side::Sell, String::from("Alice")))
+            .unwrap();
+        assert_eq!(1, alice_receipt.ordinal);
+        assert!(alice_receipt.matches.is_empty());
+
+        let charlie_receipt = trading_platform
+            .process_order(Order::new(10, 1, Side::Sell, String::from("Charlie")))
+            .unwrap();
+        assert_eq!(2, charlie_receipt.ordinal);
+        assert!(charlie_receipt.matches.is_empty());
+
+        let bob_receipt = trading_platform
+            .process_order(Order::new(10, 2, Side::Buy, String::from("Bob")))
+            .unwrap();
+        assert_eq!(3, bob_receipt.ordinal);
+        assert_eq!(
+            vec![
+                PartialOrder {
+                    price: 10.into(),
+                    current_amount: 1.into(),
+                    remaining_amount: 0.into(),
+                    side: Side::Sell,
+                    signer: "Alice".to_string(),
+                    ordinal: 1
+                },
+                PartialOrder {
+                    price: 10.into(),
+                    current_amount: 1.into(),
+                    remaining_amount: 0.into(),
+                    side: Side::Sell,
+                    signer: "Charlie".to_string(),
+                    ordinal: 2
+                }
+            ],
+            bob_receipt.matches,
+        );
+
+        // A fully matched order doesn't remain in the book
+        assert!(trading_platform.matching_engine.asks.is_empty());
+        assert!(trading_platform.matching_engine.bids.is_empty());
+
+        // Check account balances
+        assert_eq!(
+            Ok(&Money::from(110)),
+            trading_platform.accounts.balance_of("Alice")
+        );
+        assert_eq!(
+            Ok(&Money::from(80)),
+            trading_platform.accounts.balance_of("Bob")
+        );
+        assert_eq!(
+            Ok(&Money::from(110)),
+            trading_platform.accounts.balance_of("Charlie")
+        );
+    }
+
+    #[test]
+    fn process_order_fully_match_order_no_self_match_updates_accounts_sellers_first() {
+        let mut trading_platform = TradingPlatform::new();
+
+        // Set up accounts
+        assert!(trading_platform
+            .accounts
+            .deposit("Alice", 100.into())
+            .is_ok());
+        assert!(trading_platform
+            .accounts
+            .deposit("Charlie", 100.into())
+            .is_ok());
+
+        give_units(&mut trading_platform, &["Alice", "Charlie"]);
+
+        let alice_receipt = trading_platform
+            .process_order(Order::new(10, 1, Side::Sell, String::from("Alice")))
+            .unwrap();
+        assert_eq!(1, alice_receipt.ordinal);
+        assert!(alice_receipt.matches.is_empty());
+
+        let charlie_receipt = trading_platform
+            .process_order(Order::new(10, 1, Side::Sell, String::from("Charlie")))
+            .unwrap();
+        assert_eq!(2, charlie_receipt.ordinal);
+        assert!(charlie_receipt.matches.is_empty());
+
+        let alice_receipt = trading_platform
+            .process_order(Order::new(10, 2, Side::Buy, String::from("Alice")))
+            .unwrap();
+        assert_eq!(3, alice_receipt.ordinal);
+        assert_eq!(
+            vec![PartialOrder {
+                price: 10.into(),
+                current_amount: 1.into(),
+                remaining_amount: 0.into(),
+                side: Side::Sell,
+                signer: "Charlie".to_string(),
+                ordinal: 2
+            }],
+            alice_receipt.matches,
+        );
+
+        // A fully matched order doesn't remain in the book
+        assert_eq!(1, trading_platform.matching_engine.asks.len());
+        assert_eq!(1, trading_platform.matching_engine.bids.len());
+
+        // Check account balances
+        assert_eq!(
+            Ok(&Money::from(90)),
+            trading_platform.accounts.balance_of("Alice")
+        );
+        assert_eq!(
+            Ok(&Money::from(110)),
+            trading_platform.accounts.balance_of("Charlie")
+        );
+    }
+
+    #[test]
+    fn process_order_no_match_doesnt_update_accounts_all_sellers() {
+        let mut trading_platform = TradingPlatform::new();
+
+        // Set up accounts
+        assert!(trading_platform
+            .accounts
+            .deposit("Alice", 100.into())
+            .is_ok());
+        assert!(trading_platform.accounts.deposit("Bob", 100.into()).is_ok());
+
+        give_units(&mut trading_platform, &["Alice", "Bob"]);
+
+        let alice_receipt = trading_platform
+            .process_order(Order::new(10, 2, Side::Sell, String::from("Alice")))
+            .unwrap();
+        assert_eq!(1, alice_receipt.ordinal);
+        assert!(alice_receipt.matches.is_empty());
+
+        let bob_receipt = trading_platform
+            .process_order(Order::new(11, 2, Side::Sell, String::from("Bob")))
+            .unwrap();
+        assert_eq!(2, bob_receipt.ordinal);
+        assert!(bob_receipt.matches.is_empty());
+
+        assert_eq!(2, trading_platform.order_book(false, false).len());
+
+        // Check the account balances
+        assert_eq!(
+            Ok(&Money::from(100)),
+            trading_platform.accounts.balance_of("Alice")
+        );
+        assert_eq!(
+            Ok(&Money::from(100)),
+            trading_platform.accounts.balance_of("Bob")
+        );
+    }
+
+    #[test]
+    fn process_order_no_match_doesnt_update_accounts_all_buyers() {
+        let mut trading_platform = TradingPlatform::new();
+
+        // Set up accounts
+        assert!(trading_platform
+            .accounts
+            .deposit("Alice", 100.into())
+            .is_ok());
+        assert!(trading_platform.accounts.deposit("Bob", 100.into()).is_ok());
+
+        let alice_receipt = trading_platform
+            .process_order(Order::new(10, 2, Side::Buy, String::from("Alice")))
+            .unwrap();
+        assert_eq!(1, alice_receipt.ordinal);
+        assert!(alice_receipt.matches.is_empty());
+
+        let bob_receipt = trading_platform
+            .process_order(Order::new(11, 2, Side::Buy, String::from("Bob")))
+            .unwrap();
+        assert_eq!(2, bob_receipt.ordinal);
+        assert!(bob_receipt.matches.is_empty());
+
+        assert_eq!(2, trading_platform.order_book(false, false).len());
+
+        // Check the account balances
+        assert_eq!(
+            Ok(&Money::from(100)),
+            trading_platform.accounts.balance_of("Alice")
+        );
+        assert_eq!(
+            Ok(&Money::from(100)),
+            trading_platform.accounts.balance_of("Bob")
+        );
+    }
+
+    #[test]
+    fn process_order_no_match_doesnt_update_accounts() {
+        let mut trading_platform = TradingPlatform::new();
+
+        // Set up accounts
+        assert!(trading_platform
+            .accounts
+            .deposit("Alice", 100.into())
+            .is_ok());
+        assert!(trading_platform.accounts.deposit("Bob", 100.into()).is_ok());
+
+        give_units(&mut trading_platform, &["Alice"]);
+
+        let alice_receipt = trading_platform
+            .process_order(Order::new(12, 2, Side::Sell, String::from("Alice")))
+            .unwrap();
+        assert_eq!(1, alice_receipt.ordinal);
+        assert!(alice_receipt.matches.is_empty());
+
+        let bob_receipt = trading_platform
+            .process_order(Order::new(11, 2, Side::Buy, String::from("Bob")))
+            .unwrap();
+        assert_eq!(2, bob_receipt.ordinal);
+        assert!(bob_receipt.matches.is_empty());
+
+        assert_eq!(2, trading_platform.order_book(false, false).len());
+
+        // Check the account balances
+        assert_eq!(
+            Ok(&Money::from(100)),
+            trading_platform.accounts.balance_of("Alice")
+        );
+        assert_eq!(
+            Ok(&Money::from(100)),
+            trading_platform.accounts.balance_of("Bob")
+        );
+    }
+
+    #[test]
+    fn process_order_decimal_price_and_amount_settles_at_the_currency_scale() {
+        let mut trading_platform =
+            TradingPlatform::new().with_instrument(InstrumentSpec::new(2, 1));
+
+        // Set up accounts
+        assert!(trading_platform
+            .accounts
+            .deposit("Alice", 100.into())
+            .is_ok());
+        assert!(trading_platform.accounts.deposit("Bob", 100.into()).is_ok());
+
+        let price: Price = "12.35".parse().unwrap();
+        let amount: Quantity = "1.5".parse().unwrap();
+
+        give_units(&mut trading_platform, &["Alice"]);
+
+        trading_platform
+            .process_order(Order::new(price, amount, Side::Sell, String::from("Alice")))
+            .unwrap();
+        let bob_receipt = trading_platform
+            .process_order(Order::new(price, amount, Side::Buy, String::from("Bob")))
+            .unwrap();
+        assert_eq!(1, bob_receipt.matches.len());
+
+        // 12.35 * 1.5 = 18.525, rounded down to the cent
+        assert_eq!(
+            Ok(&"118.52".parse::<Money>().unwrap()),
+            trading_platform.accounts.balance_of("Alice")
+        );
+        assert_eq!(
+            Ok(&"81.48".parse::<Money>().unwrap()),
+            trading_platform.accounts.balance_of("Bob")
+        );
+    }
+
+    #[test]
+    fn balances_keep_the_currency_scale_over_many_trades() {
+        let mut trading_platform =
+            TradingPlatform::new().with_instrument(InstrumentSpec::new(2, 8));
+        trading_platform.deposit("Bob", 10_000_000.into()).unwrap();
+        trading_platform.deposit("Alice", 5_000_000.into()).unwrap();
+        trading_platform
+            .deposit_units("Alice", DEFAULT_SYMBOL, 1_000.into())
+            .unwrap();
+
+        let price: Price = "100.01".parse().unwrap();
+        let amount: Quantity = "0.12345678".parse().unwrap();
+        for _ in 0..1_000 {
+            trading_platform
+                .process_order(Order::new(price, amount, Side::Sell, "Alice".to_string()))
+                .unwrap();
+            trading_platform
+                .process_order(Order::new(price, amount, Side::Buy, "Bob".to_string()))
+                .unwrap();
+        }
+
+        // Each trade is worth 12.3469425678, which is rounded down to 12.34.
+        let bob = trading_platform.accounts.balance_of("Bob").unwrap();
+        let alice = trading_platform.accounts.balance_of("Alice").unwrap();
+        assert_eq!("9987660".parse::<Money>().unwrap(), *bob);
+        assert_eq!("5012340".parse::<Money>().unwrap(), *alice);
+        assert!(bob.scale() <= 2 && alice.scale() <= 2);
+        assert_eq!(
+            Ok("123.45678".parse().unwrap()),
+            trading_platform.accounts.units_of("Bob", DEFAULT_SYMBOL)
+        );
+        assert!(trading_platform.reconcile().is_consistent());
+    }
+
+    #[test]
+    fn process_order_err_too_many_decimals() {
+        let mut trading_platform =
+            TradingPlatform::new().with_instrument(InstrumentSpec::new(2, 0));
+        assert!(trading_platform
+            .accounts
+            .deposit("Alice", 100.into())
+            .is_ok());
+
+        let order = Order::new(
+            "12.355".parse::<Price>().unwrap(),
+            1,
+            Side::Sell,
+            String::from("Alice"),
+        );
+
+        give_units(&mut trading_platform, &["Alice"]);
+        assert_eq!(
+            Err(AccountingError::Order(OrderError::Invalid(
+                "The price 12.355 has more than 2 decimals.".to_string()
+            ))),
+            trading_platform.process_order(order)
+        );
+
+        let order = Order::new(
+            10,
+            "0.5".parse::<Quantity>().unwrap(),
+            Side::Sell,
+            String::from("Alice"),
+        );
+        assert!(trading_platform.process_order(order).is_err());
+
+        // Nothing was put in the order book
+        assert!(trading_platform.matching_engine.asks.is_empty());
+    }
+
+    /// A seller rests at 10, and a buyer willing to pay up to 12 takes 2 units from them.
+    fn settle_with_price_rule(price_rule: ExecutionPriceRule) -> (Receipt, Money, Money) {
+        let mut trading_platform = TradingPlatform::new().with_price_rule(price_rule);
+
+        assert!(trading_platform
+            .accounts
+            .deposit("Alice", 100.into())
+            .is_ok());
+        assert!(trading_platform.accounts.deposit("Bob", 100.into()).is_ok());
+
+        give_units(&mut trading_platform, &["Alice"]);
+
+        trading_platform
+            .process_order(Order::new(10, 2, Side::Sell, String::from("Alice")))
+            .unwrap();
+        let receipt = trading_platform
+            .process_order(Order::new(12, 2, Side::Buy, String::from("Bob")))
+            .unwrap();
+
+        (
+            receipt,
+            *trading_platform.accounts.balance_of("Alice").unwrap(),
+            *trading_platform.accounts.balance_of("Bob").unwrap(),
+        )
+    }
+
+    #[test]
+    fn process_order_settles_at_maker_price() {
+        let (receipt, alice, bob) = settle_with_price_rule(ExecutionPriceRule::MakerPrice);
+        assert_eq!(Price::from(10), receipt.matches[0].price);
+        assert_eq!(Money::from(120), alice);
+        assert_eq!(Money::from(80), bob);
+    }
+
+    #[test]
+    fn process_order_settles_at_taker_price() {
+        let (receipt, alice, bob) = settle_with_price_rule(ExecutionPriceRule::TakerPrice);
+        assert_eq!(Price::from(12), receipt.matches[0].price);
+        assert_eq!(Money::from(124), alice);
+        assert_eq!(Money::from(76), bob);
+    }
+
+    #[test]
+    fn process_order_settles_at_midpoint() {
+        let (receipt, alice, bob) = settle_with_price_rule(ExecutionPriceRule::Midpoint);
+        assert_eq!(Price::from(11), receipt.matches[0].price);
+        assert_eq!(Money::from(122), alice);
+        assert_eq!(Money::from(78), bob);
+    }
+
+    #[test]
+    fn process_order_settles_at_midpoint_with_extra_decimal() {
+        let mut trading_platform =
+            TradingPlatform::new().with_price_rule(ExecutionPriceRule::Midpoint);
+
+        assert!(trading_platform
+            .accounts
+            .deposit("Alice", 100.into())
+            .is_ok());
+        assert!(trading_platform.accounts.deposit("Bob", 100.into()).is_ok());
+
+        give_units(&mut trading_platform, &["Alice"]);
+
+        // A resting buyer at 11 and an incoming seller at 10 cross at 10.5.
+        trading_platform
+            .process_order(Order::new(11, 3, Side::Buy, String::from("Bob")))
+            .unwrap();
+        let receipt = trading_platform
+            .process_order(Order::new(10, 3, Side::Sell, String::from("Alice")))
+            .unwrap();
+
+        assert_eq!("10.5".parse::<Price>().unwrap(), receipt.matches[0].price);
+        assert_eq!(
+            Ok(&"131.5".parse::<Money>().unwrap()),
+            trading_platform.accounts.balance_of("Alice")
+        );
+        assert_eq!(
+            Ok(&"68.5".parse::<Money>().unwrap()),
+            trading_platform.accounts.balance_of("Bob")
+        );
+    }
+
+    #[test]
+    fn process_order_charges_maker_and_taker_fees() {
+        let mut trading_platform =
+            TradingPlatform::new().with_fees(FeeSchedule::new(FeeTier::new(10, 20).unwrap()));
+
+        assert!(trading_platform
+            .accounts
+            .deposit("Alice", 100.into())
+            .is_ok());
+        assert!(trading_platform
+            .accounts
+            .deposit("Bob", 2000.into())
+            .is_ok());
+
+        give_units(&mut trading_platform, &["Alice"]);
+
+        trading_platform
+            .process_order(Order::new(10, 100, Side::Sell, String::from("Alice")))
+            .unwrap();
+        trading_platform
+            .process_order(Order::new(10, 100, Side::Buy, String::from("Bob")))
+            .unwrap();
+
+        // The match is worth 1000; the maker pays 10 bps, and the taker pays 20 bps.
+        assert_eq!(
+            Ok(&Money::from(1099)),
+            trading_platform.accounts.balance_of("Alice")
+        );
+        assert_eq!(
+            Ok(&Money::from(998)),
+            trading_platform.accounts.balance_of("Bob")
+        );
+        assert_eq!(
+            Ok(&Money::from(3)),
+            trading_platform.accounts.balance_of(DEFAULT_FEE_ACCOUNT)
+        );
+
+        // Both fees refer to the trade, which is recorded just before them.
+        let tx_log = trading_platform.tx_log.records();
+        let [.., trade, taker_fee, maker_fee] = tx_log.as_slice() else {
+            panic!("The trade and its fees aren't recorded.");
+        };
+        assert!(matches!(trade, Tx::TradeSettlement { value, .. } if *value == 1000.into()));
+        assert_eq!(
+            &Tx::Fee {
+                id: trade.id() + 1,
+                timestamp: taker_fee.timestamp(),
+                trade_id: trade.id(),
+                account: "Bob".to_string(),
+                fee_account: DEFAULT_FEE_ACCOUNT.to_string(),
+                amount: 2.into(),
+            },
+            taker_fee
+        );
+        assert_eq!(
+            &Tx::Fee {
+                id: trade.id() + 2,
+                timestamp: maker_fee.timestamp(),
+                trade_id: trade.id(),
+                account: "Alice".to_string(),
+                fee_account: DEFAULT_FEE_ACCOUNT.to_string(),
+                amount: 1.into(),
+            },
+            maker_fee
+        );
+
+        // The journal has the settlement and both fees, and its fees account has collected them.
+        let journal = trading_platform.accounts.journal();
+        let kinds: Vec<EntryKind> = journal.entries().into_iter().map(|e| e.kind).collect();
+        assert_eq!(
+            [
+                EntryKind::Settlement,
+                EntryKind::Settlement,
+                EntryKind::Fee,
+                EntryKind::Fee
+            ],
+            kinds[kinds.len() - 4..]
+        );
+        assert!(journal.trial_balance().is_balanced());
+        assert_eq!(
+            Amount::from(3),
+            journal
+                .balance_of(&LedgerAccount::System(SystemAccount::Fees), &Asset::Cash)
+                .credit
+        );
+    }
+
+    #[test]
+    fn fees_are_rounded_to_the_currency_scale_over_many_fills() {
+        let mut trading_platform = TradingPlatform::new()
+            .with_instrument(InstrumentSpec::new(2, 8))
+            .with_fees(FeeSchedule::new("10:20".parse().unwrap()));
+        trading_platform.deposit("Bob", 10_000_000.into()).unwrap();
+        trading_platform.deposit("Alice", 1.into()).unwrap();
+        trading_platform
+            .deposit_units("Alice", DEFAULT_SYMBOL, 1_000.into())
+            .unwrap();
+
+        let price: Price = "100.01".parse().unwrap();
+        let lot: Quantity = "0.12345678".parse().unwrap();
+        for _ in 0..10 {
+            for _ in 0..100 {
+                trading_platform
+                    .process_order(Order::new(price, lot, Side::Sell, "Alice".to_string()))
+                    .unwrap();
+            }
+            // One buy takes all 100 lots, each worth 12.34, with a taker fee of 0.02
+            // and a maker fee of 0.01, rounded down from 0.02468 and 0.01234.
+            let receipt = trading_platform
+                .process_order(Order::new(
+                    price,
+                    "12.345678".parse::<Quantity>().unwrap(),
+                    Side::Buy,
+                    "Bob".to_string(),
+                ))
+                .unwrap();
+            assert_eq!(100, receipt.matches.len());
+        }
+
+        let balance = |signer: &str| *trading_platform.accounts.balance_of(signer).unwrap();
+        let fee_account = trading_platform.fees().fee_account().to_string();
+        assert_eq!(Money::from(9_987_640), balance("Bob"));
+        assert_eq!(Money::from(12_331), balance("Alice"));
+        assert_eq!(Money::from(30), balance(&fee_account));
+        for signer in ["Bob", "Alice", fee_account.as_str()] {
+            assert!(balance(signer).scale() <= 2, "{signer}");
+        }
+        assert!(trading_platform.reconcile().is_consistent());
+    }
+
+    #[test]
+    fn process_order_pays_maker_rebate_capped_at_taker_fee() {
+        let mut trading_platform = TradingPlatform::new().with_fees(
+            FeeSchedule::new(FeeTier::new(-2, 5).unwrap())
+                .with_signer_tier("Carol", FeeTier::new(0, 0).unwrap()),
+        );
+
+        assert!(trading_platform
+            .accounts
+            .deposit("Alice", 100.into())
+            .is_ok());
+        assert!(trading_platform
+            .accounts
+            .deposit("Bob", 2000.into())
+            .is_ok());
+        assert!(trading_platform
+            .accounts
+            .deposit("Carol", 2000.into())
+            .is_ok());
+
+        give_units(&mut trading_platform, &["Alice"]);
+
+        trading_platform
+            .process_order(Order::new(10, 200, Side::Sell, String::from("Alice")))
+            .unwrap();
+        trading_platform
+            .process_order(Order::new(10, 100, Side::Buy, String::from("Bob")))
+            .unwrap();
+
+        // The taker pays 0.5, and the maker gets a rebate of 0.2 out of it.
+        let money = |s: &str| s.parse::<Money>().unwrap();
+        assert_eq!(
+            Ok(&money("1100.2")),
+            trading_platform.accounts.balance_of("Alice")
+        );
+        assert_eq!(
+            Ok(&money("999.5")),
+            trading_platform.accounts.balance_of("Bob")
+        );
+        assert_eq!(
+            Ok(&money("0.3")),
+            trading_platform.accounts.balance_of(DEFAULT_FEE_ACCOUNT)
+        );
+        assert!(matches!(
+            trading_platform.tx_log.records().last(),
+            Some(Tx::Rebate { account, amount, .. }) if account == "Alice" && *amount == money("0.2")
+        ));
+
+        // Carol trades for free, so there is nothing to pay the rebate out of.
+        trading_platform
+            .process_order(Order::new(10, 100, Side::Buy, String::from("Carol")))
+            .unwrap();
+        assert_eq!(
+            Ok(&money("2100.2")),
+            trading_platform.accounts.balance_of("Alice")
+        );
+        assert_eq!(
+            Ok(&money("1000")),
+            trading_platform.accounts.balance_of("Carol")
+        );
+        assert_eq!(
+            Ok(&money("0.3")),
+            trading_platform.accounts.balance_of(DEFAULT_FEE_ACCOUNT)
+        );
+    }
+
+    #[test]
+    fn process_order_checks_for_balance_including_taker_fee() {
+        let mut trading_platform =
+            TradingPlatform::new().with_fees(FeeSchedule::new(FeeTier::new(10, 20).unwrap()));
+        assert!(trading_platform
+            .accounts
+            .deposit("Bob", 1000.into())
+            .is_ok());
+
+        assert_eq!(
+            Err(AccountingError::Order(OrderError::UnderFunded(
+                "Bob".to_string(),
+                Money::from(1002)
+            ))),
+            trading_platform.process_order(Order::new(10, 100, Side::Buy, String::from("Bob")))
+        );
+    }
+
+    #[test]
+    fn process_order_err_value_overflow() {
+        let mut trading_platform = TradingPlatform::new();
+        assert!(trading_platform.deposit("Alice", 1.into()).is_ok());
+        assert!(trading_platform.deposit("Bob", Money::MAX).is_ok());
+        give_units(&mut trading_platform, &["Alice"]);
+
+        // A single unit fits, but two of them don't.
+        trading_platform
+            .process_order(Order::new(u64::MAX, 1, Side::Sell, String::from("Alice")))
+            .unwrap();
+        let before = snapshot(&trading_platform);
+
+        assert!(matches!(
+            trading_platform.process_order(Order::new(u64::MAX, 2, Side::Buy, String::from("Bob"))),
+            Err(AccountingError::ArithmeticOverflow(_))
+        ));
+        assert_eq!(before, snapshot(&trading_platform));
+    }
+
+    /// Extreme values never panic: orders are either processed, or rejected with an error,
+    /// and the journal stays balanced.
+    #[test]
+    fn process_order_extreme_values_dont_panic() {
+        let extremes = [
+            0,
+            1,
+            2,
+            3,
+            u64::MAX / 3,
+            u64::MAX / 2,
+            u64::MAX / 2 + 1,
+            u64::MAX - 1,
+            u64::MAX,
+        ];
+        let signers = ["Alice", "Bob", "Carol"];
+
+        for price_rule in [
+            ExecutionPriceRule::MakerPrice,
+            ExecutionPriceRule::TakerPrice,
+            ExecutionPriceRule::Midpoint,
+        ] {
+            let mut trading_platform = TradingPlatform::new()
+                .with_price_rule(price_rule)
+                .with_fees(
+                    FeeSchedule::new(FeeTier::new(-2, 5).unwrap()).with_signer_tier(
+                        "Carol",
+                        FeeTier::new(MAX_FEE_BPS as i32, MAX_FEE_BPS).unwrap(),
+                    ),
+                );
+            for signer in signers {
+                assert!(trading_platform
+                    .deposit(signer, Money::from(u64::MAX / 2))
+                    .is_ok());
+                assert!(trading_platform
+                    .deposit_units(signer, DEFAULT_SYMBOL, Quantity::from(u64::MAX / 2))
+                    .is_ok());
+            }
+
+            // A simple pseudo-random sequence, so that the test is repeatable.
+            let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+            let mut next = |bound: usize| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                (seed % bound as u64) as usize
+            };
+
+            // Each round, a maker's order is taken by an order of another signer,
+            // and whatever is left of them is cancelled.
+            for _ in 0..500 {
+                let maker = next(signers.len());
+                let taker = (maker + 1 + next(signers.len() - 1)) % signers.len();
+                let (maker_side, taker_side) = match next(2) {
+                    0 => (Side::Buy, Side::Sell),
+                    _ => (Side::Sell, Side::Buy),
+                };
+
+                for (signer, side) in [(maker, maker_side), (taker, taker_side)] {
+                    let price = extremes[next(extremes.len())];
+                    let amount = extremes[next(extremes.len())];
+                    let order = Order::new(price, amount, side, signers[signer].to_string());
+                    match trading_platform.process_order(order) {
+                        Ok(_)
+                        | Err(AccountingError::ArithmeticOverflow(_))
+                        | Err(AccountingError::Account(AccountError::UnderFunded(..)))
+                        | Err(AccountingError::Order(OrderError::UnderFunded(..)))
+                        | Err(AccountingError::Order(OrderError::HoldingsInsufficient(..)))
+                        | Err(AccountingError::Account(AccountError::OverFunded(..)))
+                        | Err(AccountingError::Account(AccountError::HoldingsInsufficient(..)))
+                        | Err(AccountingError::Account(AccountError::HoldingsOverflow(..))) => {}
+                        Err(err) => panic!("Unexpected error: {:?}", err),
+                    }
+                    assert!(trading_platform
+                        .accounts
+                        .journal()
+                        .trial_balance()
+                        .is_balanced());
+                }
+
+                for signer in signers {
+                    trading_platform.cancel_all(signer, None);
+                }
+            }
+            assert!(trading_platform
+                .tx_log
+                .records()
+                .iter()
+                .any(|tx| matches!(tx, Tx::TradeSettlement { .. })));
+            assert!(trading_platform.reconcile().is_consistent());
+        }
+    }
+
+    /// Everything that a rejected order must leave as it was
+    fn snapshot(
+        trading_platform: &TradingPlatform,
+    ) -> (
+        Vec<PartialOrder>,
+        BTreeMap<String, AccountSummary>,
+        usize,
+        usize,
+        usize,
+    ) {
+        let accounts = &trading_platform.accounts;
+        (
+            trading_platform.order_book(true, false),
+            accounts
+                .accounts
+                .keys()
+                .map(|signer| (signer.clone(), accounts.summary_of(signer).unwrap()))
+                .collect(),
+            trading_platform.tx_log.len(),
+            trading_platform.accounts.journal().entries().len(),
+            trading_platform.matching_engine.history.len(),
+        )
+    }
+
+    #[test]
+    fn process_order_rolls_back_when_a_later_match_fails_to_settle() {
+        let mut trading_platform = TradingPlatform::new();
+
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
+        assert!(trading_platform.deposit("Bob", 100.into()).is_ok());
+        assert!(trading_platform
+            .deposit("Carol", Money::new(u64::MAX, 0))
+            .is_ok());
+
+        give_units(&mut trading_platform, &["Alice", "Carol"]);
+
+        trading_platform
+            .process_order(Order::new(10, 1, Side::Sell, String::from("Alice")))
+            .unwrap();
+        trading_platform
+            .process_order(Order::new(10, 1, Side::Sell, String::from("Carol")))
+            .unwrap();
+        let before = snapshot(&trading_platform);
+
+        // Alice's match could be settled, but Carol can't be paid for hers.
+        assert_eq!(
+            Err(AccountingError::Account(AccountError::OverFunded(
+                "Carol".to_string(),
+                10.into()
+            ))),
+            trading_platform.process_order(Order::new(10, 2, Side::Buy, String::from("Bob")))
+        );
+        assert_eq!(before, snapshot(&trading_platform));
+
+        // Alice's order can still be matched; the rejected order's ordinal isn't reused.
+        let receipt = trading_platform
+            .process_order(Order::new(10, 1, Side::Buy, String::from("Bob")))
+            .unwrap();
+        assert_eq!(4, receipt.ordinal);
+        assert_eq!(Ok(&Money::from(110)), trading_platform.balance_of("Alice"));
+        assert_eq!(Ok(&Money::from(90)), trading_platform.balance_of("Bob"));
+    }
+
+    #[test]
+    fn process_order_rolls_back_when_a_fee_fails_to_be_charged() {
+        let mut trading_platform =
+            TradingPlatform::new().with_fees(FeeSchedule::new(FeeTier::new(10, 20).unwrap()));
+
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
+        assert!(trading_platform.deposit("Bob", 2000.into()).is_ok());
+
+        give_units(&mut trading_platform, &["Alice"]);
 
-        let alice_receipt = trading_platform
-            .process_order(Order::new(10, 1, Side::Buy, String::from("Alice")))
+        trading_platform
+            .process_order(Order::new(10, 100, Side::Sell, String::from("Alice")))
             .unwrap();
-        assert_eq!(1, alice_receipt.ordinal);
-        assert!(alice_receipt.matches.is_empty());
+        trading_platform
+            .accounts
+            .accounts
+            .remove(DEFAULT_FEE_ACCOUNT);
+        let before = snapshot(&trading_platform);
 
-        let bob_receipt = trading_platform
-            .process_order(Order::new(10, 2, Side::Sell, String::from("Bob")))
-            .unwrap();
-        assert_eq!(2, bob_receipt.ordinal);
         assert_eq!(
-            vec![PartialOrder {
-                price: 10,
-                current_amount: 1,
-                remaining_amount: 0,
-                side: Side::Buy,
-                signer: "Alice".to_string(),
-                ordinal: 1
-            }],
-            bob_receipt.matches,
+            Err(AccountingError::Account(AccountError::NotFound(
+                DEFAULT_FEE_ACCOUNT.to_string()
+            ))),
+            trading_platform.process_order(Order::new(10, 100, Side::Buy, String::from("Bob")))
+        );
+        assert_eq!(before, snapshot(&trading_platform));
+        assert_eq!(
+            Quantity::from(100),
+            trading_platform.order_book(false, false)[0].remaining_amount
         );
-
-        assert_eq!(1, trading_platform.matching_engine.asks.len());
-        assert_eq!(0, trading_platform.matching_engine.bids.len());
-
-        // Check the account balances
-        assert_eq!(Ok(&90), trading_platform.accounts.balance_of("Alice"));
-        assert_eq!(Ok(&110), trading_platform.accounts.balance_of("Bob"));
     }
 
     #[test]
-    fn process_order_partially_match_order_updates_accounts_buyer_first_2() {
+    fn place_oco_take_profit_fill_cancels_stop_loss() {
         let mut trading_platform = TradingPlatform::new();
 
-        // Set up accounts
-        assert!(trading_platform.accounts.deposit("Alice", 100).is_ok());
-        assert!(trading_platform.accounts.deposit("Bob", 100).is_ok());
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
+        assert!(trading_platform.deposit("Bob", 100.into()).is_ok());
 
-        let alice_receipt = trading_platform
-            .process_order(Order::new(10, 2, Side::Buy, String::from("Alice")))
+        give_units(&mut trading_platform, &["Alice"]);
+
+        let group = trading_platform
+            .place_oco(
+                Order::new(12, 2, Side::Sell, String::from("Alice")),
+                Order::new(8, 2, Side::Sell, String::from("Alice")),
+            )
             .unwrap();
-        assert_eq!(1, alice_receipt.ordinal);
-        assert!(alice_receipt.matches.is_empty());
+        assert_eq!(1, group.id);
+        assert_eq!(OrderGroupKind::OneCancelsOther, group.kind);
+        assert_eq!(LegStatus::Working, group.legs[0].status);
+        assert_eq!(Some(1), group.legs[0].ordinal);
+        assert_eq!(LegStatus::Armed, group.legs[1].status);
 
-        let bob_receipt = trading_platform
-            .process_order(Order::new(10, 1, Side::Sell, String::from("Bob")))
+        // Only the take-profit is in the order book.
+        assert_eq!(1, trading_platform.order_book(false, false).len());
+
+        // A partial fill of the take-profit is enough to cancel the stop-loss.
+        trading_platform
+            .process_order(Order::new(12, 1, Side::Buy, String::from("Bob")))
             .unwrap();
-        assert_eq!(2, bob_receipt.ordinal);
-        assert_eq!(
-            vec![PartialOrder {
-                price: 10,
-                current_amount: 2,
-                remaining_amount: 1,
-                side: Side::Buy,
-                signer: "Alice".to_string(),
-                ordinal: 1
-            }],
-            bob_receipt.matches,
-        );
 
-        assert_eq!(0, trading_platform.matching_engine.asks.len());
-        assert_eq!(1, trading_platform.matching_engine.bids.len());
+        let group = trading_platform.order_group(1).unwrap();
+        assert_eq!(LegStatus::Working, group.legs[0].status);
+        assert_eq!(Quantity::from(1), group.legs[0].filled);
+        assert_eq!(LegStatus::Cancelled, group.legs[1].status);
+        assert_eq!(OrderGroupStatus::Active, group.status);
 
-        // Check the account balances
-        assert_eq!(Ok(&90), trading_platform.accounts.balance_of("Alice"));
-        assert_eq!(Ok(&110), trading_platform.accounts.balance_of("Bob"));
+        trading_platform
+            .process_order(Order::new(12, 1, Side::Buy, String::from("Bob")))
+            .unwrap();
+
+        let group = trading_platform.order_group(1).unwrap();
+        assert_eq!(LegStatus::Filled, group.legs[0].status);
+        assert_eq!(OrderGroupStatus::Completed, group.status);
+        assert!(trading_platform.order_book(false, false).is_empty());
+
+        assert_eq!(Ok(&Money::from(124)), trading_platform.balance_of("Alice"));
+        assert_eq!(Ok(&Money::from(76)), trading_platform.balance_of("Bob"));
     }
 
     #[test]
-    fn process_order_fully_match_order_updates_accounts_seller_first() {
+    fn place_oco_triggered_stop_loss_fill_cancels_take_profit() {
         let mut trading_platform = TradingPlatform::new();
 
-        // Set up accounts
-        assert!(trading_platform.accounts.deposit("Alice", 100).is_ok());
-        assert!(trading_platform.accounts.deposit("Bob", 100).is_ok());
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
+        assert!(trading_platform.deposit("Bob", 100.into()).is_ok());
+        assert!(trading_platform.deposit("Charlie", 100.into()).is_ok());
 
-        let alice_receipt = trading_platform
-            .process_order(Order::new(10, 2, Side::Sell, String::from("Alice")))
+        give_units(&mut trading_platform, &["Alice", "Charlie"]);
+
+        trading_platform
+            .place_oco(
+                Order::new(12, 1, Side::Sell, String::from("Alice")),
+                Order::new(8, 1, Side::Sell, String::from("Alice")),
+            )
             .unwrap();
-        assert_eq!(1, alice_receipt.ordinal);
-        assert!(alice_receipt.matches.is_empty());
 
-        let bob_receipt = trading_platform
-            .process_order(Order::new(10, 2, Side::Buy, String::from("Bob")))
+        // Bob's bid at 8 doesn't produce a trade, so the stop-loss stays armed.
+        trading_platform
+            .process_order(Order::new(8, 2, Side::Buy, String::from("Bob")))
             .unwrap();
-        assert_eq!(2, bob_receipt.ordinal);
         assert_eq!(
-            vec![PartialOrder {
-                price: 10,
-                current_amount: 2,
-                remaining_amount: 0,
-                side: Side::Sell,
-                signer: "Alice".to_string(),
-                ordinal: 1
-            }],
-            bob_receipt.matches,
+            LegStatus::Armed,
+            trading_platform.order_group(1).unwrap().legs[1].status
         );
 
-        // A fully matched order doesn't remain in the book
-        assert!(trading_platform.matching_engine.asks.is_empty());
-        assert!(trading_platform.matching_engine.bids.is_empty());
+        // A trade at 8 triggers the stop-loss, which then takes the rest of Bob's bid.
+        trading_platform
+            .process_order(Order::new(8, 1, Side::Sell, String::from("Charlie")))
+            .unwrap();
 
-        // Check the account balances
-        assert_eq!(Ok(&120), trading_platform.accounts.balance_of("Alice"));
-        assert_eq!(Ok(&80), trading_platform.accounts.balance_of("Bob"));
+        let group = trading_platform.order_group(1).unwrap();
+        assert_eq!(LegStatus::Cancelled, group.legs[0].status);
+        assert_eq!(LegStatus::Filled, group.legs[1].status);
+        assert_eq!(Some(4), group.legs[1].ordinal);
+        assert_eq!(OrderGroupStatus::Completed, group.status);
+
+        // The take-profit has been removed from the order book.
+        assert!(trading_platform.order_book(false, false).is_empty());
+
+        assert_eq!(Ok(&Money::from(108)), trading_platform.balance_of("Alice"));
+        assert_eq!(Ok(&Money::from(84)), trading_platform.balance_of("Bob"));
+        assert_eq!(
+            Ok(&Money::from(108)),
+            trading_platform.balance_of("Charlie")
+        );
     }
 
     #[test]
-    fn process_order_fully_match_order_updates_accounts_buyer_first() {
+    fn place_oco_stop_loss_spends_the_units_held_for_the_group() {
         let mut trading_platform = TradingPlatform::new();
 
-        // Set up accounts
-        assert!(trading_platform.accounts.deposit("Alice", 100).is_ok());
-        assert!(trading_platform.accounts.deposit("Bob", 100).is_ok());
-
-        let alice_receipt = trading_platform
-            .process_order(Order::new(10, 2, Side::Buy, String::from("Alice")))
-            .unwrap();
-        assert_eq!(1, alice_receipt.ordinal);
-        assert!(alice_receipt.matches.is_empty());
+        assert!(trading_platform.deposit("Bob", 100.into()).is_ok());
+        assert!(trading_platform
+            .deposit_units("Alice", DEFAULT_SYMBOL, 1.into())
+            .is_ok());
+        give_units(&mut trading_platform, &["Charlie"]);
 
-        let bob_receipt = trading_platform
-            .process_order(Order::new(10, 2, Side::Sell, String::from("Bob")))
+        // Only one of the exits can be filled, so Alice's only unit covers both.
+        trading_platform
+            .place_oco(
+                Order::new(12, 1, Side::Sell, String::from("Alice")),
+                Order::new(8, 1, Side::Sell, String::from("Alice")),
+            )
             .unwrap();
-        assert_eq!(2, bob_receipt.ordinal);
         assert_eq!(
-            vec![PartialOrder {
-                price: 10,
-                current_amount: 2,
-                remaining_amount: 0,
-                side: Side::Buy,
-                signer: "Alice".to_string(),
-                ordinal: 1
-            }],
-            bob_receipt.matches,
+            Quantity::from(1),
+            trading_platform
+                .accounts
+                .units_held_of("Alice", DEFAULT_SYMBOL)
         );
 
-        // A fully matched order doesn't remain in the book
-        assert!(trading_platform.matching_engine.asks.is_empty());
-        assert!(trading_platform.matching_engine.bids.is_empty());
+        trading_platform
+            .process_order(Order::new(8, 2, Side::Buy, String::from("Bob")))
+            .unwrap();
+        trading_platform
+            .process_order(Order::new(8, 1, Side::Sell, String::from("Charlie")))
+            .unwrap();
 
-        // Check the account balances
-        assert_eq!(Ok(&80), trading_platform.accounts.balance_of("Alice"));
-        assert_eq!(Ok(&120), trading_platform.accounts.balance_of("Bob"));
+        let group = trading_platform.order_group(1).unwrap();
+        assert_eq!(LegStatus::Filled, group.legs[1].status);
+        assert_eq!(Ok(&Money::from(8)), trading_platform.balance_of("Alice"));
+        assert!(trading_platform
+            .accounts
+            .units_held_of("Alice", DEFAULT_SYMBOL)
+            .is_zero());
     }
 
     #[test]
-    fn process_order_fully_match_order_multi_match_updates_accounts_sellers_first() {
+    fn place_bracket_entry_fill_activates_exits() {
         let mut trading_platform = TradingPlatform::new();
 
-        // Set up accounts
-        assert!(trading_platform.accounts.deposit("Alice", 100).is_ok());
-        assert!(trading_platform.accounts.deposit("Bob", 100).is_ok());
-        assert!(trading_platform.accounts.deposit("Charlie", 100).is_ok());
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
+        assert!(trading_platform.deposit("Bob", 100.into()).is_ok());
 
-        let alice_receipt = trading_platform
-            .process_order(Order::new(10, 1, Side::Sell, String::from("Alice")))
-            .unwrap();
-        assert_eq!(1, alice_receipt.ordinal);
-        assert!(alice_receipt.matches.is_empty());
+        give_units(&mut trading_platform, &["Alice", "Bob"]);
 
-        let charlie_receipt = trading_platform
-            .process_order(Order::new(10, 1, Side::Sell, String::from("Charlie")))
+        let group = trading_platform
+            .place_bracket(
+                Order::new(10, 2, Side::Buy, String::from("Alice")),
+                Order::new(12, 2, Side::Sell, String::from("Alice")),
+                Order::new(8, 2, Side::Sell, String::from("Alice")),
+            )
             .unwrap();
-        assert_eq!(2, charlie_receipt.ordinal);
-        assert!(charlie_receipt.matches.is_empty());
+        assert_eq!(OrderGroupKind::Bracket, group.kind);
+        assert_eq!(LegStatus::Working, group.legs[0].status);
+        assert_eq!(LegStatus::Waiting, group.legs[1].status);
+        assert_eq!(LegStatus::Waiting, group.legs[2].status);
 
-        let bob_receipt = trading_platform
-            .process_order(Order::new(10, 2, Side::Buy, String::from("Bob")))
+        // A partial fill of the entry doesn't activate the exits yet.
+        trading_platform
+            .process_order(Order::new(10, 1, Side::Sell, String::from("Bob")))
             .unwrap();
-        assert_eq!(3, bob_receipt.ordinal);
-        assert_eq!(
-            vec![
-                PartialOrder {
-                    price: 10,
-                    current_amount: 1,
-                    remaining_amount: 0,
-                    side: Side::Sell,
-                    signer: "Alice".to_string(),
-                    ordinal: 1
-                },
-                PartialOrder {
-                    price: 10,
-                    current_amount: 1,
-                    remaining_amount: 0,
-                    side: Side::Sell,
-                    signer: "Charlie".to_string(),
-                    ordinal: 2
-                }
-            ],
-            bob_receipt.matches,
-        );
-
-        // A fully matched order doesn't remain in the book
-        assert!(trading_platform.matching_engine.asks.is_empty());
-        assert!(trading_platform.matching_engine.bids.is_empty());
+        let group = trading_platform.order_group(1).unwrap();
+        assert_eq!(LegStatus::Working, group.legs[0].status);
+        assert_eq!(LegStatus::Waiting, group.legs[1].status);
 
-        // Check account balances
-        assert_eq!(Ok(&110), trading_platform.accounts.balance_of("Alice"));
-        assert_eq!(Ok(&80), trading_platform.accounts.balance_of("Bob"));
-        assert_eq!(Ok(&110), trading_platform.accounts.balance_of("Charlie"));
+        trading_platform
+            .process_order(Order::new(10, 1, Side::Sell, String::from("Bob")))
+            .unwrap();
+        let group = trading_platform.order_group(1).unwrap();
+        assert_eq!(LegStatus::Filled, group.legs[0].status);
+        assert_eq!(LegStatus::Working, group.legs[1].status);
+        assert_eq!(Some(4), group.legs[1].ordinal);
+        assert_eq!(LegStatus::Armed, group.legs[2].status);
+        assert_eq!(OrderGroupStatus::Active, group.status);
+
+        // The take-profit is the only order in the book.
+        let book = trading_platform.order_book(false, false);
+        assert_eq!(1, book.len());
+        assert_eq!(4, book[0].ordinal);
+        assert_eq!(Price::from(12), book[0].price);
     }
 
     #[test]
-    fn process_order_fully_match_order_no_self_match_updates_accounts_sellers_first() {
+    fn cancel_order_group_removes_working_legs() {
         let mut trading_platform = TradingPlatform::new();
 
-        // Set up accounts
-        assert!(trading_platform.accounts.deposit("Alice", 100).is_ok());
-        assert!(trading_platform.accounts.deposit("Charlie", 100).is_ok());
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
 
-        let alice_receipt = trading_platform
-            .process_order(Order::new(10, 1, Side::Sell, String::from("Alice")))
-            .unwrap();
-        assert_eq!(1, alice_receipt.ordinal);
-        assert!(alice_receipt.matches.is_empty());
+        give_units(&mut trading_platform, &["Alice"]);
 
-        let charlie_receipt = trading_platform
-            .process_order(Order::new(10, 1, Side::Sell, String::from("Charlie")))
+        trading_platform
+            .place_bracket(
+                Order::new(10, 2, Side::Buy, String::from("Alice")),
+                Order::new(12, 2, Side::Sell, String::from("Alice")),
+                Order::new(8, 2, Side::Sell, String::from("Alice")),
+            )
             .unwrap();
-        assert_eq!(2, charlie_receipt.ordinal);
-        assert!(charlie_receipt.matches.is_empty());
+        assert_eq!(1, trading_platform.order_book(false, false).len());
+
+        let group = trading_platform.cancel_order_group(1).unwrap();
+        assert_eq!(OrderGroupStatus::Cancelled, group.status);
+        assert!(group
+            .legs
+            .iter()
+            .all(|leg| leg.status == LegStatus::Cancelled));
+        assert!(trading_platform.order_book(false, false).is_empty());
 
-        let alice_receipt = trading_platform
-            .process_order(Order::new(10, 2, Side::Buy, String::from("Alice")))
-            .unwrap();
-        assert_eq!(3, alice_receipt.ordinal);
         assert_eq!(
-            vec![PartialOrder {
-                price: 10,
-                current_amount: 1,
-                remaining_amount: 0,
-                side: Side::Sell,
-                signer: "Charlie".to_string(),
-                ordinal: 2
-            }],
-            alice_receipt.matches,
+            AccountingError::Order(OrderError::GroupNotFound(2)),
+            trading_platform.cancel_order_group(2).unwrap_err()
         );
+    }
 
-        // A fully matched order doesn't remain in the book
-        assert_eq!(1, trading_platform.matching_engine.asks.len());
-        assert_eq!(1, trading_platform.matching_engine.bids.len());
+    #[test]
+    fn place_order_group_invalid_doesnt_touch_order_book() {
+        let mut trading_platform = TradingPlatform::new();
 
-        // Check account balances
-        assert_eq!(Ok(&90), trading_platform.accounts.balance_of("Alice"));
-        assert_eq!(Ok(&110), trading_platform.accounts.balance_of("Charlie"));
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
+
+        give_units(&mut trading_platform, &["Alice"]);
+
+        assert!(matches!(
+            trading_platform.place_oco(
+                Order::new(8, 1, Side::Sell, String::from("Alice")),
+                Order::new(12, 1, Side::Sell, String::from("Alice")),
+            ),
+            Err(AccountingError::Order(OrderError::GroupInvalid(_)))
+        ));
+        assert!(matches!(
+            trading_platform.place_bracket(
+                Order::new(10, 1, Side::Buy, String::from("Alice")),
+                Order::new(12, 1, Side::Sell, String::from("Alice")),
+                Order::new(8, 2, Side::Sell, String::from("Alice")),
+            ),
+            Err(AccountingError::Order(OrderError::GroupInvalid(_)))
+        ));
+        assert_eq!(
+            AccountingError::Order(OrderError::UnderFunded("Alice".to_string(), 110.into())),
+            trading_platform
+                .place_bracket(
+                    Order::new(10, 11, Side::Buy, String::from("Alice")),
+                    Order::new(12, 11, Side::Sell, String::from("Alice")),
+                    Order::new(8, 11, Side::Sell, String::from("Alice")),
+                )
+                .unwrap_err()
+        );
+
+        assert!(trading_platform.order_book(false, false).is_empty());
+        assert!(trading_platform.order_groups().is_empty());
     }
 
     #[test]
-    fn process_order_no_match_doesnt_update_accounts_all_sellers() {
-        let mut trading_platform = TradingPlatform::new();
+    fn release_uncommitted_keeps_funds_of_open_buy_orders() {
+        let fees = FeeSchedule::new(FeeTier::new(0, 10).unwrap());
+        let mut trading_platform = TradingPlatform::new().with_fees(fees);
 
-        // Set up accounts
-        assert!(trading_platform.accounts.deposit("Alice", 100).is_ok());
-        assert!(trading_platform.accounts.deposit("Bob", 100).is_ok());
+        assert!(trading_platform.deposit("Alice", 1000.into()).is_ok());
+        assert!(trading_platform.deposit("Bob", 1000.into()).is_ok());
 
-        let alice_receipt = trading_platform
-            .process_order(Order::new(10, 2, Side::Sell, String::from("Alice")))
-            .unwrap();
-        assert_eq!(1, alice_receipt.ordinal);
-        assert!(alice_receipt.matches.is_empty());
+        give_units(&mut trading_platform, &["Bob"]);
 
-        let bob_receipt = trading_platform
-            .process_order(Order::new(11, 2, Side::Sell, String::from("Bob")))
+        trading_platform
+            .process_order(Order::new(100, 2, Side::Buy, String::from("Alice")))
+            .unwrap();
+        trading_platform
+            .place_oco(
+                Order::new(90, 1, Side::Buy, String::from("Alice")),
+                Order::new(110, 1, Side::Buy, String::from("Alice")),
+            )
+            .unwrap();
+        trading_platform
+            .process_order(Order::new(100, 1, Side::Sell, String::from("Bob")))
             .unwrap();
-        assert_eq!(2, bob_receipt.ordinal);
-        assert!(bob_receipt.matches.is_empty());
 
-        assert_eq!(2, trading_platform.order_book(false, false).len());
+        // The remaining unit at 100, and the armed stop-loss at 110, each with a 0.1% fee;
+        // the take-profit at 90 can't be filled along with the stop-loss, so it isn't counted.
+        let committed = "210.21".parse::<Money>().unwrap();
+        assert_eq!(
+            BTreeMap::from([(String::from("Alice"), committed)]),
+            trading_platform.committed_funds()
+        );
 
-        // Check the account balances
-        assert_eq!(Ok(&100), trading_platform.accounts.balance_of("Alice"));
-        assert_eq!(Ok(&100), trading_platform.accounts.balance_of("Bob"));
+        let released = trading_platform.release_uncommitted();
+        assert_eq!(
+            vec![
+                (String::from("Alice"), "689.79".parse().unwrap()),
+                (String::from("Bob"), "1099.9".parse().unwrap()),
+                (String::from(DEFAULT_FEE_ACCOUNT), "0.1".parse().unwrap()),
+            ],
+            released
+        );
+        assert_eq!(&committed, trading_platform.balance_of("Alice").unwrap());
+        assert!(trading_platform.balance_of("Bob").unwrap().is_zero());
+
+        trading_platform.cancel_all("Alice", None);
+        assert!(trading_platform.committed_funds().is_empty());
+        assert_eq!(
+            vec![(String::from("Alice"), committed)],
+            trading_platform.release_uncommitted()
+        );
     }
 
     #[test]
-    fn process_order_no_match_doesnt_update_accounts_all_buyers() {
+    fn cancel_all_cancels_order_groups_of_removed_legs() {
         let mut trading_platform = TradingPlatform::new();
 
-        // Set up accounts
-        assert!(trading_platform.accounts.deposit("Alice", 100).is_ok());
-        assert!(trading_platform.accounts.deposit("Bob", 100).is_ok());
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
 
-        let alice_receipt = trading_platform
-            .process_order(Order::new(10, 2, Side::Buy, String::from("Alice")))
-            .unwrap();
-        assert_eq!(1, alice_receipt.ordinal);
-        assert!(alice_receipt.matches.is_empty());
+        give_units(&mut trading_platform, &["Alice"]);
 
-        let bob_receipt = trading_platform
-            .process_order(Order::new(11, 2, Side::Buy, String::from("Bob")))
+        trading_platform
+            .process_order(Order::new(20, 1, Side::Sell, String::from("Alice")))
+            .unwrap();
+        let group = trading_platform
+            .place_oco(
+                Order::new(12, 1, Side::Sell, String::from("Alice")),
+                Order::new(8, 1, Side::Sell, String::from("Alice")),
+            )
             .unwrap();
-        assert_eq!(2, bob_receipt.ordinal);
-        assert!(bob_receipt.matches.is_empty());
 
-        assert_eq!(2, trading_platform.order_book(false, false).len());
+        let cancelled = trading_platform.cancel_all("Alice", Some(Side::Sell));
+        assert_eq!(2, cancelled.len());
+        assert!(trading_platform.order_book(false, false).is_empty());
 
-        // Check the account balances
-        assert_eq!(Ok(&100), trading_platform.accounts.balance_of("Alice"));
-        assert_eq!(Ok(&100), trading_platform.accounts.balance_of("Bob"));
+        // The armed stop-loss is cancelled together with its take-profit.
+        let group = trading_platform.order_group(group.id).unwrap();
+        assert_eq!(OrderGroupStatus::Cancelled, group.status);
+        assert_eq!(LegStatus::Cancelled, group.legs[1].status);
     }
 
     #[test]
-    fn process_order_no_match_doesnt_update_accounts() {
-        let mut trading_platform = TradingPlatform::new();
+    fn with_history_ring_buffer_keeps_most_recent() {
+        let mut trading_platform = TradingPlatform::with_history(
+            Box::new(RingBufferSink::new(2)),
+            Box::new(RingBufferSink::new(1)),
+        );
 
-        // Set up accounts
-        assert!(trading_platform.accounts.deposit("Alice", 100).is_ok());
-        assert!(trading_platform.accounts.deposit("Bob", 100).is_ok());
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
+        assert!(trading_platform.deposit("Bob", 100.into()).is_ok());
+        assert!(trading_platform.withdraw("Alice", 10.into()).is_ok());
 
-        let alice_receipt = trading_platform
-            .process_order(Order::new(12, 2, Side::Sell, String::from("Alice")))
+        let tx_log = trading_platform.tx_log.records();
+        assert_eq!(
+            vec![
+                Tx::Deposit {
+                    id: 2,
+                    timestamp: tx_log[0].timestamp(),
+                    account: "Bob".to_string(),
+                    amount: 100.into(),
+                    currency: None,
+                },
+                Tx::Withdraw {
+                    id: 3,
+                    timestamp: tx_log[1].timestamp(),
+                    account: "Alice".to_string(),
+                    amount: 10.into(),
+                    currency: None,
+                },
+            ],
+            tx_log
+        );
+
+        give_units(&mut trading_platform, &["Alice"]);
+
+        trading_platform
+            .process_order(Order::new(10, 1, Side::Sell, String::from("Alice")))
+            .unwrap();
+        trading_platform
+            .process_order(Order::new(10, 1, Side::Buy, String::from("Bob")))
             .unwrap();
-        assert_eq!(1, alice_receipt.ordinal);
-        assert!(alice_receipt.matches.is_empty());
 
-        let bob_receipt = trading_platform
-            .process_order(Order::new(11, 2, Side::Buy, String::from("Bob")))
+        let receipts = trading_platform.matching_engine.history.records();
+        assert_eq!(1, receipts.len());
+        assert_eq!(2, receipts[0].ordinal);
+    }
+
+    #[test]
+    fn with_history_config_appends_to_new_files() {
+        let dir = std::env::temp_dir().join(format!("fintech_history_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut trading_platform =
+            TradingPlatform::with_history_config(&HistoryConfig::AppendFile(dir.clone())).unwrap();
+        assert!(trading_platform.deposit("Alice", 100.into()).is_ok());
+        assert!(trading_platform.deposit("Bob", 100.into()).is_ok());
+        assert!(trading_platform
+            .send("Alice", "Bob", 10.into(), None)
+            .is_ok());
+
+        give_units(&mut trading_platform, &["Alice"]);
+        trading_platform
+            .process_order(Order::new(10, 1, Side::Sell, String::from("Alice")))
             .unwrap();
-        assert_eq!(2, bob_receipt.ordinal);
-        assert!(bob_receipt.matches.is_empty());
 
-        assert_eq!(2, trading_platform.order_book(false, false).len());
+        assert_eq!(3, trading_platform.tx_log.len());
+        assert_eq!(1, trading_platform.matching_engine.history.len());
 
-        // Check the account balances
-        assert_eq!(Ok(&100), trading_platform.accounts.balance_of("Alice"));
-        assert_eq!(Ok(&100), trading_platform.accounts.balance_of("Bob"));
+        // A new platform on the same directory starts new files, and keeps the previous ones.
+        let mut trading_platform =
+            TradingPlatform::with_history_config(&HistoryConfig::AppendFile(dir.clone())).unwrap();
+        assert!(trading_platform.tx_log.records().is_empty());
+        assert!(trading_platform
+            .matching_engine
+            .history
+            .records()
+            .is_empty());
+        let lines = |name: &str| {
+            std::fs::read_to_string(dir.join(name))
+                .unwrap()
+                .lines()
+                .count()
+        };
+        assert_eq!(3, lines("tx_log.1.jsonl"));
+        assert_eq!(1, lines("receipts.1.jsonl"));
+
+        // The new run reconciles with its own transactions only.
+        assert!(trading_platform.deposit("Bob", 50.into()).is_ok());
+        let reconciliation = trading_platform.reconcile();
+        assert!(reconciliation.is_consistent(), "{}", reconciliation);
+
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
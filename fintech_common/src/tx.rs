@@ -1,11 +1,807 @@
+use crate::accounts::AccountStatus;
+use crate::types::{Money, Price, Quantity};
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// **A transaction type**
 ///
 /// Transactions should be able to rebuild a ledger's state
 /// when they are applied in the same sequence to an empty state.
+///
+/// Every transaction has an `id`, which is its sequence number among the transactions
+/// of its [`Accounts`](crate::accounts::Accounts), starting with 1, and a `timestamp`,
+/// in milliseconds since the Unix epoch.
+///
+/// Funds are in the currency of the account's balance, unless a transaction has a `currency`,
+/// which then holds the code of the currency that they are in.
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub enum Tx {
-    Deposit { account: String, amount: u64 },
-    Withdraw { account: String, amount: u64 },
+    Deposit {
+        id: u64,
+        timestamp: u64,
+        account: String,
+        amount: Money,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        currency: Option<String>,
+    },
+    Withdraw {
+        id: u64,
+        timestamp: u64,
+        account: String,
+        amount: Money,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        currency: Option<String>,
+    },
+    /// Units of the instrument with the `symbol` put into the account
+    DepositUnits {
+        id: u64,
+        timestamp: u64,
+        account: String,
+        symbol: String,
+        units: Quantity,
+    },
+    /// Units of the instrument with the `symbol` taken out of the account
+    WithdrawUnits {
+        id: u64,
+        timestamp: u64,
+        account: String,
+        symbol: String,
+        units: Quantity,
+    },
+    /// Funds sent from one account to another, with an optional note from the sender
+    Transfer {
+        id: u64,
+        timestamp: u64,
+        from: String,
+        to: String,
+        amount: Money,
+        memo: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        currency: Option<String>,
+    },
+    /// Units sent from one account to another, with an optional note from the sender
+    TransferUnits {
+        id: u64,
+        timestamp: u64,
+        from: String,
+        to: String,
+        symbol: String,
+        units: Quantity,
+        memo: Option<String>,
+    },
+    /// A match between a resting (maker) order and an incoming (taker) order:
+    /// the `value` goes from the buyer to the seller, and the `units` go the other way.
+    ///
+    /// Its `id` is the trade's id, which its fees refer to.
+    TradeSettlement {
+        id: u64,
+        timestamp: u64,
+        /// The ordinal of the incoming order
+        taker_ordinal: u64,
+        /// The ordinal of the resting order
+        maker_ordinal: u64,
+        buyer: String,
+        seller: String,
+        symbol: String,
+        price: Price,
+        units: Quantity,
+        value: Money,
+    },
+    /// A trading fee for the trade with the `trade_id`, paid by the account to the fee account
+    Fee {
+        id: u64,
+        timestamp: u64,
+        trade_id: u64,
+        account: String,
+        fee_account: String,
+        amount: Money,
+    },
+    /// A maker rebate for the trade with the `trade_id`, paid by the fee account to the account
+    Rebate {
+        id: u64,
+        timestamp: u64,
+        trade_id: u64,
+        account: String,
+        fee_account: String,
+        amount: Money,
+    },
+    /// The account was removed; it was empty, or its funds and units were swept
+    /// to another account by the transactions just before this one.
+    AccountClosed {
+        id: u64,
+        timestamp: u64,
+        account: String,
+    },
+    /// The account's status was set, such as frozen or active again; closures are `AccountClosed`.
+    AccountStatusChanged {
+        id: u64,
+        timestamp: u64,
+        account: String,
+        status: AccountStatus,
+    },
+    /// The account can trade on margin from now on.
+    MarginAccountOpened {
+        id: u64,
+        timestamp: u64,
+        account: String,
+    },
+    /// Funds lent to a margin account, to buy with
+    MarginLoan {
+        id: u64,
+        timestamp: u64,
+        account: String,
+        amount: Money,
+    },
+    /// Funds paid back by a margin account, from its balance
+    MarginRepayment {
+        id: u64,
+        timestamp: u64,
+        account: String,
+        amount: Money,
+    },
+    /// The margin account's `equity` fell below the maintenance margin's `requirement`;
+    /// it doesn't change any balance.
+    MarginCall {
+        id: u64,
+        timestamp: u64,
+        account: String,
+        equity: Money,
+        requirement: Money,
+    },
+    /// The `units` of the called margin account are being sold, by the trades that follow;
+    /// it doesn't change any balance.
+    Liquidation {
+        id: u64,
+        timestamp: u64,
+        account: String,
+        symbol: String,
+        units: Quantity,
+    },
+    /// The `amount` of the account's funds in the `from_currency` exchanged at the `rate`
+    /// for the `converted` amount in the `to_currency`
+    Conversion {
+        id: u64,
+        timestamp: u64,
+        account: String,
+        from_currency: String,
+        to_currency: String,
+        amount: Money,
+        rate: Price,
+        converted: Money,
+    },
+    /// Funds reserved for the account's orders in a market, on their way from the ledger to the market
+    /// through suspense; see [`Leg`].
+    Reservation {
+        id: u64,
+        timestamp: u64,
+        account: String,
+        amount: Money,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        currency: Option<String>,
+        leg: Leg,
+    },
+    /// Units of the instrument with the `symbol` reserved for the account's orders in a market,
+    /// in the same way as a `Reservation`
+    ReservationUnits {
+        id: u64,
+        timestamp: u64,
+        account: String,
+        symbol: String,
+        units: Quantity,
+        leg: Leg,
+    },
+    /// Funds that the account's orders in a market don't need, on their way from the market
+    /// back to the ledger through suspense; see [`Leg`].
+    Release {
+        id: u64,
+        timestamp: u64,
+        account: String,
+        amount: Money,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        currency: Option<String>,
+        leg: Leg,
+    },
+    /// Units of the instrument with the `symbol` that the account's orders in a market don't need,
+    /// released in the same way as a `Release`
+    ReleaseUnits {
+        id: u64,
+        timestamp: u64,
+        account: String,
+        symbol: String,
+        units: Quantity,
+        leg: Leg,
+    },
+}
+
+/// **The side of a reservation or a release that a transaction records**
+///
+/// Funds and units move between the ledger and a market through suspense, so both of them
+/// record a transaction: the one that they leave, and the one that they arrive in.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Leg {
+    /// Out of the account, and into suspense
+    Out,
+    /// Out of suspense, and into the account
+    In,
+}
+
+impl fmt::Display for Leg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Leg::Out => write!(f, "out of"),
+            Leg::In => write!(f, "into"),
+        }
+    }
+}
+
+impl Tx {
+    /// The transaction's sequence number
+    pub fn id(&self) -> u64 {
+        match self {
+            Tx::Deposit { id, .. }
+            | Tx::Withdraw { id, .. }
+            | Tx::DepositUnits { id, .. }
+            | Tx::WithdrawUnits { id, .. }
+            | Tx::Transfer { id, .. }
+            | Tx::TransferUnits { id, .. }
+            | Tx::TradeSettlement { id, .. }
+            | Tx::Fee { id, .. }
+            | Tx::Rebate { id, .. }
+            | Tx::AccountClosed { id, .. }
+            | Tx::AccountStatusChanged { id, .. }
+            | Tx::MarginAccountOpened { id, .. }
+            | Tx::MarginLoan { id, .. }
+            | Tx::MarginRepayment { id, .. }
+            | Tx::MarginCall { id, .. }
+            | Tx::Liquidation { id, .. }
+            | Tx::Conversion { id, .. }
+            | Tx::Reservation { id, .. }
+            | Tx::ReservationUnits { id, .. }
+            | Tx::Release { id, .. }
+            | Tx::ReleaseUnits { id, .. } => *id,
+        }
+    }
+
+    /// When the transaction took place, in milliseconds since the Unix epoch
+    pub fn timestamp(&self) -> u64 {
+        match self {
+            Tx::Deposit { timestamp, .. }
+            | Tx::Withdraw { timestamp, .. }
+            | Tx::DepositUnits { timestamp, .. }
+            | Tx::WithdrawUnits { timestamp, .. }
+            | Tx::Transfer { timestamp, .. }
+            | Tx::TransferUnits { timestamp, .. }
+            | Tx::TradeSettlement { timestamp, .. }
+            | Tx::Fee { timestamp, .. }
+            | Tx::Rebate { timestamp, .. }
+            | Tx::AccountClosed { timestamp, .. }
+            | Tx::AccountStatusChanged { timestamp, .. }
+            | Tx::MarginAccountOpened { timestamp, .. }
+            | Tx::MarginLoan { timestamp, .. }
+            | Tx::MarginRepayment { timestamp, .. }
+            | Tx::MarginCall { timestamp, .. }
+            | Tx::Liquidation { timestamp, .. }
+            | Tx::Conversion { timestamp, .. }
+            | Tx::Reservation { timestamp, .. }
+            | Tx::ReservationUnits { timestamp, .. }
+            | Tx::Release { timestamp, .. }
+            | Tx::ReleaseUnits { timestamp, .. } => *timestamp,
+        }
+    }
+
+    /// The transaction's variant, without its data
+    pub fn kind(&self) -> TxKind {
+        match self {
+            Tx::Deposit { .. } => TxKind::Deposit,
+            Tx::Withdraw { .. } => TxKind::Withdraw,
+            Tx::DepositUnits { .. } => TxKind::DepositUnits,
+            Tx::WithdrawUnits { .. } => TxKind::WithdrawUnits,
+            Tx::Transfer { .. } => TxKind::Transfer,
+            Tx::TransferUnits { .. } => TxKind::TransferUnits,
+            Tx::TradeSettlement { .. } => TxKind::TradeSettlement,
+            Tx::Fee { .. } => TxKind::Fee,
+            Tx::Rebate { .. } => TxKind::Rebate,
+            Tx::AccountClosed { .. } => TxKind::AccountClosed,
+            Tx::AccountStatusChanged { .. } => TxKind::AccountStatusChanged,
+            Tx::MarginAccountOpened { .. } => TxKind::MarginAccountOpened,
+            Tx::MarginLoan { .. } => TxKind::MarginLoan,
+            Tx::MarginRepayment { .. } => TxKind::MarginRepayment,
+            Tx::MarginCall { .. } => TxKind::MarginCall,
+            Tx::Liquidation { .. } => TxKind::Liquidation,
+            Tx::Conversion { .. } => TxKind::Conversion,
+            Tx::Reservation { .. } => TxKind::Reservation,
+            Tx::ReservationUnits { .. } => TxKind::ReservationUnits,
+            Tx::Release { .. } => TxKind::Release,
+            Tx::ReleaseUnits { .. } => TxKind::ReleaseUnits,
+        }
+    }
+
+    /// Whether the account takes part in the transaction, on either side
+    pub fn involves(&self, name: &str) -> bool {
+        self.accounts().contains(&name)
+    }
+
+    /// The accounts that take part in the transaction, on either side
+    pub fn accounts(&self) -> Vec<&str> {
+        match self {
+            Tx::Transfer { from, to, .. } | Tx::TransferUnits { from, to, .. } => vec![from, to],
+            Tx::TradeSettlement { buyer, seller, .. } => vec![buyer, seller],
+            Tx::Fee {
+                account,
+                fee_account,
+                ..
+            }
+            | Tx::Rebate {
+                account,
+                fee_account,
+                ..
+            } => vec![account, fee_account],
+            Tx::Deposit { account, .. }
+            | Tx::Withdraw { account, .. }
+            | Tx::DepositUnits { account, .. }
+            | Tx::WithdrawUnits { account, .. }
+            | Tx::AccountClosed { account, .. }
+            | Tx::AccountStatusChanged { account, .. }
+            | Tx::MarginAccountOpened { account, .. }
+            | Tx::MarginLoan { account, .. }
+            | Tx::MarginRepayment { account, .. }
+            | Tx::MarginCall { account, .. }
+            | Tx::Liquidation { account, .. }
+            | Tx::Conversion { account, .. }
+            | Tx::Reservation { account, .. }
+            | Tx::ReservationUnits { account, .. }
+            | Tx::Release { account, .. }
+            | Tx::ReleaseUnits { account, .. } => vec![account],
+        }
+    }
+}
+
+/// **The variant of a [`Tx`], such as a deposit, which transactions can be filtered by**
+///
+/// Displayed and parsed in snake case, such as `trade_settlement`.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxKind {
+    Deposit,
+    Withdraw,
+    DepositUnits,
+    WithdrawUnits,
+    Transfer,
+    TransferUnits,
+    TradeSettlement,
+    Fee,
+    Rebate,
+    AccountClosed,
+    AccountStatusChanged,
+    MarginAccountOpened,
+    MarginLoan,
+    MarginRepayment,
+    MarginCall,
+    Liquidation,
+    Conversion,
+    Reservation,
+    ReservationUnits,
+    Release,
+    ReleaseUnits,
+}
+
+impl TxKind {
+    /// Every kind, in the order of the variants
+    pub const ALL: [TxKind; 21] = [
+        TxKind::Deposit,
+        TxKind::Withdraw,
+        TxKind::DepositUnits,
+        TxKind::WithdrawUnits,
+        TxKind::Transfer,
+        TxKind::TransferUnits,
+        TxKind::TradeSettlement,
+        TxKind::Fee,
+        TxKind::Rebate,
+        TxKind::AccountClosed,
+        TxKind::AccountStatusChanged,
+        TxKind::MarginAccountOpened,
+        TxKind::MarginLoan,
+        TxKind::MarginRepayment,
+        TxKind::MarginCall,
+        TxKind::Liquidation,
+        TxKind::Conversion,
+        TxKind::Reservation,
+        TxKind::ReservationUnits,
+        TxKind::Release,
+        TxKind::ReleaseUnits,
+    ];
+}
+
+impl fmt::Display for TxKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TxKind::Deposit => "deposit",
+            TxKind::Withdraw => "withdraw",
+            TxKind::DepositUnits => "deposit_units",
+            TxKind::WithdrawUnits => "withdraw_units",
+            TxKind::Transfer => "transfer",
+            TxKind::TransferUnits => "transfer_units",
+            TxKind::TradeSettlement => "trade_settlement",
+            TxKind::Fee => "fee",
+            TxKind::Rebate => "rebate",
+            TxKind::AccountClosed => "account_closed",
+            TxKind::AccountStatusChanged => "account_status_changed",
+            TxKind::MarginAccountOpened => "margin_account_opened",
+            TxKind::MarginLoan => "margin_loan",
+            TxKind::MarginRepayment => "margin_repayment",
+            TxKind::MarginCall => "margin_call",
+            TxKind::Liquidation => "liquidation",
+            TxKind::Conversion => "conversion",
+            TxKind::Reservation => "reservation",
+            TxKind::ReservationUnits => "reservation_units",
+            TxKind::Release => "release",
+            TxKind::ReleaseUnits => "release_units",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for TxKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TxKind::ALL
+            .into_iter()
+            .find(|kind| kind.to_string() == s.trim())
+            .ok_or_else(|| {
+                format!("Invalid transaction kind: \"{s}\"; expected one such as \"deposit\" or \"trade_settlement\"")
+            })
+    }
+}
+
+/// The current time, in milliseconds since the Unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Formats milliseconds since the Unix epoch as a UTC date and time, such as `2024-03-05 14:07:09.250`.
+pub fn format_timestamp(timestamp: u64) -> String {
+    let (days, millis) = (timestamp / 86_400_000, timestamp % 86_400_000);
+
+    // The civil date of a day number, after Howard Hinnant's `civil_from_days`.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}",
+        year,
+        month,
+        day,
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1_000 % 60,
+        millis % 1_000
+    )
+}
+
+impl fmt::Display for Tx {
+    /// A single line, starting with the id and the time of the transaction
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{} {} ", self.id(), format_timestamp(self.timestamp()))?;
+
+        let memo = |memo: &Option<String>| {
+            memo.as_ref()
+                .map(|memo| format!(" \"{}\"", memo))
+                .unwrap_or_default()
+        };
+        let currency = |currency: &Option<String>| {
+            currency
+                .as_ref()
+                .map(|currency| format!(" {}", currency))
+                .unwrap_or_default()
+        };
+        match self {
+            Tx::Deposit {
+                account,
+                amount,
+                currency: code,
+                ..
+            } => write!(f, "Deposit of {}{} to {}", amount, currency(code), account),
+            Tx::Withdraw {
+                account,
+                amount,
+                currency: code,
+                ..
+            } => write!(
+                f,
+                "Withdrawal of {}{} from {}",
+                amount,
+                currency(code),
+                account
+            ),
+            Tx::DepositUnits {
+                account,
+                symbol,
+                units,
+                ..
+            } => write!(f, "Deposit of {} {} to {}", units, symbol, account),
+            Tx::WithdrawUnits {
+                account,
+                symbol,
+                units,
+                ..
+            } => write!(f, "Withdrawal of {} {} from {}", units, symbol, account),
+            Tx::Transfer {
+                from,
+                to,
+                amount,
+                memo: note,
+                currency: code,
+                ..
+            } => write!(
+                f,
+                "Transfer of {}{} from {} to {}{}",
+                amount,
+                currency(code),
+                from,
+                to,
+                memo(note)
+            ),
+            Tx::TransferUnits {
+                from,
+                to,
+                symbol,
+                units,
+                memo: note,
+                ..
+            } => write!(
+                f,
+                "Transfer of {} {} from {} to {}{}",
+                units,
+                symbol,
+                from,
+                to,
+                memo(note)
+            ),
+            Tx::TradeSettlement {
+                taker_ordinal,
+                maker_ordinal,
+                buyer,
+                seller,
+                symbol,
+                price,
+                units,
+                value,
+                ..
+            } => write!(
+                f,
+                "Trade of {} {} at {} for {}, from {} to {} (orders {} and {})",
+                units, symbol, price, value, seller, buyer, maker_ordinal, taker_ordinal
+            ),
+            Tx::Fee {
+                trade_id,
+                account,
+                fee_account,
+                amount,
+                ..
+            } => write!(
+                f,
+                "Fee of {} from {} to {}, for trade #{}",
+                amount, account, fee_account, trade_id
+            ),
+            Tx::Rebate {
+                trade_id,
+                account,
+                fee_account,
+                amount,
+                ..
+            } => write!(
+                f,
+                "Rebate of {} from {} to {}, for trade #{}",
+                amount, fee_account, account, trade_id
+            ),
+            Tx::AccountClosed { account, .. } => write!(f, "Closure of {}", account),
+            Tx::AccountStatusChanged {
+                account, status, ..
+            } => write!(f, "Status of {} set to {}", account, status),
+            Tx::MarginAccountOpened { account, .. } => {
+                write!(f, "Margin account of {} opened", account)
+            }
+            Tx::MarginLoan {
+                account, amount, ..
+            } => write!(f, "Margin loan of {} to {}", amount, account),
+            Tx::MarginRepayment {
+                account, amount, ..
+            } => write!(f, "Margin repayment of {} from {}", amount, account),
+            Tx::MarginCall {
+                account,
+                equity,
+                requirement,
+                ..
+            } => write!(
+                f,
+                "Margin call of {}, with an equity of {} below {}",
+                account, equity, requirement
+            ),
+            Tx::Liquidation {
+                account,
+                symbol,
+                units,
+                ..
+            } => write!(f, "Liquidation of {} {} of {}", units, symbol, account),
+            Tx::Conversion {
+                account,
+                from_currency,
+                to_currency,
+                amount,
+                rate,
+                converted,
+                ..
+            } => write!(
+                f,
+                "Conversion of {} {} to {} {} at {}, for {}",
+                amount, from_currency, converted, to_currency, rate, account
+            ),
+            Tx::Reservation {
+                account,
+                amount,
+                currency: code,
+                leg,
+                ..
+            } => write!(
+                f,
+                "Reservation of {}{} for the orders of {}, {} the account",
+                amount,
+                currency(code),
+                account,
+                leg
+            ),
+            Tx::ReservationUnits {
+                account,
+                symbol,
+                units,
+                leg,
+                ..
+            } => write!(
+                f,
+                "Reservation of {} {} for the orders of {}, {} the account",
+                units, symbol, account, leg
+            ),
+            Tx::Release {
+                account,
+                amount,
+                currency: code,
+                leg,
+                ..
+            } => write!(
+                f,
+                "Release of {}{} from the orders of {}, {} the account",
+                amount,
+                currency(code),
+                account,
+                leg
+            ),
+            Tx::ReleaseUnits {
+                account,
+                symbol,
+                units,
+                leg,
+                ..
+            } => write!(
+                f,
+                "Release of {} {} from the orders of {}, {} the account",
+                units, symbol, account, leg
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_timestamp_utc() {
+        assert_eq!("1970-01-01 00:00:00.000", format_timestamp(0));
+        assert_eq!(
+            "2024-02-29 23:59:59.999",
+            format_timestamp(1_709_251_199_999)
+        );
+        assert_eq!(
+            "2024-03-05 14:07:09.250",
+            format_timestamp(1_709_647_629_250)
+        );
+    }
+
+    #[test]
+    fn display_starts_with_id_and_time() {
+        let tx = Tx::Transfer {
+            id: 7,
+            timestamp: 0,
+            from: "Alice".to_string(),
+            to: "Bob".to_string(),
+            amount: Money::new(1050, 2),
+            memo: Some("Lunch".to_string()),
+            currency: None,
+        };
+        assert_eq!(
+            "#7 1970-01-01 00:00:00.000 Transfer of 10.50 from Alice to Bob \"Lunch\"",
+            tx.to_string()
+        );
+
+        let tx = Tx::Deposit {
+            id: 8,
+            timestamp: 0,
+            account: "Alice".to_string(),
+            amount: 100.into(),
+            currency: Some("EUR".to_string()),
+        };
+        assert_eq!(
+            "#8 1970-01-01 00:00:00.000 Deposit of 100 EUR to Alice",
+            tx.to_string()
+        );
+        let json = serde_json::to_string(&tx).unwrap();
+        assert_eq!(tx, serde_json::from_str(&json).unwrap());
+
+        // Transactions in the balance's currency serialize as they always have.
+        let tx = Tx::Withdraw {
+            id: 9,
+            timestamp: 0,
+            account: "Alice".to_string(),
+            amount: 100.into(),
+            currency: None,
+        };
+        let json = serde_json::to_value(&tx).unwrap();
+        assert_eq!(None, json["Withdraw"].get("currency"));
+
+        let tx = Tx::Reservation {
+            id: 10,
+            timestamp: 0,
+            account: "Alice".to_string(),
+            amount: 20.into(),
+            currency: None,
+            leg: Leg::Out,
+        };
+        assert_eq!(
+            "#10 1970-01-01 00:00:00.000 Reservation of 20 for the orders of Alice, out of the account",
+            tx.to_string()
+        );
+        assert_eq!(
+            serde_json::json!("out"),
+            serde_json::to_value(&tx).unwrap()["Reservation"]["leg"]
+        );
+    }
+
+    #[test]
+    fn kinds_round_trip_and_involve_both_sides() {
+        for kind in TxKind::ALL {
+            assert_eq!(Ok(kind), kind.to_string().parse());
+            assert_eq!(
+                format!("\"{}\"", kind),
+                serde_json::to_string(&kind).unwrap()
+            );
+        }
+        assert!("Deposit".parse::<TxKind>().is_err());
+
+        let tx = Tx::Fee {
+            id: 2,
+            timestamp: 0,
+            trade_id: 1,
+            account: "Alice".to_string(),
+            fee_account: "Fees".to_string(),
+            amount: 1.into(),
+        };
+        assert_eq!(TxKind::Fee, tx.kind());
+        assert!(tx.involves("Alice") && tx.involves("Fees"));
+        assert!(!tx.involves("Bob"));
+    }
 }
@@ -1,9 +1,17 @@
 use crate::DEFAULT_BASE_URL;
+use fintech_common::accounts::{AccountStatus, AccountSummary};
 use fintech_common::cli::constants::*;
 use fintech_common::cli::helpers::*;
+use fintech_common::errors::ErrorResponse;
+use fintech_common::limits::Allowance;
+use fintech_common::order_groups::OrderGroup;
+use fintech_common::positions::{MarkPrice, PositionSummary};
+use fintech_common::reconciliation::Reconciliation;
 use fintech_common::requests::*;
+use fintech_common::statements::{Statement, StatementFilter};
+use fintech_common::trading_platform::PlatformArchive;
 use fintech_common::tx::Tx;
-use fintech_common::types::{Order, PartialOrder, Receipt, Side};
+use fintech_common::types::{Money, Order, PartialOrder, Price, Quantity, Receipt, Side};
 use reqwest::{header, Client, StatusCode, Url};
 use std::collections::BTreeMap;
 use std::error::Error;
@@ -20,7 +28,17 @@ pub async fn main_loop(base_url: Url) -> Result<(), Box<dyn Error>> {
                 HELP | "h" => help(),
                 DEPOSIT | "d" => deposit(words, &client, &base_url).await?,
                 WITHDRAW | "w" => withdraw(words, &client, &base_url).await?,
+                DEPOSIT_UNITS | "du" => deposit_units(words, &client, &base_url).await?,
+                WITHDRAW_UNITS | "wu" => withdraw_units(words, &client, &base_url).await?,
                 SEND | "s" => send(words, &client, &base_url).await?,
+                CONVERT | "cv" => convert(words, &client, &base_url).await?,
+                CLOSE | "cl" => close(words, &client, &base_url).await?,
+                STATUS | "st" => status(words, &client, &base_url).await?,
+                LIMITS | "li" => limits(words, &client, &base_url).await?,
+                // Markets only settle with reserved funds, so they can't lend any.
+                MARGIN | "mg" => println!("Margin trading is only available in the local CLI."),
+                POSITIONS | "ps" => positions(words, &client, &base_url).await?,
+                STATEMENT | "sm" => statement(words, &client, &base_url).await?,
                 PRINT | LEDGER | TX_LOG | "p" | "l" | "t" => {
                     print_ledger(&client, &base_url).await?
                 }
@@ -31,6 +49,14 @@ pub async fn main_loop(base_url: Url) -> Result<(), Box<dyn Error>> {
                 ORDER_BOOK_BY_PRICE | "obp" => {
                     order_book_by_price(words, &client, &base_url).await?
                 }
+                OCO | "oc" => oco(words, &client, &base_url).await?,
+                BRACKET | "br" => bracket(words, &client, &base_url).await?,
+                ORDER_GROUP | "g" => print_order_group(words, &client, &base_url).await?,
+                ORDER_GROUPS | "gs" => print_order_groups(&client, &base_url).await?,
+                CANCEL_ORDER_GROUP | "cg" => cancel_order_group(words, &client, &base_url).await?,
+                CANCEL_ALL | "ca" => cancel_all(words, &client, &base_url).await?,
+                RESET | "r" => reset(words, &client, &base_url).await?,
+                VERIFY | "v" => verify(&client, &base_url).await?,
                 QUIT | "q" => break,
                 _ => println!("Unrecognized command; try `help`."),
             }
@@ -78,19 +104,26 @@ pub fn get_base_url(base_url: Option<String>) -> Url {
 }
 
 /// **Send a POST request for `deposit` and `withdraw`**
+///
+/// The funds are in the ledger's currency if `currency` is `None`.
 async fn account_update_request(
     client: &Client,
     base_url: &Url,
     path: &str,
     signer: &str,
-    amount: u64,
+    amount: Money,
+    currency: Option<String>,
 ) -> Result<(), Box<dyn Error>> {
     let signer = signer.to_string();
     let url = base_url.join(path)?;
 
     let response = client
         .post(url)
-        .json(&AccountUpdateRequest { signer, amount })
+        .json(&AccountUpdateRequest {
+            signer,
+            amount,
+            currency,
+        })
         .send()
         .await?;
 
@@ -98,7 +131,7 @@ async fn account_update_request(
         let tx: Tx = response.json().await?;
         println!("{:?}", tx);
     } else {
-        eprintln!("[ERROR] \"{}\"", response.text().await?);
+        print_error_response(response).await?;
     }
 
     Ok(())
@@ -114,29 +147,33 @@ async fn account_update_request(
 /// If it doesn't exist, it will be created on this occasion.
 /// It is allowed to deposit 0, and this transaction will be recorded.
 ///
+/// An optional currency, such as EUR, can follow the amount;
+/// without it, the funds are in the ledger's currency.
+///
 /// Performs basic input validation of the signer's name,
-/// and of the amount, which should be a non-negative integer.
+/// and of the amount, which should be a non-negative decimal number.
 ///
 /// Prints a success or an error message depending on the status of the
 /// transaction, and records the transaction in the success case.
 ///
 /// # Errors
-/// - Attempted overflow (account over-funded), `AccountingError::AccountOverFunded`
+/// - Attempted overflow (account over-funded), `AccountError::OverFunded`
+///   or `FxError::OverFunded`
 ///
-/// We could pattern-match it for a different output format and the message
-/// contents, but haven't done that here. Error is still printed.
+/// The error is printed with its code and message.
 async fn deposit(words: Vec<&str>, client: &Client, base_url: &Url) -> Result<(), Box<dyn Error>> {
+    let (words, currency) = words_and_optional_currency(&words);
     let words_len = words.len();
 
     if words_len < 3 {
-        println!("The deposit command: {DEPOSIT} 'signer full name' <amount>");
+        println!("The deposit command: {DEPOSIT} 'signer full name' <amount> [currency]");
         return Ok(());
     }
 
     let signer = words[1..(words_len - 1)].join(" ");
     let signer = signer.trim_matches(|c| c == '\'' || c == '\"').trim();
 
-    let amount = match words[words_len - 1].parse::<u64>() {
+    let amount = match words[words_len - 1].parse::<Money>() {
         Ok(amount) => amount,
         Err(_err) => {
             cannot_parse_number(words[words_len - 1]);
@@ -144,8 +181,16 @@ async fn deposit(words: Vec<&str>, client: &Client, base_url: &Url) -> Result<()
         }
     };
 
-    if is_valid_name(&signer) {
-        account_update_request(client, base_url, "account/deposit", signer, amount).await?;
+    if is_valid_name(signer) {
+        account_update_request(
+            client,
+            base_url,
+            "account/deposit",
+            signer,
+            amount,
+            currency,
+        )
+        .await?;
     }
 
     Ok(())
@@ -163,32 +208,155 @@ async fn deposit(words: Vec<&str>, client: &Client, base_url: &Url) -> Result<()
 ///
 /// It is allowed to withdraw 0, and this transaction will be recorded.
 ///
+/// An optional currency can follow the amount, same as in `deposit`.
+///
 /// Performs basic input validation of the signer's name,
-/// and of the amount, which should be a non-negative integer.
+/// and of the amount, which should be a non-negative decimal number.
 ///
 /// Prints a success or an error message depending on the status of the
 /// transaction, and records the transaction in the success case.
 ///
 /// # Errors
-/// - Account doesn't exist, `AccountingError::AccountNotFound`;
-/// - Attempted overflow (account under-funded), `AccountingError::AccountUnderFunded`.
+/// - Account doesn't exist, `AccountError::NotFound`;
+/// - Attempted overflow (account under-funded), `AccountError::UnderFunded`
+///   or `FxError::UnderFunded`.
 ///
-/// We could pattern-match them for a different output format and the message
-/// contents, but haven't done that here. Errors are still printed.
+/// Errors are printed with their codes and messages.
 async fn withdraw(words: Vec<&str>, client: &Client, base_url: &Url) -> Result<(), Box<dyn Error>> {
+    let (words, currency) = words_and_optional_currency(&words);
     let words_len = words.len();
 
     if words_len < 3 {
-        println!("The withdraw command: {WITHDRAW} 'signer full name' <amount>");
+        println!("The withdraw command: {WITHDRAW} 'signer full name' <amount> [currency]");
         return Ok(());
     }
 
     let signer = words[1..(words_len - 1)].join(" ");
     let signer = signer.trim_matches(|c| c == '\'' || c == '\"').trim();
 
-    if let Ok(amount) = words[words_len - 1].parse::<u64>() {
-        if is_valid_name(&signer) {
-            account_update_request(client, base_url, "account/withdraw", signer, amount).await?;
+    if let Ok(amount) = words[words_len - 1].parse::<Money>() {
+        if is_valid_name(signer) {
+            account_update_request(
+                client,
+                base_url,
+                "account/withdraw",
+                signer,
+                amount,
+                currency,
+            )
+            .await?;
+        }
+    } else {
+        cannot_parse_number(words[words_len - 1]);
+    }
+
+    Ok(())
+}
+
+/// **Send a POST request for `deposit_units` and `withdraw_units`**
+///
+/// The units are of the instrument of the default market.
+async fn account_units_update_request(
+    client: &Client,
+    base_url: &Url,
+    path: &str,
+    signer: &str,
+    units: Quantity,
+) -> Result<(), Box<dyn Error>> {
+    let signer = signer.to_string();
+    let url = base_url.join(path)?;
+
+    let response = client
+        .post(url)
+        .json(&AccountUnitsUpdateRequest {
+            signer,
+            units,
+            symbol: None,
+        })
+        .send()
+        .await?;
+
+    if response.status().is_success() {
+        let tx: Tx = response.json().await?;
+        println!("{:?}", tx);
+    } else {
+        print_error_response(response).await?;
+    }
+
+    Ok(())
+}
+
+/// **Deposit units of the traded instrument to an account**
+///
+/// The signer's name can consist of multiple words, same as in `deposit`.
+///
+/// The deposit account doesn't need to exist in advance.
+/// If it doesn't exist, it will be created on this occasion, with a zero balance.
+///
+/// Performs basic input validation of the signer's name,
+/// and of the units, which should be a non-negative decimal number.
+///
+/// # Errors
+/// - Attempted overflow (too many units), `AccountError::HoldingsOverflow`
+async fn deposit_units(
+    words: Vec<&str>,
+    client: &Client,
+    base_url: &Url,
+) -> Result<(), Box<dyn Error>> {
+    let words_len = words.len();
+
+    if words_len < 3 {
+        println!("The deposit units command: {DEPOSIT_UNITS} 'signer full name' <units>");
+        return Ok(());
+    }
+
+    let signer = words[1..(words_len - 1)].join(" ");
+    let signer = signer.trim_matches(|c| c == '\'' || c == '\"').trim();
+
+    if let Ok(units) = words[words_len - 1].parse::<Quantity>() {
+        if is_valid_name(signer) {
+            account_units_update_request(client, base_url, "account/units/deposit", signer, units)
+                .await?;
+        }
+    } else {
+        cannot_parse_number(words[words_len - 1]);
+    }
+
+    Ok(())
+}
+
+/// **Withdraw units of the traded instrument from an account**
+///
+/// The signer's name can consist of multiple words, same as in `withdraw`.
+///
+/// The withdrawal account needs to exist in advance.
+/// Units that are reserved for resting sell orders can't be withdrawn.
+///
+/// Performs basic input validation of the signer's name,
+/// and of the units, which should be a non-negative decimal number.
+///
+/// # Errors
+/// - Account doesn't exist, `AccountError::NotFound`;
+/// - Not enough units held, `AccountError::HoldingsInsufficient`.
+async fn withdraw_units(
+    words: Vec<&str>,
+    client: &Client,
+    base_url: &Url,
+) -> Result<(), Box<dyn Error>> {
+    let words_len = words.len();
+
+    if words_len < 3 {
+        println!("The withdraw units command: {WITHDRAW_UNITS} 'signer full name' <units>");
+        return Ok(());
+    }
+
+    let signer = words[1..(words_len - 1)].join(" ");
+    let signer = signer.trim_matches(|c| c == '\'' || c == '\"').trim();
+
+    if let Ok(units) = words[words_len - 1].parse::<Quantity>() {
+        if is_valid_name(signer) {
+            account_units_update_request(client, base_url, "account/units/withdraw", signer, units)
+                .await?;
         }
     } else {
         cannot_parse_number(words[words_len - 1]);
@@ -211,34 +379,37 @@ async fn withdraw(words: Vec<&str>, client: &Client, base_url: &Url) -> Result<(
 /// If it doesn't exist, an error message will be output to
 /// the user, but the execution won't break.
 ///
-/// It is allowed to send 0, and these two transactions,
-/// withdraw and deposit, will be recorded.
+/// An optional currency can follow the amount, same as in `deposit`,
+/// and an optional memo for the recipient can follow another separator after it.
+///
+/// It is allowed to send 0, and the transfer will be recorded.
 ///
 /// Performs basic input validation of the sender's and recipient's name,
-/// and of the amount, which should be a non-negative integer.
+/// and of the amount, which should be a non-negative decimal number.
 ///
 /// Prints a success or an error message depending on the status of the
 /// transaction, and records the two transactions in the success case.
 ///
 /// # Errors
-/// - Any of the two accounts doesn't exist, `AccountingError::AccountNotFound`;
-/// - Attempted overflow (sender's account under-funded), `AccountingError::AccountUnderFunded`;
-/// - Attempted overflow (recipient's account over-funded), `AccountingError::AccountOverFunded`.
+/// - Any of the two accounts doesn't exist, `AccountError::NotFound`;
+/// - Attempted overflow (sender's account under-funded), `AccountError::UnderFunded`;
+/// - Attempted overflow (recipient's account over-funded), `AccountError::OverFunded`.
 ///
-/// We could pattern-match them for a different output format and the message
-/// contents, but haven't done that here. Errors are still printed.
+/// Errors are printed with their codes and messages.
 async fn send(words: Vec<&str>, client: &Client, base_url: &Url) -> Result<(), Box<dyn Error>> {
+    let (words, memo) = words_and_optional_memo(&words);
+    let (words, currency) = words_and_optional_currency(words);
     let words_len = words.len();
 
     if (words_len < 4) || !words.contains(&SEPARATOR) {
-        println!("The send command: {SEND} 'sender full name' {SEPARATOR} 'recipient full name' <amount>");
+        println!("The send command: {SEND} 'sender full name' {SEPARATOR} 'recipient full name' <amount> [currency] [{SEPARATOR} memo]");
         return Ok(());
     }
 
     let to_pos = words
         .iter()
         .position(|&r| r == SEPARATOR)
-        .expect(format!("The send command must contain '{}'.", SEPARATOR).as_str());
+        .unwrap_or_else(|| panic!("The send command must contain '{}'.", SEPARATOR));
 
     let sender = words[1..to_pos].join(" ");
     let sender = sender
@@ -252,7 +423,7 @@ async fn send(words: Vec<&str>, client: &Client, base_url: &Url) -> Result<(), B
         .trim()
         .to_string();
 
-    if let Ok(amount) = words[words_len - 1].parse::<u64>() {
+    if let Ok(amount) = words[words_len - 1].parse::<Money>() {
         if is_valid_name(&sender) && is_valid_name(&recipient) {
             let url = base_url.join("account/send")?;
             let response = client
@@ -261,15 +432,17 @@ async fn send(words: Vec<&str>, client: &Client, base_url: &Url) -> Result<(), B
                     sender,
                     recipient,
                     amount,
+                    memo,
+                    currency,
                 })
                 .send()
                 .await?;
 
             if response.status().is_success() {
-                let txs: (Tx, Tx) = response.json().await?;
-                println!("{:?}", txs);
+                let tx: Tx = response.json().await?;
+                println!("{}", tx);
             } else {
-                eprintln!("[ERROR] \"{}\"", response.text().await?);
+                print_error_response(response).await?;
             }
         }
     } else {
@@ -279,6 +452,318 @@ async fn send(words: Vec<&str>, client: &Client, base_url: &Url) -> Result<(), B
     Ok(())
 }
 
+/// **Convert funds of an account from one currency into another**
+///
+/// The signer's name can consist of multiple words, same as in `deposit`,
+/// and is followed by the amount, and the codes of the two currencies, such as `10 USD EUR`.
+/// The funds are converted at the service's exchange rates.
+///
+/// # Errors
+/// - There's no rate for the two currencies, `FxError::RateNotFound`;
+/// - Account doesn't exist, `AccountError::NotFound`;
+/// - Attempted overflow (funds too low), `AccountError::UnderFunded`
+///   or `FxError::UnderFunded`.
+async fn convert(words: Vec<&str>, client: &Client, base_url: &Url) -> Result<(), Box<dyn Error>> {
+    let words_len = words.len();
+
+    if words_len < 5 {
+        println!("The convert command: {CONVERT} 'signer full name' <amount> <from> <to>");
+        return Ok(());
+    }
+
+    let signer = words[1..(words_len - 3)].join(" ");
+    let signer = signer
+        .trim_matches(|c| c == '\'' || c == '\"')
+        .trim()
+        .to_string();
+    let (from, to) = (words[words_len - 2], words[words_len - 1]);
+
+    if let Ok(amount) = words[words_len - 3].parse::<Money>() {
+        if is_valid_name(&signer) {
+            let url = base_url.join("account/convert")?;
+            let response = client
+                .post(url)
+                .json(&ConvertRequest {
+                    signer,
+                    from: from.to_string(),
+                    to: to.to_string(),
+                    amount,
+                })
+                .send()
+                .await?;
+
+            if response.status().is_success() {
+                let tx: Tx = response.json().await?;
+                println!("{}", tx);
+            } else {
+                print_error_response(response).await?;
+            }
+        }
+    } else {
+        cannot_parse_number(words[words_len - 3]);
+    }
+
+    Ok(())
+}
+
+/// **Close an account**
+///
+/// The signer's name, and the optional recipient's name after the separator,
+/// can consist of multiple words, same as in `send`.
+///
+/// The account needs to be empty, unless a recipient is given, who then gets all of
+/// its funds and units. Accounts with open orders can't be closed.
+///
+/// # Errors
+/// - Any of the two accounts doesn't exist, `AccountError::NotFound`;
+/// - The signer has open orders, `AccountError::HasOpenOrders`;
+/// - The account isn't empty, and there's no recipient, `AccountError::NotEmpty`.
+async fn close(words: Vec<&str>, client: &Client, base_url: &Url) -> Result<(), Box<dyn Error>> {
+    if words.len() < 2 {
+        println!(
+            "The close command: {CLOSE} 'signer full name' [{SEPARATOR} 'recipient full name']"
+        );
+        return Ok(());
+    }
+
+    let (signer, sweep_to) = signer_and_optional_recipient(&words[1..]);
+
+    if is_valid_name(&signer) && sweep_to.as_deref().is_none_or(is_valid_name) {
+        let url = base_url.join("account")?;
+        let response = client
+            .delete(url)
+            .query(&AccountCloseRequest { signer, sweep_to })
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            let txs: Vec<Tx> = response.json().await?;
+            println!("{:?}", txs);
+        } else {
+            print_error_response(response).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// **Print or set the status of an account**
+///
+/// The signer's name can consist of multiple words, same as in `close`.
+/// Without a status after the separator, the account's current status is printed.
+/// Otherwise, it's set to active, frozen, withdraw-only or closed, and the resting orders
+/// that the status doesn't allow are cancelled in all markets.
+///
+/// Setting the status is administrative, so it requires the admin token,
+/// which is taken from the `FINTECH_ADMIN_TOKEN` environment variable.
+///
+/// # Errors
+/// - Account doesn't exist, `AccountError::NotFound`;
+/// - Account is closed, and isn't being made active, `AccountError::Closed`;
+/// - Any of the errors from `close`, when closing it.
+///
+/// Errors are printed with their codes and messages.
+async fn status(words: Vec<&str>, client: &Client, base_url: &Url) -> Result<(), Box<dyn Error>> {
+    if words.len() < 2 {
+        println!(
+            "The status command: {STATUS} 'signer full name' [{SEPARATOR} active|frozen|withdraw-only|closed]"
+        );
+        return Ok(());
+    }
+
+    let (signer, status) = signer_and_optional_recipient(&words[1..]);
+
+    if !is_valid_name(&signer) {
+        return Ok(());
+    }
+
+    let Some(status) = status else {
+        let url = base_url.join("account")?;
+        let response = client
+            .post(url)
+            .json(&AccountBalanceRequest {
+                signer: signer.clone(),
+            })
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            let summary: AccountSummary = response.json().await?;
+            println!(r#"The client "{}" is {}."#, signer, summary.status);
+        } else {
+            print_error_response(response).await?;
+        }
+        return Ok(());
+    };
+
+    let status = match status.parse::<AccountStatus>() {
+        Ok(status) => status,
+        Err(msg) => {
+            eprintln!("[ERROR] {}", msg);
+            return Ok(());
+        }
+    };
+    let Some(token) = std::env::var("FINTECH_ADMIN_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+    else {
+        eprintln!("[ERROR] Setting the status requires the admin token in FINTECH_ADMIN_TOKEN.");
+        return Ok(());
+    };
+
+    let url = base_url.join("admin/account/status")?;
+    let response = client
+        .post(url)
+        .bearer_auth(token)
+        .json(&AccountStatusRequest { signer, status })
+        .send()
+        .await?;
+
+    if response.status().is_success() {
+        let txs: Vec<Tx> = response.json().await?;
+        for tx in txs {
+            println!("{}", tx);
+        }
+    } else {
+        print_error_response(response).await?;
+    }
+
+    Ok(())
+}
+
+/// **Print what's left of a client's limits on withdrawals and transfers**
+async fn limits(words: Vec<&str>, client: &Client, base_url: &Url) -> Result<(), Box<dyn Error>> {
+    if words.len() < 2 {
+        println!("The limits command: {LIMITS} 'signer full name'");
+        return Ok(());
+    }
+
+    let signer = words[1..].join(" ");
+    let signer = signer.trim_matches(|c| c == '\'' || c == '\"').trim();
+
+    if !is_valid_name(signer) {
+        return Ok(());
+    }
+
+    let mut url = base_url.join("account/limits")?;
+    url.query_pairs_mut().append_pair("signer", signer);
+    let response = client.get(url).send().await?;
+
+    if response.status().is_success() {
+        let allowance: Allowance = response.json().await?;
+        println!(r#"The limits of "{}": {}"#, signer, allowance);
+    } else {
+        print_error_response(response).await?;
+    }
+
+    Ok(())
+}
+
+/// **Print the positions of all clients, or of one, with their profit and loss**
+///
+/// The signer's name, if any, can consist of multiple words, same as in `close`.
+/// The open units are valued at the last trade price, unless `mid` follows the separator,
+/// which values them halfway between the best bid and the best ask.
+///
+/// Covers all markets.
+async fn positions(
+    words: Vec<&str>,
+    client: &Client,
+    base_url: &Url,
+) -> Result<(), Box<dyn Error>> {
+    let (signer, mark) = signer_and_optional_recipient(&words[1..]);
+
+    if !signer.is_empty() && !is_valid_name(&signer) {
+        return Ok(());
+    }
+
+    let mark = match mark.map(|mark| mark.parse::<MarkPrice>()) {
+        None => None,
+        Some(Ok(mark)) => Some(mark),
+        Some(Err(msg)) => {
+            eprintln!("[ERROR] {}", msg);
+            return Ok(());
+        }
+    };
+
+    let url = base_url.join("positions")?;
+    let response = client
+        .get(url)
+        .query(&PositionsRequest {
+            signer: Some(signer).filter(|signer| !signer.is_empty()),
+            symbol: None,
+            mark,
+        })
+        .send()
+        .await?;
+
+    if response.status() == StatusCode::OK {
+        let positions: Vec<PositionSummary> = response.json().await?;
+        if positions.is_empty() {
+            println!("No positions.");
+        }
+        for position in positions {
+            println!("{}", position);
+        }
+    } else {
+        print_error_response(response).await?;
+    }
+
+    Ok(())
+}
+
+/// **Print a page of a client's statement, with their balance after each transaction**
+///
+/// The signer's name can consist of multiple words, same as in `close`.
+/// An optional filter follows the separator, as space-separated `<part>=<value>` pairs,
+/// such as `kinds=deposit,withdraw since=1700000000000 after=12 limit=20`;
+/// see [`StatementFilter`]. A page that isn't the last one ends with the cursor
+/// that goes into `after` for the next page.
+///
+/// From the ledger's transaction log.
+async fn statement(
+    words: Vec<&str>,
+    client: &Client,
+    base_url: &Url,
+) -> Result<(), Box<dyn Error>> {
+    if words.len() < 2 {
+        println!("The statement command: {STATEMENT} 'signer full name' [{SEPARATOR} <filter>]");
+        return Ok(());
+    }
+
+    let (signer, filter) = signer_and_optional_recipient(&words[1..]);
+
+    if !is_valid_name(&signer) {
+        return Ok(());
+    }
+
+    let filter = match filter.unwrap_or_default().parse::<StatementFilter>() {
+        Ok(filter) => filter,
+        Err(msg) => {
+            eprintln!("[ERROR] {}", msg);
+            return Ok(());
+        }
+    };
+
+    // The name is a path segment, which percent-encodes its spaces and slashes.
+    let mut url = base_url.join("account/")?;
+    url.path_segments_mut()
+        .map_err(|_| "The base URL can't take a path.")?
+        .pop_if_empty()
+        .push(&signer)
+        .push("statement");
+    let response = client.get(url).query(&filter).send().await?;
+
+    if response.status() == StatusCode::OK {
+        let statement: Statement = response.json().await?;
+        println!("{}", statement);
+    } else {
+        print_error_response(response).await?;
+    }
+
+    Ok(())
+}
+
 /// **Print the entire ledger (all transactions ever) - transaction log**
 async fn print_ledger(client: &Client, base_url: &Url) -> Result<(), Box<dyn Error>> {
     let url = base_url.join("order/history")?;
@@ -286,27 +771,27 @@ async fn print_ledger(client: &Client, base_url: &Url) -> Result<(), Box<dyn Err
 
     if response.status() == StatusCode::OK {
         let history: Vec<Tx> = response.json().await?;
-        println!(
-            "The ledger (full transaction log, complete order history): {:#?}",
-            history
-        );
+        println!("The ledger (full transaction log, complete order history):");
+        for tx in history {
+            println!("{}", tx);
+        }
     } else {
-        eprintln!("[ERROR] \"{}\"", response.text().await?);
+        print_error_response(response).await?;
     }
 
     Ok(())
 }
 
-/// **Print all accounts and their balances**
+/// **Print all accounts with their balances and holdings**
 pub async fn print_accounts(client: &Client, base_url: &Url) -> Result<(), Box<dyn Error>> {
     let url = base_url.join("accounts")?;
     let response = client.get(url).send().await?;
 
     if response.status() == StatusCode::OK {
-        let accounts: BTreeMap<String, u64> = response.json().await?;
-        println!("Accounts and their balances: {:#?}", accounts);
+        let accounts: BTreeMap<String, AccountSummary> = response.json().await?;
+        println!("Accounts with their balances and holdings: {:#?}", accounts);
     } else {
-        eprintln!("[ERROR] \"{}\"", response.text().await?);
+        print_error_response(response).await?;
     }
 
     Ok(())
@@ -318,7 +803,8 @@ pub async fn print_accounts(client: &Client, base_url: &Url) -> Result<(), Box<d
 /// We can wrap the signer's name in single or double quotes,
 /// but we don't have to use any quotes at all.
 ///
-/// Prints the signer's balance.
+/// Prints the signer's balance and holdings, and the parts of them held for resting orders,
+/// along with the account's status, unless it's active.
 async fn print_single_account(
     words: Vec<&str>,
     client: &Client,
@@ -350,21 +836,28 @@ async fn print_single_account(
 
         match response.status().as_u16() {
             200..=299 => {
-                let balance: u64 = response.json().await?;
+                let summary: AccountSummary = response.json().await?;
                 println!(
                     r#"The client "{}" has the following balance: {}."#,
-                    signer, balance
-                )
-            }
-            400..=599 => {
-                eprintln!(r#"The client "{}" doesn't exist."#, signer);
-                eprintln!(
-                    "[ERROR] {} \"{}\"",
-                    response.status(),
-                    response.text().await?
+                    signer, summary.balance
                 );
+                if summary.status != AccountStatus::Active {
+                    println!("The account is {}.", summary.status);
+                }
+                if !summary.held.is_zero() {
+                    println!("Of that, {} is held for resting orders.", summary.held);
+                }
+                for (symbol, units) in summary.holdings {
+                    match summary.held_units.get(&symbol) {
+                        Some(held) => println!(
+                            "Holds {} {}, of which {} are held for resting orders.",
+                            units, symbol, held
+                        ),
+                        None => println!("Holds {} {}.", units, symbol),
+                    }
+                }
             }
-            _ => println!("[ERROR] Unexpected status code: {}", response.status()),
+            _ => print_error_response(response).await?,
         }
     }
 
@@ -380,15 +873,16 @@ async fn print_single_account(
 /// The account needs to exist in advance.
 ///
 /// Performs basic input validation of the signer's name, of the side,
-/// and of the price and amount, which should be non-negative integers.
+/// and of the price and amount, which should be non-negative decimal numbers.
 ///
 /// Prints a success or an error message depending on the status of the
 /// receipt (of the processing of the order).
 ///
 /// # Errors
-/// - Account not found, `AccountingError::AccountNotFound`;
-/// - Account has insufficient funds, `AccountingError::AccountUnderFunded`;
-/// - Account would be over-funded, `AccountingError::AccountOverFunded`.
+/// - Account not found, `AccountError::NotFound`;
+/// - Buyer can't afford the order, `OrderError::UnderFunded`;
+/// - Seller doesn't hold enough units, `OrderError::HoldingsInsufficient`;
+/// - Settling a match would over-fund an account, `AccountError::OverFunded`.
 async fn order(words: Vec<&str>, client: &Client, base_url: &Url) -> Result<(), Box<dyn Error>> {
     let words_len = words.len();
 
@@ -409,7 +903,7 @@ async fn order(words: Vec<&str>, client: &Client, base_url: &Url) -> Result<(),
         }
     };
 
-    let price = match words[words_len - 2].parse::<u64>() {
+    let price = match words[words_len - 2].parse::<Price>() {
         Ok(price) => price,
         Err(_err) => {
             cannot_parse_number(words[words_len - 2]);
@@ -417,7 +911,7 @@ async fn order(words: Vec<&str>, client: &Client, base_url: &Url) -> Result<(),
         }
     };
 
-    let amount = match words[words_len - 1].parse::<u64>() {
+    let amount = match words[words_len - 1].parse::<Quantity>() {
         Ok(amount) => amount,
         Err(_err) => {
             cannot_parse_number(words[words_len - 1]);
@@ -435,8 +929,7 @@ async fn order(words: Vec<&str>, client: &Client, base_url: &Url) -> Result<(),
             let receipt: Receipt = response.json().await?;
             println!("{:?}", receipt);
         } else {
-            eprintln!(r#"The client "{}" doesn't exist."#, signer);
-            eprintln!("[ERROR] \"{}\"", response.text().await?);
+            print_error_response(response).await?;
         }
     }
 
@@ -489,7 +982,11 @@ async fn order_book(
     let response = client
         .get(url)
         .headers(headers)
-        .query(&OrderBookRequest { sort, desc })
+        .query(&OrderBookRequest {
+            sort,
+            desc,
+            symbol: None,
+        })
         .send()
         .await?;
 
@@ -497,7 +994,7 @@ async fn order_book(
         let book = response.json::<Vec<PartialOrder>>().await?;
         println!("\nThe order book: {:#?}", book);
     } else {
-        eprintln!("[ERROR] \"{}\"", response.text().await?);
+        print_error_response(response).await?;
     }
 
     Ok(())
@@ -534,7 +1031,7 @@ async fn order_book_by_price(
     let url = base_url.join("orderbookbyprice")?;
     let response = client
         .get(url)
-        .query(&OrderBookByPriceRequest { desc })
+        .query(&OrderBookByPriceRequest { desc, symbol: None })
         .send()
         .await?;
 
@@ -542,16 +1039,401 @@ async fn order_book_by_price(
         let book: Vec<PartialOrder> = response.json().await?;
         println!("\nThe order book sorted by price points: {:#?}", book);
     } else {
-        eprintln!("[ERROR] \"{}\"", response.text().await?);
+        print_error_response(response).await?;
+    }
+
+    Ok(())
+}
+
+/// **Place a one-cancels-other pair of a take-profit and a stop-loss order**
+///
+/// The signer's name can consist of multiple words.
+/// We can wrap the signer's name in single or double quotes,
+/// but we don't have to use any quotes at all.
+///
+/// The side is the side of both exits, i.e., "sell" to close a long position.
+///
+/// The take-profit is put in the order book right away, while the stop-loss waits
+/// for the last trade price to reach its price. A fill on either cancels the other.
+///
+/// Prints the order group, or an error message.
+async fn oco(words: Vec<&str>, client: &Client, base_url: &Url) -> Result<(), Box<dyn Error>> {
+    let words_len = words.len();
+
+    if words_len < 6 {
+        println!(
+            "The oco command: {OCO} 'signer full name' <side> <take-profit price> <stop-loss price> <amount>"
+        );
+        return Ok(());
+    }
+
+    let signer = words[1..(words_len - 4)].join(" ");
+    let signer = signer.trim_matches(|c| c == '\'' || c == '\"').trim();
+
+    let side = match words[words_len - 4] {
+        "buy" | "bid" => Side::Buy,
+        "sell" | "ask" => Side::Sell,
+        _ => {
+            eprintln!(r#"[ERROR] Side can be either "buy"/"bid" or "sell"/"ask"."#);
+            return Ok(());
+        }
+    };
+
+    let mut prices = [Price::ZERO; 2];
+    for (price, word) in prices
+        .iter_mut()
+        .zip(&words[(words_len - 3)..(words_len - 1)])
+    {
+        match word.parse::<Price>() {
+            Ok(p) => *price = p,
+            Err(_err) => {
+                cannot_parse_number(word);
+                return Ok(());
+            }
+        }
+    }
+    let [take_profit_price, stop_loss_price] = prices;
+
+    let amount = match words[words_len - 1].parse::<Quantity>() {
+        Ok(amount) => amount,
+        Err(_err) => {
+            cannot_parse_number(words[words_len - 1]);
+            return Ok(());
+        }
+    };
+
+    if is_valid_name(signer) {
+        let request = OcoRequest {
+            take_profit: Order::new(take_profit_price, amount, side.clone(), signer.to_string()),
+            stop_loss: Order::new(stop_loss_price, amount, side, signer.to_string()),
+        };
+
+        let url = base_url.join("ordergroup/oco")?;
+        let response = client.post(url).json(&request).send().await?;
+        print_order_group_response(response).await?;
     }
 
     Ok(())
 }
 
+/// **Place an entry order with a bracket of a take-profit and a stop-loss order**
+///
+/// The signer's name can consist of multiple words.
+/// We can wrap the signer's name in single or double quotes,
+/// but we don't have to use any quotes at all.
+///
+/// The side is the side of the entry; the exits are on the opposite side.
+///
+/// The entry is put in the order book right away. Once it has been completely filled,
+/// the take-profit and the stop-loss become active as a one-cancels-other pair.
+///
+/// Prints the order group, or an error message.
+async fn bracket(words: Vec<&str>, client: &Client, base_url: &Url) -> Result<(), Box<dyn Error>> {
+    let words_len = words.len();
+
+    if words_len < 7 {
+        println!(
+            "The bracket command: {BRACKET} 'signer full name' <side> <entry price> \
+            <take-profit price> <stop-loss price> <amount>"
+        );
+        return Ok(());
+    }
+
+    let signer = words[1..(words_len - 5)].join(" ");
+    let signer = signer.trim_matches(|c| c == '\'' || c == '\"').trim();
+
+    let (entry_side, exit_side) = match words[words_len - 5] {
+        "buy" | "bid" => (Side::Buy, Side::Sell),
+        "sell" | "ask" => (Side::Sell, Side::Buy),
+        _ => {
+            eprintln!(r#"[ERROR] Side can be either "buy"/"bid" or "sell"/"ask"."#);
+            return Ok(());
+        }
+    };
+
+    let mut prices = [Price::ZERO; 3];
+    for (price, word) in prices
+        .iter_mut()
+        .zip(&words[(words_len - 4)..(words_len - 1)])
+    {
+        match word.parse::<Price>() {
+            Ok(p) => *price = p,
+            Err(_err) => {
+                cannot_parse_number(word);
+                return Ok(());
+            }
+        }
+    }
+    let [entry_price, take_profit_price, stop_loss_price] = prices;
+
+    let amount = match words[words_len - 1].parse::<Quantity>() {
+        Ok(amount) => amount,
+        Err(_err) => {
+            cannot_parse_number(words[words_len - 1]);
+            return Ok(());
+        }
+    };
+
+    if is_valid_name(signer) {
+        let request = BracketRequest {
+            entry: Order::new(entry_price, amount, entry_side, signer.to_string()),
+            take_profit: Order::new(
+                take_profit_price,
+                amount,
+                exit_side.clone(),
+                signer.to_string(),
+            ),
+            stop_loss: Order::new(stop_loss_price, amount, exit_side, signer.to_string()),
+        };
+
+        let url = base_url.join("ordergroup/bracket")?;
+        let response = client.post(url).json(&request).send().await?;
+        print_order_group_response(response).await?;
+    }
+
+    Ok(())
+}
+
+/// **Print a single order group and the status of each of its legs**
+async fn print_order_group(
+    words: Vec<&str>,
+    client: &Client,
+    base_url: &Url,
+) -> Result<(), Box<dyn Error>> {
+    if words.len() != 2 {
+        println!("The group command: {ORDER_GROUP} <id>");
+        return Ok(());
+    }
+
+    match words[1].parse::<u64>() {
+        Ok(id) => {
+            let url = base_url.join(&format!("ordergroup/{}", id))?;
+            let response = client.get(url).send().await?;
+            print_order_group_response(response).await?;
+        }
+        Err(_err) => cannot_parse_number(words[1]),
+    }
+
+    Ok(())
+}
+
+/// **Print all order groups ever placed**
+async fn print_order_groups(client: &Client, base_url: &Url) -> Result<(), Box<dyn Error>> {
+    let url = base_url.join("ordergroups")?;
+    let response = client.get(url).send().await?;
+
+    if response.status() == StatusCode::OK {
+        let groups: Vec<OrderGroup> = response.json().await?;
+        println!("Order groups: {:#?}", groups);
+    } else {
+        print_error_response(response).await?;
+    }
+
+    Ok(())
+}
+
+/// **Cancel all open legs of an order group**
+async fn cancel_order_group(
+    words: Vec<&str>,
+    client: &Client,
+    base_url: &Url,
+) -> Result<(), Box<dyn Error>> {
+    if words.len() != 2 {
+        println!("The cancel group command: {CANCEL_ORDER_GROUP} <id>");
+        return Ok(());
+    }
+
+    match words[1].parse::<u64>() {
+        Ok(id) => {
+            let url = base_url.join(&format!("ordergroup/{}", id))?;
+            let response = client.delete(url).send().await?;
+            print_order_group_response(response).await?;
+        }
+        Err(_err) => cannot_parse_number(words[1]),
+    }
+
+    Ok(())
+}
+
+/// **Cancel all resting orders of a signer**
+///
+/// The signer's name can consist of multiple words.
+/// We can wrap the signer's name in single or double quotes,
+/// but we don't have to use any quotes at all.
+///
+/// Optionally ends with a side, to only cancel the orders on that side of the order book.
+///
+/// Prints the cancelled orders, or an error message.
+async fn cancel_all(
+    words: Vec<&str>,
+    client: &Client,
+    base_url: &Url,
+) -> Result<(), Box<dyn Error>> {
+    if words.len() < 2 {
+        println!("The cancel all command: {CANCEL_ALL} 'signer full name' [<side>]");
+        return Ok(());
+    }
+
+    let (signer, side) = signer_and_optional_side(&words[1..]);
+
+    if is_valid_name(&signer) {
+        let url = base_url.join("orders")?;
+        let response = client
+            .delete(url)
+            .query(&CancelAllRequest {
+                signer,
+                side,
+                symbol: None,
+            })
+            .send()
+            .await?;
+
+        if response.status() == StatusCode::OK {
+            let cancelled: Vec<PartialOrder> = response.json().await?;
+            println!("Cancelled orders: {:#?}", cancelled);
+        } else {
+            print_error_response(response).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// **Clear everything: all accounts, the entire transaction log, and all order books**
+///
+/// Asks for a confirmation first. The service requires the admin token,
+/// which is taken from the `FINTECH_ADMIN_TOKEN` environment variable.
+///
+/// Optionally takes a path of a file, to archive everything into as it's cleared.
+/// As the service has already been reset by then, the archive is printed
+/// in case it can't be written.
+async fn reset(words: Vec<&str>, client: &Client, base_url: &Url) -> Result<(), Box<dyn Error>> {
+    let path = words[1..].join(" ");
+    let path = path.trim_matches(|c| c == '\'' || c == '\"').trim();
+
+    let Some(token) = std::env::var("FINTECH_ADMIN_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+    else {
+        eprintln!("[ERROR] The reset requires the admin token in FINTECH_ADMIN_TOKEN.");
+        return Ok(());
+    };
+
+    if !confirm("This clears all accounts, the transaction log and all order books.") {
+        println!("The reset was cancelled.");
+        return Ok(());
+    }
+
+    let url = base_url.join("admin/reset")?;
+    let response = client
+        .post(url)
+        .bearer_auth(token)
+        .json(&ResetRequest {
+            archive: !path.is_empty(),
+        })
+        .send()
+        .await?;
+
+    if response.status() == StatusCode::OK {
+        let archives: Vec<PlatformArchive> = response.json().await?;
+        if !path.is_empty() {
+            match save_archives(path, &archives) {
+                Ok(_) => println!("Archived into \"{}\".", path),
+                Err(err) => {
+                    eprintln!(
+                        "[ERROR] Failed to write the archive to \"{}\": {}",
+                        path, err
+                    );
+                    println!("{:#?}", archives);
+                }
+            }
+        }
+        println!("The platform has been reset.");
+    } else {
+        print_error_response(response).await?;
+    }
+
+    Ok(())
+}
+
+/// **Check the accounts of the ledger and of every market against their transaction logs**
+///
+/// The service requires the admin token, which is taken from the `FINTECH_ADMIN_TOKEN`
+/// environment variable. Prints the accounts that diverge, if any.
+async fn verify(client: &Client, base_url: &Url) -> Result<(), Box<dyn Error>> {
+    let Some(token) = std::env::var("FINTECH_ADMIN_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+    else {
+        eprintln!("[ERROR] The verification requires the admin token in FINTECH_ADMIN_TOKEN.");
+        return Ok(());
+    };
+
+    let url = base_url.join("admin/verify")?;
+    let response = client.get(url).bearer_auth(token).send().await?;
+
+    if response.status() == StatusCode::OK {
+        let reconciliations: Vec<Reconciliation> = response.json().await?;
+        for reconciliation in reconciliations {
+            println!("{}", reconciliation);
+        }
+    } else {
+        print_error_response(response).await?;
+    }
+
+    Ok(())
+}
+
+/// **Print an order group from a response, or the error from it**
+async fn print_order_group_response(response: reqwest::Response) -> Result<(), Box<dyn Error>> {
+    if response.status() == StatusCode::OK {
+        let group: OrderGroup = response.json().await?;
+        println!("{:#?}", group);
+    } else {
+        print_error_response(response).await?;
+    }
+
+    Ok(())
+}
+
+/// **The message of an error response**
+///
+/// As in: `ACCOUNT_NOT_FOUND: The account "Bob" doesn't exist.`,
+/// or the status and the body, if the body isn't an [`ErrorResponse`].
+fn error_message(status: StatusCode, body: &str) -> String {
+    match serde_json::from_str::<ErrorResponse>(body) {
+        Ok(err) => err.to_string(),
+        Err(_) => format!("{} \"{}\"", status, body),
+    }
+}
+
+/// **Print the error from a response**
+async fn print_error_response(response: reqwest::Response) -> Result<(), Box<dyn Error>> {
+    let status = response.status();
+    let body = response.text().await?;
+    eprintln!("[ERROR] {}", error_message(status, &body));
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
-    use super::get_base_url;
+    use super::{error_message, get_base_url};
     use crate::DEFAULT_BASE_URL;
+    use reqwest::StatusCode;
+
+    #[test]
+    fn test_error_message() {
+        let body = r#"{"code":"ACCOUNT_NOT_FOUND","message":"The account \"Bob\" doesn't exist.","details":{"kind":"not_found","account":"Bob"}}"#;
+        assert_eq!(
+            error_message(StatusCode::NOT_FOUND, body),
+            r#"ACCOUNT_NOT_FOUND: The account "Bob" doesn't exist."#
+        );
+        assert_eq!(
+            error_message(StatusCode::BAD_GATEWAY, "Bad Gateway"),
+            r#"502 Bad Gateway "Bad Gateway""#
+        );
+    }
 
     #[test]
     fn test_default_url_none() {
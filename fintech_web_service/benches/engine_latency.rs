@@ -0,0 +1,188 @@
+//! Order-entry latency under concurrent order book reads
+//!
+//! Compares the previous design, a `tokio::sync::Mutex<TradingPlatform>` shared by all handlers,
+//! with the single-writer [`Engine`] task, which serves reads from a published snapshot.
+//!
+//! A large order book is prefilled, and a number of reader tasks keep fetching it,
+//! as `GET /orderbook/byprice` would. Meanwhile, orders are submitted one at a time,
+//! and the latency of each submission is recorded.
+//!
+//! Run with `cargo bench -p fintech_web_service --bench engine_latency`.
+
+use fintech_common::trading_platform::{TradingPlatform, DEFAULT_SYMBOL};
+use fintech_common::types::{Order, Side};
+use fintech_web_service::engine::{Engine, DEFAULT_COMMAND_CAPACITY};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Resting orders in the book before the measurement starts
+const BOOK_SIZE: u64 = 10_000;
+/// Tasks that keep reading the order book
+const READERS: usize = 8;
+/// Orders whose latency is measured
+const ORDERS: u64 = 2_000;
+
+fn prefilled_platform() -> TradingPlatform {
+    let mut trading_platform = TradingPlatform::new();
+    trading_platform
+        .deposit("Maker", 1_000_000_000.into())
+        .unwrap();
+    trading_platform
+        .deposit("Taker", 1_000_000_000.into())
+        .unwrap();
+    trading_platform
+        .deposit_units("Maker", DEFAULT_SYMBOL, BOOK_SIZE.into())
+        .unwrap();
+
+    for i in 0..BOOK_SIZE {
+        trading_platform
+            .process_order(Order::new(
+                1_000 + i % 500,
+                1,
+                Side::Sell,
+                "Maker".to_string(),
+            ))
+            .unwrap();
+    }
+
+    trading_platform
+}
+
+/// A buy below the best ask, so it rests in the book without matching.
+fn next_order(i: u64) -> Order {
+    Order::new(1 + i % 500, 1, Side::Buy, "Taker".to_string())
+}
+
+struct Report {
+    latencies: Vec<Duration>,
+    reads: u64,
+}
+
+impl Report {
+    fn print(mut self, name: &str, elapsed: Duration) {
+        self.latencies.sort();
+        let percentile = |p: usize| self.latencies[(self.latencies.len() - 1) * p / 100];
+        let mean = self.latencies.iter().sum::<Duration>() / self.latencies.len() as u32;
+
+        println!(
+            "{:<6} p50 {:>10.1?}  p99 {:>10.1?}  mean {:>10.1?}  max {:>10.1?}  reads {:>7}  in {:.2?}",
+            name,
+            percentile(50),
+            percentile(99),
+            mean,
+            self.latencies.last().unwrap(),
+            self.reads,
+            elapsed
+        );
+    }
+}
+
+async fn bench_mutex() -> Report {
+    let trading_platform = Arc::new(Mutex::new(prefilled_platform()));
+    let stop = Arc::new(AtomicBool::new(false));
+    let reads = Arc::new(AtomicU64::new(0));
+
+    let readers: Vec<_> = (0..READERS)
+        .map(|_| {
+            let (trading_platform, stop, reads) =
+                (trading_platform.clone(), stop.clone(), reads.clone());
+            tokio::spawn(async move {
+                while !stop.load(Ordering::Relaxed) {
+                    let book = trading_platform.lock().await.order_book_by_price(false);
+                    std::hint::black_box(book);
+                    reads.fetch_add(1, Ordering::Relaxed);
+                    tokio::task::yield_now().await;
+                }
+            })
+        })
+        .collect();
+
+    let mut latencies = Vec::with_capacity(ORDERS as usize);
+    for i in 0..ORDERS {
+        let start = Instant::now();
+        trading_platform
+            .lock()
+            .await
+            .process_order(next_order(i))
+            .unwrap();
+        latencies.push(start.elapsed());
+    }
+
+    stop.store(true, Ordering::Relaxed);
+    for reader in readers {
+        reader.await.unwrap();
+    }
+
+    Report {
+        latencies,
+        reads: reads.load(Ordering::Relaxed),
+    }
+}
+
+async fn bench_engine() -> Report {
+    let engine = Engine::spawn(prefilled_platform(), DEFAULT_COMMAND_CAPACITY);
+    let stop = Arc::new(AtomicBool::new(false));
+    let reads = Arc::new(AtomicU64::new(0));
+
+    let readers: Vec<_> = (0..READERS)
+        .map(|_| {
+            let (engine, stop, reads) = (engine.clone(), stop.clone(), reads.clone());
+            tokio::spawn(async move {
+                while !stop.load(Ordering::Relaxed) {
+                    let book = engine.snapshot().order_book_by_price(false);
+                    std::hint::black_box(book);
+                    reads.fetch_add(1, Ordering::Relaxed);
+                    tokio::task::yield_now().await;
+                }
+            })
+        })
+        .collect();
+
+    let mut latencies = Vec::with_capacity(ORDERS as usize);
+    for i in 0..ORDERS {
+        let start = Instant::now();
+        engine
+            .execute(move |trading_platform| trading_platform.process_order(next_order(i)))
+            .await
+            .unwrap()
+            .unwrap();
+        latencies.push(start.elapsed());
+    }
+
+    stop.store(true, Ordering::Relaxed);
+    for reader in readers {
+        reader.await.unwrap();
+    }
+
+    Report {
+        latencies,
+        reads: reads.load(Ordering::Relaxed),
+    }
+}
+
+fn main() {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(4)
+        .enable_all()
+        .build()
+        .unwrap();
+
+    // Every measured order rests, so the book grows by as many orders while it's measured.
+    println!(
+        "{} resting orders, growing to {}, {} readers, {} measured orders",
+        BOOK_SIZE,
+        BOOK_SIZE + ORDERS,
+        READERS,
+        ORDERS
+    );
+
+    let start = Instant::now();
+    let report = runtime.block_on(bench_mutex());
+    report.print("mutex", start.elapsed());
+
+    let start = Instant::now();
+    let report = runtime.block_on(bench_engine());
+    report.print("engine", start.elapsed());
+}
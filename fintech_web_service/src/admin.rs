@@ -0,0 +1,175 @@
+//! Administrative routes, guarded by an admin credential
+//!
+//! The credential is a token that the service is configured with. Every administrative
+//! request has to present it as a bearer token, in the `Authorization: Bearer <token>` header.
+//! Without a configured token, all administrative requests are refused.
+
+use crate::errors::{WebServiceInternalError, WebServiceUnauthorized};
+use crate::handlers::{accounting_error, engine_stopped, is_valid_name};
+use crate::markets::Markets;
+use fintech_common::requests::{AccountStatusRequest, ResetRequest};
+use std::convert::Infallible;
+use std::sync::Arc;
+use warp::{Filter, Rejection, Reply};
+
+/// **The administrative routes**
+///
+/// POST /admin/reset, with a [`ResetRequest`] body
+/// GET /admin/verify
+/// POST /admin/account/status, with an [`AccountStatusRequest`] body
+pub fn route(
+    markets_state: impl Filter<Extract = (Markets,), Error = Infallible> + Clone + Send + Sync + 'static,
+    admin_token: Option<String>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let reset = warp::path!("admin" / "reset")
+        .and(warp::post())
+        .and(authorized(admin_token.clone()))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(markets_state.clone())
+        .and_then(reset);
+
+    let verify = warp::path!("admin" / "verify")
+        .and(warp::get())
+        .and(authorized(admin_token.clone()))
+        .and(markets_state.clone())
+        .and_then(verify);
+
+    let account_status = warp::path!("admin" / "account" / "status")
+        .and(warp::post())
+        .and(authorized(admin_token))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(markets_state)
+        .and_then(account_status);
+
+    reset.or(verify).or(account_status)
+}
+
+/// Passes only requests that present the admin token.
+fn authorized(admin_token: Option<String>) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    let admin_token: Option<Arc<str>> = admin_token.map(Into::into);
+
+    warp::header::optional::<String>("authorization")
+        .and_then(move |authorization: Option<String>| {
+            let admin_token = admin_token.clone();
+            async move {
+                let presented = authorization
+                    .as_deref()
+                    .and_then(|value| value.strip_prefix("Bearer "));
+                match (admin_token.as_deref(), presented) {
+                    (Some(expected), Some(presented)) if tokens_match(expected, presented) => {
+                        Ok(())
+                    }
+                    _ => {
+                        log::warn!("Refused an administrative request without a valid token.");
+                        Err(warp::reject::custom(WebServiceUnauthorized))
+                    }
+                }
+            }
+        })
+        .untuple_one()
+}
+
+/// Compares the tokens in time that doesn't depend on where they differ.
+fn tokens_match(expected: &str, presented: &str) -> bool {
+    expected.len() == presented.len()
+        && expected
+            .bytes()
+            .zip(presented.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// The `reset` handler
+///
+/// Clears all accounts, the entire transaction log, and all order books and their history,
+/// in the ledger and in every market. See [`Markets::reset`].
+///
+/// Responds with the archives of everything that got cleared, if requested,
+/// or with an empty list otherwise.
+///
+/// POST /admin/reset
+async fn reset(request: ResetRequest, markets: Markets) -> Result<impl Reply, Rejection> {
+    log::debug!("reset; request = {:?}", request);
+
+    match markets
+        .reset(request.archive)
+        .await
+        .map_err(engine_stopped)?
+    {
+        Ok(archives) => {
+            log::warn!("The platform has been reset.");
+            Ok(warp::reply::json(&archives))
+        }
+        Err(err) => {
+            let msg = format!("Failed to reset the platform: {}", err);
+            log::error!("{}", msg);
+            Err(warp::reject::custom(WebServiceInternalError(msg)))
+        }
+    }
+}
+
+/// The `verify` handler
+///
+/// Rebuilds the accounts of the ledger and of every market from their transaction logs,
+/// and compares them with the live ones. See [`Markets::reconcile`].
+///
+/// Responds with a [`Reconciliation`](fintech_common::reconciliation::Reconciliation)
+/// for each of them, which lists the accounts that diverge.
+///
+/// GET /admin/verify
+async fn verify(markets: Markets) -> Result<impl Reply, Rejection> {
+    let reconciliations = markets.reconcile().await.map_err(engine_stopped)?;
+    for reconciliation in reconciliations.iter().filter(|r| !r.is_consistent()) {
+        log::error!("Inconsistent accounts: {}", reconciliation);
+    }
+
+    Ok(warp::reply::json(&reconciliations))
+}
+
+/// The `account_status` handler
+///
+/// Sets the status of the signer's account, and cancels the resting orders that it doesn't allow.
+/// See [`Markets::set_account_status`].
+///
+/// Responds with the recorded transactions: the status change, or the closure.
+///
+/// POST /admin/account/status
+async fn account_status(
+    request: AccountStatusRequest,
+    markets: Markets,
+) -> Result<impl Reply, Rejection> {
+    log::debug!("account_status; request = {:?}", request);
+
+    is_valid_name(&request.signer)?;
+
+    match markets
+        .set_account_status(&request.signer, request.status)
+        .await
+        .map_err(engine_stopped)?
+    {
+        Ok(txs) => {
+            log::warn!(
+                "The status of \"{}\" has been set to {}.",
+                request.signer,
+                request.status
+            );
+            Ok(warp::reply::json(&txs))
+        }
+        Err(acc_err) => Err(accounting_error(acc_err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tokens_match;
+
+    #[test]
+    fn tokens_match_only_when_equal() {
+        assert!(tokens_match("secret", "secret"));
+        assert!(!tokens_match("secret", "secreT"));
+        assert!(!tokens_match("secret", "secret2"));
+        assert!(!tokens_match("secret", ""));
+    }
+}
@@ -0,0 +1,342 @@
+//! The single-writer matching engine task
+//!
+//! The [`TradingPlatform`] is owned by a dedicated task, which is the only one that ever
+//! touches it. Handlers send it commands over a bounded channel and wait for the results
+//! over oneshot channels, so no handler ever holds a lock on the platform.
+//!
+//! When the channel is full, senders wait for space in it, which propagates back-pressure
+//! to the clients instead of queueing an unbounded amount of work.
+//!
+//! After every batch of commands, the task publishes a read-only [`Snapshot`] of the
+//! accounts, with their holdings, and of the order book. Queries read the latest snapshot without going through
+//! the task at all, so they never delay order entry. A snapshot is published before the
+//! replies to the commands of its batch are sent, so a client always sees its own writes.
+//!
+//! A snapshot shares everything that the batch didn't change with the previous one, see
+//! [`TradingPlatform::take_changes`]; a batch that changes nothing doesn't publish one at all.
+
+use fintech_common::accounts::AccountSummary;
+use fintech_common::errors::{AccountError, AccountingError};
+use fintech_common::trading_platform::{Changes, TradingPlatform};
+use fintech_common::types::{Money, PartialOrder};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot, watch};
+
+/// The number of commands that can wait for the engine before senders have to wait, by default
+pub const DEFAULT_COMMAND_CAPACITY: usize = 1024;
+
+/// The most commands that are applied before a snapshot is published
+const MAX_BATCH: usize = 64;
+
+/// Sends the result of a command back to its caller.
+type Reply = Box<dyn FnOnce() + Send>;
+
+/// Applies a command to the platform, and returns the reply to send once the batch is done.
+type Command = Box<dyn FnOnce(&mut TradingPlatform) -> Reply + Send>;
+
+/// **The engine task isn't running anymore, so the command couldn't be executed**
+#[derive(Debug)]
+pub struct EngineStopped;
+
+/// **A read-only view of the platform, as of the end of a batch of commands**
+///
+/// Summaries and the book are shared with the previous snapshot, unless the batch changed them.
+#[derive(Debug, Default)]
+pub struct Snapshot {
+    accounts: BTreeMap<String, Arc<AccountSummary>>,
+    /// Ascending by the ordinal sequence number; the other orders are derived from it when read,
+    /// so that the engine task only copies the book when it changes.
+    order_book: Arc<Vec<PartialOrder>>,
+}
+
+impl Snapshot {
+    fn of(trading_platform: &TradingPlatform) -> Self {
+        Self {
+            accounts: trading_platform
+                .accounts
+                .summaries()
+                .into_iter()
+                .map(|(signer, summary)| (signer, Arc::new(summary)))
+                .collect(),
+            order_book: Arc::new(trading_platform.order_book(true, false)),
+        }
+    }
+
+    /// The snapshot after the `changes`, sharing everything else with this one
+    fn updated(&self, trading_platform: &TradingPlatform, changes: Changes) -> Self {
+        if changes.all {
+            return Self::of(trading_platform);
+        }
+
+        let mut accounts = self.accounts.clone();
+        for signer in changes.accounts {
+            match trading_platform.accounts.summary_of(&signer) {
+                Ok(summary) => accounts.insert(signer, Arc::new(summary)),
+                Err(_) => accounts.remove(&signer),
+            };
+        }
+
+        let order_book = match changes.order_book {
+            true => Arc::new(trading_platform.order_book(true, false)),
+            false => Arc::clone(&self.order_book),
+        };
+
+        Self {
+            accounts,
+            order_book,
+        }
+    }
+
+    /// All accounts with their balances and holdings
+    pub fn accounts(&self) -> &BTreeMap<String, Arc<AccountSummary>> {
+        &self.accounts
+    }
+
+    /// The balance and the holdings of an account
+    ///
+    /// # Errors
+    /// - Account doesn't exist, `AccountError::NotFound`
+    pub fn account(&self, signer: &str) -> Result<&AccountSummary, AccountingError> {
+        self.accounts
+            .get(signer)
+            .map(Arc::as_ref)
+            .ok_or(AccountingError::Account(AccountError::NotFound(
+                signer.to_string(),
+            )))
+    }
+
+    /// The same as [`TradingPlatform::balance_of`]
+    ///
+    /// # Errors
+    /// - Account doesn't exist, `AccountError::NotFound`
+    pub fn balance_of(&self, signer: &str) -> Result<&Money, AccountingError> {
+        self.account(signer).map(|account| &account.balance)
+    }
+
+    /// The same as [`TradingPlatform::order_book`]
+    pub fn order_book(&self, sort: bool, desc: bool) -> Vec<PartialOrder> {
+        let mut book = self.order_book.to_vec();
+
+        // Unsorted can be in any order, so it's simply left ascending.
+        if sort && desc {
+            book.reverse();
+        }
+
+        book
+    }
+
+    /// The same as [`TradingPlatform::order_book_by_price`]
+    pub fn order_book_by_price(&self, desc: bool) -> Vec<PartialOrder> {
+        let mut book = self.order_book.to_vec();
+
+        // Sorting is stable, so orders inside of a price point stay ascending by their ordinal.
+        if desc {
+            book.sort_by_key(|po| std::cmp::Reverse(po.price));
+        } else {
+            book.sort_by_key(|po| po.price);
+        }
+
+        book
+    }
+}
+
+/// **A handle to the engine task**
+///
+/// Cheap to clone; all clones talk to the same task.
+/// The task stops once all handles are gone.
+#[derive(Clone)]
+pub struct Engine {
+    commands: mpsc::Sender<Command>,
+    snapshot: watch::Receiver<Arc<Snapshot>>,
+}
+
+impl Engine {
+    /// Moves the platform into a new engine task, with room for `capacity` waiting commands.
+    ///
+    /// # Panics
+    /// - Panics if `capacity` is 0, or if it's called outside of a Tokio runtime.
+    pub fn spawn(mut trading_platform: TradingPlatform, capacity: usize) -> Self {
+        // The first snapshot has everything, so nothing that happened so far is a change to it.
+        trading_platform.take_changes();
+        let (commands, receiver) = mpsc::channel(capacity);
+        let (publisher, snapshot) = watch::channel(Arc::new(Snapshot::of(&trading_platform)));
+
+        tokio::spawn(run(trading_platform, receiver, publisher));
+
+        Self { commands, snapshot }
+    }
+
+    /// Executes `f` on the platform, inside of the engine task, and returns its result.
+    ///
+    /// Waits while the command channel is full.
+    ///
+    /// # Errors
+    /// - The engine task has stopped, `EngineStopped`
+    pub async fn execute<F, R>(&self, f: F) -> Result<R, EngineStopped>
+    where
+        F: FnOnce(&mut TradingPlatform) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+
+        let command: Command = Box::new(move |trading_platform| {
+            let result = f(trading_platform);
+            Box::new(move || {
+                // The caller may have given up on the result, which is fine.
+                let _ = sender.send(result);
+            })
+        });
+
+        self.commands
+            .send(command)
+            .await
+            .map_err(|_| EngineStopped)?;
+        receiver.await.map_err(|_| EngineStopped)
+    }
+
+    /// The most recently published snapshot
+    pub fn snapshot(&self) -> Arc<Snapshot> {
+        self.snapshot.borrow().clone()
+    }
+}
+
+/// The engine task: applies commands in batches, and publishes a snapshot after each batch.
+async fn run(
+    mut trading_platform: TradingPlatform,
+    mut commands: mpsc::Receiver<Command>,
+    publisher: watch::Sender<Arc<Snapshot>>,
+) {
+    let mut replies = Vec::with_capacity(MAX_BATCH);
+
+    while let Some(command) = commands.recv().await {
+        replies.push(command(&mut trading_platform));
+
+        // Apply the commands that queued up in the meantime, and publish a single snapshot for all of them.
+        while replies.len() < MAX_BATCH {
+            match commands.try_recv() {
+                Ok(command) => replies.push(command(&mut trading_platform)),
+                Err(_) => break,
+            }
+        }
+
+        let changes = trading_platform.take_changes();
+        if !changes.is_empty() {
+            let snapshot = publisher.borrow().updated(&trading_platform, changes);
+            publisher.send_replace(Arc::new(snapshot));
+        }
+
+        for reply in replies.drain(..) {
+            reply();
+        }
+    }
+
+    log::info!("The engine task has stopped.");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fintech_common::types::{Order, Side};
+
+    #[tokio::test]
+    async fn execute_applies_commands_in_order() {
+        let engine = Engine::spawn(TradingPlatform::new(), 1);
+
+        let handles: Vec<_> = (1..=10)
+            .map(|i| {
+                let engine = engine.clone();
+                tokio::spawn(async move {
+                    engine
+                        .execute(move |trading_platform| {
+                            trading_platform.deposit("Alice", Money::from(i))
+                        })
+                        .await
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert!(handle.await.unwrap().unwrap().is_ok());
+        }
+
+        let balance = engine
+            .execute(|trading_platform| *trading_platform.balance_of("Alice").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(Money::from(55), balance);
+    }
+
+    #[tokio::test]
+    async fn snapshot_reflects_completed_commands() {
+        let engine = Engine::spawn(TradingPlatform::new(), DEFAULT_COMMAND_CAPACITY);
+        assert!(engine.snapshot().accounts().is_empty());
+
+        engine
+            .execute(|trading_platform| {
+                trading_platform.deposit("Alice", 100.into()).unwrap();
+                trading_platform
+                    .deposit_units(
+                        "Alice",
+                        trading_platform.symbol().to_string().as_str(),
+                        2.into(),
+                    )
+                    .unwrap();
+                trading_platform
+                    .process_order(Order::new(12, 1, Side::Sell, "Alice".to_string()))
+                    .unwrap();
+                trading_platform
+                    .process_order(Order::new(10, 1, Side::Sell, "Alice".to_string()))
+                    .unwrap();
+            })
+            .await
+            .unwrap();
+
+        let snapshot = engine.snapshot();
+        assert_eq!(Ok(&Money::from(100)), snapshot.balance_of("Alice"));
+        assert_eq!(
+            Err(AccountingError::Account(AccountError::NotFound(
+                "Bob".to_string()
+            ))),
+            snapshot.balance_of("Bob")
+        );
+
+        let ordinals =
+            |book: Vec<PartialOrder>| book.iter().map(|po| po.ordinal).collect::<Vec<_>>();
+        assert_eq!(vec![1, 2], ordinals(snapshot.order_book(true, false)));
+        assert_eq!(vec![2, 1], ordinals(snapshot.order_book(true, true)));
+        assert_eq!(vec![2, 1], ordinals(snapshot.order_book_by_price(false)));
+        assert_eq!(vec![1, 2], ordinals(snapshot.order_book_by_price(true)));
+    }
+
+    #[tokio::test]
+    async fn snapshot_shares_what_the_batch_did_not_change() {
+        let engine = Engine::spawn(TradingPlatform::new(), DEFAULT_COMMAND_CAPACITY);
+        engine
+            .execute(|trading_platform| {
+                trading_platform.deposit("Alice", 100.into()).unwrap();
+                trading_platform.deposit("Bob", 100.into()).unwrap();
+            })
+            .await
+            .unwrap();
+        let before = engine.snapshot();
+
+        engine
+            .execute(|trading_platform| trading_platform.deposit("Bob", 10.into()).unwrap())
+            .await
+            .unwrap();
+        let after = engine.snapshot();
+        assert!(Arc::ptr_eq(
+            &before.accounts()["Alice"],
+            &after.accounts()["Alice"]
+        ));
+        assert_eq!(Ok(&Money::from(110)), after.balance_of("Bob"));
+        assert!(Arc::ptr_eq(&before.order_book, &after.order_book));
+
+        // A batch that changes nothing doesn't publish anything.
+        engine
+            .execute(|trading_platform| trading_platform.balance_of("Bob").is_ok())
+            .await
+            .unwrap();
+        assert!(Arc::ptr_eq(&after, &engine.snapshot()));
+    }
+}
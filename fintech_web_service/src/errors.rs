@@ -1,12 +1,230 @@
-use fintech_common::errors::AccountingError;
+//! The web service's rejections, and how they turn into error responses.
+
+use crate::engine::EngineStopped;
+use fintech_common::errors::{AccountingError, ErrorKind, ErrorResponse};
+use std::convert::Infallible;
+use warp::http::StatusCode;
 use warp::reject::Reject;
+use warp::{Rejection, Reply};
 
 #[derive(Debug)]
 pub struct WebServiceAccountingError(pub AccountingError);
 
 impl Reject for WebServiceAccountingError {}
 
+/// An invalid request, such as an invalid signer's name
 #[derive(Debug)]
 pub struct WebServiceStringError(pub String);
 
 impl Reject for WebServiceStringError {}
+
+#[derive(Debug)]
+pub struct WebServiceEngineError(pub EngineStopped);
+
+impl Reject for WebServiceEngineError {}
+
+#[derive(Debug)]
+pub struct WebServiceUnauthorized;
+
+impl Reject for WebServiceUnauthorized {}
+
+/// A failure of the service itself, such as a failed reset
+#[derive(Debug)]
+pub struct WebServiceInternalError(pub String);
+
+impl Reject for WebServiceInternalError {}
+
+/// The status that an accounting error is responded with, by the domain that it comes from
+fn status_of(err: &AccountingError) -> StatusCode {
+    match err {
+        AccountingError::Account(err) => status_of_kind(err.kind()),
+        AccountingError::Order(err) => status_of_kind(err.kind()),
+        AccountingError::Margin(err) => status_of_kind(err.kind()),
+        AccountingError::Fx(err) => status_of_kind(err.kind()),
+        AccountingError::Limit(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        AccountingError::FeeScheduleInvalid(_) | AccountingError::ArithmeticOverflow(_) => {
+            StatusCode::BAD_REQUEST
+        }
+        AccountingError::MarketNotFound(_) | AccountingError::HistoryUnavailable(_) => {
+            StatusCode::NOT_FOUND
+        }
+    }
+}
+
+/// The status that an error of a domain, of the kind, is responded with
+fn status_of_kind(kind: ErrorKind) -> StatusCode {
+    match kind {
+        ErrorKind::Validation => StatusCode::BAD_REQUEST,
+        ErrorKind::NotFound => StatusCode::NOT_FOUND,
+        ErrorKind::Conflict => StatusCode::CONFLICT,
+        ErrorKind::Accounting | ErrorKind::Risk => StatusCode::UNPROCESSABLE_ENTITY,
+    }
+}
+
+/// Turns every rejection into a JSON [`ErrorResponse`], `{code, message, details}`,
+/// with a matching status.
+///
+/// Accounting errors are responded with, by their type and its kind:
+/// - 400 Bad Request, if they are validation errors,
+/// - 404 Not Found, if what they refer to doesn't exist,
+/// - 409 Conflict, if they conflict with the current state,
+/// - 422 Unprocessable Entity, if the accounts, or the risk checks, don't allow them.
+pub async fn handle_rejection(rejection: Rejection) -> Result<impl Reply, Infallible> {
+    let (status, response) = if let Some(WebServiceAccountingError(err)) = rejection.find() {
+        (status_of(err), ErrorResponse::from(err))
+    } else if let Some(WebServiceStringError(msg)) = rejection.find() {
+        (
+            StatusCode::BAD_REQUEST,
+            ErrorResponse::new("INVALID_REQUEST", msg),
+        )
+    } else if rejection.find::<WebServiceUnauthorized>().is_some() {
+        (
+            StatusCode::UNAUTHORIZED,
+            ErrorResponse::new("UNAUTHORIZED", "The request isn't authorized."),
+        )
+    } else if rejection.find::<WebServiceEngineError>().is_some() {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            ErrorResponse::new("ENGINE_STOPPED", "The engine isn't running."),
+        )
+    } else if let Some(WebServiceInternalError(msg)) = rejection.find() {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorResponse::new("INTERNAL_ERROR", msg),
+        )
+    } else if rejection.is_not_found() {
+        (
+            StatusCode::NOT_FOUND,
+            ErrorResponse::new("NOT_FOUND", "There's no such endpoint."),
+        )
+    } else if let Some(err) = rejection.find::<warp::filters::body::BodyDeserializeError>() {
+        (
+            StatusCode::BAD_REQUEST,
+            ErrorResponse::new("INVALID_BODY", err),
+        )
+    } else if let Some(err) = rejection.find::<warp::reject::InvalidQuery>() {
+        (
+            StatusCode::BAD_REQUEST,
+            ErrorResponse::new("INVALID_QUERY", err),
+        )
+    } else if let Some(err) = rejection.find::<warp::reject::PayloadTooLarge>() {
+        (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorResponse::new("PAYLOAD_TOO_LARGE", err),
+        )
+    } else if let Some(err) = rejection.find::<warp::reject::UnsupportedMediaType>() {
+        (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ErrorResponse::new("UNSUPPORTED_MEDIA_TYPE", err),
+        )
+    } else if let Some(err) = rejection.find::<warp::reject::MethodNotAllowed>() {
+        (
+            StatusCode::METHOD_NOT_ALLOWED,
+            ErrorResponse::new("METHOD_NOT_ALLOWED", err),
+        )
+    } else {
+        log::error!("Unhandled rejection: {:?}", rejection);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorResponse::new("INTERNAL_ERROR", "Something went wrong."),
+        )
+    };
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&response),
+        status,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fintech_common::errors::{AccountError, LimitError, OrderError};
+    use fintech_common::limits::Limit;
+    use serde_json::json;
+    use warp::Filter;
+
+    /// Responds to the accounting error through the rejection handler.
+    async fn respond_to(err: impl Into<AccountingError>) -> (StatusCode, ErrorResponse) {
+        let response =
+            handle_rejection(warp::reject::custom(WebServiceAccountingError(err.into())))
+                .await
+                .unwrap()
+                .into_response();
+        let status = response.status();
+        let body = warp::hyper::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn accounting_errors_are_responded_with_the_status_of_their_kind() {
+        let (status, response) = respond_to(AccountError::NotFound("Alice".to_string())).await;
+        assert_eq!(StatusCode::NOT_FOUND, status);
+        assert_eq!(
+            ErrorResponse {
+                code: "ACCOUNT_NOT_FOUND".to_string(),
+                message: "The account \"Alice\" doesn't exist.".to_string(),
+                details: json!({"kind": "not_found", "account": "Alice"}),
+            },
+            response
+        );
+
+        let (status, response) = respond_to(OrderError::Invalid("Invalid.".to_string())).await;
+        assert_eq!(StatusCode::BAD_REQUEST, status);
+        assert_eq!("ORDER_INVALID", response.code);
+
+        let (status, _) = respond_to(AccountError::HasOpenOrders("Alice".to_string())).await;
+        assert_eq!(StatusCode::CONFLICT, status);
+
+        let (status, response) =
+            respond_to(OrderError::UnderFunded("Alice".to_string(), 10.into())).await;
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, status);
+        assert_eq!(json!("risk"), response.details["kind"]);
+
+        let (status, _) =
+            respond_to(AccountError::UnderFunded("Alice".to_string(), 10.into())).await;
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, status);
+
+        let (status, response) =
+            respond_to(LimitError::Exceeded("Alice".to_string(), Limit::Daily)).await;
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, status);
+        assert_eq!("LIMIT_EXCEEDED", response.code);
+
+        let (status, _) = respond_to(AccountingError::MarketNotFound("ABC".to_string())).await;
+        assert_eq!(StatusCode::NOT_FOUND, status);
+
+        let (status, _) = respond_to(AccountingError::HistoryUnavailable(3)).await;
+        assert_eq!(StatusCode::NOT_FOUND, status);
+    }
+
+    #[tokio::test]
+    async fn malformed_requests_are_responded_with_json_errors() {
+        let route = warp::path!("account")
+            .and(warp::post())
+            .and(warp::body::json())
+            .map(|body: serde_json::Value| warp::reply::json(&body))
+            .recover(handle_rejection);
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/account")
+            .header("content-type", "application/json")
+            .body("{")
+            .reply(&route)
+            .await;
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        let body: ErrorResponse = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!("INVALID_BODY", body.code);
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/nowhere")
+            .reply(&route)
+            .await;
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+        let body: ErrorResponse = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!("NOT_FOUND", body.code);
+    }
+}
@@ -1,21 +1,21 @@
 //! Handler functions
 
-use crate::errors::{WebServiceAccountingError, WebServiceStringError};
-use fintech_common::errors::SIGNER_NAME_NOT_VALID_MSG;
+use crate::engine::EngineStopped;
+use crate::errors::{WebServiceAccountingError, WebServiceEngineError, WebServiceStringError};
+use crate::markets::Markets;
+use fintech_common::errors::{AccountingError, SIGNER_NAME_NOT_VALID_MSG};
 use fintech_common::requests::*;
-use fintech_common::trading_platform::TradingPlatform;
+use fintech_common::statements::StatementFilter;
 use fintech_common::types::Order;
 use fintech_common::validation;
-use std::convert::Infallible;
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use percent_encoding::percent_decode_str;
 use warp::{Rejection, Reply};
 
 /// **Basic input validation for a signer's name**
 ///
 /// Checks for:
 /// - An empty string.
-fn is_valid_name(signer: &str) -> Result<(), Rejection> {
+pub(crate) fn is_valid_name(signer: &str) -> Result<(), Rejection> {
     match validation::is_valid_name(signer) {
         Some(msg) => {
             let ret_msg = format!("{}: \"{}\". {}", SIGNER_NAME_NOT_VALID_MSG, signer, msg);
@@ -26,51 +26,134 @@ fn is_valid_name(signer: &str) -> Result<(), Rejection> {
     }
 }
 
+/// Turns a stopped engine into a rejection.
+pub(crate) fn engine_stopped(err: EngineStopped) -> Rejection {
+    log::error!("The engine task has stopped.");
+    warp::reject::custom(WebServiceEngineError(err))
+}
+
 /// The `deposit` handler
 ///
+/// Deposits funds in the optional `currency`, or in the ledger's currency.
+///
 /// POST /account/deposit
 pub async fn deposit(
     request: AccountUpdateRequest,
-    trading_platform: Arc<Mutex<TradingPlatform>>,
+    markets: Markets,
 ) -> Result<impl Reply, Rejection> {
     log::debug!("deposit; request = {:?}", request);
 
-    match trading_platform
-        .lock()
+    match markets
+        .ledger()
+        .execute(move |trading_platform| match &request.currency {
+            Some(currency) => {
+                trading_platform.deposit_in(&request.signer, currency, request.amount)
+            }
+            None => trading_platform.deposit(&request.signer, request.amount),
+        })
         .await
-        .deposit(&request.signer, request.amount)
+        .map_err(engine_stopped)?
     {
         Ok(tx) => Ok(warp::reply::json(&tx)),
-        Err(acc_err) => Err(warp::reject::custom(WebServiceAccountingError(acc_err))),
+        Err(acc_err) => Err(accounting_error(acc_err)),
     }
 }
 
 /// The `withdraw` handler
 ///
+/// Withdraws funds in the optional `currency`, or in the ledger's currency.
+///
 /// POST /account/withdraw
 pub async fn withdraw(
     request: AccountUpdateRequest,
-    trading_platform: Arc<Mutex<TradingPlatform>>,
+    markets: Markets,
 ) -> Result<impl Reply, Rejection> {
     log::debug!("withdraw; request = {:?}", request);
 
-    match trading_platform
-        .lock()
+    match markets
+        .ledger()
+        .execute(move |trading_platform| match &request.currency {
+            Some(currency) => {
+                trading_platform.withdraw_in(&request.signer, currency, request.amount)
+            }
+            None => trading_platform.withdraw(&request.signer, request.amount),
+        })
         .await
-        .withdraw(&request.signer, request.amount)
+        .map_err(engine_stopped)?
     {
         Ok(tx) => Ok(warp::reply::json(&tx)),
-        Err(acc_err) => Err(warp::reject::custom(WebServiceAccountingError(acc_err))),
+        Err(acc_err) => Err(accounting_error(acc_err)),
+    }
+}
+
+/// The `deposit_units` handler
+///
+/// Deposits units of the instrument of the optional `symbol`'s market, or of the default market.
+///
+/// POST /account/units/deposit
+pub async fn deposit_units(
+    request: AccountUnitsUpdateRequest,
+    markets: Markets,
+) -> Result<impl Reply, Rejection> {
+    log::debug!("deposit_units; request = {:?}", request);
+
+    if let Some(rejection) = is_valid_name(&request.signer).err() {
+        return Err(rejection);
+    }
+    let symbol = markets
+        .symbol(request.symbol.as_deref())
+        .map_err(accounting_error)?
+        .to_string();
+
+    match markets
+        .ledger()
+        .execute(move |trading_platform| {
+            trading_platform.deposit_units(&request.signer, &symbol, request.units)
+        })
+        .await
+        .map_err(engine_stopped)?
+    {
+        Ok(tx) => Ok(warp::reply::json(&tx)),
+        Err(acc_err) => Err(accounting_error(acc_err)),
+    }
+}
+
+/// The `withdraw_units` handler
+///
+/// Withdraws units of the instrument of the optional `symbol`'s market, or of the default market.
+/// Units that are reserved for resting sell orders can't be withdrawn.
+///
+/// POST /account/units/withdraw
+pub async fn withdraw_units(
+    request: AccountUnitsUpdateRequest,
+    markets: Markets,
+) -> Result<impl Reply, Rejection> {
+    log::debug!("withdraw_units; request = {:?}", request);
+
+    let symbol = markets
+        .symbol(request.symbol.as_deref())
+        .map_err(accounting_error)?
+        .to_string();
+
+    match markets
+        .ledger()
+        .execute(move |trading_platform| {
+            trading_platform.withdraw_units(&request.signer, &symbol, request.units)
+        })
+        .await
+        .map_err(engine_stopped)?
+    {
+        Ok(tx) => Ok(warp::reply::json(&tx)),
+        Err(acc_err) => Err(accounting_error(acc_err)),
     }
 }
 
 /// The `send` handler
 ///
+/// Sends funds in the optional `currency`, or in the ledger's currency.
+///
 /// POST /account/send
-pub async fn send(
-    request: AccountSendRequest,
-    trading_platform: Arc<Mutex<TradingPlatform>>,
-) -> Result<impl Reply, Rejection> {
+pub async fn send(request: AccountSendRequest, markets: Markets) -> Result<impl Reply, Rejection> {
     log::debug!("send; request = {:?}", request);
 
     if let Some(rejection) = is_valid_name(&request.sender).err() {
@@ -80,24 +163,87 @@ pub async fn send(
         return Err(rejection);
     }
 
-    match trading_platform
-        .lock()
+    match markets
+        .ledger()
+        .execute(move |trading_platform| match &request.currency {
+            Some(currency) => trading_platform.send_in(
+                &request.sender,
+                &request.recipient,
+                currency,
+                request.amount,
+                request.memo.as_deref(),
+            ),
+            None => trading_platform.send(
+                &request.sender,
+                &request.recipient,
+                request.amount,
+                request.memo.as_deref(),
+            ),
+        })
+        .await
+        .map_err(engine_stopped)?
+    {
+        Ok(tx) => Ok(warp::reply::json(&tx)),
+        Err(acc_err) => Err(accounting_error(acc_err)),
+    }
+}
+
+/// The `convert` handler
+///
+/// Converts the signer's funds from one currency into another, at the ledger's exchange rates.
+///
+/// POST /account/convert
+pub async fn convert(request: ConvertRequest, markets: Markets) -> Result<impl Reply, Rejection> {
+    log::debug!("convert; request = {:?}", request);
+
+    match markets
+        .ledger()
+        .execute(move |trading_platform| {
+            trading_platform.convert(&request.signer, &request.from, &request.to, request.amount)
+        })
+        .await
+        .map_err(engine_stopped)?
+    {
+        Ok(tx) => Ok(warp::reply::json(&tx)),
+        Err(acc_err) => Err(accounting_error(acc_err)),
+    }
+}
+
+/// The `close_account` handler
+///
+/// Closes the signer's account, optionally sweeping its funds and units to another account.
+///
+/// DELETE /account?signer=Alice
+///
+/// DELETE /account?signer=Alice&sweep_to=Bob
+pub async fn close_account(
+    request: AccountCloseRequest,
+    markets: Markets,
+) -> Result<impl Reply, Rejection> {
+    log::debug!("close_account; request = {:?}", request);
+
+    if let Some(rejection) = is_valid_name(&request.signer).err() {
+        return Err(rejection);
+    }
+
+    match markets
+        .close_account(&request.signer, request.sweep_to.as_deref())
         .await
-        .send(&request.sender, &request.recipient, request.amount)
+        .map_err(engine_stopped)?
     {
         Ok(txs) => Ok(warp::reply::json(&txs)),
-        Err(acc_err) => Err(warp::reject::custom(WebServiceAccountingError(acc_err))),
+        Err(acc_err) => Err(accounting_error(acc_err)),
     }
 }
 
 /// The `balance_of` handler
 ///
-/// Responds with the signer's balance.
+/// Responds with the signer's balance and holdings.
 ///
 /// POST /account
 pub async fn balance_of(
     request: AccountBalanceRequest,
-    trading_platform: Arc<Mutex<TradingPlatform>>,
+    markets: Markets,
 ) -> Result<impl Reply, Rejection> {
     log::debug!("balance_of; request = {:?}", request);
 
@@ -105,24 +251,63 @@ pub async fn balance_of(
         return Err(rejection);
     }
 
-    match trading_platform.lock().await.balance_of(&request.signer) {
-        Ok(balance) => Ok(warp::reply::json(balance)),
-        Err(acc_err) => Err(warp::reject::custom(WebServiceAccountingError(acc_err))),
+    match markets.account(&request.signer) {
+        Ok(account) => Ok(warp::reply::json(&account)),
+        Err(acc_err) => Err(accounting_error(acc_err)),
     }
 }
 
+/// The `account_limits` handler
+///
+/// Responds with what's left of the signer's limits on withdrawals and transfers,
+/// an [`Allowance`](fintech_common::limits::Allowance), in which `null` is unlimited.
+///
+/// GET /account/limits?signer=Alice
+pub async fn account_limits(
+    request: AccountLimitsRequest,
+    markets: Markets,
+) -> Result<impl Reply, Rejection> {
+    log::debug!("account_limits; request = {:?}", request);
+
+    if let Some(rejection) = is_valid_name(&request.signer).err() {
+        return Err(rejection);
+    }
+
+    let allowance = markets
+        .ledger()
+        .execute(move |trading_platform| trading_platform.allowance_of(&request.signer))
+        .await
+        .map_err(engine_stopped)?;
+    Ok(warp::reply::json(&allowance))
+}
+
+/// Turns an accounting error into a rejection, and logs its code and message.
+pub(crate) fn accounting_error(acc_err: AccountingError) -> Rejection {
+    log::info!("{}: {}", acc_err.code(), acc_err);
+    warp::reject::custom(WebServiceAccountingError(acc_err))
+}
+
 /// The `process_order` handler
 ///
+/// Processes the order in the market of the optional `symbol`, or in the default market.
+///
 /// POST /order
+///
+/// POST /order?symbol=ABC
 pub async fn process_order(
+    market: MarketRequest,
     order: Order,
-    trading_platform: Arc<Mutex<TradingPlatform>>,
+    markets: Markets,
 ) -> Result<impl Reply, Rejection> {
-    log::debug!("process_order; order = {:?}", order);
+    log::debug!("process_order; market = {:?}, order = {:?}", market, order);
 
-    match trading_platform.lock().await.process_order(order) {
+    match markets
+        .process_order(market.symbol.as_deref(), order)
+        .await
+        .map_err(engine_stopped)?
+    {
         Ok(receipt) => Ok(warp::reply::json(&receipt)),
-        Err(acc_err) => Err(warp::reject::custom(WebServiceAccountingError(acc_err))),
+        Err(acc_err) => Err(accounting_error(acc_err)),
     }
 }
 
@@ -130,31 +315,88 @@ pub async fn process_order(
 ///
 /// Responds with the entire ledger (all transactions ever) - transaction log - entire order history
 ///
+/// The transactions of the ledger come first, followed by the ones of each market, by symbol.
+///
 /// GET /order/history
-pub async fn order_history(
-    trading_platform: Arc<Mutex<TradingPlatform>>,
-) -> Result<impl Reply, Infallible> {
+pub async fn order_history(markets: Markets) -> Result<impl Reply, Rejection> {
     log::debug!("order_history");
-    let history = &trading_platform.lock().await.tx_log;
+    let history = markets.tx_log().await.map_err(engine_stopped)?;
     let response = warp::reply::json(&history);
     Ok(response)
 }
 
+/// The `statement` handler
+///
+/// Responds with a page of the signer's statement: their transactions, oldest first,
+/// with their balance after each one, and the balances that the page opens and closes with.
+/// The page has the transactions after the `after` cursor, up to `limit` of them,
+/// and the cursor of the next page as `next_cursor`, unless it's the last one.
+///
+/// Narrowed down by `kinds`, comma-separated, by `since` and `until`, in milliseconds since
+/// the Unix epoch, and by `from_id` and `to_id`, all of them inclusive.
+/// From the ledger's transaction log, unless a `symbol` is provided.
+///
+/// GET /account/{signer}/statement
+///
+/// GET /account/{signer}/statement?kinds=deposit,withdraw&since=1700000000000&after=12&limit=20&symbol=ABC
+pub async fn statement(
+    signer: String,
+    market: MarketRequest,
+    filter: StatementFilter,
+    markets: Markets,
+) -> Result<impl Reply, Rejection> {
+    log::debug!(
+        "statement; signer = {}, market = {:?}, filter = {:?}",
+        signer,
+        market,
+        filter
+    );
+
+    // Path segments come percent-encoded, such as the space in "Ana%20Ivanovi%C4%87".
+    let signer = percent_decode_str(&signer)
+        .decode_utf8()
+        .map_err(|err| {
+            warp::reject::custom(WebServiceStringError(format!(
+                "Invalid signer's name: \"{signer}\"; {err}"
+            )))
+        })?
+        .into_owned();
+    is_valid_name(&signer)?;
+
+    match markets
+        .statement(market.symbol.as_deref(), &signer, filter)
+        .await
+        .map_err(engine_stopped)?
+    {
+        Ok(statement) => Ok(warp::reply::json(&statement)),
+        Err(acc_err) => Err(accounting_error(acc_err)),
+    }
+}
+
+/// The `trial_balance` handler
+///
+/// Responds with the trial balance of the double-entry journal, combined over the ledger and all markets
+///
+/// GET /ledger/trial-balance
+pub async fn trial_balance(markets: Markets) -> Result<impl Reply, Rejection> {
+    log::debug!("trial_balance");
+    let trial_balance = markets.trial_balance().await.map_err(engine_stopped)?;
+    Ok(warp::reply::json(&trial_balance))
+}
+
 /// The `all_accounts` handler
 ///
-/// Responds with all accounts and their balances
+/// Responds with all accounts and their balances, including the funds reserved in markets
+///
+/// A sum that doesn't fit is capped at the largest value; `/account` reports it as an error.
 ///
 /// GET /accounts
-pub async fn all_accounts(
-    trading_platform: Arc<Mutex<TradingPlatform>>,
-) -> Result<impl Reply, Infallible> {
+pub async fn all_accounts(markets: Markets) -> Result<impl Reply, Rejection> {
     log::debug!("all_accounts");
-    let accounts = &trading_platform.lock().await.accounts.accounts;
-    let response = warp::reply::json(&accounts);
-    Ok(response)
+    Ok(warp::reply::json(&markets.accounts()))
 }
 
-/// **Fetches the complete order book**
+/// **Fetches the complete order book of a market**
 ///
 /// The `order_book` handler
 ///
@@ -169,23 +411,26 @@ pub async fn all_accounts(
 ///
 /// If sorting is requested, the order is ascending by default.
 ///
+/// The order book of the default market, unless a `symbol` is provided.
+///
 /// GET /orderbook (sort=false and desc=false by default)
 ///
-/// GET /orderbook?sort=true&desc=true
+/// GET /orderbook?sort=true&desc=true&symbol=ABC
 pub async fn order_book(
     request: OrderBookRequest,
-    trading_platform: Arc<Mutex<TradingPlatform>>,
-) -> Result<impl Reply, Infallible> {
+    markets: Markets,
+) -> Result<impl Reply, Rejection> {
     log::debug!("order_book; request = {:?}", request);
-    let book = trading_platform
-        .lock()
-        .await
+    let book = markets
+        .market(request.symbol.as_deref())
+        .map_err(accounting_error)?
+        .snapshot()
         .order_book(request.sort.unwrap_or(false), request.desc.unwrap_or(false));
     let response = warp::reply::json(&book);
     Ok(response)
 }
 
-/// **Fetches the complete order book sorted by price**
+/// **Fetches the complete order book of a market sorted by price**
 ///
 /// The `order_book_by_price` handler
 ///
@@ -198,22 +443,232 @@ pub async fn order_book(
 ///
 /// Inside of a price point, always ordered ascending by the ordinal sequence number.
 ///
+/// The order book of the default market, unless a `symbol` is provided.
+///
 /// GET /orderbookbyprice (desc=false by default)
 ///
-/// GET /orderbookbyprice?desc=true
+/// GET /orderbookbyprice?desc=true&symbol=ABC
 pub async fn order_book_by_price(
     request: OrderBookByPriceRequest,
-    trading_platform: Arc<Mutex<TradingPlatform>>,
-) -> Result<impl Reply, Infallible> {
+    markets: Markets,
+) -> Result<impl Reply, Rejection> {
     log::debug!("order_book_by_price; request = {:?}", request);
-    let book = trading_platform
-        .lock()
-        .await
+    let book = markets
+        .market(request.symbol.as_deref())
+        .map_err(accounting_error)?
+        .snapshot()
         .order_book_by_price(request.desc.unwrap_or(false));
     let response = warp::reply::json(&book);
     Ok(response)
 }
 
+/// The `place_oco` handler
+///
+/// Places a one-cancels-other pair of a take-profit and a stop-loss order.
+///
+/// POST /ordergroup/oco
+///
+/// POST /ordergroup/oco?symbol=ABC
+pub async fn place_oco(
+    market: MarketRequest,
+    request: OcoRequest,
+    markets: Markets,
+) -> Result<impl Reply, Rejection> {
+    log::debug!("place_oco; market = {:?}, request = {:?}", market, request);
+
+    if let Some(rejection) = is_valid_name(&request.take_profit.signer).err() {
+        return Err(rejection);
+    }
+
+    match markets
+        .place_oco(
+            market.symbol.as_deref(),
+            request.take_profit,
+            request.stop_loss,
+        )
+        .await
+        .map_err(engine_stopped)?
+    {
+        Ok(group) => Ok(warp::reply::json(&group)),
+        Err(acc_err) => Err(accounting_error(acc_err)),
+    }
+}
+
+/// The `place_bracket` handler
+///
+/// Places an entry order with a bracket of a take-profit and a stop-loss order.
+///
+/// POST /ordergroup/bracket
+///
+/// POST /ordergroup/bracket?symbol=ABC
+pub async fn place_bracket(
+    market: MarketRequest,
+    request: BracketRequest,
+    markets: Markets,
+) -> Result<impl Reply, Rejection> {
+    log::debug!(
+        "place_bracket; market = {:?}, request = {:?}",
+        market,
+        request
+    );
+
+    if let Some(rejection) = is_valid_name(&request.entry.signer).err() {
+        return Err(rejection);
+    }
+
+    match markets
+        .place_bracket(
+            market.symbol.as_deref(),
+            request.entry,
+            request.take_profit,
+            request.stop_loss,
+        )
+        .await
+        .map_err(engine_stopped)?
+    {
+        Ok(group) => Ok(warp::reply::json(&group)),
+        Err(acc_err) => Err(accounting_error(acc_err)),
+    }
+}
+
+/// The `order_group` handler
+///
+/// Responds with an order group and the status of each of its legs.
+/// Each market numbers its groups on its own.
+///
+/// GET /ordergroup/{id}
+///
+/// GET /ordergroup/{id}?symbol=ABC
+pub async fn order_group(
+    id: u64,
+    market: MarketRequest,
+    markets: Markets,
+) -> Result<impl Reply, Rejection> {
+    log::debug!("order_group; id = {}, market = {:?}", id, market);
+
+    match markets
+        .market(market.symbol.as_deref())
+        .map_err(accounting_error)?
+        .execute(move |trading_platform| trading_platform.order_group(id).cloned())
+        .await
+        .map_err(engine_stopped)?
+    {
+        Ok(group) => Ok(warp::reply::json(&group)),
+        Err(acc_err) => Err(accounting_error(acc_err)),
+    }
+}
+
+/// The `order_groups` handler
+///
+/// Responds with all order groups ever placed in a market.
+///
+/// GET /ordergroups
+///
+/// GET /ordergroups?symbol=ABC
+pub async fn order_groups(
+    market: MarketRequest,
+    markets: Markets,
+) -> Result<impl Reply, Rejection> {
+    log::debug!("order_groups; market = {:?}", market);
+    let groups = markets
+        .market(market.symbol.as_deref())
+        .map_err(accounting_error)?
+        .execute(|trading_platform| trading_platform.order_groups())
+        .await
+        .map_err(engine_stopped)?;
+    let response = warp::reply::json(&groups);
+    Ok(response)
+}
+
+/// The `cancel_order_group` handler
+///
+/// Cancels all open legs of an order group.
+///
+/// DELETE /ordergroup/{id}
+///
+/// DELETE /ordergroup/{id}?symbol=ABC
+pub async fn cancel_order_group(
+    id: u64,
+    market: MarketRequest,
+    markets: Markets,
+) -> Result<impl Reply, Rejection> {
+    log::debug!("cancel_order_group; id = {}, market = {:?}", id, market);
+
+    match markets
+        .cancel_order_group(market.symbol.as_deref(), id)
+        .await
+        .map_err(engine_stopped)?
+    {
+        Ok(group) => Ok(warp::reply::json(&group)),
+        Err(acc_err) => Err(accounting_error(acc_err)),
+    }
+}
+
+/// The `cancel_all` handler
+///
+/// Cancels all resting orders of a signer, optionally only on one side of the order book,
+/// and responds with the removed orders.
+///
+/// Acts on all markets, unless a `symbol` is provided.
+///
+/// DELETE /orders?signer=Alice
+///
+/// DELETE /orders?signer=Alice&side=Buy&symbol=ABC
+pub async fn cancel_all(
+    request: CancelAllRequest,
+    markets: Markets,
+) -> Result<impl Reply, Rejection> {
+    log::debug!("cancel_all; request = {:?}", request);
+
+    if let Some(rejection) = is_valid_name(&request.signer).err() {
+        return Err(rejection);
+    }
+
+    match markets
+        .cancel_all(request.symbol.as_deref(), &request.signer, request.side)
+        .await
+        .map_err(engine_stopped)?
+    {
+        Ok(cancelled) => Ok(warp::reply::json(&cancelled)),
+        Err(acc_err) => Err(accounting_error(acc_err)),
+    }
+}
+
+/// The `positions` handler
+///
+/// Responds with the signers' positions, built from their fills, with the profit or loss
+/// that closing their units has realized, and that closing the rest at the mark price would.
+/// The mark price is the price of the last trade, unless `mark=mid` is provided.
+///
+/// Covers all signers and all markets, unless a `signer` or a `symbol` is provided.
+///
+/// GET /positions
+///
+/// GET /positions?signer=Alice&symbol=ABC&mark=mid
+pub async fn positions(
+    request: PositionsRequest,
+    markets: Markets,
+) -> Result<impl Reply, Rejection> {
+    log::debug!("positions; request = {:?}", request);
+
+    if let Some(signer) = &request.signer {
+        is_valid_name(signer)?;
+    }
+
+    match markets
+        .positions(
+            request.symbol.as_deref(),
+            request.signer.as_deref(),
+            request.mark.unwrap_or_default(),
+        )
+        .await
+        .map_err(engine_stopped)?
+    {
+        Ok(positions) => Ok(warp::reply::json(&positions)),
+        Err(acc_err) => Err(accounting_error(acc_err)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::is_valid_name;
@@ -1,2 +1,6 @@
+pub mod admin;
+pub mod engine;
 pub mod errors;
 pub mod handlers;
+pub mod markets;
+pub mod session;
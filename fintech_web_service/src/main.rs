@@ -1,11 +1,26 @@
 //! The "Fintech Web Service's" entry point.
 
+// The routes are nested deep enough for an optimized build to go past the default limit.
+#![recursion_limit = "256"]
+
+use fintech_common::core::ExecutionPriceRule;
+use fintech_common::fees::{FeeSchedule, FeeTier};
+use fintech_common::fx::{validate_currency, CurrencyScales, FxRates};
+use fintech_common::history::{HistoryConfig, NoOpSink};
+use fintech_common::limits::{LimitSchedule, Limits};
+use fintech_common::statements::StatementFilter;
 use fintech_common::trading_platform::TradingPlatform;
-use fintech_common::{OrderBookByPriceRequest, OrderBookRequest};
-use fintech_web_service::handlers;
+use fintech_common::types::InstrumentSpec;
+use fintech_common::{
+    AccountCloseRequest, AccountLimitsRequest, CancelAllRequest, MarketRequest,
+    OrderBookByPriceRequest, OrderBookRequest, PositionsRequest,
+};
+use fintech_web_service::engine;
+use fintech_web_service::markets::{self, Markets};
+use fintech_web_service::{admin, errors, handlers, session};
+use std::collections::BTreeMap;
 use std::env;
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::Duration;
 use warp::Filter;
 
 /// The "Fintech Web Service's" entry point.
@@ -18,75 +33,391 @@ async fn main() {
 
     let log = warp::log("fintech");
 
-    let trading_platform = Arc::new(Mutex::new(TradingPlatform::new()));
-    let trading_platform_state = warp::any().map(move || trading_platform.clone());
+    // Where the transaction log and the receipts go; see `HistoryConfig` for the format.
+    let history_config = match env::var("FINTECH_HISTORY") {
+        Ok(config) => config.parse::<HistoryConfig>().unwrap_or_else(|err| {
+            log::error!("{}", err);
+            std::process::exit(1);
+        }),
+        Err(_) => HistoryConfig::default(),
+    };
+    log::info!("History: {:?}", history_config);
+
+    // Each symbol is traded in a market of its own; the first one is the default market.
+    let symbols: Vec<String> = match env::var("FINTECH_SYMBOLS") {
+        Ok(symbols) => symbols.split(',').map(|s| s.trim().to_string()).collect(),
+        Err(_) => vec![markets::DEFAULT_SYMBOL.to_string()],
+    };
+    for (i, symbol) in symbols.iter().enumerate() {
+        if symbol.is_empty()
+            || !symbol.chars().all(|c| c.is_ascii_alphanumeric())
+            || symbols[..i].contains(symbol)
+        {
+            log::error!(
+                "Invalid symbol: \"{}\"; symbols have to be unique and alphanumeric",
+                symbol
+            );
+            std::process::exit(1);
+        }
+    }
+    log::info!("Symbols: {:?}", symbols);
+
+    let history_failed = |err: std::io::Error| -> ! {
+        log::error!("Failed to set up the history: {}", err);
+        std::process::exit(1);
+    };
+    // Withdrawals and transfers only happen in the ledger, so that's where the limits apply.
+    let invalid_limits = |err: String| -> ! {
+        log::error!("{}", err);
+        std::process::exit(1);
+    };
+    let mut limits = match env::var("FINTECH_LIMITS") {
+        Ok(limits) => LimitSchedule::new(
+            limits
+                .parse::<Limits>()
+                .unwrap_or_else(|err| invalid_limits(err)),
+        ),
+        Err(_) => LimitSchedule::default(),
+    };
+    // The limits of individual accounts, such as "Alice=100:1000:5/60;Bob=::10/60".
+    if let Ok(account_limits) = env::var("FINTECH_ACCOUNT_LIMITS") {
+        for entry in account_limits
+            .split(';')
+            .filter(|entry| !entry.trim().is_empty())
+        {
+            let Some((signer, account_limits)) = entry.split_once('=') else {
+                invalid_limits(format!(
+                    "Invalid account limits: \"{entry}\"; expected \"<signer>=<limits>\""
+                ));
+            };
+            limits = limits.with_account_limits(
+                signer.trim(),
+                account_limits
+                    .parse::<Limits>()
+                    .unwrap_or_else(|err| invalid_limits(err)),
+            );
+        }
+    }
+    log::info!("Limits: {:?}", limits);
+
+    // Conversions happen in the ledger, such as "EUR/USD=1.08,USD/EUR=0.92".
+    let fx_rates = match env::var("FINTECH_FX_RATES") {
+        Ok(fx_rates) => fx_rates.parse::<FxRates>().unwrap_or_else(|err| {
+            log::error!("{}", err);
+            std::process::exit(1);
+        }),
+        Err(_) => FxRates::default(),
+    };
+    log::info!("Exchange rates: {}", fx_rates);
+
+    // The decimals of the currencies that don't have two, such as "JPY=0,BTC=8".
+    let currency_scales = match env::var("FINTECH_CURRENCY_SCALES") {
+        Ok(scales) => scales.parse::<CurrencyScales>().unwrap_or_else(|err| {
+            log::error!("{}", err);
+            std::process::exit(1);
+        }),
+        Err(_) => CurrencyScales::default(),
+    };
+    log::info!("Currency scales: {}", currency_scales);
+
+    // The quote currencies of the symbols that aren't quoted in the ledger's, such as "ABC=EUR".
+    let mut quote_currencies = BTreeMap::new();
+    if let Ok(currencies) = env::var("FINTECH_QUOTE_CURRENCIES") {
+        for entry in currencies
+            .split(',')
+            .filter(|entry| !entry.trim().is_empty())
+        {
+            let quote_currency = entry
+                .split_once('=')
+                .map(|(symbol, currency)| (symbol.trim(), currency.trim()))
+                .filter(|(symbol, currency)| {
+                    symbols.iter().any(|s| s == symbol) && validate_currency(currency).is_ok()
+                });
+            let Some((symbol, currency)) = quote_currency else {
+                log::error!(
+                    "Invalid quote currency: \"{}\"; expected \"<symbol>=<currency>\" for one of the symbols",
+                    entry
+                );
+                std::process::exit(1);
+            };
+            quote_currencies.insert(symbol.to_string(), currency.to_string());
+        }
+    }
+    log::info!("Quote currencies: {:?}", quote_currencies);
+
+    // Every run starts with empty accounts, so file histories of a previous run are moved aside.
+    let ledger = TradingPlatform::with_history(
+        history_config
+            .build("tx_log")
+            .unwrap_or_else(|err| history_failed(err)),
+        Box::new(NoOpSink),
+    )
+    .with_limits(limits)
+    .with_fx_rates(fx_rates)
+    .with_currency_scales(currency_scales.clone());
+    let instrument = match env::var("FINTECH_INSTRUMENT") {
+        Ok(instrument) => instrument.parse::<InstrumentSpec>().unwrap_or_else(|err| {
+            log::error!("{}", err);
+            std::process::exit(1);
+        }),
+        Err(_) => InstrumentSpec::default(),
+    };
+    log::info!("Instrument: {:?}", instrument);
+
+    let fees = match env::var("FINTECH_FEES") {
+        Ok(fees) => FeeSchedule::new(fees.parse::<FeeTier>().unwrap_or_else(|err| {
+            log::error!("{}", err);
+            std::process::exit(1);
+        })),
+        Err(_) => FeeSchedule::default(),
+    };
+    log::info!("Fees: {:?}", fees);
+    // Markets only reserve what the ledger holds, so margin, which lends funds, isn't set up here.
+
+    let heartbeat_timeout = match env::var("FINTECH_HEARTBEAT_TIMEOUT") {
+        Ok(secs) => Duration::from_secs(secs.parse::<u64>().unwrap_or_else(|_| {
+            log::error!("Invalid heartbeat timeout in seconds: \"{}\"", secs);
+            std::process::exit(1);
+        })),
+        Err(_) => session::DEFAULT_HEARTBEAT_TIMEOUT,
+    };
+    log::info!("Session heartbeat timeout: {:?}", heartbeat_timeout);
+
+    let price_rule = match env::var("FINTECH_PRICE_RULE") {
+        Ok(price_rule) => price_rule
+            .parse::<ExecutionPriceRule>()
+            .unwrap_or_else(|err| {
+                log::error!("{}", err);
+                std::process::exit(1);
+            }),
+        Err(_) => ExecutionPriceRule::default(),
+    };
+    log::info!("Execution price rule: {}", price_rule);
+
+    let markets: Vec<(String, TradingPlatform)> = symbols
+        .into_iter()
+        .map(|symbol| {
+            let currency = quote_currencies
+                .get(&symbol)
+                .map_or(ledger.currency(), String::as_str);
+            let market = TradingPlatform::with_history(
+                history_config
+                    .build(&format!("{symbol}_tx_log"))
+                    .unwrap_or_else(|err| history_failed(err)),
+                history_config
+                    .build(&format!("{symbol}_receipts"))
+                    .unwrap_or_else(|err| history_failed(err)),
+            )
+            .with_currency(currency)
+            .with_currency_scales(currency_scales.clone())
+            .with_instrument(instrument.clone())
+            .with_fees(fees.clone())
+            .with_price_rule(price_rule);
+            (symbol, market)
+        })
+        .collect();
+    let command_capacity = match env::var("FINTECH_COMMAND_CAPACITY") {
+        Ok(capacity) => match capacity.parse::<usize>() {
+            Ok(capacity) if capacity > 0 => capacity,
+            _ => {
+                log::error!("Invalid command capacity: \"{}\"", capacity);
+                std::process::exit(1);
+            }
+        },
+        Err(_) => engine::DEFAULT_COMMAND_CAPACITY,
+    };
+    log::info!("Engine command capacity: {}", command_capacity);
+
+    // Administrative requests have to present this token; without it, they are all refused.
+    let admin_token = env::var("FINTECH_ADMIN_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty());
+    if admin_token.is_none() {
+        log::info!("No admin token; administrative requests are disabled");
+    }
+
+    let markets = Markets::spawn(ledger, markets, command_capacity);
+    let markets_state = warp::any().map(move || markets.clone());
 
     let deposit = warp::path!("account" / "deposit")
         .and(warp::post())
         .and(warp::body::content_length_limit(1024 * 16))
         .and(warp::body::json())
-        .and(trading_platform_state.clone())
+        .and(markets_state.clone())
         .and_then(handlers::deposit);
 
     let withdraw = warp::path!("account" / "withdraw")
         .and(warp::post())
         .and(warp::body::content_length_limit(1024 * 16))
         .and(warp::body::json())
-        .and(trading_platform_state.clone())
+        .and(markets_state.clone())
         .and_then(handlers::withdraw);
 
+    let deposit_units = warp::path!("account" / "units" / "deposit")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(markets_state.clone())
+        .and_then(handlers::deposit_units);
+
+    let withdraw_units = warp::path!("account" / "units" / "withdraw")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(markets_state.clone())
+        .and_then(handlers::withdraw_units);
+
     let send = warp::path!("account" / "send")
         .and(warp::post())
         .and(warp::body::content_length_limit(1024 * 16))
         .and(warp::body::json())
-        .and(trading_platform_state.clone())
+        .and(markets_state.clone())
         .and_then(handlers::send);
 
+    let convert = warp::path!("account" / "convert")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(markets_state.clone())
+        .and_then(handlers::convert);
+
     let balance_of = warp::path!("account")
         .and(warp::post())
         .and(warp::body::content_length_limit(1024 * 16))
         .and(warp::body::json())
-        .and(trading_platform_state.clone())
+        .and(markets_state.clone())
         .and_then(handlers::balance_of);
 
+    let account_limits = warp::path!("account" / "limits")
+        .and(warp::get())
+        .and(warp::query::<AccountLimitsRequest>())
+        .and(markets_state.clone())
+        .and_then(handlers::account_limits);
+
+    let close_account = warp::path!("account")
+        .and(warp::delete())
+        .and(warp::query::<AccountCloseRequest>())
+        .and(markets_state.clone())
+        .and_then(handlers::close_account);
+
     let process_order = warp::path!("order")
         .and(warp::post())
+        .and(warp::query::<MarketRequest>())
         .and(warp::body::content_length_limit(1024 * 16))
         .and(warp::body::json())
-        .and(trading_platform_state.clone())
+        .and(markets_state.clone())
         .and_then(handlers::process_order);
 
     let order_book = warp::path!("orderbook")
         .and(warp::get())
         .and(warp::query::<OrderBookRequest>())
-        .and(trading_platform_state.clone())
+        .and(markets_state.clone())
         .and_then(handlers::order_book);
 
     let order_book_by_price = warp::path!("orderbookbyprice")
         .and(warp::get())
         .and(warp::query::<OrderBookByPriceRequest>())
-        .and(trading_platform_state.clone())
+        .and(markets_state.clone())
         .and_then(handlers::order_book_by_price);
 
     let order_history = warp::path!("order" / "history")
         .and(warp::get())
-        .and(trading_platform_state.clone())
+        .and(markets_state.clone())
         .and_then(handlers::order_history);
 
+    let statement = warp::path!("account" / String / "statement")
+        .and(warp::get())
+        .and(warp::query::<MarketRequest>())
+        .and(warp::query::<StatementFilter>())
+        .and(markets_state.clone())
+        .and_then(handlers::statement);
+
+    let trial_balance = warp::path!("ledger" / "trial-balance")
+        .and(warp::get())
+        .and(markets_state.clone())
+        .and_then(handlers::trial_balance);
+
     let all_accounts = warp::path!("accounts")
         .and(warp::get())
-        .and(trading_platform_state.clone())
+        .and(markets_state.clone())
         .and_then(handlers::all_accounts);
 
+    let place_oco = warp::path!("ordergroup" / "oco")
+        .and(warp::post())
+        .and(warp::query::<MarketRequest>())
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(markets_state.clone())
+        .and_then(handlers::place_oco);
+
+    let place_bracket = warp::path!("ordergroup" / "bracket")
+        .and(warp::post())
+        .and(warp::query::<MarketRequest>())
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(markets_state.clone())
+        .and_then(handlers::place_bracket);
+
+    let order_group = warp::path!("ordergroup" / u64)
+        .and(warp::get())
+        .and(warp::query::<MarketRequest>())
+        .and(markets_state.clone())
+        .and_then(handlers::order_group);
+
+    let order_groups = warp::path!("ordergroups")
+        .and(warp::get())
+        .and(warp::query::<MarketRequest>())
+        .and(markets_state.clone())
+        .and_then(handlers::order_groups);
+
+    let cancel_order_group = warp::path!("ordergroup" / u64)
+        .and(warp::delete())
+        .and(warp::query::<MarketRequest>())
+        .and(markets_state.clone())
+        .and_then(handlers::cancel_order_group);
+
+    let cancel_all = warp::path!("orders")
+        .and(warp::delete())
+        .and(warp::query::<CancelAllRequest>())
+        .and(markets_state.clone())
+        .and_then(handlers::cancel_all);
+
+    let positions = warp::path!("positions")
+        .and(warp::get())
+        .and(warp::query::<PositionsRequest>())
+        .and(markets_state.clone())
+        .and_then(handlers::positions);
+
+    let session = session::route(markets_state.clone(), heartbeat_timeout);
+
+    let admin = admin::route(markets_state.clone(), admin_token);
+
     let routes = deposit
         .or(withdraw)
+        .or(deposit_units)
+        .or(withdraw_units)
         .or(send)
+        .or(convert)
         .or(balance_of)
+        .or(account_limits)
+        .or(close_account)
         .or(process_order)
         .or(order_book)
         .or(order_book_by_price)
         .or(order_history)
+        .or(statement)
+        .or(trial_balance)
         .or(all_accounts)
+        .or(place_oco)
+        .or(place_bracket)
+        .or(order_group)
+        .or(order_groups)
+        .or(cancel_order_group)
+        .or(cancel_all)
+        .or(positions)
+        .or(session)
+        .or(admin)
+        .recover(errors::handle_rejection)
         .with(log);
 
     // Start up the server
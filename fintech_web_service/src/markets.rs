@@ -0,0 +1,1722 @@
+//! Per-symbol markets, each matched by its own engine task
+//!
+//! Every symbol is traded in its own market, a [`TradingPlatform`] owned by its own [`Engine`],
+//! so orders for unrelated symbols are matched in parallel instead of waiting for each other.
+//!
+//! Cash and holdings are kept in the ledger, one more engine, whose platform only holds the accounts.
+//! A market can only settle with the funds and the units that have been reserved into it:
+//! 1. The worst-case cost of the buy orders, and the units of the sell orders, are withdrawn
+//!    from the signer's account in the ledger. If the signer can't afford them,
+//!    the orders are rejected without reaching the market.
+//! 2. The market deposits the reservation, processes the orders, and then releases
+//!    all funds and units that its open orders don't need anymore, including the proceeds
+//!    of sales and the units that were bought.
+//! 3. The released funds and units are deposited back into the ledger.
+//!
+//! The same funds are never in two places, so they can't be spent in two markets at once.
+//! A resting order keeps its reservation in its market until it's filled or cancelled,
+//! so deposits, withdrawals and transfers only have the ledger's part of an account at hand.
+//!
+//! A market's instrument can be quoted in another currency than the ledger's, in which case
+//! its orders are reserved for with the signer's funds in that currency.
+//!
+//! A signer's account, as reported, is the sum of their ledger account and of their accounts
+//! in all markets, with each market's funds in its quote currency. While an order is being processed, its reservation is briefly
+//! in neither of the snapshots.
+
+use crate::engine::{Engine, EngineStopped};
+use fintech_common::accounts::{AccountStatus, AccountSummary};
+use fintech_common::errors::{AccountError, AccountingError, FxError, OrderError};
+use fintech_common::fees::FeeSchedule;
+use fintech_common::journal::TrialBalance;
+use fintech_common::order_groups::OrderGroup;
+use fintech_common::positions::{MarkPrice, PositionSummary};
+use fintech_common::reconciliation::Reconciliation;
+use fintech_common::statements::{Statement, StatementFilter};
+use fintech_common::trading_platform::{PlatformArchive, TradingPlatform};
+use fintech_common::tx::{Leg, Tx};
+use fintech_common::types::{Money, Order, PartialOrder, Quantity, Receipt, Side};
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::Arc;
+
+pub use fintech_common::trading_platform::DEFAULT_SYMBOL;
+
+/// A market's engine, and the symbol, quote currency, its scale and the fees
+/// that its reservations are based on
+struct Market {
+    engine: Engine,
+    symbol: String,
+    currency: String,
+    scale: u8,
+    fees: FeeSchedule,
+}
+
+/// **A handle to the ledger and to the markets of all symbols**
+///
+/// Cheap to clone; all clones talk to the same engine tasks.
+#[derive(Clone)]
+pub struct Markets {
+    ledger: Engine,
+    markets: Arc<BTreeMap<String, Market>>,
+    /// The market of requests without a symbol
+    default_symbol: String,
+    /// The currency of the ledger's balances
+    currency: String,
+}
+
+impl Markets {
+    /// Moves the ledger and every market's platform into engine tasks of their own,
+    /// each with room for `capacity` waiting commands.
+    ///
+    /// The first market is the default one. Every market's platform trades the instrument
+    /// with the market's symbol.
+    ///
+    /// # Panics
+    /// - Panics if there are no markets, if `capacity` is 0, or if it's called outside of a Tokio runtime.
+    pub fn spawn(
+        ledger: TradingPlatform,
+        markets: Vec<(String, TradingPlatform)>,
+        capacity: usize,
+    ) -> Self {
+        let default_symbol = markets
+            .first()
+            .map(|(symbol, _)| symbol.clone())
+            .expect("There has to be at least one market!");
+
+        let markets = markets
+            .into_iter()
+            .map(|(symbol, trading_platform)| {
+                let fees = trading_platform.fees().clone();
+                let currency = trading_platform.currency().to_string();
+                let scale = trading_platform.currency_scales().scale(&currency);
+                let trading_platform = trading_platform.with_symbol(&symbol);
+                let engine = Engine::spawn(trading_platform, capacity);
+                let market = Market {
+                    engine,
+                    symbol: symbol.clone(),
+                    currency,
+                    scale,
+                    fees,
+                };
+                (symbol, market)
+            })
+            .collect();
+
+        Self {
+            currency: ledger.currency().to_string(),
+            ledger: Engine::spawn(ledger, capacity),
+            markets: Arc::new(markets),
+            default_symbol,
+        }
+    }
+
+    /// The engine that holds the funds that aren't reserved into any market
+    pub fn ledger(&self) -> &Engine {
+        &self.ledger
+    }
+
+    /// All symbols, in alphabetical order
+    pub fn symbols(&self) -> impl Iterator<Item = &str> {
+        self.markets.keys().map(String::as_str)
+    }
+
+    /// **The engine of a symbol's market, or of the default market if `symbol` is `None`**
+    ///
+    /// # Errors
+    /// - There's no market for the symbol, `AccountingError::MarketNotFound`
+    pub fn market(&self, symbol: Option<&str>) -> Result<&Engine, AccountingError> {
+        self.market_of(symbol).map(|market| &market.engine)
+    }
+
+    /// **The symbol of a market, or of the default market if `symbol` is `None`**
+    ///
+    /// # Errors
+    /// - There's no market for the symbol, `AccountingError::MarketNotFound`
+    pub fn symbol<'a>(&'a self, symbol: Option<&'a str>) -> Result<&'a str, AccountingError> {
+        let symbol = symbol.unwrap_or(&self.default_symbol);
+        match self.markets.contains_key(symbol) {
+            true => Ok(symbol),
+            false => Err(AccountingError::MarketNotFound(symbol.to_string())),
+        }
+    }
+
+    fn market_of(&self, symbol: Option<&str>) -> Result<&Market, AccountingError> {
+        let symbol = symbol.unwrap_or(&self.default_symbol);
+        self.markets
+            .get(symbol)
+            .ok_or(AccountingError::MarketNotFound(symbol.to_string()))
+    }
+
+    /// **All accounts with their balances and holdings, summed over the ledger and all markets**
+    ///
+    /// A market's funds count towards the balance in its quote currency; held funds are
+    /// only counted for the markets quoted in the ledger's currency.
+    ///
+    /// A sum that doesn't fit, which [`account`](Self::account) reports as an error,
+    /// is capped at the largest value and logged, so that one account doesn't fail the listing.
+    pub fn accounts(&self) -> BTreeMap<String, AccountSummary> {
+        let mut accounts: BTreeMap<String, AccountSummary> = self
+            .ledger
+            .snapshot()
+            .accounts()
+            .iter()
+            .map(|(signer, summary)| (signer.clone(), AccountSummary::clone(summary)))
+            .collect();
+
+        for market in self.markets.values() {
+            for (signer, reserved) in market.engine.snapshot().accounts() {
+                let sum = accounts.entry(signer.clone()).or_default();
+                if let Err(err) = self.add_to(signer, sum, reserved, &market.currency) {
+                    log::warn!("The summary of \"{}\" is capped: {}", signer, err);
+                }
+            }
+        }
+
+        accounts
+    }
+
+    /// **A signer's balance and holdings, summed over the ledger and all markets**
+    ///
+    /// See [`accounts`](Self::accounts).
+    ///
+    /// # Errors
+    /// - Account doesn't exist, `AccountError::NotFound`;
+    /// - A sum doesn't fit, `AccountError::OverFunded`, `FxError::OverFunded`
+    ///   or `AccountError::HoldingsOverflow`.
+    pub fn account(&self, signer: &str) -> Result<AccountSummary, AccountingError> {
+        let mut account = self.ledger.snapshot().account(signer).cloned();
+
+        for market in self.markets.values() {
+            if let Ok(reserved) = market.engine.snapshot().account(signer) {
+                let mut sum = account.unwrap_or_default();
+                self.add_to(signer, &mut sum, reserved, &market.currency)?;
+                account = Ok(sum);
+            }
+        }
+
+        account
+    }
+
+    /// **A signer's balance, summed over the ledger and all markets**
+    ///
+    /// # Errors
+    /// - Account doesn't exist, `AccountError::NotFound`
+    pub fn balance_of(&self, signer: &str) -> Result<Money, AccountingError> {
+        self.account(signer).map(|account| account.balance)
+    }
+
+    /// **Closes a signer's account in the ledger, and removes it from all markets**
+    ///
+    /// The markets go first, so that an account with resting orders in any of them stays open.
+    /// A market account that's left without open orders is empty, as the market has released
+    /// everything to the ledger, and its closure is recorded in the market, same as the releases.
+    /// See [`TradingPlatform::close_account`].
+    ///
+    /// An order placed while the account is being closed can still reserve from the ledger.
+    /// Its released funds then reopen the account, so they aren't lost.
+    pub async fn close_account(
+        &self,
+        signer: &str,
+        sweep_to: Option<&str>,
+    ) -> Result<Result<Vec<Tx>, AccountingError>, EngineStopped> {
+        // Statuses are kept in the ledger, so a frozen account is left alone in the markets, too.
+        let owner = signer.to_string();
+        let allowed = self
+            .ledger
+            .execute(move |trading_platform| trading_platform.accounts.check_outflow(&owner))
+            .await?;
+        if let Err(err) = allowed {
+            return Ok(Err(err));
+        }
+
+        for market in self.markets.values() {
+            let owner = signer.to_string();
+            let closed = market
+                .engine
+                .execute(move |trading_platform| {
+                    match trading_platform.close_account(&owner, None) {
+                        Err(AccountingError::Account(AccountError::NotFound(_))) => Ok(()),
+                        closed => closed.map(|_| ()),
+                    }
+                })
+                .await?;
+            if let Err(err) = closed {
+                return Ok(Err(err));
+            }
+        }
+
+        let (owner, recipient) = (signer.to_string(), sweep_to.map(str::to_string));
+        self.ledger
+            .execute(move |trading_platform| {
+                trading_platform.close_account(&owner, recipient.as_deref())
+            })
+            .await
+    }
+
+    /// **Sets the status of a signer's account in the ledger, and cancels the orders it doesn't allow**
+    ///
+    /// Statuses are kept in the ledger, which checks them before reserving for any order.
+    /// Resting orders that the account can't place anymore are cancelled in all markets,
+    /// and their funds are released. Closing the account is the same as
+    /// [`close_account`](Self::close_account) without a sweep.
+    /// See [`TradingPlatform::set_account_status`].
+    pub async fn set_account_status(
+        &self,
+        signer: &str,
+        status: AccountStatus,
+    ) -> Result<Result<Vec<Tx>, AccountingError>, EngineStopped> {
+        if status == AccountStatus::Closed {
+            return self.close_account(signer, None).await;
+        }
+
+        let owner = signer.to_string();
+        let txs = self
+            .ledger
+            .execute(move |trading_platform| trading_platform.set_account_status(&owner, status))
+            .await?;
+        if txs.is_ok() {
+            for side in [Side::Buy, Side::Sell] {
+                if !status.allows_order(&side) {
+                    let (cancelled, returned) =
+                        self.cancel_everywhere(signer, Some(side.clone())).await?;
+                    if let Err(err) = returned {
+                        log::error!(
+                            "Cancelled {} order(s) of \"{}\", whose funds couldn't all be returned: {}",
+                            cancelled.len(),
+                            signer,
+                            err
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(txs)
+    }
+
+    /// **The transaction logs of the ledger and of all markets**
+    ///
+    /// The ledger's transactions come first, followed by the ones of each market, by symbol.
+    pub async fn tx_log(&self) -> Result<Vec<Tx>, EngineStopped> {
+        let mut tx_log = self
+            .ledger
+            .execute(|trading_platform| trading_platform.tx_log.records())
+            .await?;
+
+        for market in self.markets.values() {
+            tx_log.extend(
+                market
+                    .engine
+                    .execute(|trading_platform| trading_platform.tx_log.records())
+                    .await?,
+            );
+        }
+
+        Ok(tx_log)
+    }
+
+    /// **Resets every market and then the ledger, optionally archiving each of them first**
+    ///
+    /// See [`TradingPlatform::reset`]. The ledger's archive comes first, followed by the one
+    /// of each market, by symbol; there are none if `archive` is `false`.
+    ///
+    /// The engines are reset one after another, not all at once. Funds of an order that is
+    /// being processed during the reset can land in the ledger after it has been reset,
+    /// so the service shouldn't take any other requests meanwhile.
+    pub async fn reset(
+        &self,
+        archive: bool,
+    ) -> Result<io::Result<Vec<PlatformArchive>>, EngineStopped> {
+        let reset = move |trading_platform: &mut TradingPlatform| {
+            let archived = archive.then(|| trading_platform.archive());
+            trading_platform.reset().map(|_| archived)
+        };
+
+        let mut archives = vec![];
+        for market in self.markets.values() {
+            match market.engine.execute(reset).await? {
+                Ok(archived) => archives.extend(archived),
+                Err(err) => return Ok(Err(err)),
+            }
+        }
+        let ledger = self.ledger.execute(reset).await?;
+
+        Ok(ledger.map(|archived| archived.into_iter().chain(archives).collect()))
+    }
+
+    /// **Checks the accounts of the ledger and of every market against their transaction logs**
+    ///
+    /// See [`TradingPlatform::reconcile`]. The ledger's reconciliation comes first,
+    /// followed by the one of each market, by symbol.
+    pub async fn reconcile(&self) -> Result<Vec<Reconciliation>, EngineStopped> {
+        let reconcile = |trading_platform: &mut TradingPlatform| trading_platform.reconcile();
+
+        let mut reconciliations = vec![self.ledger.execute(reconcile).await?];
+        for market in self.markets.values() {
+            reconciliations.push(market.engine.execute(reconcile).await?);
+        }
+
+        Ok(reconciliations)
+    }
+
+    /// **The trial balance of the ledger and of all markets, combined**
+    ///
+    /// The suspense account holds whatever is on its way between the ledger and a market,
+    /// so it nets to zero unless a reservation or a release is in flight.
+    /// See [`TrialBalance::combine`].
+    pub async fn trial_balance(&self) -> Result<TrialBalance, EngineStopped> {
+        let trial_balance = |trading_platform: &mut TradingPlatform| {
+            trading_platform.accounts.journal().trial_balance()
+        };
+
+        let mut parts = vec![self.ledger.execute(trial_balance).await?];
+        for market in self.markets.values() {
+            parts.push(market.engine.execute(trial_balance).await?);
+        }
+
+        Ok(TrialBalance::combine(parts))
+    }
+
+    /// **Processes an order in a symbol's market, with the funds reserved for it**
+    ///
+    /// See [`TradingPlatform::process_order`].
+    pub async fn process_order(
+        &self,
+        symbol: Option<&str>,
+        order: Order,
+    ) -> Result<Result<Receipt, AccountingError>, EngineStopped> {
+        let legs = [order.clone()];
+
+        self.reserve_and_execute(symbol, &legs, move |trading_platform| {
+            trading_platform.process_order(order)
+        })
+        .await
+    }
+
+    /// **Places a one-cancels-other pair in a symbol's market, with the funds reserved for it**
+    ///
+    /// See [`TradingPlatform::place_oco`].
+    pub async fn place_oco(
+        &self,
+        symbol: Option<&str>,
+        take_profit: Order,
+        stop_loss: Order,
+    ) -> Result<Result<OrderGroup, AccountingError>, EngineStopped> {
+        // Only one of the exits can be filled, and the stop-loss may need the most.
+        let legs = [stop_loss.clone()];
+
+        self.reserve_and_execute(symbol, &legs, move |trading_platform| {
+            trading_platform.place_oco(take_profit, stop_loss)
+        })
+        .await
+    }
+
+    /// **Places an entry with a bracket in a symbol's market, with the funds reserved for it**
+    ///
+    /// See [`TradingPlatform::place_bracket`].
+    pub async fn place_bracket(
+        &self,
+        symbol: Option<&str>,
+        entry: Order,
+        take_profit: Order,
+        stop_loss: Order,
+    ) -> Result<Result<OrderGroup, AccountingError>, EngineStopped> {
+        // Only one of the exits can be filled, and the stop-loss may need the most.
+        let legs = [entry.clone(), stop_loss.clone()];
+
+        self.reserve_and_execute(symbol, &legs, move |trading_platform| {
+            trading_platform.place_bracket(entry, take_profit, stop_loss)
+        })
+        .await
+    }
+
+    /// **Cancels an order group in a symbol's market, and releases its funds**
+    ///
+    /// See [`TradingPlatform::cancel_order_group`].
+    pub async fn cancel_order_group(
+        &self,
+        symbol: Option<&str>,
+        id: u64,
+    ) -> Result<Result<OrderGroup, AccountingError>, EngineStopped> {
+        let market = match self.market(symbol) {
+            Ok(market) => market,
+            Err(err) => return Ok(Err(err)),
+        };
+
+        self.execute_and_release(market, move |trading_platform| {
+            trading_platform.cancel_order_group(id)
+        })
+        .await
+    }
+
+    /// **Cancels all resting orders of a signer, and releases their funds**
+    ///
+    /// Acts on the market of the `symbol`, or on all markets if it's `None`.
+    /// See [`TradingPlatform::cancel_all`].
+    ///
+    /// # Errors
+    /// - Funds or units of the cancelled orders were left in suspense; see
+    ///   [`cancel_everywhere`](Self::cancel_everywhere) for the orders that were cancelled anyway.
+    pub async fn cancel_all(
+        &self,
+        symbol: Option<&str>,
+        signer: &str,
+        side: Option<Side>,
+    ) -> Result<Result<Vec<PartialOrder>, AccountingError>, EngineStopped> {
+        let markets: Vec<&Engine> = match symbol {
+            Some(symbol) => match self.market(Some(symbol)) {
+                Ok(market) => vec![market],
+                Err(err) => return Ok(Err(err)),
+            },
+            None => self.markets.values().map(|market| &market.engine).collect(),
+        };
+
+        let (cancelled, returned) = self.cancel_in(markets, signer, side).await?;
+        Ok(returned.map(|_| cancelled))
+    }
+
+    /// **Cancels all resting orders of a signer in all markets, whether or not their funds return**
+    ///
+    /// Returns the cancelled orders, along with the first error of returning their funds
+    /// and units to the ledger, if there's one: the orders are cancelled all the same,
+    /// and what the ledger can't take stays in the market or in suspense;
+    /// see [`cancel_all`](Self::cancel_all).
+    pub async fn cancel_everywhere(
+        &self,
+        signer: &str,
+        side: Option<Side>,
+    ) -> Result<(Vec<PartialOrder>, Result<(), AccountingError>), EngineStopped> {
+        let markets = self.markets.values().map(|market| &market.engine).collect();
+        self.cancel_in(markets, signer, side).await
+    }
+
+    /// Cancels the signer's orders in each of the `markets`, carrying on after any of them
+    /// fails to return the released funds, and returns the first such error.
+    async fn cancel_in(
+        &self,
+        markets: Vec<&Engine>,
+        signer: &str,
+        side: Option<Side>,
+    ) -> Result<(Vec<PartialOrder>, Result<(), AccountingError>), EngineStopped> {
+        let mut cancelled = vec![];
+        let mut returned = Ok(());
+        for market in markets {
+            let (signer, side) = (signer.to_string(), side.clone());
+            let (orders, released) = market
+                .execute(move |trading_platform| {
+                    let orders = trading_platform.cancel_all(&signer, side);
+                    (orders, release(trading_platform))
+                })
+                .await?;
+            cancelled.extend(orders);
+            let result = self.return_to_ledger(market, released).await?;
+            returned = returned.and(result);
+        }
+
+        Ok((cancelled, returned))
+    }
+
+    /// **Values the positions of all signers, or of the one `signer`, at the `mark` price**
+    ///
+    /// See [`TradingPlatform::positions`]. Covers all markets, by symbol, unless a `symbol` is provided.
+    pub async fn positions(
+        &self,
+        symbol: Option<&str>,
+        signer: Option<&str>,
+        mark: MarkPrice,
+    ) -> Result<Result<Vec<PositionSummary>, AccountingError>, EngineStopped> {
+        let markets: Vec<&Engine> = match symbol {
+            Some(symbol) => match self.market(Some(symbol)) {
+                Ok(market) => vec![market],
+                Err(err) => return Ok(Err(err)),
+            },
+            None => self.markets.values().map(|market| &market.engine).collect(),
+        };
+
+        let mut positions = vec![];
+        for market in markets {
+            let signer = signer.map(str::to_string);
+            positions.extend(
+                market
+                    .execute(move |trading_platform| {
+                        trading_platform.positions(signer.as_deref(), mark)
+                    })
+                    .await?,
+            );
+        }
+
+        Ok(Ok(positions))
+    }
+
+    /// **Builds a page of a signer's statement**
+    ///
+    /// From the ledger's transaction log, or from the market's if a `symbol` is provided;
+    /// the one of a market has the reservations that went into it, and what they were spent on.
+    /// See [`TradingPlatform::statement`].
+    pub async fn statement(
+        &self,
+        symbol: Option<&str>,
+        signer: &str,
+        filter: StatementFilter,
+    ) -> Result<Result<Statement, AccountingError>, EngineStopped> {
+        let engine = match symbol {
+            Some(symbol) => match self.market(Some(symbol)) {
+                Ok(market) => market,
+                Err(err) => return Ok(Err(err)),
+            },
+            None => &self.ledger,
+        };
+
+        let signer = signer.to_string();
+        engine
+            .execute(move |trading_platform| trading_platform.statement(&signer, &filter))
+            .await
+    }
+
+    /// Runs the reservation protocol around `f`, which is executed in the symbol's market.
+    ///
+    /// The funds of the first order's signer are reserved for all buy `orders`,
+    /// at their worst-case costs, and their units for all sell `orders`;
+    /// orders that are processed together share a signer.
+    ///
+    /// The market's platform holds what its resting orders need, so the reservation
+    /// is the only part of the signer's account there that new orders can use.
+    async fn reserve_and_execute<F, R>(
+        &self,
+        symbol: Option<&str>,
+        orders: &[Order],
+        f: F,
+    ) -> Result<Result<R, AccountingError>, EngineStopped>
+    where
+        F: FnOnce(&mut TradingPlatform) -> Result<R, AccountingError> + Send + 'static,
+        R: Send + 'static,
+    {
+        let market = match self.market_of(symbol) {
+            Ok(market) => market,
+            Err(err) => return Ok(Err(err)),
+        };
+
+        let signer = orders[0].signer.as_str();
+        let symbol = market.symbol.clone();
+        let currency = market.currency.clone();
+        let overflow = || {
+            AccountingError::ArithmeticOverflow(format!(
+                "The orders of {} are too large to reserve for.",
+                signer
+            ))
+        };
+        let reservation = orders
+            .iter()
+            .filter(|order| order.side == Side::Buy)
+            .try_fold(Money::ZERO, |sum, order| {
+                let cost = market.fees.worst_case_cost(
+                    signer,
+                    order.price,
+                    order.get_initial_amount(),
+                    market.scale,
+                )?;
+                sum.checked_add(cost).ok_or_else(overflow)
+            });
+        let units = orders
+            .iter()
+            .filter(|order| order.side == Side::Sell)
+            .try_fold(Quantity::ZERO, |sum, order| {
+                sum.checked_add(order.get_initial_amount())
+                    .ok_or_else(overflow)
+            });
+        let (reservation, units) = match (reservation, units) {
+            (Ok(reservation), Ok(units)) => (reservation, units),
+            (Err(err), _) | (_, Err(err)) => return Ok(Err(err)),
+        };
+
+        // Reserving nothing still makes sure that the signer's account exists.
+        let (owner, held, quoted) = (signer.to_string(), symbol.clone(), currency.clone());
+        let sides: Vec<Side> = orders.iter().map(|order| order.side.clone()).collect();
+        let reserved = self
+            .ledger
+            .execute(move |trading_platform| {
+                let (accounts, tx_log) =
+                    (&mut trading_platform.accounts, &mut trading_platform.tx_log);
+                // The markets don't know the statuses, so they are checked before reserving.
+                for side in &sides {
+                    accounts.check_order(&owner, side)?;
+                }
+                tx_log.record(accounts.reserve_in(&owner, &quoted, reservation, Leg::Out)?);
+                match accounts.reserve_units(&owner, &held, units, Leg::Out) {
+                    Ok(tx) => tx_log.record(tx),
+                    Err(err) => {
+                        // The funds were just reserved, so they fit back in.
+                        if let Ok(tx) = accounts.release_in(&owner, &quoted, reservation, Leg::In) {
+                            tx_log.record(tx);
+                        }
+                        return Err(err);
+                    }
+                }
+                Ok(())
+            })
+            .await?;
+        // Not having what the orders need is the orders' risk check failing.
+        match reserved {
+            Err(AccountingError::Account(AccountError::UnderFunded(signer, amount)))
+            | Err(AccountingError::Fx(FxError::UnderFunded(signer, _, amount))) => {
+                return Ok(Err(OrderError::UnderFunded(signer, amount).into()))
+            }
+            Err(AccountingError::Account(AccountError::HoldingsInsufficient(
+                signer,
+                symbol,
+                units,
+            ))) => {
+                return Ok(Err(
+                    OrderError::HoldingsInsufficient(signer, symbol, units).into()
+                ))
+            }
+            Err(err) => return Ok(Err(err)),
+            Ok(()) => {}
+        }
+
+        let (owner, held, quoted) = (signer.to_string(), symbol.clone(), currency.clone());
+        let executed = market
+            .engine
+            .execute(move |trading_platform| {
+                let (accounts, tx_log) =
+                    (&mut trading_platform.accounts, &mut trading_platform.tx_log);
+                match accounts.reserve_in(&owner, &quoted, reservation, Leg::In) {
+                    Ok(tx) => tx_log.record(tx),
+                    Err(err) => {
+                        let released = Released {
+                            funds: vec![(owner.clone(), reservation)],
+                            units: vec![(owner, units)],
+                            symbol: held,
+                            currency: quoted,
+                        };
+                        return (Err(err), released);
+                    }
+                }
+                match accounts.reserve_units(&owner, &held, units, Leg::In) {
+                    Ok(tx) => tx_log.record(tx),
+                    Err(err) => {
+                        // The funds are released along with all the others.
+                        let mut released = release(trading_platform);
+                        released.units.push((owner, units));
+                        return (Err(err), released);
+                    }
+                }
+
+                let result = f(trading_platform);
+                (result, release(trading_platform))
+            })
+            .await;
+
+        match executed {
+            Ok((result, released)) => {
+                let returned = self.return_to_ledger(&market.engine, released).await?;
+                Ok(returned.and(result))
+            }
+            Err(stopped) => {
+                let released = Released {
+                    funds: vec![(signer.to_string(), reservation)],
+                    units: vec![(signer.to_string(), units)],
+                    symbol,
+                    currency,
+                };
+                // The market has stopped, so what the ledger can't take stays in suspense.
+                let _ = self.return_to_ledger(&market.engine, released).await?;
+                Err(stopped)
+            }
+        }
+    }
+
+    /// Executes `f` in a market, and returns the funds and units that it released to the ledger.
+    async fn execute_and_release<F, R>(
+        &self,
+        market: &Engine,
+        f: F,
+    ) -> Result<Result<R, AccountingError>, EngineStopped>
+    where
+        F: FnOnce(&mut TradingPlatform) -> Result<R, AccountingError> + Send + 'static,
+        R: Send + 'static,
+    {
+        let (result, released) = market
+            .execute(move |trading_platform| {
+                let result = f(trading_platform);
+                (result, release(trading_platform))
+            })
+            .await?;
+
+        let returned = self.return_to_ledger(market, released).await?;
+        Ok(returned.and(result))
+    }
+
+    /// Deposits the funds and units released by the `market` back into the ledger.
+    ///
+    /// Whatever the ledger can't take, as it would overflow the account, is put back into the market,
+    /// where it's still the signer's, and where it's released again after the market's next command.
+    /// Both logs record it, so both reconcile.
+    ///
+    /// # Errors
+    /// - The market can't take it back either, the error of putting it back;
+    ///   it's left in suspense then.
+    async fn return_to_ledger(
+        &self,
+        market: &Engine,
+        released: Released,
+    ) -> Result<Result<(), AccountingError>, EngineStopped> {
+        if released.funds.is_empty() && released.units.is_empty() {
+            return Ok(Ok(()));
+        }
+
+        let kept = self
+            .ledger
+            .execute(move |trading_platform| deposit_released(trading_platform, released, false))
+            .await?;
+        if kept.funds.is_empty() && kept.units.is_empty() {
+            return Ok(Ok(()));
+        }
+
+        log::warn!(
+            "The ledger couldn't take back funds and units released by {}; they stay in the market: {:?}",
+            kept.symbol,
+            kept
+        );
+        let lost = market
+            .execute(move |trading_platform| deposit_released(trading_platform, kept, true))
+            .await?;
+        match (lost.funds.first(), lost.units.first()) {
+            (None, None) => Ok(Ok(())),
+            (Some((signer, amount)), _) => {
+                log::error!("Funds and units were left in suspense: {:?}", lost);
+                Ok(Err(AccountError::OverFunded(signer.clone(), *amount).into()))
+            }
+            (None, Some((signer, units))) => {
+                log::error!("Funds and units were left in suspense: {:?}", lost);
+                Ok(Err(AccountError::HoldingsOverflow(
+                    signer.clone(),
+                    lost.symbol.clone(),
+                    *units,
+                )
+                .into()))
+            }
+        }
+    }
+
+    /// Adds the `signer`'s part in a market quoted in the `currency` to their sum.
+    ///
+    /// # Errors
+    /// - A sum doesn't fit, see [`accounts`](Self::accounts), the first one that doesn't;
+    ///   all parts are still added, and every sum that doesn't fit is capped at the largest value.
+    fn add_to(
+        &self,
+        signer: &str,
+        sum: &mut AccountSummary,
+        part: &AccountSummary,
+        currency: &str,
+    ) -> Result<(), AccountingError> {
+        let mut overflow = None;
+        let over_funded =
+            |amount| AccountingError::Account(AccountError::OverFunded(signer.to_string(), amount));
+        if currency == self.currency {
+            if sum.balance.checked_add(part.balance).is_none() {
+                overflow.get_or_insert(over_funded(part.balance));
+            }
+            if sum.held.checked_add(part.held).is_none() {
+                overflow.get_or_insert(over_funded(part.held));
+            }
+            sum.balance = sum.balance.saturating_add(part.balance);
+            sum.held = sum.held.saturating_add(part.held);
+        } else if !part.balance.is_zero() {
+            let balance = sum.balances.entry(currency.to_string()).or_default();
+            if balance.checked_add(part.balance).is_none() {
+                overflow.get_or_insert(AccountingError::Fx(FxError::OverFunded(
+                    signer.to_string(),
+                    currency.to_string(),
+                    part.balance,
+                )));
+            }
+            *balance = balance.saturating_add(part.balance);
+        }
+        for (units, part_units) in [
+            (&mut sum.holdings, &part.holdings),
+            (&mut sum.held_units, &part.held_units),
+        ] {
+            for (symbol, part_units) in part_units {
+                let held = units.entry(symbol.clone()).or_default();
+                if held.checked_add(*part_units).is_none() {
+                    overflow.get_or_insert(AccountingError::Account(
+                        AccountError::HoldingsOverflow(
+                            signer.to_string(),
+                            symbol.clone(),
+                            *part_units,
+                        ),
+                    ));
+                }
+                *held = held.saturating_add(*part_units);
+            }
+        }
+
+        overflow.map_or(Ok(()), Err)
+    }
+}
+
+/// The funds, in its quote currency, and the units of its symbol that a market has released
+#[derive(Debug)]
+struct Released {
+    funds: Vec<(String, Money)>,
+    units: Vec<(String, Quantity)>,
+    symbol: String,
+    currency: String,
+}
+
+/// Deposits the `released` funds and units into the platform's accounts from suspense,
+/// and returns the ones that don't fit.
+///
+/// They arrive as a release, unless they go back into the market that released them,
+/// where they arrive as a reservation again.
+fn deposit_released(
+    trading_platform: &mut TradingPlatform,
+    released: Released,
+    reserve: bool,
+) -> Released {
+    let (accounts, tx_log) = (&mut trading_platform.accounts, &mut trading_platform.tx_log);
+    let mut kept = Released {
+        funds: vec![],
+        units: vec![],
+        symbol: released.symbol,
+        currency: released.currency,
+    };
+    for (signer, amount) in released.funds {
+        let deposited = match reserve {
+            true => accounts.reserve_in(&signer, &kept.currency, amount, Leg::In),
+            false => accounts.release_in(&signer, &kept.currency, amount, Leg::In),
+        };
+        match deposited {
+            Ok(tx) => tx_log.record(tx),
+            Err(_) => kept.funds.push((signer, amount)),
+        }
+    }
+    for (signer, units) in released.units {
+        let deposited = match reserve {
+            true => accounts.reserve_units(&signer, &kept.symbol, units, Leg::In),
+            false => accounts.release_units(&signer, &kept.symbol, units, Leg::In),
+        };
+        match deposited {
+            Ok(tx) => tx_log.record(tx),
+            Err(_) => kept.units.push((signer, units)),
+        }
+    }
+    kept
+}
+
+/// Releases all funds and units that a market's open orders don't need.
+fn release(trading_platform: &mut TradingPlatform) -> Released {
+    Released {
+        funds: trading_platform.release_uncommitted(),
+        units: trading_platform.release_uncommitted_units(),
+        symbol: trading_platform.symbol().to_string(),
+        currency: trading_platform.currency().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::DEFAULT_COMMAND_CAPACITY;
+    use fintech_common::errors::LimitError;
+    use fintech_common::fees::{FeeTier, DEFAULT_FEE_ACCOUNT};
+    use fintech_common::journal::{Asset, LedgerAccount, SystemAccount};
+    use fintech_common::limits::{Limit, LimitSchedule, Limits};
+    use fintech_common::types::{Amount, Price};
+
+    fn markets(symbols: &[&str]) -> Markets {
+        let mut ledger = TradingPlatform::new();
+        for signer in ["Alice", "Bob"] {
+            ledger.deposit(signer, 100.into()).unwrap();
+            for symbol in symbols {
+                ledger.deposit_units(signer, symbol, 10.into()).unwrap();
+            }
+        }
+        let fees = FeeSchedule::new(FeeTier::new(0, 10).unwrap());
+
+        Markets::spawn(
+            ledger,
+            symbols
+                .iter()
+                .map(|symbol| {
+                    let market = TradingPlatform::new().with_fees(fees.clone());
+                    (symbol.to_string(), market)
+                })
+                .collect(),
+            DEFAULT_COMMAND_CAPACITY,
+        )
+    }
+
+    #[tokio::test]
+    async fn reservations_prevent_spending_the_same_funds_in_two_markets() {
+        let markets = markets(&["AAA", "BBB"]);
+
+        // 9 units at 11 cost 99.09 with the fee, rounded down to the cent,
+        // which leaves less than 1 in the ledger.
+        let receipt = markets
+            .process_order(
+                Some("AAA"),
+                Order::new(11, 9, Side::Buy, "Alice".to_string()),
+            )
+            .await
+            .unwrap();
+        assert!(receipt.is_ok());
+        assert_eq!(
+            AccountingError::Order(OrderError::UnderFunded(
+                "Alice".to_string(),
+                "11.01".parse().unwrap()
+            )),
+            markets
+                .process_order(
+                    Some("BBB"),
+                    Order::new(11, 1, Side::Buy, "Alice".to_string()),
+                )
+                .await
+                .unwrap()
+                .unwrap_err()
+        );
+
+        assert_eq!(Ok(Money::from(100)), markets.balance_of("Alice"));
+        assert_eq!(
+            Ok(&"0.91".parse().unwrap()),
+            markets.ledger().snapshot().balance_of("Alice")
+        );
+        assert!(markets
+            .market(Some("BBB"))
+            .unwrap()
+            .snapshot()
+            .order_book(false, false)
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn limits_apply_to_withdrawals_but_not_to_reservations() {
+        let mut ledger = TradingPlatform::new().with_limits(LimitSchedule::new(Limits {
+            per_transaction: Some(50.into()),
+            ..Default::default()
+        }));
+        ledger.deposit("Alice", 100.into()).unwrap();
+        let markets = Markets::spawn(
+            ledger,
+            vec![("AAA".to_string(), TradingPlatform::new())],
+            DEFAULT_COMMAND_CAPACITY,
+        );
+
+        markets
+            .process_order(None, Order::new(10, 6, Side::Buy, "Alice".to_string()))
+            .await
+            .unwrap()
+            .unwrap();
+        let withdraw = |amount: Money| {
+            markets
+                .ledger()
+                .execute(move |trading_platform| trading_platform.withdraw("Alice", amount))
+        };
+        assert_eq!(
+            Err(AccountingError::Limit(LimitError::Exceeded(
+                "Alice".to_string(),
+                Limit::PerTransaction
+            ))),
+            withdraw(60.into()).await.unwrap()
+        );
+        assert!(withdraw(40.into()).await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn fills_and_cancels_release_funds_to_the_ledger() {
+        let markets = markets(&["AAA", "BBB"]);
+
+        markets
+            .process_order(None, Order::new(10, 5, Side::Buy, "Alice".to_string()))
+            .await
+            .unwrap()
+            .unwrap();
+        markets
+            .process_order(None, Order::new(10, 2, Side::Sell, "Bob".to_string()))
+            .await
+            .unwrap()
+            .unwrap();
+
+        // Bob's proceeds, less the 0.1% taker fee, are back in the ledger right away.
+        let ledger = markets.ledger().snapshot();
+        assert_eq!(Ok(&"119.98".parse().unwrap()), ledger.balance_of("Bob"));
+        assert_eq!(Ok(Money::from(80)), markets.balance_of("Alice"));
+
+        // The units that Alice bought are back in the ledger, as well as the ones Bob didn't sell.
+        let ledger = markets.ledger().snapshot();
+        assert_eq!(
+            Some(&Quantity::from(12)),
+            ledger.account("Alice").unwrap().holdings.get("AAA")
+        );
+        assert_eq!(
+            Some(&Quantity::from(8)),
+            ledger.account("Bob").unwrap().holdings.get("AAA")
+        );
+
+        let cancelled = markets.cancel_all(None, "Alice", None).await.unwrap();
+        assert_eq!(1, cancelled.unwrap().len());
+        assert_eq!(
+            Ok(&Money::from(80)),
+            markets.ledger().snapshot().balance_of("Alice")
+        );
+        assert_eq!(
+            Some("0.02".parse().unwrap()),
+            markets
+                .accounts()
+                .get(fintech_common::fees::DEFAULT_FEE_ACCOUNT)
+                .map(|account| account.balance)
+        );
+    }
+
+    #[tokio::test]
+    async fn resting_sell_orders_keep_their_units_in_the_market() {
+        let markets = markets(&["AAA", "BBB"]);
+
+        markets
+            .process_order(
+                Some("BBB"),
+                Order::new(10, 6, Side::Sell, "Bob".to_string()),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        // Only 4 units are left in the ledger to sell.
+        assert_eq!(
+            AccountingError::Order(OrderError::HoldingsInsufficient(
+                "Bob".to_string(),
+                "BBB".to_string(),
+                5.into()
+            )),
+            markets
+                .process_order(
+                    Some("BBB"),
+                    Order::new(11, 5, Side::Sell, "Bob".to_string()),
+                )
+                .await
+                .unwrap()
+                .unwrap_err()
+        );
+
+        let bob = markets.account("Bob").unwrap();
+        assert_eq!(Some(&Quantity::from(10)), bob.holdings.get("BBB"));
+        assert_eq!(
+            Some(&Quantity::from(4)),
+            markets
+                .ledger()
+                .snapshot()
+                .account("Bob")
+                .unwrap()
+                .holdings
+                .get("BBB")
+        );
+        // The funds of a sell order aren't touched.
+        assert_eq!(
+            Ok(&Money::from(100)),
+            markets.ledger().snapshot().balance_of("Bob")
+        );
+    }
+
+    #[tokio::test]
+    async fn close_account_waits_for_resting_orders_in_all_markets() {
+        let markets = markets(&["AAA", "BBB"]);
+
+        markets
+            .process_order(
+                Some("BBB"),
+                Order::new(10, 1, Side::Buy, "Alice".to_string()),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            AccountingError::Account(AccountError::HasOpenOrders("Alice".to_string())),
+            markets
+                .close_account("Alice", Some("Bob"))
+                .await
+                .unwrap()
+                .unwrap_err()
+        );
+
+        markets
+            .cancel_all(None, "Alice", None)
+            .await
+            .unwrap()
+            .unwrap();
+        markets
+            .close_account("Alice", Some("Bob"))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            Err(AccountingError::Account(AccountError::NotFound(
+                "Alice".to_string()
+            ))),
+            markets.account("Alice")
+        );
+        let bob = markets.account("Bob").unwrap();
+        assert_eq!(Money::from(200), bob.balance);
+        assert_eq!(Some(&Quantity::from(20)), bob.holdings.get("BBB"));
+
+        // The closures in the markets are recorded, too.
+        let reconciliations = markets.reconcile().await.unwrap();
+        assert!(reconciliations.iter().all(Reconciliation::is_consistent));
+    }
+
+    #[tokio::test]
+    async fn reset_archives_and_clears_the_ledger_and_all_markets() {
+        let markets = markets(&["AAA", "BBB"]);
+
+        markets
+            .process_order(
+                Some("BBB"),
+                Order::new(10, 1, Side::Buy, "Alice".to_string()),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        let archives = markets.reset(true).await.unwrap().unwrap();
+        assert_eq!(3, archives.len());
+        assert_eq!(2, archives[0].accounts.len());
+        assert!(archives[1].order_book.is_empty());
+        assert_eq!("BBB", archives[2].symbol);
+        assert_eq!(1, archives[2].order_book.len());
+
+        // Only the markets' fee account is opened again.
+        assert_eq!(
+            vec![DEFAULT_FEE_ACCOUNT],
+            markets.accounts().keys().collect::<Vec<_>>()
+        );
+        assert!(markets.tx_log().await.unwrap().is_empty());
+        assert!(markets
+            .market(Some("BBB"))
+            .unwrap()
+            .snapshot()
+            .order_book(false, false)
+            .is_empty());
+
+        assert!(markets.reset(false).await.unwrap().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn trial_balance_nets_the_suspense_account_across_markets() {
+        let markets = markets(&["AAA", "BBB"]);
+
+        markets
+            .process_order(
+                Some("AAA"),
+                Order::new(10, 2, Side::Sell, "Alice".to_string()),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        markets
+            .process_order(Some("AAA"), Order::new(10, 1, Side::Buy, "Bob".to_string()))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let trial_balance = markets.trial_balance().await.unwrap();
+        assert!(trial_balance.is_balanced());
+        // Alice's remaining unit is reserved into the market, but it's still hers.
+        assert!(trial_balance
+            .lines
+            .iter()
+            .all(|line| line.account != LedgerAccount::System(SystemAccount::Suspense)));
+        let credit_of = |account: LedgerAccount, asset: Asset| {
+            trial_balance
+                .lines
+                .iter()
+                .find(|line| line.account == account && line.asset == asset)
+                .map(|line| line.credit)
+        };
+        assert_eq!(
+            Some(Amount::from(9)),
+            credit_of(
+                LedgerAccount::Customer("Alice".to_string()),
+                Asset::Units("AAA".to_string())
+            )
+        );
+        // Bob pays 10 bps as the taker.
+        assert_eq!(
+            Some(Amount::from(Money::new(1, 2))),
+            credit_of(LedgerAccount::System(SystemAccount::Fees), Asset::Cash)
+        );
+    }
+
+    #[tokio::test]
+    async fn released_funds_that_the_ledger_cant_take_stay_in_the_market() {
+        let mut ledger = TradingPlatform::new();
+        ledger
+            .deposit("Alice", Money::from(u64::MAX - 100))
+            .unwrap();
+        ledger.deposit_units("Alice", "AAA", 10.into()).unwrap();
+        ledger.deposit("Bob", 1_000.into()).unwrap();
+        let markets = Markets::spawn(
+            ledger,
+            vec![("AAA".to_string(), TradingPlatform::new())],
+            DEFAULT_COMMAND_CAPACITY,
+        );
+
+        for order in [
+            Order::new(100, 10, Side::Sell, "Alice".to_string()),
+            Order::new(100, 10, Side::Buy, "Bob".to_string()),
+        ] {
+            assert!(markets.process_order(None, order).await.unwrap().is_ok());
+        }
+
+        // Alice's proceeds would overflow her balance in the ledger, so the market keeps them.
+        let market = markets.market(None).unwrap();
+        assert_eq!(
+            Ok(&Money::from(1_000)),
+            market.snapshot().balance_of("Alice")
+        );
+        assert_eq!(
+            Err(AccountingError::Account(AccountError::OverFunded(
+                "Alice".to_string(),
+                1_000.into()
+            ))),
+            markets.account("Alice")
+        );
+        // The listing caps her balance, rather than failing for everyone.
+        let accounts = markets.accounts();
+        assert_eq!(Money::MAX, accounts["Alice"].balance);
+        assert!(accounts.contains_key("Bob"));
+        let reconciliations = markets.reconcile().await.unwrap();
+        assert!(reconciliations.iter().all(Reconciliation::is_consistent));
+
+        // Once there's room, the market's next command returns them.
+        markets
+            .ledger()
+            .execute(|trading_platform| trading_platform.withdraw("Alice", 1_000.into()))
+            .await
+            .unwrap()
+            .unwrap();
+        markets
+            .cancel_all(None, "Bob", None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(Ok(Money::from(u64::MAX - 100)), markets.balance_of("Alice"));
+        assert!(market.snapshot().balance_of("Alice").unwrap().is_zero());
+        let reconciliations = markets.reconcile().await.unwrap();
+        assert!(reconciliations.iter().all(Reconciliation::is_consistent));
+    }
+
+    #[tokio::test]
+    async fn extreme_values_are_errors_rather_than_panics() {
+        let mut ledger = TradingPlatform::new();
+        ledger.deposit("Alice", Money::MAX).unwrap();
+        ledger
+            .deposit_units("Alice", "AAA", Quantity::from(u64::MAX))
+            .unwrap();
+        let markets = Markets::spawn(
+            ledger,
+            vec![(
+                "AAA".to_string(),
+                TradingPlatform::new().with_fees(FeeSchedule::new(FeeTier::new(10, 20).unwrap())),
+            )],
+            DEFAULT_COMMAND_CAPACITY,
+        );
+
+        // A reservation that doesn't fit is rejected, and nothing moves.
+        assert!(matches!(
+            markets
+                .process_order(
+                    None,
+                    Order::new(u64::MAX, 2, Side::Buy, "Alice".to_string())
+                )
+                .await
+                .unwrap(),
+            Err(AccountingError::ArithmeticOverflow(_))
+        ));
+        assert_eq!(Ok(Money::MAX), markets.balance_of("Alice"));
+
+        // All of Alice's units rest in the market, so units that she gets in the ledger
+        // make her holdings too large to sum up.
+        markets
+            .process_order(
+                None,
+                Order::new(1, u64::MAX, Side::Sell, "Alice".to_string()),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        markets
+            .ledger()
+            .execute(|trading_platform| trading_platform.deposit_units("Alice", "AAA", 1.into()))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            Err(AccountingError::Account(AccountError::HoldingsOverflow(
+                "Alice".to_string(),
+                "AAA".to_string(),
+                Quantity::from(u64::MAX)
+            ))),
+            markets.account("Alice")
+        );
+        assert_eq!(
+            Some(&Quantity::MAX),
+            markets.accounts()["Alice"].holdings.get("AAA")
+        );
+
+        // Cancelling brings the units back to the ledger, which can't take all of them,
+        // so they stay in the market, and the books still balance.
+        markets
+            .cancel_all(None, "Alice", None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(markets.account("Alice").is_err());
+        let reconciliations = markets.reconcile().await.unwrap();
+        assert!(reconciliations.iter().all(Reconciliation::is_consistent));
+        assert!(markets.trial_balance().await.unwrap().is_balanced());
+    }
+
+    #[tokio::test]
+    async fn reconcile_replays_reservations_and_releases() {
+        let markets = markets(&["AAA", "BBB"]);
+
+        markets
+            .process_order(
+                Some("AAA"),
+                Order::new(10, 2, Side::Sell, "Alice".to_string()),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        markets
+            .process_order(Some("AAA"), Order::new(10, 1, Side::Buy, "Bob".to_string()))
+            .await
+            .unwrap()
+            .unwrap();
+        markets
+            .cancel_all(None, "Alice", None)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let reconciliations = markets.reconcile().await.unwrap();
+        assert_eq!(3, reconciliations.len());
+        assert!(reconciliations.iter().all(Reconciliation::is_consistent));
+    }
+
+    #[tokio::test]
+    async fn markets_quoted_in_another_currency_reserve_it() {
+        let mut ledger = TradingPlatform::new();
+        ledger.deposit_in("Alice", "EUR", 100.into()).unwrap();
+        ledger.deposit("Bob", 100.into()).unwrap();
+        ledger.deposit_units("Bob", "EUX", 10.into()).unwrap();
+        let markets = Markets::spawn(
+            ledger,
+            vec![(
+                "EUX".to_string(),
+                TradingPlatform::new().with_currency("EUR"),
+            )],
+            DEFAULT_COMMAND_CAPACITY,
+        );
+
+        // Bob only has dollars.
+        assert_eq!(
+            Err(AccountingError::Order(OrderError::UnderFunded(
+                "Bob".to_string(),
+                10.into()
+            ))),
+            markets
+                .process_order(None, Order::new(10, 1, Side::Buy, "Bob".to_string()))
+                .await
+                .unwrap()
+                .map(|_| ())
+        );
+        markets
+            .process_order(None, Order::new(10, 3, Side::Buy, "Alice".to_string()))
+            .await
+            .unwrap()
+            .unwrap();
+        markets
+            .process_order(None, Order::new(10, 2, Side::Sell, "Bob".to_string()))
+            .await
+            .unwrap()
+            .unwrap();
+
+        // Alice's resting order keeps 10 euros in the market, which still count as hers.
+        let alice = markets.account("Alice").unwrap();
+        assert_eq!(Money::ZERO, alice.balance);
+        assert_eq!(
+            BTreeMap::from([("EUR".to_string(), 80.into())]),
+            alice.balances
+        );
+        let bob = markets.account("Bob").unwrap();
+        assert_eq!(Money::from(100), bob.balance);
+        assert_eq!(
+            BTreeMap::from([("EUR".to_string(), 20.into())]),
+            bob.balances
+        );
+
+        let trial_balance = markets.trial_balance().await.unwrap();
+        assert!(trial_balance.is_balanced());
+        assert!(trial_balance
+            .lines
+            .iter()
+            .all(|line| line.account != LedgerAccount::System(SystemAccount::Suspense)));
+        let reconciliations = markets.reconcile().await.unwrap();
+        assert!(reconciliations.iter().all(Reconciliation::is_consistent));
+    }
+
+    #[tokio::test]
+    async fn unknown_symbol_is_rejected() {
+        let markets = markets(&["AAA"]);
+
+        assert_eq!(vec!["AAA"], markets.symbols().collect::<Vec<_>>());
+        assert_eq!(
+            AccountingError::MarketNotFound("ZZZ".to_string()),
+            markets
+                .process_order(
+                    Some("ZZZ"),
+                    Order::new(10, 1, Side::Buy, "Alice".to_string()),
+                )
+                .await
+                .unwrap()
+                .unwrap_err()
+        );
+        assert_eq!(Ok(Money::from(100)), markets.balance_of("Alice"));
+    }
+
+    #[tokio::test]
+    async fn oversized_orders_are_rejected_without_a_reservation() {
+        let markets = markets(&["AAA"]);
+
+        for order in [
+            Order::new(u64::MAX, 2, Side::Buy, "Alice".to_string()),
+            Order::new(u64::MAX, 1, Side::Buy, "Alice".to_string()),
+        ] {
+            assert!(matches!(
+                markets.process_order(Some("AAA"), order).await.unwrap(),
+                Err(AccountingError::ArithmeticOverflow(_))
+            ));
+        }
+        assert_eq!(Ok(Money::from(100)), markets.balance_of("Alice"));
+        assert_eq!(
+            Ok(&Money::from(100)),
+            markets.ledger().snapshot().balance_of("Alice")
+        );
+    }
+
+    #[tokio::test]
+    async fn freezing_cancels_orders_in_all_markets_and_stops_reservations() {
+        let markets = markets(&["AAA", "BBB"]);
+
+        for symbol in ["AAA", "BBB"] {
+            markets
+                .process_order(
+                    Some(symbol),
+                    Order::new(5, 2, Side::Buy, "Alice".to_string()),
+                )
+                .await
+                .unwrap()
+                .unwrap();
+        }
+        markets
+            .process_order(
+                Some("AAA"),
+                Order::new(50, 1, Side::Sell, "Alice".to_string()),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        // Withdraw-only cancels the buys, and keeps the sell.
+        markets
+            .set_account_status("Alice", AccountStatus::WithdrawOnly)
+            .await
+            .unwrap()
+            .unwrap();
+        let alice = markets.account("Alice").unwrap();
+        assert_eq!(AccountStatus::WithdrawOnly, alice.status);
+        assert!(alice.held.is_zero());
+        assert_eq!(Some(&Quantity::from(1)), alice.held_units.get("AAA"));
+        assert_eq!(
+            AccountingError::Account(AccountError::WithdrawOnly("Alice".to_string())),
+            markets
+                .process_order(
+                    Some("BBB"),
+                    Order::new(5, 1, Side::Buy, "Alice".to_string()),
+                )
+                .await
+                .unwrap()
+                .unwrap_err()
+        );
+
+        // Frozen cancels everything, and nothing can be reserved anymore.
+        markets
+            .set_account_status("Alice", AccountStatus::Frozen)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(markets.account("Alice").unwrap().held_units.is_empty());
+        assert_eq!(
+            AccountingError::Account(AccountError::Frozen("Alice".to_string())),
+            markets
+                .process_order(
+                    Some("AAA"),
+                    Order::new(50, 1, Side::Sell, "Alice".to_string()),
+                )
+                .await
+                .unwrap()
+                .unwrap_err()
+        );
+        assert_eq!(
+            AccountingError::Account(AccountError::Frozen("Alice".to_string())),
+            markets
+                .close_account("Alice", Some("Bob"))
+                .await
+                .unwrap()
+                .unwrap_err()
+        );
+        assert_eq!(Ok(Money::from(100)), markets.balance_of("Alice"));
+
+        let reconciliations = markets.reconcile().await.unwrap();
+        assert!(reconciliations.iter().all(Reconciliation::is_consistent));
+    }
+
+    #[tokio::test]
+    async fn cancelling_everywhere_returns_the_orders_of_every_market() {
+        let markets = markets(&["AAA", "BBB"]);
+
+        for symbol in ["AAA", "BBB"] {
+            markets
+                .process_order(
+                    Some(symbol),
+                    Order::new(5, 2, Side::Buy, "Alice".to_string()),
+                )
+                .await
+                .unwrap()
+                .unwrap();
+        }
+
+        let (cancelled, returned) = markets.cancel_everywhere("Alice", None).await.unwrap();
+        assert_eq!(Ok(()), returned);
+        assert_eq!(2, cancelled.len());
+        assert!(markets.account("Alice").unwrap().held.is_zero());
+        assert_eq!(Ok(Money::from(100)), markets.balance_of("Alice"));
+    }
+
+    #[tokio::test]
+    async fn positions_cover_every_market() {
+        let markets = markets(&["AAA", "BBB"]);
+
+        for (symbol, price) in [("AAA", 10), ("BBB", 20)] {
+            markets
+                .process_order(
+                    Some(symbol),
+                    Order::new(price, 2, Side::Sell, "Bob".to_string()),
+                )
+                .await
+                .unwrap()
+                .unwrap();
+            markets
+                .process_order(
+                    Some(symbol),
+                    Order::new(price, 2, Side::Buy, "Alice".to_string()),
+                )
+                .await
+                .unwrap()
+                .unwrap();
+        }
+
+        let positions = markets
+            .positions(None, Some("Alice"), MarkPrice::LastTrade)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            vec![
+                ("AAA", Side::Buy, Some(Price::from(10))),
+                ("BBB", Side::Buy, Some(Price::from(20)))
+            ],
+            positions
+                .iter()
+                .map(|position| (
+                    position.symbol.as_str(),
+                    position.side.clone(),
+                    position.average_price
+                ))
+                .collect::<Vec<_>>()
+        );
+
+        let positions = markets
+            .positions(Some("BBB"), None, MarkPrice::LastTrade)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(2, positions.len());
+        assert_eq!(Side::Sell, positions[1].side);
+        assert_eq!(
+            AccountingError::MarketNotFound("CCC".to_string()),
+            markets
+                .positions(Some("CCC"), None, MarkPrice::Mid)
+                .await
+                .unwrap()
+                .unwrap_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn statements_of_the_ledger_and_of_a_market() {
+        let markets = markets(&["AAA"]);
+        for (price, side, signer) in [(10, Side::Sell, "Bob"), (10, Side::Buy, "Alice")] {
+            markets
+                .process_order(Some("AAA"), Order::new(price, 2, side, signer.to_string()))
+                .await
+                .unwrap()
+                .unwrap();
+        }
+
+        let ledger = markets
+            .statement(None, "Alice", StatementFilter::default())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(Money::ZERO, ledger.opening_balance);
+        assert_eq!(Ok(ledger.closing_balance), markets.balance_of("Alice"));
+        // Reserving for the order, and getting the units back, aren't withdrawals or deposits.
+        assert!(ledger
+            .entries
+            .iter()
+            .any(|entry| matches!(entry.tx, Tx::Reservation { leg: Leg::Out, .. })));
+        assert!(ledger.entries.iter().any(|entry| matches!(
+            entry.tx,
+            Tx::ReleaseUnits { leg: Leg::In, units, .. } if units == 2.into()
+        )));
+        let withdrawals = markets
+            .statement(None, "Alice", "kinds=withdraw".parse().unwrap())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(withdrawals.entries.is_empty());
+
+        let trades = markets
+            .statement(
+                Some("AAA"),
+                "Alice",
+                "kinds=trade_settlement".parse().unwrap(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(1, trades.entries.len());
+        assert_eq!(
+            AccountingError::Account(AccountError::NotFound("Carol".to_string())),
+            markets
+                .statement(None, "Carol", StatementFilter::default())
+                .await
+                .unwrap()
+                .unwrap_err()
+        );
+    }
+}
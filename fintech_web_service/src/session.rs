@@ -0,0 +1,224 @@
+//! Sessions with cancel-on-disconnect
+//!
+//! A signer opts in by opening a WebSocket connection to `GET /session?signer=...`.
+//! While the session is open, the client has to send a message, any message,
+//! at least once per heartbeat timeout.
+//!
+//! As soon as the connection is closed or dropped, or a heartbeat is missed,
+//! all of the signer's resting orders are cancelled. If the connection is still up,
+//! the cancelled orders are sent to the client as JSON, before the connection is closed.
+//!
+//! Orders are cancelled in all markets.
+//! Orders of signers without a session are never cancelled automatically.
+//! Each session acts on its own, so closing any of a signer's sessions
+//! cancels all of their orders.
+
+use crate::handlers::is_valid_name;
+use crate::markets::Markets;
+use fintech_common::requests::SessionRequest;
+use futures_util::{SinkExt, StreamExt};
+use std::convert::Infallible;
+use std::time::Duration;
+use warp::ws::{Message, WebSocket, Ws};
+use warp::{Filter, Rejection, Reply};
+
+/// The heartbeat timeout that is used unless configured otherwise
+pub const DEFAULT_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// **The session route**
+///
+/// GET /session?signer=Alice, upgraded to a WebSocket connection
+pub fn route(
+    markets_state: impl Filter<Extract = (Markets,), Error = Infallible> + Clone + Send + Sync + 'static,
+    heartbeat_timeout: Duration,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path!("session")
+        .and(warp::ws())
+        .and(warp::query::<SessionRequest>())
+        .and(markets_state)
+        .and_then(move |ws: Ws, request: SessionRequest, markets| async move {
+            log::debug!("session; request = {:?}", request);
+
+            is_valid_name(&request.signer)?;
+
+            Ok::<_, Rejection>(
+                ws.on_upgrade(move |socket| {
+                    run(socket, request.signer, markets, heartbeat_timeout)
+                }),
+            )
+        })
+}
+
+/// Waits for the session to end, and then cancels all of the signer's resting orders.
+async fn run(mut socket: WebSocket, signer: String, markets: Markets, heartbeat_timeout: Duration) {
+    log::info!("Session opened for \"{}\"", signer);
+
+    let reason = loop {
+        match tokio::time::timeout(heartbeat_timeout, socket.next()).await {
+            Ok(Some(Ok(message))) if message.is_close() => break "closed",
+            // Every other message counts as a heartbeat.
+            Ok(Some(Ok(_))) => continue,
+            Ok(Some(Err(_))) | Ok(None) => break "dropped",
+            Err(_) => break "heartbeat timed out",
+        }
+    };
+
+    let cancelled = match markets.cancel_everywhere(&signer, None).await {
+        Ok((cancelled, Ok(()))) => cancelled,
+        Ok((cancelled, Err(err))) => {
+            log::error!(
+                "Session for \"{}\" {}; the funds of the cancelled orders couldn't all be returned: {}",
+                signer,
+                reason,
+                err
+            );
+            cancelled
+        }
+        Err(_) => {
+            log::error!(
+                "Session for \"{}\" {}; the engine has stopped",
+                signer,
+                reason
+            );
+            return;
+        }
+    };
+    log::info!(
+        "Session for \"{}\" {}; cancelled {} order(s)",
+        signer,
+        reason,
+        cancelled.len()
+    );
+
+    // The client may not be listening anymore, so failing to reach it is fine.
+    if let Ok(json) = serde_json::to_string(&cancelled) {
+        let _ = socket.send(Message::text(json)).await;
+    }
+    let _ = socket.close().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::DEFAULT_COMMAND_CAPACITY;
+    use crate::markets::DEFAULT_SYMBOL;
+    use fintech_common::trading_platform::TradingPlatform;
+    use fintech_common::types::{Order, PartialOrder, Side};
+
+    fn markets_with_orders() -> Markets {
+        let mut ledger = TradingPlatform::new();
+        let mut market = TradingPlatform::new();
+        for signer in ["Alice", "Bob"] {
+            ledger.deposit(signer, 100.into()).unwrap();
+            market
+                .deposit_units(signer, DEFAULT_SYMBOL, 1.into())
+                .unwrap();
+            market
+                .process_order(Order::new(10, 1, Side::Sell, signer.to_string()))
+                .unwrap();
+        }
+        Markets::spawn(
+            ledger,
+            vec![(DEFAULT_SYMBOL.to_string(), market)],
+            DEFAULT_COMMAND_CAPACITY,
+        )
+    }
+
+    fn book(markets: &Markets) -> Vec<PartialOrder> {
+        markets
+            .market(None)
+            .unwrap()
+            .snapshot()
+            .order_book(false, false)
+    }
+
+    fn session_route(
+        markets: &Markets,
+        heartbeat_timeout: Duration,
+    ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+        let markets = markets.clone();
+        route(warp::any().map(move || markets.clone()), heartbeat_timeout)
+    }
+
+    /// Waits for the session to cancel the orders, which happens after the connection is gone.
+    async fn wait_for_book_len(markets: &Markets, len: usize) {
+        for _ in 0..100 {
+            if book(markets).len() == len {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("The order book never got to {} order(s).", len);
+    }
+
+    #[tokio::test]
+    async fn missed_heartbeat_cancels_signers_orders() {
+        let markets = markets_with_orders();
+        let route = session_route(&markets, Duration::from_millis(50));
+
+        let mut client = warp::test::ws()
+            .path("/session?signer=Alice")
+            .handshake(route)
+            .await
+            .unwrap();
+
+        let message = client.recv().await.unwrap();
+        let cancelled: Vec<PartialOrder> = serde_json::from_str(message.to_str().unwrap()).unwrap();
+        assert_eq!(1, cancelled.len());
+        assert_eq!("Alice", cancelled[0].signer);
+
+        let book = book(&markets);
+        assert_eq!(1, book.len());
+        assert_eq!("Bob", book[0].signer);
+    }
+
+    #[tokio::test]
+    async fn heartbeats_keep_session_alive_until_closed() {
+        let markets = markets_with_orders();
+        let route = session_route(&markets, Duration::from_millis(200));
+
+        let mut client = warp::test::ws()
+            .path("/session?signer=Alice")
+            .handshake(route)
+            .await
+            .unwrap();
+
+        for _ in 0..5 {
+            client.send_text("heartbeat").await;
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert_eq!(2, book(&markets).len());
+
+        client.send(Message::close()).await;
+        wait_for_book_len(&markets, 1).await;
+    }
+
+    #[tokio::test]
+    async fn dropped_connection_cancels_signers_orders() {
+        let markets = markets_with_orders();
+        let route = session_route(&markets, DEFAULT_HEARTBEAT_TIMEOUT);
+
+        let client = warp::test::ws()
+            .path("/session?signer=Bob")
+            .handshake(route)
+            .await
+            .unwrap();
+        drop(client);
+
+        wait_for_book_len(&markets, 1).await;
+        let book = book(&markets);
+        assert_eq!("Alice", book[0].signer);
+    }
+
+    #[tokio::test]
+    async fn empty_signer_is_rejected() {
+        let markets = markets_with_orders();
+        let route = session_route(&markets, DEFAULT_HEARTBEAT_TIMEOUT);
+
+        assert!(warp::test::ws()
+            .path("/session?signer=")
+            .handshake(route)
+            .await
+            .is_err());
+    }
+}